@@ -35,6 +35,7 @@ use polyglot_common::{
     MetricsCollector,
     ContextWindowManager, ContextWindowConfig,
     Database, AuditLogEntry, StoredSession,
+    Redactor, RedactionConfig,
 };
 
 #[derive(Parser, Debug)]
@@ -188,6 +189,8 @@ struct BridgeState {
     config: BridgeConfig,
     token_sessions: RwLock<HashMap<String, TokenSession>>,
     database: Option<Database>,
+    /// Cleans prompts before they become cache keys
+    redactor: Option<Redactor>,
 }
 
 #[derive(Debug, Clone)]
@@ -199,7 +202,12 @@ struct TokenSession {
 }
 
 impl BridgeState {
-    fn new(config: BridgeConfig) -> Arc<Self> {
+    fn new(config: BridgeConfig) -> Result<Arc<Self>> {
+        let redactor = config.redaction.enabled
+            .then(|| Redactor::new(&config.redaction))
+            .transpose()
+            .context("Invalid [redaction] settings")?;
+
         // Initialize database if audit logging is enabled
         let database = if config.audit_log {
             // Create parent directory if needed
@@ -209,7 +217,10 @@ impl BridgeState {
             match Database::open(&config.database_path) {
                 Ok(db) => {
                     info!("Opened database at {:?}", config.database_path);
-                    Some(db)
+                    Some(match redactor.clone() {
+                        Some(redactor) => db.with_redactor(redactor),
+                        None => db,
+                    })
                 }
                 Err(e) => {
                     warn!("Failed to open database: {}, audit logging disabled", e);
@@ -220,7 +231,7 @@ impl BridgeState {
             None
         };
 
-        Arc::new(Self {
+        Ok(Arc::new(Self {
             rate_limiter: RateLimiter::new(RateLimitConfig {
                 max_requests: config.rate_limit,
                 window_seconds: 60,
@@ -242,8 +253,9 @@ impl BridgeState {
             }),
             token_sessions: RwLock::new(HashMap::new()),
             database,
+            redactor,
             config,
-        })
+        }))
     }
 
     /// Get tool with automatic failover if enabled and tool is unhealthy
@@ -258,6 +270,13 @@ impl BridgeState {
     }
 
     /// Log an audit entry to the database
+    fn redact<'a>(&self, text: &'a str) -> std::borrow::Cow<'a, str> {
+        match self.redactor {
+            Some(ref redactor) => redactor.redact(text).text,
+            None => std::borrow::Cow::Borrowed(text),
+        }
+    }
+
     fn log_audit(&self, entry: &AuditLogEntry) {
         if let Some(ref db) = self.database {
             if let Err(e) = db.log_audit(entry) {
@@ -449,6 +468,7 @@ struct BridgeConfig {
     auto_failover: bool,
     audit_log: bool,
     database_path: PathBuf,
+    redaction: RedactionConfig,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -477,6 +497,7 @@ struct BridgeConfigFile {
     qr_host: Option<String>,
     drive_remote: Option<String>,
     drive_path: Option<PathBuf>,
+    redaction: Option<RedactionConfig>,
 }
 
 #[derive(Copy, Clone)]
@@ -578,7 +599,7 @@ async fn main() -> Result<()> {
     };
 
     // Create shared bridge state
-    let state = BridgeState::new(config.clone());
+    let state = BridgeState::new(config.clone())?;
 
     // Spawn background cleanup task for rate limiter
     let cleanup_state = state.clone();
@@ -931,7 +952,7 @@ where
                 };
                 send_ws_message(&mut ws_write, codec, &response).await?;
             }
-            ClientMessage::Prompt { tool, message, working_dir } => {
+            ClientMessage::Prompt { tool, message, working_dir, model } => {
                 // Validate prompt length
                 if let Err(err_msg) = state.validate_prompt(&message) {
                     send_ws_message(&mut ws_write, codec, &err_msg).await?;
@@ -954,9 +975,10 @@ where
                 }
                 current_tool = Some(selected_tool);
 
-                // Check cache first if enabled
+                // Check cache first if enabled. Keys and replies are kept
+                // redacted, like the audit log.
+                let cache_key = format!("{}:{}:{}", selected_tool.as_str(), model.as_deref().unwrap_or(""), state.redact(&message));
                 if config.enable_cache {
-                    let cache_key = format!("{}:{}", selected_tool.as_str(), &message);
                     if let Some(cached) = state.response_cache.get(&cache_key) {
                         let response = ServerMessage::ToolResponse {
                             tool: selected_tool,
@@ -978,6 +1000,7 @@ where
                     &message,
                     use_tool_flag,
                     working_dir.as_deref(),
+                    model.as_deref(),
                     &env_entries,
                 ).await;
 
@@ -997,9 +1020,17 @@ where
                 };
                 state.log_audit(&audit_entry);
 
-                if let Err(e) = result {
-                    state.health_checker.record_failure(selected_tool);
-                    return Err(e);
+                let reply = match result {
+                    Ok(reply) => reply,
+                    Err(e) => {
+                        state.health_checker.record_failure(selected_tool);
+                        return Err(e);
+                    }
+                };
+                if let (true, Some(reply)) = (config.enable_cache, reply) {
+                    let reply = state.redact(&reply).into_owned();
+                    let size = cache_key.len() + reply.len();
+                    state.response_cache.insert(cache_key, reply, size);
                 }
 
                 // Record metrics
@@ -1060,6 +1091,8 @@ where
                     monthly_limit: status.monthly_limit,
                     monthly_used: status.monthly_used,
                     reset_at: Some(status.daily_reset),
+                    daily_tokens_used: status.daily_tokens_used,
+                    monthly_tokens_used: status.monthly_tokens_used,
                 };
                 send_ws_message(&mut ws_write, codec, &response).await?;
             }
@@ -1149,8 +1182,9 @@ async fn handle_prompt_local<S>(
     prompt: &str,
     use_tool_flag: bool,
     working_dir: Option<&str>,
+    model: Option<&str>,
     env_entries: &[(String, String)],
-) -> Result<()>
+) -> Result<Option<String>>
 where
     S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
@@ -1173,6 +1207,10 @@ where
         cmd.arg("--tool").arg(tool.as_str());
     }
 
+    if let Some(model) = model {
+        cmd.arg("--model").arg(model);
+    }
+
     let mut child = match cmd.spawn() {
         Ok(child) => child,
         Err(err) => {
@@ -1181,7 +1219,7 @@ where
                 message: format!("Failed to start {}: {}", config.local_bin, err),
             };
             send_ws_message(ws_write, codec, &msg).await?;
-            return Ok(());
+            return Ok(None);
         }
     };
 
@@ -1193,12 +1231,15 @@ where
 
     let mut stdout_done = false;
     let mut stderr_done = false;
+    let mut reply = String::new();
 
     while !(stdout_done && stderr_done) {
         tokio::select! {
             line = stdout_lines.next_line(), if !stdout_done => {
                 match line? {
                     Some(text) => {
+                        reply.push_str(&text);
+                        reply.push('\n');
                         let msg = ServerMessage::ToolResponse {
                             tool,
                             content: text,
@@ -1243,7 +1284,8 @@ where
     };
     send_ws_message(ws_write, codec, &done).await?;
 
-    Ok(())
+    // Only a complete reply is worth caching.
+    Ok(status.success().then(|| reply.trim_end().to_string()))
 }
 
 async fn list_local_tools(local_bin: &str) -> Result<Vec<ToolInfo>> {
@@ -1277,6 +1319,8 @@ async fn list_local_tools(local_bin: &str) -> Result<Vec<ToolInfo>> {
             enabled: true,
             available: *availability.get(tool).unwrap_or(&false),
             priority: (index + 1) as u8,
+            models: tool.known_models().iter().map(|m| m.to_string()).collect(),
+            default_model: None,
         });
     }
 
@@ -1647,6 +1691,7 @@ impl BridgeConfig {
             auto_failover: cli.auto_failover,
             audit_log: cli.audit_log,
             database_path: cli.database.clone(),
+            redaction: RedactionConfig::default(),
         }
     }
 }
@@ -1685,6 +1730,7 @@ fn load_config(path: &Path) -> Result<BridgeConfig> {
         auto_failover: true,
         audit_log: false,
         database_path: PathBuf::from("./bridge-data/polyglot.db"),
+        redaction: RedactionConfig::default(),
     };
 
     if let Some(listen) = parsed.listen {
@@ -1750,6 +1796,9 @@ fn load_config(path: &Path) -> Result<BridgeConfig> {
     if let Some(path) = parsed.drive_path {
         config.drive_path = path;
     }
+    if let Some(redaction) = parsed.redaction {
+        config.redaction = redaction;
+    }
 
     Ok(config)
 }
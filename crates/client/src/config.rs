@@ -4,7 +4,7 @@
 
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
-use polyglot_common::SyncMode;
+use polyglot_common::{RedactionConfig, Redactor, SyncMode};
 use anyhow::{Context, Result};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,6 +12,9 @@ pub struct ClientConfig {
     pub connection: ConnectionSettings,
     pub sync: SyncSettings,
     pub ui: UiSettings,
+    /// Secrets removed from prompts before they are sent
+    #[serde(default)]
+    pub redaction: RedactionConfig,
 }
 
 impl Default for ClientConfig {
@@ -20,6 +23,7 @@ impl Default for ClientConfig {
             connection: ConnectionSettings::default(),
             sync: SyncSettings::default(),
             ui: UiSettings::default(),
+            redaction: RedactionConfig::default(),
         }
     }
 }
@@ -33,6 +37,14 @@ impl ClientConfig {
         Ok(config)
     }
 
+    /// The prompt redactor, unless `[redaction]` is turned off
+    pub fn redactor(&self) -> Result<Option<Redactor>> {
+        self.redaction.enabled
+            .then(|| Redactor::new(&self.redaction))
+            .transpose()
+            .context("Invalid [redaction] settings")
+    }
+
     pub fn save(&self, path: &PathBuf) -> Result<()> {
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)?;
@@ -61,6 +73,10 @@ pub struct ConnectionSettings {
     pub ca_path: PathBuf,
     pub timeout: u64,
     pub auto_reconnect: bool,
+    /// Send the keys stored with `polyglot-local keys` for the tool a
+    /// prompt runs with to the server. Off unless the server is trusted.
+    #[serde(default)]
+    pub relay_keys: bool,
 }
 
 impl Default for ConnectionSettings {
@@ -72,6 +88,7 @@ impl Default for ConnectionSettings {
             ca_path: PathBuf::from("./certs/ca.crt"),
             timeout: 30,
             auto_reconnect: true,
+            relay_keys: false,
         }
     }
 }
@@ -9,10 +9,11 @@ use std::time::Duration;
 use anyhow::{Context, Result};
 use quinn::{ClientConfig as QuinnClientConfig, Endpoint, Connection};
 use tokio::sync::mpsc;
-use tracing::{info, error};
+use tracing::{info, warn, error};
 
 use polyglot_common::{
-    ClientMessage, ServerMessage, Tool, SyncMode, ExportFormat,
+    ClientMessage, ServerMessage, Tool, SyncMode, ExportFormat, KeyVault, OutputType,
+    Redactor, RedactionReport,
     encode_message, decode_message, PROTOCOL_VERSION, MAX_MESSAGE_SIZE,
 };
 
@@ -35,6 +36,14 @@ pub struct ClientConnection {
     state: ConnectionState,
     session_id: Option<String>,
     cert_fingerprint: String,
+    /// Vault keys that may be relayed, when `relay_keys` is on
+    vault_env: Vec<(Tool, String, String)>,
+    /// The tool whose keys the server holds for this session
+    relayed_tool: Option<Tool>,
+    /// Cleans prompts before they are sent
+    redactor: Option<Redactor>,
+    /// Refuse prompts with secrets instead of redacting them
+    block_secrets: bool,
 }
 
 impl ClientConnection {
@@ -51,6 +60,18 @@ impl ClientConnection {
             String::new()
         };
 
+        let vault_env = if settings.relay_keys {
+            match KeyVault::load_env(&KeyVault::default_dir()) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    warn!("Not relaying stored API keys: {}", e);
+                    Vec::new()
+                }
+            }
+        } else {
+            Vec::new()
+        };
+
         Ok(Self {
             endpoint,
             connection: None,
@@ -59,9 +80,20 @@ impl ClientConnection {
             state: ConnectionState::Disconnected,
             session_id: None,
             cert_fingerprint: fingerprint,
+            vault_env,
+            relayed_tool: None,
+            redactor: None,
+            block_secrets: false,
         })
     }
 
+    /// Redacts prompts before they leave the machine, or with `block_secrets`
+    /// refuses to send those that contain secrets.
+    pub fn set_redactor(&mut self, redactor: Option<Redactor>, block_secrets: bool) {
+        self.redactor = redactor;
+        self.block_secrets = block_secrets;
+    }
+
     pub async fn connect(&mut self, settings: &ConnectionSettings) -> Result<()> {
         self.state = ConnectionState::Connecting;
 
@@ -92,6 +124,7 @@ impl ClientConnection {
         self.handshake().await?;
 
         self.authenticate().await?;
+        self.relayed_tool = None;
 
         Ok(())
     }
@@ -147,6 +180,64 @@ impl ClientConnection {
         }
     }
 
+    /// The tool a prompt runs with, asking the server when none is named.
+    async fn resolve_tool(&mut self, tool: Option<Tool>) -> Result<Option<Tool>> {
+        if tool.is_some() {
+            return Ok(tool);
+        }
+        match self.list_tools().await? {
+            ServerMessage::ToolList { current, .. } => Ok(current),
+            _ => Ok(None),
+        }
+    }
+
+    /// Removes secrets from `message`, and fails when they block it instead.
+    fn redact_prompt(&self, message: &str) -> Result<(String, RedactionReport)> {
+        let Some(ref redactor) = self.redactor else {
+            return Ok((message.to_string(), RedactionReport::default()));
+        };
+        let redacted = redactor.redact(message);
+        if self.block_secrets && !redacted.report.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Prompt not sent, it contains secrets ({}). Remove them, or set block_prompts = false under [redaction] to send it redacted.",
+                redacted.report
+            ));
+        }
+        Ok((redacted.text.into_owned(), redacted.report))
+    }
+
+    /// Sends the stored keys of the tool a prompt runs with, replacing those
+    /// of the tool before it. Nothing is sent unless `relay_keys` is on.
+    async fn relay_keys_for(&mut self, tool: Option<Tool>) -> Result<()> {
+        if self.vault_env.is_empty() {
+            return Ok(());
+        }
+        let Some(tool) = self.resolve_tool(tool).await? else {
+            return Ok(());
+        };
+        if self.relayed_tool == Some(tool) {
+            return Ok(());
+        }
+
+        let entries = self.vault_env.iter()
+            .filter(|(owner, _, _)| *owner == tool)
+            .map(|(_, name, value)| (name.clone(), value.clone()))
+            .collect();
+        self.send_message(&ClientMessage::SetEnv { entries }).await?;
+
+        match self.recv_message().await? {
+            ServerMessage::EnvAck { applied } => {
+                info!("Relayed {} stored API keys for {}", applied, tool);
+                self.relayed_tool = Some(tool);
+                Ok(())
+            }
+            ServerMessage::Error { code, message } => {
+                Err(anyhow::anyhow!("Failed to relay API keys: {} - {}", code, message))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response to SetEnv")),
+        }
+    }
+
     pub async fn send_message(&mut self, msg: &ClientMessage) -> Result<()> {
         let send = self.send_stream.as_mut()
             .ok_or_else(|| anyhow::anyhow!("Not connected"))?;
@@ -183,27 +274,71 @@ impl ClientConnection {
         Ok(msg)
     }
 
-    pub async fn prompt(&mut self, message: &str, tool: Option<Tool>) -> Result<ServerMessage> {
+    /// Sends a prompt and waits for the whole reply, returned as one
+    /// `ToolResponse` (or the message that ended it early), along with what
+    /// was redacted from the prompt.
+    pub async fn prompt(&mut self, message: &str, tool: Option<Tool>, model: Option<String>) -> Result<(ServerMessage, RedactionReport)> {
+        let (message, redacted) = self.redact_prompt(message)?;
+        self.relay_keys_for(tool).await?;
         let msg = ClientMessage::Prompt {
             tool,
-            message: message.to_string(),
+            message,
             working_dir: std::env::current_dir().ok().map(|p| p.to_string_lossy().to_string()),
+            model,
         };
 
         self.send_message(&msg).await?;
-        self.recv_message().await
+
+        let mut reply = String::new();
+        loop {
+            match self.recv_message().await? {
+                ServerMessage::ToolResponse { tool, content, done, tokens } => {
+                    if !content.is_empty() {
+                        reply.push_str(&content);
+                        reply.push('\n');
+                    }
+                    if done {
+                        let response = ServerMessage::ToolResponse {
+                            tool,
+                            content: reply.trim_end().to_string(),
+                            done,
+                            tokens,
+                        };
+                        return Ok((response, redacted));
+                    }
+                }
+                ServerMessage::TextDelta { content, .. } => reply.push_str(&content),
+                // Nothing follows a switch notice for this prompt.
+                ended @ (ServerMessage::Error { .. } | ServerMessage::ToolSwitchNotice { .. }) => return Ok((ended, redacted)),
+                _ => {}
+            }
+        }
     }
 
     pub async fn prompt_streaming(
         &mut self,
         message: &str,
         tool: Option<Tool>,
+        model: Option<String>,
         response_tx: mpsc::Sender<ServerMessage>,
     ) -> Result<()> {
+        let (message, redacted) = self.redact_prompt(message)?;
+        self.relay_keys_for(tool).await?;
+        if !redacted.is_empty() {
+            if let Some(tool) = self.resolve_tool(tool).await? {
+                let notice = ServerMessage::ToolOutput {
+                    tool,
+                    output_type: OutputType::Status,
+                    content: format!("Redacted from the prompt: {}", redacted),
+                };
+                response_tx.send(notice).await.ok();
+            }
+        }
         let msg = ClientMessage::Prompt {
             tool,
-            message: message.to_string(),
+            message,
             working_dir: std::env::current_dir().ok().map(|p| p.to_string_lossy().to_string()),
+            model,
         };
 
         self.send_message(&msg).await?;
@@ -418,18 +553,19 @@ impl ClientConnection {
         settings: &ConnectionSettings,
         message: &str,
         tool: Option<Tool>,
-    ) -> Result<ServerMessage> {
+        model: Option<String>,
+    ) -> Result<(ServerMessage, RedactionReport)> {
         if !self.is_ready() && settings.auto_reconnect {
             self.reconnect(settings, 3).await?;
         }
 
-        match self.prompt(message, tool).await {
+        match self.prompt(message, tool, model.clone()).await {
             Ok(response) => Ok(response),
             Err(e) => {
                 if settings.auto_reconnect {
                     info!("Request failed, attempting reconnect: {}", e);
                     self.reconnect(settings, 3).await?;
-                    self.prompt(message, tool).await
+                    self.prompt(message, tool, model).await
                 } else {
                     Err(e)
                 }
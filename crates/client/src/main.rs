@@ -54,6 +54,10 @@ enum Commands {
 
         #[arg(short, long)]
         tool: Option<String>,
+
+        /// Model to use instead of the tool's default
+        #[arg(short, long)]
+        model: Option<String>,
     },
 
     Sync {
@@ -150,7 +154,7 @@ async fn main() -> Result<()> {
 
     match cli.command {
         Some(Commands::Connect) | None => run_interactive(&config).await,
-        Some(Commands::Prompt { message, tool }) => run_prompt(&config, &message, tool).await,
+        Some(Commands::Prompt { message, tool, model }) => run_prompt(&config, &message, tool, model).await,
         Some(Commands::Sync { path, mode }) => run_sync(&config, &path, &mode).await,
         Some(Commands::Usage) => run_usage(&config).await,
         Some(Commands::Tools) => run_tools(&config).await,
@@ -175,6 +179,7 @@ async fn run_interactive(config: &ClientConfig) -> Result<()> {
     app.add_output(OutputType::System, format!("Connecting to {}...", config.connection.server_address));
 
     let mut conn = ClientConnection::new(&config.connection).await?;
+    conn.set_redactor(config.redactor()?, config.redaction.block_prompts);
 
     match conn.connect(&config.connection).await {
         Ok(_) => {
@@ -182,7 +187,7 @@ async fn run_interactive(config: &ClientConfig) -> Result<()> {
             app.add_output(OutputType::System, "Connected successfully!".to_string());
 
             if let Ok(ServerMessage::ToolList { tools, current }) = conn.list_tools().await {
-                app.tools = tools.iter().map(|t| (t.tool, t.available)).collect();
+                app.set_tool_list(&tools);
                 app.current_tool = current;
             }
 
@@ -493,7 +498,7 @@ async fn run_tui_interactive(
                                     AppAction::Quit => break,
                                     AppAction::SendPrompt(message) => {
                                         let tx = response_tx.clone();
-                                        if let Err(e) = conn.prompt_streaming(&message, app.current_tool, tx).await {
+                                        if let Err(e) = conn.prompt_streaming(&message, app.current_tool, app.selected_model(), tx).await {
                                             app.add_output(OutputType::Error, format!("Error: {}", e));
                                         }
                                     }
@@ -514,7 +519,7 @@ async fn run_tui_interactive(
                                     AppAction::RequestTools => {
                                         match conn.list_tools().await {
                                             Ok(ServerMessage::ToolList { tools, current }) => {
-                                                app.tools = tools.iter().map(|t| (t.tool, t.available)).collect();
+                                                app.set_tool_list(&tools);
                                                 app.current_tool = current;
                                             }
                                             Err(e) => {
@@ -591,9 +596,18 @@ async fn run_tui_interactive(
                                 }
                             }
                         }
+                        ServerMessage::ToolOutput { output_type: polyglot_common::OutputType::Status, content, .. } => {
+                            app.add_output(OutputType::System, content);
+                        }
                         ServerMessage::ToolOutput { content, .. } => {
                             app.add_output(OutputType::System, format!("[stderr] {}", content));
                         }
+                        ServerMessage::TextDelta { content, .. } => {
+                            app.append_assistant_text(&content);
+                        }
+                        ServerMessage::ToolAction { action, .. } => {
+                            app.add_output(OutputType::System, format!("> {}", action));
+                        }
                         ServerMessage::ToolSwitchNotice { from, to, reason, countdown } => {
                             app.add_output(
                                 OutputType::System,
@@ -679,7 +693,12 @@ async fn run_cli_loop(conn: &mut ClientConnection, app: &mut App) -> Result<()>
             continue;
         }
 
-        match conn.prompt(input, app.current_tool).await {
+        match conn.prompt(input, app.current_tool, app.selected_model()).await.map(|(response, redacted)| {
+            if !redacted.is_empty() {
+                println!("(Redacted from the prompt: {})", redacted);
+            }
+            response
+        }) {
             Ok(ServerMessage::ToolResponse { tool, content, done: _, tokens }) => {
                 println!("[{}] {}", tool.display_name(), content);
                 if let Some(t) = tokens {
@@ -701,14 +720,21 @@ async fn run_cli_loop(conn: &mut ClientConnection, app: &mut App) -> Result<()>
     Ok(())
 }
 
-async fn run_prompt(config: &ClientConfig, message: &str, tool: Option<String>) -> Result<()> {
+async fn run_prompt(config: &ClientConfig, message: &str, tool: Option<String>, model: Option<String>) -> Result<()> {
     let tool = tool.map(|t| t.parse::<Tool>()).transpose()
         .map_err(|e| anyhow::anyhow!("Invalid tool: {}", e))?;
 
     let mut conn = ClientConnection::new(&config.connection).await?;
+    conn.set_redactor(config.redactor()?, config.redaction.block_prompts);
     conn.connect(&config.connection).await?;
 
-    match conn.prompt(message, tool).await {
+    let response = conn.prompt(message, tool, model).await.map(|(response, redacted)| {
+        if !redacted.is_empty() {
+            eprintln!("(Redacted from the prompt: {})", redacted);
+        }
+        response
+    });
+    match response {
         Ok(ServerMessage::ToolResponse { tool: _, content, done: _, tokens }) => {
             println!("{}", content);
             if let Some(t) = tokens {
@@ -785,6 +811,9 @@ async fn run_usage(config: &ClientConfig) -> Result<()> {
                 println!("{}", stat.tool.display_name());
                 println!("  Requests:      {}", stat.requests);
                 println!("  Tokens Used:   {}", stat.tokens_used);
+                if stat.input_tokens + stat.output_tokens > 0 {
+                    println!("    In / Out:    {} / {}", stat.input_tokens, stat.output_tokens);
+                }
                 println!("  Errors:        {}", stat.errors);
                 println!("  Rate Limits:   {}", stat.rate_limit_hits);
                 if let Some(last) = stat.last_used {
@@ -821,6 +850,12 @@ async fn run_tools(config: &ClientConfig) -> Result<()> {
                 let status = if tool_info.available { "[OK]" } else { "[--]" };
                 let current_marker = if Some(tool_info.tool) == current { " (current)" } else { "" };
                 println!("  {} {}{}", status, tool_info.tool.display_name(), current_marker);
+                if !tool_info.models.is_empty() {
+                    println!("       models: {}", tool_info.models.join(", "));
+                }
+                if let Some(model) = &tool_info.default_model {
+                    println!("       default: {}", model);
+                }
             }
         }
         Ok(ServerMessage::Error { code, message }) => {
@@ -920,7 +955,10 @@ async fn run_quota(config: &ClientConfig) -> Result<()> {
     conn.connect(&config.connection).await?;
 
     match conn.quota_check().await {
-        Ok(ServerMessage::QuotaInfo { daily_limit, daily_used, monthly_limit, monthly_used, reset_at }) => {
+        Ok(ServerMessage::QuotaInfo {
+            daily_limit, daily_used, monthly_limit, monthly_used, reset_at,
+            daily_tokens_used, monthly_tokens_used,
+        }) => {
             println!("Quota Status");
             println!("============");
             println!();
@@ -951,6 +989,9 @@ async fn run_quota(config: &ClientConfig) -> Result<()> {
             }
             println!();
 
+            println!("Tokens: {} today, {} this month", daily_tokens_used, monthly_tokens_used);
+            println!();
+
             if let Some(reset) = reset_at {
                 println!("Resets at: {}", reset);
             }
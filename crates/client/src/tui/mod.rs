@@ -21,7 +21,9 @@ use ratatui::{
     widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
     Frame, Terminal,
 };
-use polyglot_common::{Tool, ToolUsage};
+use std::collections::HashMap;
+
+use polyglot_common::{Tool, ToolUsage, ToolInfo};
 use unicode_width::UnicodeWidthChar;
 
 pub struct App {
@@ -37,6 +39,13 @@ pub struct App {
     pub should_quit: bool,
     pub status: String,
     pub scroll_offset: usize,
+    /// Models chosen with `/model`, per tool
+    pub models: HashMap<Tool, String>,
+    /// Models each tool reported in the last tool list
+    pub tool_models: HashMap<Tool, Vec<String>>,
+    pub default_models: HashMap<Tool, String>,
+    /// Whether the last output line is still receiving streamed text
+    delta_open: bool,
 }
 
 #[derive(Clone)]
@@ -78,6 +87,10 @@ impl Default for App {
             should_quit: false,
             status: "Disconnected".to_string(),
             scroll_offset: 0,
+            models: HashMap::new(),
+            tool_models: HashMap::new(),
+            default_models: HashMap::new(),
+            delta_open: false,
         }
     }
 }
@@ -95,6 +108,7 @@ impl App {
     }
 
     pub fn add_output(&mut self, line_type: OutputType, content: String) {
+        self.delta_open = false;
         self.output.push(OutputLine {
             timestamp: chrono::Utc::now(),
             line_type,
@@ -106,6 +120,73 @@ impl App {
         }
     }
 
+    /// Appends streamed assistant text, continuing the current line until a newline arrives.
+    pub fn append_assistant_text(&mut self, text: &str) {
+        for (i, piece) in text.split('\n').enumerate() {
+            if i > 0 {
+                if !self.delta_open {
+                    self.add_output(OutputType::Assistant, String::new());
+                }
+                self.delta_open = false;
+            }
+            if piece.is_empty() {
+                continue;
+            }
+
+            match self.output.last_mut() {
+                Some(last) if self.delta_open => last.content.push_str(piece),
+                _ => {
+                    self.add_output(OutputType::Assistant, piece.to_string());
+                    self.delta_open = true;
+                }
+            }
+        }
+    }
+
+    pub fn set_tool_list(&mut self, tools: &[ToolInfo]) {
+        self.tools = tools.iter().map(|t| (t.tool, t.available)).collect();
+        self.tool_models = tools.iter().map(|t| (t.tool, t.models.clone())).collect();
+        self.default_models = tools.iter()
+            .filter_map(|t| t.default_model.clone().map(|m| (t.tool, m)))
+            .collect();
+    }
+
+    /// Model picked for the current tool, if any; `None` lets the server use its default.
+    pub fn selected_model(&self) -> Option<String> {
+        self.current_tool.and_then(|t| self.models.get(&t).cloned())
+    }
+
+    fn handle_model_command(&mut self, name: Option<&str>) {
+        let Some(tool) = self.current_tool else {
+            self.add_output(OutputType::Error, "Select a tool before choosing a model.".to_string());
+            return;
+        };
+
+        match name {
+            None => {
+                let current = self.selected_model()
+                    .or_else(|| self.default_models.get(&tool).cloned())
+                    .unwrap_or_else(|| "tool default".to_string());
+                self.add_output(OutputType::System, format!("{} model: {}", tool.display_name(), current));
+                if let Some(models) = self.tool_models.get(&tool).filter(|m| !m.is_empty()) {
+                    self.add_output(OutputType::System, format!("Available: {}", models.join(", ")));
+                }
+            }
+            Some("default") => {
+                self.models.remove(&tool);
+                self.add_output(OutputType::System, format!("{} will use its default model", tool.display_name()));
+            }
+            Some(model) => {
+                if !tool.supports_model_selection() {
+                    self.add_output(OutputType::Error, format!("{} does not support choosing a model", tool.display_name()));
+                    return;
+                }
+                self.models.insert(tool, model.to_string());
+                self.add_output(OutputType::System, format!("{} model set to {}", tool.display_name(), model));
+            }
+        }
+    }
+
     pub fn handle_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Option<AppAction> {
         match (code, modifiers) {
             (KeyCode::Char('c'), KeyModifiers::CONTROL) |
@@ -250,6 +331,10 @@ impl App {
                 self.add_output(OutputType::Error, "Usage: /switch <tool>".to_string());
                 AppAction::None
             }
+            Some("model") => {
+                self.handle_model_command(parts.get(1).copied());
+                AppAction::None
+            }
             Some("help") => {
                 self.view = View::Help;
                 AppAction::None
@@ -444,10 +529,16 @@ fn draw_tools_view(f: &mut Frame, area: Rect, app: &App) {
                 Style::default().fg(Color::Red)
             };
 
+            let model = app.models.get(tool)
+                .or_else(|| app.default_models.get(tool))
+                .map(|m| format!(" [{}]", m))
+                .unwrap_or_default();
+
             ListItem::new(Line::from(vec![
                 Span::styled(status, style),
                 Span::raw(" "),
                 Span::styled(tool.display_name(), Style::default().fg(Color::White)),
+                Span::styled(model, Style::default().fg(Color::Cyan)),
                 Span::styled(current, Style::default().fg(Color::Yellow)),
             ]))
         })
@@ -488,6 +579,7 @@ fn draw_help_view(f: &mut Frame, area: Rect, _app: &App) {
         Line::from("  /usage      - Show usage statistics"),
         Line::from("  /tools      - Show available tools"),
         Line::from("  /switch <t> - Switch to tool (claude, gemini, codex, copilot)"),
+        Line::from("  /model [m]  - Show or set the model for the current tool (default resets)"),
         Line::from("  /sync [p]   - Sync files (optional path)"),
         Line::from("  /update     - Check for updates"),
         Line::from("  /clear      - Clear chat history"),
@@ -627,8 +719,13 @@ fn draw_status_bar(f: &mut Frame, area: Rect, app: &App) {
         Style::default().fg(Color::Red)
     };
 
+    let model = app.selected_model()
+        .map(|m| format!(" | Model: {}", m))
+        .unwrap_or_default();
+
     let status = Paragraph::new(Line::from(vec![
         Span::styled(&app.status, status_style),
+        Span::raw(model),
         Span::raw(" | "),
         Span::raw("Ctrl+Q to quit"),
     ]))
@@ -0,0 +1,207 @@
+//! Parser for Claude Code's `--output-format stream-json` output
+//!
+//! Each stdout line is a JSON object. Assistant text arrives either as whole
+//! content blocks or, with `--include-partial-messages`, as `text_delta`
+//! stream events; the final `result` line carries token usage. Lines also
+//! carry the CLI's session ID, which `--resume` accepts later.
+
+use serde_json::Value;
+
+use crate::models::TokenUsage;
+use crate::protocol::ToolAction;
+
+/// Arguments that switch `claude --print` to line-delimited JSON events.
+pub const CLAUDE_STREAM_ARGS: &[&str] = &["--output-format", "stream-json", "--verbose"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClaudeEvent {
+    /// Assistant text, not necessarily ending on a line boundary
+    Text(String),
+    Action(ToolAction),
+    Usage(TokenUsage),
+    /// ID to pass to `--resume` to continue this conversation
+    Session(String),
+    Error(String),
+}
+
+#[derive(Debug, Default)]
+pub struct ClaudeStreamParser {
+    /// Set once a `text_delta` has been seen; whole text blocks are then duplicates.
+    partial_text: bool,
+    in_text_block: bool,
+    session_id: Option<String>,
+}
+
+impl ClaudeStreamParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse_line(&mut self, line: &str) -> Vec<ClaudeEvent> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Vec::new();
+        }
+
+        // Older CLIs or wrapper scripts may print plain text; pass it through.
+        let value: Value = match serde_json::from_str(line) {
+            Ok(v @ Value::Object(_)) => v,
+            _ => return vec![ClaudeEvent::Text(format!("{}\n", line))],
+        };
+
+        let mut events = Vec::new();
+        if let Some(id) = value["session_id"].as_str() {
+            if self.session_id.as_deref() != Some(id) {
+                self.session_id = Some(id.to_string());
+                events.push(ClaudeEvent::Session(id.to_string()));
+            }
+        }
+
+        events.extend(match value["type"].as_str() {
+            Some("stream_event") => self.parse_stream_event(&value["event"]),
+            Some("assistant") => self.parse_assistant(&value["message"]),
+            Some("result") => parse_result(&value),
+            _ => Vec::new(),
+        });
+        events
+    }
+
+    fn parse_stream_event(&mut self, event: &Value) -> Vec<ClaudeEvent> {
+        match event["type"].as_str() {
+            Some("content_block_start") => {
+                self.in_text_block = event["content_block"]["type"] == "text";
+                Vec::new()
+            }
+            Some("content_block_delta") if event["delta"]["type"] == "text_delta" => {
+                self.partial_text = true;
+                let text = event["delta"]["text"].as_str().unwrap_or_default();
+                vec![ClaudeEvent::Text(text.to_string())]
+            }
+            Some("content_block_stop") if self.in_text_block => {
+                self.in_text_block = false;
+                vec![ClaudeEvent::Text("\n".to_string())]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn parse_assistant(&self, message: &Value) -> Vec<ClaudeEvent> {
+        let Some(blocks) = message["content"].as_array() else {
+            return Vec::new();
+        };
+
+        let mut events = Vec::new();
+        for block in blocks {
+            match block["type"].as_str() {
+                Some("text") if !self.partial_text => {
+                    let mut text = block["text"].as_str().unwrap_or_default().to_string();
+                    if !text.ends_with('\n') {
+                        text.push('\n');
+                    }
+                    events.push(ClaudeEvent::Text(text));
+                }
+                Some("tool_use") => {
+                    let name = block["name"].as_str().unwrap_or("tool");
+                    events.push(ClaudeEvent::Action(action_for(name, &block["input"])));
+                }
+                _ => {}
+            }
+        }
+        events
+    }
+}
+
+fn parse_result(value: &Value) -> Vec<ClaudeEvent> {
+    let mut events = Vec::new();
+
+    let usage = &value["usage"];
+    if usage.is_object() {
+        let count = |key: &str| usage[key].as_u64().unwrap_or(0);
+        events.push(ClaudeEvent::Usage(TokenUsage {
+            input_tokens: count("input_tokens"),
+            output_tokens: count("output_tokens"),
+            cache_read_tokens: count("cache_read_input_tokens"),
+            cache_creation_tokens: count("cache_creation_input_tokens"),
+        }));
+    }
+
+    if value["is_error"].as_bool().unwrap_or(false) {
+        let message = value["result"].as_str()
+            .or_else(|| value["subtype"].as_str())
+            .unwrap_or("Claude reported an error");
+        events.push(ClaudeEvent::Error(message.to_string()));
+    }
+
+    events
+}
+
+pub(crate) fn action_for(name: &str, input: &Value) -> ToolAction {
+    let field = |key: &str| input[key].as_str().unwrap_or_default().to_string();
+
+    match name {
+        "Bash" => ToolAction::ShellCommand { command: field("command") },
+        "Edit" | "MultiEdit" | "Write" => ToolAction::FileEdit { path: field("file_path") },
+        "NotebookEdit" => ToolAction::FileEdit { path: field("notebook_path") },
+        _ => {
+            let detail = ["file_path", "path", "pattern", "url", "query", "description"]
+                .iter()
+                .find_map(|key| input[*key].as_str())
+                .unwrap_or_default()
+                .to_string();
+            ToolAction::Other { name: name.to_string(), detail }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assistant_blocks_and_result_usage() {
+        let mut parser = ClaudeStreamParser::new();
+        assert_eq!(
+            parser.parse_line(r#"{"type":"system","subtype":"init","session_id":"abc"}"#),
+            vec![ClaudeEvent::Session("abc".to_string())]
+        );
+
+        let events = parser.parse_line(r#"{"type":"assistant","message":{"content":[
+            {"type":"text","text":"Fixing it"},
+            {"type":"tool_use","id":"t1","name":"Edit","input":{"file_path":"src/lib.rs","old_string":"a","new_string":"b"}},
+            {"type":"tool_use","id":"t2","name":"Bash","input":{"command":"cargo test"}}
+        ]}}"#.replace('\n', "").as_str());
+        assert_eq!(events, vec![
+            ClaudeEvent::Text("Fixing it\n".to_string()),
+            ClaudeEvent::Action(ToolAction::FileEdit { path: "src/lib.rs".to_string() }),
+            ClaudeEvent::Action(ToolAction::ShellCommand { command: "cargo test".to_string() }),
+        ]);
+
+        let events = parser.parse_line(r#"{"type":"result","subtype":"success","session_id":"abc","is_error":false,"result":"Fixing it","usage":{"input_tokens":12,"output_tokens":30,"cache_read_input_tokens":100}}"#);
+        let ClaudeEvent::Usage(usage) = &events[0] else { panic!("expected usage") };
+        assert_eq!(usage.input_tokens, 12);
+        assert_eq!(usage.output_tokens, 30);
+        assert_eq!(usage.total(), 142);
+    }
+
+    #[test]
+    fn test_partial_messages_suppress_whole_blocks() {
+        let mut parser = ClaudeStreamParser::new();
+        parser.parse_line(r#"{"type":"stream_event","event":{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}}"#);
+        let delta = parser.parse_line(r#"{"type":"stream_event","event":{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hel"}}}"#);
+        assert_eq!(delta, vec![ClaudeEvent::Text("Hel".to_string())]);
+        let stop = parser.parse_line(r#"{"type":"stream_event","event":{"type":"content_block_stop","index":0}}"#);
+        assert_eq!(stop, vec![ClaudeEvent::Text("\n".to_string())]);
+
+        let whole = parser.parse_line(r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Hello"}]}}"#);
+        assert!(whole.is_empty());
+    }
+
+    #[test]
+    fn test_plain_text_and_errors() {
+        let mut parser = ClaudeStreamParser::new();
+        assert_eq!(parser.parse_line("not json"), vec![ClaudeEvent::Text("not json\n".to_string())]);
+
+        let events = parser.parse_line(r#"{"type":"result","subtype":"error_max_turns","is_error":true}"#);
+        assert_eq!(events, vec![ClaudeEvent::Error("error_max_turns".to_string())]);
+    }
+}
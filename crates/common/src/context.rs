@@ -1,8 +1,11 @@
+use std::collections::HashMap;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::Tool;
+use crate::repo_map::RepoMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
@@ -11,6 +14,9 @@ pub struct Message {
     pub content: String,
     pub timestamp: DateTime<Utc>,
     pub token_estimate: u32,
+    /// Model that produced an assistant message, when known
+    #[serde(default)]
+    pub model: Option<String>,
 }
 
 impl Message {
@@ -22,9 +28,15 @@ impl Message {
             content,
             timestamp: Utc::now(),
             token_estimate,
+            model: None,
         }
     }
 
+    pub fn with_model(mut self, model: Option<String>) -> Self {
+        self.model = model;
+        self
+    }
+
     pub fn user(content: impl Into<String>) -> Self {
         Self::new(MessageRole::User, content.into())
     }
@@ -58,6 +70,23 @@ pub struct ChatSession {
     pub summary: Option<String>,
     pub key_references: Vec<CodeReference>,
     pub total_tokens: u32,
+    /// Session IDs reported by each tool's CLI, used to resume them natively
+    #[serde(default)]
+    pub native_sessions: HashMap<Tool, String>,
+    /// Number of leading messages `summary` covers
+    #[serde(default)]
+    pub summarized_messages: usize,
+    /// The session this one was forked from
+    #[serde(default)]
+    pub parent: Option<ForkPoint>,
+}
+
+/// Where a forked session branched off its parent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForkPoint {
+    pub session_id: Uuid,
+    /// Number of the parent's messages the fork starts with
+    pub messages: usize,
 }
 
 impl ChatSession {
@@ -74,7 +103,30 @@ impl ChatSession {
             summary: None,
             key_references: Vec::new(),
             total_tokens: 0,
+            native_sessions: HashMap::new(),
+            summarized_messages: 0,
+            parent: None,
+        }
+    }
+
+    /// A new session holding the first `messages` messages of this one,
+    /// linked back to it. Tools' native sessions aren't carried over, as
+    /// they would include the turns the fork leaves out.
+    pub fn fork(&self, messages: usize) -> ChatSession {
+        let messages = messages.min(self.messages.len());
+        let mut fork = ChatSession::new(self.project_path.clone());
+        fork.title = Some(format!("{} (fork)", self.display_title()));
+        fork.tool = self.tool;
+        fork.key_references = self.key_references.clone();
+        for message in &self.messages[..messages] {
+            fork.add_message(message.clone());
+        }
+        if self.summarized_messages <= messages {
+            fork.summary = self.summary.clone();
+            fork.summarized_messages = self.summarized_messages;
         }
+        fork.parent = Some(ForkPoint { session_id: self.id, messages });
+        fork
     }
 
     pub fn auto_title(&mut self) {
@@ -118,6 +170,43 @@ impl ChatSession {
     pub fn needs_summarization(&self, token_threshold: u32) -> bool {
         self.total_tokens > token_threshold
     }
+
+    /// Messages the summary should cover but doesn't yet. The summary is only
+    /// refreshed once `keep_recent_messages` more messages have aged out of
+    /// the recent window, so it isn't regenerated on every turn.
+    pub fn unsummarized_range(&self, config: &SummarizerConfig) -> Option<std::ops::Range<usize>> {
+        if !self.needs_summarization(config.summarize_threshold) {
+            return None;
+        }
+
+        let end = self.messages.len().saturating_sub(config.keep_recent_messages);
+        let start = self.summarized_messages.min(end);
+        let batch = config.keep_recent_messages.max(1);
+        if end == start || (self.summary.is_some() && end - start < batch) {
+            return None;
+        }
+        Some(start..end)
+    }
+
+    /// Stores a summary of the first `covered` messages.
+    pub fn set_summary(&mut self, summary: String, covered: usize) {
+        self.summary = Some(summary);
+        self.summarized_messages = covered.min(self.messages.len());
+    }
+
+    pub fn native_session(&self, tool: Tool) -> Option<&str> {
+        self.native_sessions.get(&tool).map(|s| s.as_str())
+    }
+
+    pub fn set_native_session(&mut self, tool: Tool, session_id: String) {
+        self.native_sessions.insert(tool, session_id);
+    }
+
+    /// Whether the tool will see earlier turns through its own session,
+    /// making Polyglot's context prefix redundant.
+    pub fn resumes_natively(&self, tool: Tool) -> bool {
+        tool.resume_flag().is_some() && self.native_session(tool).is_some()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -136,9 +225,19 @@ pub struct TransferContext {
     pub code_context: Vec<CodeReference>,
     pub project_path: Option<String>,
     pub token_estimate: u32,
+    /// Rendered `RepoMap`, included on request
+    #[serde(default)]
+    pub repo_map: Option<String>,
 }
 
 impl TransferContext {
+    /// Adds a map of the repository, cut down to `token_budget` tokens.
+    pub fn include_repo_map(&mut self, map: &RepoMap, token_budget: u32) {
+        let rendered = map.render(token_budget);
+        self.token_estimate += (rendered.len() / 4) as u32;
+        self.repo_map = Some(rendered);
+    }
+
     pub fn as_prompt_prefix(&self) -> String {
         let mut parts = Vec::new();
 
@@ -146,6 +245,10 @@ impl TransferContext {
             parts.push(format!("[Project: {}]", path));
         }
 
+        if let Some(ref map) = self.repo_map {
+            parts.push(map.clone());
+        }
+
         if !self.summary.is_empty() {
             parts.push(format!("[Context: {}]", self.summary));
         }
@@ -196,6 +299,9 @@ pub struct HistoryEntry {
     pub tool: Option<Tool>,
     pub preview: String,
     pub message_count: u32,
+    /// The session this one was forked from
+    #[serde(default)]
+    pub parent_id: Option<Uuid>,
 }
 
 impl From<&ChatSession> for HistoryEntry {
@@ -214,10 +320,40 @@ impl From<&ChatSession> for HistoryEntry {
             tool: session.tool,
             preview,
             message_count: session.messages.len() as u32,
+            parent_id: session.parent.map(|p| p.session_id),
         }
     }
 }
 
+/// Orders `entries` so each fork follows its parent, with its depth in the
+/// tree. Entries keep their relative order among siblings; forks whose
+/// parent isn't listed are placed at the top level.
+pub fn branch_tree(entries: &[HistoryEntry]) -> Vec<(usize, &HistoryEntry)> {
+    tree_order(entries, |e| e.session_id, |e| e.parent_id)
+}
+
+fn tree_order<T>(items: &[T], id: impl Fn(&T) -> Uuid, parent: impl Fn(&T) -> Option<Uuid>) -> Vec<(usize, &T)> {
+    let ids: std::collections::HashSet<Uuid> = items.iter().map(&id).collect();
+    let mut children: HashMap<Uuid, Vec<&T>> = HashMap::new();
+    let mut stack = Vec::new();
+    for item in items.iter().rev() {
+        match parent(item).filter(|p| ids.contains(p) && *p != id(item)) {
+            Some(p) => children.entry(p).or_default().push(item),
+            None => stack.push((0, item)),
+        }
+    }
+
+    let mut ordered = Vec::with_capacity(items.len());
+    while let Some((depth, item)) = stack.pop() {
+        ordered.push((depth, item));
+        // Pushed in reverse so siblings come out in their original order.
+        if let Some(kids) = children.remove(&id(item)) {
+            stack.extend(kids.into_iter().map(|k| (depth + 1, k)));
+        }
+    }
+    ordered
+}
+
 impl HistoryEntry {
     pub fn matches_search(&self, query: &str) -> bool {
         let query_lower = query.to_lowercase();
@@ -230,12 +366,17 @@ impl HistoryEntry {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SummarizerConfig {
     pub max_summary_tokens: u32,
     pub summarize_threshold: u32,
     pub keep_recent_messages: usize,
     pub max_snippet_length: usize,
     pub max_history_sessions: usize,
+    /// Tool that writes summaries; the heuristic summarizer is used when unset or when it fails
+    pub tool: Option<Tool>,
+    /// Model to ask, e.g. a small local Ollama model
+    pub model: Option<String>,
 }
 
 impl Default for SummarizerConfig {
@@ -246,6 +387,8 @@ impl Default for SummarizerConfig {
             keep_recent_messages: 4,
             max_snippet_length: 500,
             max_history_sessions: 100,
+            tool: None,
+            model: None,
         }
     }
 }
@@ -397,6 +540,59 @@ pub fn summarize_messages(messages: &[Message], config: &SummarizerConfig) -> St
     truncate_smart(&full_summary, max_chars)
 }
 
+/// Longest excerpt of a single message included in a summary prompt
+const SUMMARY_PROMPT_MESSAGE_CHARS: usize = 2000;
+
+/// Builds the prompt asking a tool to summarize `messages`, folding them into
+/// `previous` when the session already has a summary.
+pub fn summary_prompt(previous: Option<&str>, messages: &[Message], config: &SummarizerConfig) -> String {
+    let mut prompt = String::from(
+        "Summarize this conversation between a user and a coding assistant so another \
+         assistant can continue it. Reply with only the summary, using these sections:\n\
+         Goal: what the user is trying to achieve\n\
+         Decisions: choices made and approaches ruled out\n\
+         Code: files, functions and commands involved\n\
+         Open: unresolved questions and next steps\n",
+    );
+    prompt.push_str(&format!(
+        "Keep it under {} words. Keep concrete names, paths and error messages.\n",
+        config.max_summary_tokens * 3 / 4
+    ));
+
+    if let Some(previous) = previous.filter(|p| !p.is_empty()) {
+        prompt.push_str("\nUpdate this existing summary with the new messages below:\n");
+        prompt.push_str(previous);
+        prompt.push('\n');
+    }
+
+    prompt.push_str("\nMessages:\n");
+    for message in messages {
+        let role = match message.role {
+            MessageRole::User => "User",
+            MessageRole::Assistant => "Assistant",
+            MessageRole::System => "System",
+        };
+        prompt.push_str(&format!(
+            "{}: {}\n\n",
+            role,
+            truncate_smart(&message.content, SUMMARY_PROMPT_MESSAGE_CHARS)
+        ));
+    }
+
+    prompt.trim_end().to_string()
+}
+
+/// Trims a tool's summary reply and caps it at `max_summary_tokens`. Returns
+/// `None` for an empty reply, so the caller falls back to the heuristic.
+pub fn clean_summary(reply: &str, config: &SummarizerConfig) -> Option<String> {
+    let reply = reply.trim();
+    let reply = reply.strip_prefix("Summary:").unwrap_or(reply).trim();
+    if reply.is_empty() {
+        return None;
+    }
+    Some(truncate_smart(reply, (config.max_summary_tokens * 4) as usize))
+}
+
 pub fn create_transfer_context(session: &ChatSession, config: &SummarizerConfig) -> TransferContext {
     let messages_to_summarize = if session.messages.len() > config.keep_recent_messages {
         &session.messages[..session.messages.len() - config.keep_recent_messages]
@@ -437,6 +633,7 @@ pub fn create_transfer_context(session: &ChatSession, config: &SummarizerConfig)
         code_context,
         project_path: session.project_path.clone(),
         token_estimate,
+        repo_map: None,
     }
 }
 
@@ -473,21 +670,40 @@ fn export_to_markdown(session: &ChatSession) -> String {
     if let Some(ref tool) = session.tool {
         output.push_str(&format!("**Tool:** {}\n", tool.display_name()));
     }
+    if let Some(parent) = session.parent {
+        output.push_str(&format!(
+            "**Forked from:** {} after message {}\n", parent.session_id, parent.messages
+        ));
+    }
 
     output.push_str("\n---\n\n");
 
-    // Messages
-    for message in &session.messages {
+    // Messages, marking where a fork's own turns begin
+    let branch_at = session.parent.map(|p| p.messages);
+    let branch_marker = "*Branch starts here*\n\n---\n\n";
+    for (i, message) in session.messages.iter().enumerate() {
+        if branch_at == Some(i) {
+            output.push_str(branch_marker);
+        }
+
         let role_label = match message.role {
             MessageRole::User => "**User**",
             MessageRole::Assistant => "**Assistant**",
             MessageRole::System => "**System**",
         };
 
-        output.push_str(&format!("### {} ({})\n\n", role_label, message.timestamp.format("%H:%M:%S")));
+        match &message.model {
+            Some(model) => output.push_str(&format!(
+                "### {} ({}, {})\n\n", role_label, model, message.timestamp.format("%H:%M:%S")
+            )),
+            None => output.push_str(&format!("### {} ({})\n\n", role_label, message.timestamp.format("%H:%M:%S"))),
+        }
         output.push_str(&message.content);
         output.push_str("\n\n---\n\n");
     }
+    if branch_at == Some(session.messages.len()) {
+        output.push_str(branch_marker);
+    }
 
     // Summary if available
     if let Some(ref summary) = session.summary {
@@ -532,11 +748,22 @@ fn export_to_html(session: &ChatSession) -> String {
     if let Some(ref tool) = session.tool {
         output.push_str(&format!("<p><strong>Tool:</strong> {}</p>\n", html_escape(tool.display_name())));
     }
+    if let Some(parent) = session.parent {
+        output.push_str(&format!(
+            "<p><strong>Forked from:</strong> {} after message {}</p>\n", parent.session_id, parent.messages
+        ));
+    }
     output.push_str(&format!("<p><strong>Messages:</strong> {}</p>\n", session.messages.len()));
     output.push_str("</div>\n");
 
-    // Messages
-    for message in &session.messages {
+    // Messages, marking where a fork's own turns begin
+    let branch_at = session.parent.map(|p| p.messages);
+    let branch_marker = "<hr><p class=\"timestamp\">Branch starts here</p>\n";
+    for (i, message) in session.messages.iter().enumerate() {
+        if branch_at == Some(i) {
+            output.push_str(branch_marker);
+        }
+
         let (role_class, role_label) = match message.role {
             MessageRole::User => ("user", "User"),
             MessageRole::Assistant => ("assistant", "Assistant"),
@@ -552,6 +779,9 @@ fn export_to_html(session: &ChatSession) -> String {
         output.push_str(&format!("<div class=\"content\">{}</div>\n", html_escape(&message.content)));
         output.push_str("</div>\n");
     }
+    if branch_at == Some(session.messages.len()) {
+        output.push_str(branch_marker);
+    }
 
     output.push_str("</body>\n</html>");
     output
@@ -572,10 +802,25 @@ pub fn export_sessions(sessions: &[ChatSession], format: ExportFormat) -> String
             serde_json::to_string_pretty(sessions).unwrap_or_else(|_| "[]".to_string())
         }
         ExportFormat::Markdown => {
-            sessions.iter()
-                .map(|s| export_to_markdown(s))
+            let tree = tree_order(sessions, |s| s.id, |s| s.parent.map(|p| p.session_id));
+            let mut output = String::new();
+            if tree.iter().any(|(depth, _)| *depth > 0) {
+                output.push_str("# Branches\n\n");
+                for (depth, session) in &tree {
+                    output.push_str(&format!(
+                        "{}- {} ({} messages)\n",
+                        "  ".repeat(*depth),
+                        session.display_title(),
+                        session.messages.len()
+                    ));
+                }
+                output.push_str("\n---\n\n");
+            }
+            output.push_str(&tree.iter()
+                .map(|(_, s)| export_to_markdown(s))
                 .collect::<Vec<_>>()
-                .join("\n\n---\n\n")
+                .join("\n\n---\n\n"));
+            output
         }
         ExportFormat::Html => {
             let mut output = String::new();
@@ -588,13 +833,18 @@ pub fn export_sessions(sessions: &[ChatSession], format: ExportFormat) -> String
             output.push_str("</style>\n</head>\n<body>\n");
             output.push_str(&format!("<h1>Chat History ({} sessions)</h1>\n", sessions.len()));
 
-            for session in sessions {
-                output.push_str("<div class=\"session\">\n");
+            for (depth, session) in tree_order(sessions, |s| s.id, |s| s.parent.map(|p| p.session_id)) {
+                output.push_str(&format!(
+                    "<div class=\"session\" style=\"margin-left: {}px\">\n", depth * 30
+                ));
                 output.push_str(&format!("<h2>{}</h2>\n", html_escape(&session.display_title())));
                 output.push_str(&format!("<p>{} messages | {}</p>\n",
                     session.messages.len(),
                     session.updated_at.format("%Y-%m-%d")
                 ));
+                if let Some(parent) = session.parent {
+                    output.push_str(&format!("<p>Forked after message {}</p>\n", parent.messages));
+                }
                 output.push_str("</div>\n");
             }
 
@@ -641,9 +891,71 @@ mod tests {
             code_context: vec![],
             project_path: Some("/my/project".to_string()),
             token_estimate: 50,
+            repo_map: None,
         };
 
         let minimal = ctx.minimal();
         assert!(minimal.contains("How do I add error handling?"));
     }
+
+    #[test]
+    fn test_unsummarized_range_is_incremental() {
+        let config = SummarizerConfig { summarize_threshold: 10, ..Default::default() };
+        let mut session = ChatSession::new(None);
+        for i in 0..10 {
+            session.add_message(Message::user(format!("message {} {}", i, "x".repeat(40))));
+        }
+
+        assert_eq!(session.unsummarized_range(&config), Some(0..6));
+        session.set_summary("first six".to_string(), 6);
+        assert_eq!(session.unsummarized_range(&config), None);
+
+        for i in 10..14 {
+            session.add_message(Message::assistant(format!("reply {}", i)));
+        }
+        assert_eq!(session.unsummarized_range(&config), Some(6..10));
+    }
+
+    #[test]
+    fn test_fork_links_to_parent_and_exports_the_tree() {
+        let mut session = ChatSession::new(None);
+        session.title = Some("Pick a parser".to_string());
+        session.add_message(Message::user("Which parser crate?"));
+        session.add_message(Message::assistant("Try nom"));
+        session.add_message(Message::user("Show an example"));
+        session.set_native_session(Tool::Claude, "abc".to_string());
+
+        let fork = session.fork(2);
+        assert_eq!(fork.messages.len(), 2);
+        assert_eq!(fork.parent, Some(ForkPoint { session_id: session.id, messages: 2 }));
+        assert!(fork.native_sessions.is_empty());
+        let nested = fork.fork(1);
+
+        let markdown = export_to_markdown(&fork);
+        assert!(markdown.contains(&format!("**Forked from:** {} after message 2", session.id)));
+        assert!(markdown.ends_with("*Branch starts here*\n\n---\n\n"));
+
+        let entries: Vec<_> = [&nested, &session, &fork].into_iter().map(HistoryEntry::from).collect();
+        let tree: Vec<_> = branch_tree(&entries).into_iter()
+            .map(|(depth, e)| (depth, e.session_id))
+            .collect();
+        assert_eq!(tree, vec![(0, session.id), (1, fork.id), (2, nested.id)]);
+
+        let all = export_sessions(&[nested.clone(), session.clone(), fork.clone()], ExportFormat::Markdown);
+        assert!(all.starts_with("# Branches\n\n- Pick a parser (3 messages)\n  - Pick a parser (fork) (2 messages)\n"));
+    }
+
+    #[test]
+    fn test_summary_prompt_folds_in_previous() {
+        let config = SummarizerConfig::default();
+        let messages = vec![Message::user("Use sqlx"), Message::assistant("Added sqlx to Cargo.toml")];
+
+        let prompt = summary_prompt(Some("Goal: build an API"), &messages, &config);
+        assert!(prompt.contains("Update this existing summary"));
+        assert!(prompt.contains("Goal: build an API"));
+        assert!(prompt.ends_with("Assistant: Added sqlx to Cargo.toml"));
+
+        assert_eq!(clean_summary("  Summary: Goal: API  ", &config), Some("Goal: API".to_string()));
+        assert_eq!(clean_summary(" \n", &config), None);
+    }
 }
@@ -0,0 +1,254 @@
+//! Builds the conversation context sent along with prompts to stateless tools
+//!
+//! Tools that neither resume their own sessions nor accept chat history see
+//! each prompt in isolation. The builder fills a per-tool token budget with,
+//! in priority order: the rolling summary, pinned code references, and as many
+//! recent turns as fit, newest first.
+
+use crate::context::{
+    summarize_messages, ChatSession, CodeReference, Message, MessageRole, SummarizerConfig,
+};
+use crate::features::{ContextWindowConfig, ContextWindowManager, TokenEstimationMethod};
+
+/// Below this many tokens a partially fitting turn is dropped rather than cut down.
+const MIN_TRUNCATED_TURN_TOKENS: u32 = 32;
+
+/// Context assembled for one prompt
+#[derive(Debug, Clone, Default)]
+pub struct BuiltContext {
+    pub summary: Option<String>,
+    pub references: Vec<String>,
+    /// Oldest first
+    pub turns: Vec<(MessageRole, String)>,
+    /// Earlier turns that did not fit in the budget
+    pub omitted_turns: usize,
+    pub tokens: u32,
+}
+
+impl BuiltContext {
+    pub fn is_empty(&self) -> bool {
+        self.summary.is_none() && self.references.is_empty() && self.turns.is_empty()
+    }
+
+    /// Renders the context alone, as shown by `/context`.
+    pub fn render(&self) -> String {
+        let mut parts = Vec::new();
+
+        if let Some(ref summary) = self.summary {
+            parts.push(format!("[Earlier conversation: {}]", summary));
+        }
+
+        if !self.references.is_empty() {
+            parts.push(format!("[Pinned code]\n{}", self.references.join("\n")));
+        }
+
+        if !self.turns.is_empty() {
+            let turns: Vec<String> = self.turns.iter()
+                .map(|(role, content)| format!("{}: {}", role_label(*role), content))
+                .collect();
+            parts.push(format!("[Recent conversation]\n{}", turns.join("\n\n")));
+        }
+
+        parts.join("\n\n")
+    }
+
+    /// Prepends the context to `question`, or returns it unchanged when there is none.
+    pub fn into_prompt(self, question: &str) -> String {
+        if self.is_empty() {
+            question.to_string()
+        } else {
+            format!("{}\n\n[Current request]\n{}", self.render(), question)
+        }
+    }
+}
+
+pub struct ContextBuilder {
+    window: ContextWindowManager,
+    summarizer: SummarizerConfig,
+}
+
+impl ContextBuilder {
+    /// `budget` is the number of tokens the injected context may use, on top of the question.
+    pub fn new(budget: u32) -> Self {
+        Self {
+            window: ContextWindowManager::new(window_config(budget)),
+            summarizer: SummarizerConfig::default(),
+        }
+    }
+
+    pub fn budget(&self) -> u32 {
+        self.window.available_tokens()
+    }
+
+    /// Builds the context for the next prompt in `session`. If the session's
+    /// last message is the pending question, it is left out of the turns.
+    pub fn build(&self, session: &ChatSession, question: &str) -> BuiltContext {
+        let mut remaining = self.budget();
+        let mut context = BuiltContext::default();
+
+        let mut history: &[Message] = &session.messages;
+        if let Some((last, earlier)) = history.split_last() {
+            if last.role == MessageRole::User && last.content == question {
+                history = earlier;
+            }
+        }
+
+        // The summary may take a quarter of the budget; turns matter more.
+        let summary_text = session.summary.clone().filter(|s| !s.is_empty());
+        if let Some(ref summary) = summary_text {
+            let text = self.fit(summary, remaining / 4);
+            remaining = remaining.saturating_sub(self.window.estimate_tokens(&text));
+            context.summary = Some(text);
+        }
+
+        for reference in &session.key_references {
+            let rendered = render_reference(reference);
+            let text = self.fit(&rendered, remaining / 4);
+            let tokens = self.window.estimate_tokens(&text);
+            if text.is_empty() || tokens > remaining {
+                break;
+            }
+            remaining -= tokens;
+            context.references.push(text);
+        }
+
+        let mut turns = Vec::new();
+        for (index, message) in history.iter().enumerate().rev() {
+            if message.role == MessageRole::System {
+                continue;
+            }
+
+            let tokens = self.window.estimate_tokens(&message.content);
+            if tokens <= remaining {
+                remaining -= tokens;
+                turns.push((message.role, message.content.clone()));
+                continue;
+            }
+
+            if remaining >= MIN_TRUNCATED_TURN_TOKENS {
+                let text = self.fit(&message.content, remaining);
+                remaining = remaining.saturating_sub(self.window.estimate_tokens(&text));
+                turns.push((message.role, text));
+                context.omitted_turns = index;
+            } else {
+                context.omitted_turns = index + 1;
+            }
+
+            // Without a stored summary, summarize what had to be dropped.
+            if summary_text.is_none() && context.omitted_turns > 0 {
+                let summary = summarize_messages(&history[..context.omitted_turns], &self.summarizer);
+                let text = self.fit(&summary, remaining);
+                if !text.is_empty() {
+                    remaining = remaining.saturating_sub(self.window.estimate_tokens(&text));
+                    context.summary = Some(text);
+                }
+            }
+            break;
+        }
+        turns.reverse();
+        context.turns = turns;
+
+        context.tokens = self.budget() - remaining;
+        context
+    }
+
+    /// Truncates `text` to roughly `tokens` with `truncate_smart`, so cuts
+    /// land on a sentence or word boundary.
+    fn fit(&self, text: &str, tokens: u32) -> String {
+        if tokens == 0 {
+            return String::new();
+        }
+        ContextWindowManager::new(window_config(tokens)).truncate_to_fit(text)
+    }
+}
+
+fn window_config(max_tokens: u32) -> ContextWindowConfig {
+    ContextWindowConfig {
+        max_tokens,
+        response_reserve: 0,
+        estimation_method: TokenEstimationMethod::CharDivide4,
+    }
+}
+
+fn render_reference(reference: &CodeReference) -> String {
+    let location = match reference.line_range {
+        Some((start, end)) => format!("{}:L{}-{}", reference.file_path, start, end),
+        None => reference.file_path.clone(),
+    };
+
+    match reference.snippet {
+        Some(ref snippet) => format!(
+            "{}\n```{}\n{}\n```",
+            location,
+            reference.language.as_deref().unwrap_or(""),
+            snippet
+        ),
+        None => location,
+    }
+}
+
+fn role_label(role: MessageRole) -> &'static str {
+    match role {
+        MessageRole::User => "User",
+        MessageRole::Assistant => "Assistant",
+        MessageRole::System => "System",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session_with_turns(count: usize, len: usize) -> ChatSession {
+        let mut session = ChatSession::new(None);
+        for i in 0..count {
+            let text = format!("turn {} {}", i, "word ".repeat(len / 5));
+            if i % 2 == 0 {
+                session.add_message(Message::user(text));
+            } else {
+                session.add_message(Message::assistant(text));
+            }
+        }
+        session
+    }
+
+    #[test]
+    fn test_recent_turns_within_budget() {
+        let mut session = session_with_turns(10, 400);
+        session.add_message(Message::user("what next?"));
+
+        let context = ContextBuilder::new(300).build(&session, "what next?");
+        assert!(context.tokens <= 300);
+        assert!(!context.turns.is_empty());
+        // The pending question is not repeated, and the newest turn survives.
+        assert!(context.turns.iter().all(|(_, text)| text != "what next?"));
+        assert!(context.turns.last().unwrap().1.starts_with("turn 9"));
+        assert!(context.omitted_turns > 0);
+        assert!(context.summary.is_some());
+    }
+
+    #[test]
+    fn test_summary_and_references_first() {
+        let mut session = session_with_turns(2, 20);
+        session.summary = Some("We chose tokio.".to_string());
+        session.key_references.push(CodeReference {
+            file_path: "src/main.rs".to_string(),
+            language: Some("rust".to_string()),
+            snippet: Some("fn main() {}".to_string()),
+            line_range: Some((1, 1)),
+        });
+
+        let prompt = ContextBuilder::new(1000).build(&session, "and then?").into_prompt("and then?");
+        assert!(prompt.starts_with("[Earlier conversation: We chose tokio.]"));
+        assert!(prompt.contains("src/main.rs:L1-1"));
+        assert!(prompt.ends_with("[Current request]\nand then?"));
+    }
+
+    #[test]
+    fn test_empty_session_leaves_prompt_alone() {
+        let session = ChatSession::new(None);
+        let context = ContextBuilder::new(1000).build(&session, "hi");
+        assert!(context.is_empty());
+        assert_eq!(context.into_prompt("hi"), "hi");
+    }
+}
@@ -379,6 +379,24 @@ impl QuotaTracker {
             }
         }
 
+        // Check token limits
+        if let Some(limit) = self.config.daily_token_limit {
+            if quota.daily_tokens >= limit {
+                return QuotaResult::Exceeded {
+                    reason: "Daily token limit exceeded".to_string(),
+                    reset_at: quota.daily_reset,
+                };
+            }
+        }
+        if let Some(limit) = self.config.monthly_token_limit {
+            if quota.monthly_tokens >= limit {
+                return QuotaResult::Exceeded {
+                    reason: "Monthly token limit exceeded".to_string(),
+                    reset_at: quota.monthly_reset,
+                };
+            }
+        }
+
         QuotaResult::Allowed {
             daily_remaining: self.config.daily_limit.map(|l| l.saturating_sub(quota.daily_requests)),
             monthly_remaining: self.config.monthly_limit.map(|l| l.saturating_sub(quota.monthly_requests)),
@@ -409,6 +427,8 @@ impl QuotaTracker {
             monthly_used: quota.monthly_requests,
             daily_reset: quota.daily_reset,
             monthly_reset: quota.monthly_reset,
+            daily_tokens_used: quota.daily_tokens,
+            monthly_tokens_used: quota.monthly_tokens,
         }
     }
 }
@@ -439,6 +459,8 @@ pub struct QuotaStatus {
     pub monthly_used: u64,
     pub daily_reset: DateTime<Utc>,
     pub monthly_reset: DateTime<Utc>,
+    pub daily_tokens_used: u64,
+    pub monthly_tokens_used: u64,
 }
 
 // =============================================================================
@@ -1055,12 +1077,17 @@ pub struct ApiKeyManager {
 impl ApiKeyManager {
     /// Create a new manager with a derived key from password
     pub fn new(password: &str) -> Self {
+        Self::with_salt(password, b"polyglot-ai-salt")
+    }
+
+    /// Create a manager with a key derived from password and a per-installation salt
+    pub fn with_salt(password: &str, salt: &[u8]) -> Self {
         let mut key = [0u8; 32];
         // Use PBKDF2 to derive key from password
         ring::pbkdf2::derive(
             ring::pbkdf2::PBKDF2_HMAC_SHA256,
             std::num::NonZeroU32::new(100_000).unwrap(),
-            b"polyglot-ai-salt", // In production, use unique salt per installation
+            salt,
             password.as_bytes(),
             &mut key,
         );
@@ -1082,6 +1109,11 @@ impl ApiKeyManager {
 
     /// Encrypt an API key
     pub fn encrypt(&self, api_key: &str) -> Result<Vec<u8>, ApiKeyError> {
+        self.seal(api_key.as_bytes())
+    }
+
+    /// Encrypt arbitrary bytes, such as file contents
+    pub fn seal(&self, data: &[u8]) -> Result<Vec<u8>, ApiKeyError> {
         let rng = ring::rand::SystemRandom::new();
 
         // Generate a random 12-byte nonce
@@ -1095,7 +1127,7 @@ impl ApiKeyManager {
         let sealing_key = ring::aead::LessSafeKey::new(unbound_key);
 
         // Encrypt the data
-        let mut in_out = api_key.as_bytes().to_vec();
+        let mut in_out = data.to_vec();
         sealing_key
             .seal_in_place_append_tag(
                 ring::aead::Nonce::assume_unique_for_key(nonce),
@@ -1112,6 +1144,12 @@ impl ApiKeyManager {
 
     /// Decrypt an API key
     pub fn decrypt(&self, encrypted: &[u8]) -> Result<String, ApiKeyError> {
+        String::from_utf8(self.open(encrypted)?)
+            .map_err(|_| ApiKeyError::DecryptionFailed("Invalid UTF-8".to_string()))
+    }
+
+    /// Decrypt bytes sealed with `seal`
+    pub fn open(&self, encrypted: &[u8]) -> Result<Vec<u8>, ApiKeyError> {
         if encrypted.len() < 13 {
             return Err(ApiKeyError::DecryptionFailed("Data too short".to_string()));
         }
@@ -1135,8 +1173,7 @@ impl ApiKeyManager {
             )
             .map_err(|_| ApiKeyError::DecryptionFailed("Decryption failed".to_string()))?;
 
-        String::from_utf8(plaintext.to_vec())
-            .map_err(|_| ApiKeyError::DecryptionFailed("Invalid UTF-8".to_string()))
+        Ok(plaintext.to_vec())
     }
 
     /// Validate that an API key looks reasonable (basic format check)
@@ -1908,6 +1945,22 @@ mod tests {
         assert!(!limiter.check("user1").is_allowed());
     }
 
+    #[test]
+    fn test_quota_token_limit() {
+        let tracker = QuotaTracker::new(QuotaConfig {
+            daily_limit: None,
+            monthly_limit: None,
+            daily_token_limit: Some(100),
+            monthly_token_limit: None,
+        });
+
+        tracker.record_usage("user1", 60);
+        assert!(tracker.check("user1").is_allowed());
+        tracker.record_usage("user1", 60);
+        assert!(!tracker.check("user1").is_allowed());
+        assert_eq!(tracker.get_status("user1").daily_tokens_used, 120);
+    }
+
     #[test]
     fn test_response_cache() {
         let config = CacheConfig {
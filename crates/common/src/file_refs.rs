@@ -0,0 +1,487 @@
+//! `@path` references that inline file contents into a prompt
+//!
+//! `@src/main.rs` includes a whole file, `@src/main.rs:10-40` a range of
+//! lines and `@src/` every text file under a directory. References are
+//! resolved against a root directory, each path goes through a caller's
+//! access check, and size limits keep a stray `@target/` from flooding the
+//! prompt.
+
+use std::io::{BufRead, BufReader};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+/// Directories left out when a whole directory is referenced
+pub(crate) const SKIPPED_DIRS: &[&str] = &["target", "node_modules", "__pycache__", "venv", "dist", "build"];
+
+/// Trailing characters taken as punctuation rather than part of a path
+const TRAILING_PUNCTUATION: &[char] = &[',', '.', ';', ':', '!', '?', ')', '\'', '"'];
+
+#[derive(Debug, Clone)]
+pub struct FileRefLimits {
+    /// Largest file, or excerpt of a file, that is inlined
+    pub max_file_bytes: u64,
+    /// Total inlined across all references in a prompt
+    pub max_total_bytes: u64,
+    /// Files taken from one directory reference
+    pub max_dir_files: usize,
+}
+
+impl Default for FileRefLimits {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: 256 * 1024,
+            max_total_bytes: 1024 * 1024,
+            max_dir_files: 50,
+        }
+    }
+}
+
+/// A reference as written in a prompt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileRef {
+    pub path: String,
+    /// 1-based and inclusive
+    pub lines: Option<(u32, u32)>,
+    /// Byte range of the reference in the prompt, including the `@`
+    pub span: Range<usize>,
+}
+
+/// A file whose contents were inlined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReferencedFile {
+    /// Relative to the root where possible, with `/` separators
+    pub path: String,
+    pub lines: Option<(u32, u32)>,
+    pub language: Option<&'static str>,
+}
+
+#[derive(Debug, Clone)]
+pub enum FileRefError {
+    /// Missing, outside the root or denied; these look the same so a
+    /// rejected reference does not reveal whether the path exists
+    Unavailable { path: String },
+    Denied { path: String, reason: String },
+    TooLarge { path: String, size: u64, max: u64 },
+    TotalTooLarge { path: String, max: u64 },
+    Binary { path: String },
+    TooManyFiles { path: String, skipped: usize },
+    Read { path: String, message: String },
+}
+
+impl std::fmt::Display for FileRefError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unavailable { path } => write!(f, "@{}: no such file, or not allowed", path),
+            Self::Denied { path, reason } => write!(f, "@{}: {}", path, reason),
+            Self::TooLarge { path, size, max } => {
+                write!(f, "@{}: {} bytes is over the {} byte limit; reference a line range instead", path, size, max)
+            }
+            Self::TotalTooLarge { path, max } => {
+                write!(f, "@{}: left out, the prompt already inlines {} bytes of files", path, max)
+            }
+            Self::Binary { path } => write!(f, "@{}: not a text file", path),
+            Self::TooManyFiles { path, skipped } => {
+                write!(f, "@{}: {} more files left out", path, skipped)
+            }
+            Self::Read { path, message } => write!(f, "@{}: {}", path, message),
+        }
+    }
+}
+
+impl std::error::Error for FileRefError {}
+
+/// A prompt with its references expanded
+#[derive(Debug, Clone, Default)]
+pub struct ExpandedPrompt {
+    pub prompt: String,
+    pub files: Vec<ReferencedFile>,
+    /// References that were left out, and why
+    pub problems: Vec<FileRefError>,
+}
+
+/// Finds the `@` references in `prompt`. An `@` only starts a reference at
+/// the start of the prompt or after whitespace, so e-mail addresses are left alone.
+pub fn parse_file_refs(prompt: &str) -> Vec<FileRef> {
+    let mut refs = Vec::new();
+    let mut previous = None;
+
+    for (index, c) in prompt.char_indices() {
+        let starts_word = previous.is_none_or(|p: char| p.is_whitespace());
+        previous = Some(c);
+        if c != '@' || !starts_word {
+            continue;
+        }
+
+        let rest = &prompt[index + 1..];
+        let word_len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let word = rest[..word_len].trim_end_matches(TRAILING_PUNCTUATION);
+        if word.is_empty() {
+            continue;
+        }
+
+        let (path, lines) = split_line_range(word);
+        refs.push(FileRef {
+            path: path.to_string(),
+            lines,
+            span: index..index + 1 + word.len(),
+        });
+    }
+
+    refs
+}
+
+/// Splits `path:10-40` or `path:10` into the path and its line range.
+fn split_line_range(word: &str) -> (&str, Option<(u32, u32)>) {
+    let Some((path, range)) = word.rsplit_once(':') else {
+        return (word, None);
+    };
+    let (start, end) = range.split_once('-').unwrap_or((range, range));
+    match (start.parse::<u32>(), end.parse::<u32>()) {
+        (Ok(start), Ok(end)) if start >= 1 && end >= start && !path.is_empty() => (path, Some((start, end))),
+        _ => (word, None),
+    }
+}
+
+/// Expands the references in `prompt` against `root`. `check` is asked
+/// about every file before it is read and returns why access is denied.
+/// The prompt keeps each reference as the plain path, and the contents
+/// follow it in fenced blocks.
+pub fn expand_file_refs<F>(prompt: &str, root: &Path, limits: &FileRefLimits, check: F) -> ExpandedPrompt
+where
+    F: Fn(&Path) -> Result<(), String>,
+{
+    let refs = parse_file_refs(prompt);
+    let mut expanded = ExpandedPrompt::default();
+    if refs.is_empty() {
+        expanded.prompt = prompt.to_string();
+        return expanded;
+    }
+
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let mut text = String::new();
+    let mut blocks = Vec::new();
+    let mut total = 0u64;
+    let mut last = 0;
+
+    for file_ref in refs {
+        let Some(resolved) = resolve_reference(&root, &file_ref.path, &check) else {
+            // A bare `@name` is more likely a mention than a file.
+            if file_ref.path.contains(['/', '.']) {
+                expanded.problems.push(FileRefError::Unavailable { path: file_ref.path.clone() });
+            }
+            continue;
+        };
+
+        text.push_str(&prompt[last..file_ref.span.start]);
+        text.push_str(&prompt[file_ref.span.start + 1..file_ref.span.end]);
+        last = file_ref.span.end;
+
+        let files = if resolved.is_dir() {
+            let mut files = Vec::new();
+            collect_dir(&resolved, &mut files);
+            files.sort();
+            if files.len() > limits.max_dir_files {
+                expanded.problems.push(FileRefError::TooManyFiles {
+                    path: file_ref.path.clone(),
+                    skipped: files.len() - limits.max_dir_files,
+                });
+                files.truncate(limits.max_dir_files);
+            }
+            files.into_iter().map(|path| (path, None)).collect()
+        } else {
+            vec![(resolved, file_ref.lines)]
+        };
+
+        for (path, lines) in files {
+            let display = display_path(&root, &path);
+            if expanded.files.iter().any(|f| f.path == display && f.lines == lines) {
+                continue;
+            }
+
+            match read_reference(&path, &display, lines, limits, &check) {
+                Ok(content) => {
+                    if total + content.len() as u64 > limits.max_total_bytes {
+                        expanded.problems.push(FileRefError::TotalTooLarge {
+                            path: display,
+                            max: limits.max_total_bytes,
+                        });
+                        continue;
+                    }
+                    total += content.len() as u64;
+
+                    let language = language_for_path(&path);
+                    blocks.push(fenced_block(&display, lines, language, &content));
+                    expanded.files.push(ReferencedFile { path: display, lines, language });
+                }
+                Err(e) => expanded.problems.push(e),
+            }
+        }
+    }
+    text.push_str(&prompt[last..]);
+
+    if !blocks.is_empty() {
+        text.push_str("\n\n[Referenced files]\n");
+        text.push_str(&blocks.join("\n"));
+    }
+    expanded.prompt = text;
+    expanded
+}
+
+/// Resolves `path` against `root`, following symlinks where it exists.
+/// Containment and `check` are decided before existence is looked at.
+fn resolve_reference<F>(root: &Path, path: &str, check: &F) -> Option<PathBuf>
+where
+    F: Fn(&Path) -> Result<(), String>,
+{
+    let mut joined = PathBuf::new();
+    for component in root.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                joined.pop();
+            }
+            other => joined.push(other),
+        }
+    }
+    let resolved = joined.canonicalize().unwrap_or(joined);
+    let allowed = resolved.starts_with(root) && check(&resolved).is_ok();
+    (allowed && resolved.exists()).then_some(resolved)
+}
+
+fn read_reference<F>(
+    path: &Path,
+    display: &str,
+    lines: Option<(u32, u32)>,
+    limits: &FileRefLimits,
+    check: &F,
+) -> Result<String, FileRefError>
+where
+    F: Fn(&Path) -> Result<(), String>,
+{
+    let read_error = |e: std::io::Error| FileRefError::Read { path: display.to_string(), message: e.to_string() };
+    let canonical = path.canonicalize().map_err(read_error)?;
+    check(&canonical).map_err(|reason| FileRefError::Denied { path: display.to_string(), reason })?;
+
+    let too_large = |size| FileRefError::TooLarge { path: display.to_string(), size, max: limits.max_file_bytes };
+    let bytes = match lines {
+        None => {
+            let size = std::fs::metadata(&canonical).map_err(read_error)?.len();
+            if size > limits.max_file_bytes {
+                return Err(too_large(size));
+            }
+            std::fs::read(&canonical).map_err(read_error)?
+        }
+        Some((start, end)) => {
+            // Read line by line, so a range of a huge log still works.
+            let mut reader = BufReader::new(std::fs::File::open(&canonical).map_err(read_error)?);
+            let mut excerpt = Vec::new();
+            let mut line = Vec::new();
+            let mut number = 0;
+            while number < end {
+                line.clear();
+                if reader.read_until(b'\n', &mut line).map_err(read_error)? == 0 {
+                    break;
+                }
+                number += 1;
+                if number >= start {
+                    excerpt.extend_from_slice(&line);
+                    if excerpt.len() as u64 > limits.max_file_bytes {
+                        return Err(too_large(excerpt.len() as u64));
+                    }
+                }
+            }
+            excerpt
+        }
+    };
+
+    if bytes.contains(&0) {
+        return Err(FileRefError::Binary { path: display.to_string() });
+    }
+    String::from_utf8(bytes).map_err(|_| FileRefError::Binary { path: display.to_string() })
+}
+
+/// Text files under `dir`, skipping hidden entries and build output.
+fn collect_dir(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') {
+            continue;
+        }
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            if !SKIPPED_DIRS.contains(&name.as_ref()) {
+                collect_dir(&entry.path(), files);
+            }
+        } else if file_type.is_file() {
+            files.push(entry.path());
+        }
+    }
+}
+
+fn display_path(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// A fence longer than any run of backticks in `content`, so it can't be closed early.
+fn fenced_block(path: &str, lines: Option<(u32, u32)>, language: Option<&str>, content: &str) -> String {
+    let longest_run = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    let title = match lines {
+        Some((start, end)) => format!("{} (lines {}-{})", path, start, end),
+        None => path.to_string(),
+    };
+    let newline = if content.ends_with('\n') || content.is_empty() { "" } else { "\n" };
+    format!("{}:\n{}{}\n{}{}{}\n", title, fence, language.unwrap_or(""), content, newline, fence)
+}
+
+/// Completions for a reference being typed, given the text after the `@`.
+/// Directories end in `/`; hidden entries only match a prefix starting with `.`.
+pub fn complete_file_ref(root: &Path, partial: &str) -> Vec<String> {
+    let (dir, prefix) = match partial.rfind('/') {
+        Some(pos) => partial.split_at(pos + 1),
+        None => ("", partial),
+    };
+    let Ok(entries) = std::fs::read_dir(root.join(dir)) else {
+        return Vec::new();
+    };
+
+    let mut candidates: Vec<String> = entries.flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.file_type().ok()?.is_dir() || entry.path().is_dir();
+            Some(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+/// The language of a source file, judged by its extension.
+pub fn language_for_path(path: &Path) -> Option<&'static str> {
+    let language = match path.extension()?.to_str()? {
+        "rs" => "rust",
+        "py" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "ts" | "tsx" => "typescript",
+        "go" => "go",
+        "java" => "java",
+        "kt" => "kotlin",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "hpp" => "cpp",
+        "cs" => "csharp",
+        "rb" => "ruby",
+        "php" => "php",
+        "swift" => "swift",
+        "sh" | "bash" => "bash",
+        "toml" => "toml",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "md" => "markdown",
+        "html" => "html",
+        "css" => "css",
+        "sql" => "sql",
+        _ => return None,
+    };
+    Some(language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_file_refs() {
+        let refs = parse_file_refs("see @src/lib.rs:10-40, @docs/ and mail me@example.com about @notes.md.");
+        let paths: Vec<_> = refs.iter().map(|r| (r.path.as_str(), r.lines)).collect();
+        assert_eq!(paths, vec![("src/lib.rs", Some((10, 40))), ("docs/", None), ("notes.md", None)]);
+        assert_eq!(&"see @src/lib.rs:10-40,"[refs[0].span.clone()], "@src/lib.rs:10-40");
+
+        let refs = parse_file_refs("@Cargo.toml:7");
+        assert_eq!(refs[0].lines, Some((7, 7)));
+    }
+
+    #[test]
+    fn test_expand_files_ranges_and_dirs() {
+        let root = tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("src/nested")).unwrap();
+        std::fs::write(root.path().join("src/lib.rs"), "one\ntwo\nthree\nfour\n").unwrap();
+        std::fs::write(root.path().join("src/nested/mod.rs"), "mod a;\n").unwrap();
+        std::fs::write(root.path().join("secret.txt"), "hunter2\n").unwrap();
+        std::fs::write(root.path().join("big.log"), "x".repeat(300)).unwrap();
+
+        let limits = FileRefLimits { max_file_bytes: 100, ..FileRefLimits::default() };
+        let deny_secrets = |path: &Path| {
+            if path.ends_with("secret.txt") { Err("outside sandbox".to_string()) } else { Ok(()) }
+        };
+
+        let expanded = expand_file_refs(
+            "explain @src/lib.rs:2-3 then @src/nested/ using @secret.txt and @big.log, thanks @alice",
+            root.path(), &limits, deny_secrets,
+        );
+
+        assert!(expanded.prompt.starts_with(
+            "explain src/lib.rs:2-3 then src/nested/ using @secret.txt and big.log, thanks @alice\n\n[Referenced files]\n"));
+        assert!(expanded.prompt.contains("src/lib.rs (lines 2-3):\n```rust\ntwo\nthree\n```\n"));
+        assert!(expanded.prompt.contains("src/nested/mod.rs:\n```rust\nmod a;\n```\n"));
+        assert!(!expanded.prompt.contains("hunter2"));
+
+        let files: Vec<_> = expanded.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(files, vec!["src/lib.rs", "src/nested/mod.rs"]);
+        assert!(matches!(expanded.problems[0], FileRefError::Unavailable { .. }));
+        assert!(matches!(expanded.problems[1], FileRefError::TooLarge { size: 300, .. }));
+        assert_eq!(expanded.problems.len(), 2);
+    }
+
+    #[test]
+    fn test_rejected_refs_do_not_reveal_existence() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("project");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(dir.path().join("outside.txt"), "secret\n").unwrap();
+        std::fs::write(root.join("denied.txt"), "secret\n").unwrap();
+        let deny = |path: &Path| {
+            if path.ends_with("denied.txt") || path.ends_with("gone.txt") { Err("denied".to_string()) } else { Ok(()) }
+        };
+
+        let expanded = expand_file_refs(
+            "@../outside.txt @../missing.txt @denied.txt @gone.txt @absent.txt",
+            &root, &FileRefLimits::default(), deny,
+        );
+
+        assert!(expanded.files.is_empty());
+        assert!(!expanded.prompt.contains("secret"));
+        let messages: Vec<_> = expanded.problems.iter()
+            .map(|p| p.to_string().split_once(": ").unwrap().1.to_string())
+            .collect();
+        assert_eq!(messages.len(), 5);
+        assert!(messages.iter().all(|m| *m == messages[0]));
+    }
+
+    #[test]
+    fn test_complete_file_ref() {
+        let root = tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("src")).unwrap();
+        std::fs::write(root.path().join("src/main.rs"), "").unwrap();
+        std::fs::write(root.path().join("src/model.rs"), "").unwrap();
+        std::fs::write(root.path().join(".env"), "").unwrap();
+
+        assert_eq!(complete_file_ref(root.path(), "s"), vec!["src/"]);
+        assert_eq!(complete_file_ref(root.path(), "src/m"), vec!["src/main.rs", "src/model.rs"]);
+        assert_eq!(complete_file_ref(root.path(), ".e"), vec![".env"]);
+        assert!(complete_file_ref(root.path(), "").iter().all(|c| !c.starts_with('.')));
+    }
+}
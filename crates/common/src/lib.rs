@@ -5,27 +5,52 @@ pub mod context;
 pub mod updater;
 pub mod features;
 pub mod storage;
+pub mod ollama;
+pub mod claude_stream;
+pub mod context_builder;
+pub mod file_refs;
+pub mod repo_map;
+pub mod transcript;
+pub mod vault;
+pub mod redact;
 
 pub use protocol::{
     ClientMessage, ServerMessage, OutputType, ToolInfo, SwitchReason, ErrorCode,
-    ExportFormat, ToolHealthInfo, ToolMetrics, CacheStats,
+    ExportFormat, ToolHealthInfo, ToolMetrics, CacheStats, ToolAction,
     encode_message, decode_message, frame_message,
     PROTOCOL_VERSION, MAX_MESSAGE_SIZE,
 };
 
 pub use models::{
     Tool, SyncMode, RotationStrategy, AuthMode,
-    User, Session, ToolUsage, FileInfo, FileConflict,
+    User, Session, ToolUsage, TokenUsage, FileInfo, FileConflict,
     ConflictResolution, ToolConfig,
 };
 
 pub use context::{
-    Message, MessageRole, ChatSession, CodeReference,
-    TransferContext, HistoryEntry, SummarizerConfig,
-    truncate_smart, summarize_messages, create_transfer_context,
+    Message, MessageRole, ChatSession, CodeReference, ForkPoint,
+    TransferContext, HistoryEntry, SummarizerConfig, branch_tree,
+    truncate_smart, summarize_messages, summary_prompt, clean_summary, create_transfer_context,
     generate_title, export_session, export_sessions,
 };
 
+pub use context_builder::{ContextBuilder, BuiltContext};
+
+pub use file_refs::{
+    FileRef, FileRefLimits, FileRefError, ReferencedFile, ExpandedPrompt,
+    parse_file_refs, expand_file_refs, complete_file_ref, language_for_path,
+};
+
+pub use repo_map::{RepoMap, RepoEntry, RepoMapCache};
+
+pub use transcript::{TranscriptFormat, ImportError, import_sessions};
+
+pub use vault::{KeyVault, VaultEntry, VaultError};
+
+pub use redact::{
+    Redactor, Redacted, RedactionConfig, RedactionPattern, RedactionReport, RedactError, DETECTORS,
+};
+
 pub use features::{
     RateLimiter, RateLimitConfig, RateLimitResult,
     ResponseCache, CacheConfig,
@@ -55,3 +80,10 @@ pub use storage::{
     StoredQuota, StoredSession, StoredApiKey, StoredWebhook,
     CachedResponse, AuditLogEntry,
 };
+
+pub use ollama::{
+    OllamaMessage, OllamaChatRequest, OllamaChatChunk, OllamaTags, OllamaModelInfo,
+    OllamaPullRequest, OllamaPullProgress, NdjsonBuffer, ollama_url, DEFAULT_OLLAMA_HOST,
+};
+
+pub use claude_stream::{ClaudeStreamParser, ClaudeEvent, CLAUDE_STREAM_ARGS};
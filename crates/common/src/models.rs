@@ -44,6 +44,78 @@ impl Tool {
     pub fn all() -> &'static [Tool] {
         &[Tool::Claude, Tool::Gemini, Tool::Codex, Tool::Copilot, Tool::Perplexity, Tool::Cursor, Tool::Ollama]
     }
+
+    /// CLI flag used to pick a model, if the tool's CLI has one.
+    /// Ollama selects its model through the HTTP request body instead.
+    pub fn model_flag(&self) -> Option<&'static str> {
+        match self {
+            Tool::Claude | Tool::Gemini | Tool::Codex | Tool::Cursor => Some("--model"),
+            Tool::Copilot | Tool::Perplexity | Tool::Ollama => None,
+        }
+    }
+
+    /// Well-known model names accepted by the tool. Ollama models are
+    /// discovered from the running daemon, so none are listed here.
+    pub fn known_models(&self) -> &'static [&'static str] {
+        match self {
+            Tool::Claude => &["sonnet", "opus", "haiku"],
+            Tool::Gemini => &["gemini-2.5-pro", "gemini-2.5-flash"],
+            Tool::Codex => &["gpt-5-codex", "gpt-5", "o4-mini"],
+            Tool::Cursor => &["auto", "sonnet-4", "gpt-5"],
+            Tool::Copilot | Tool::Perplexity | Tool::Ollama => &[],
+        }
+    }
+
+    pub fn supports_model_selection(&self) -> bool {
+        self.model_flag().is_some() || *self == Tool::Ollama
+    }
+
+    /// CLI flag that continues one of the tool's own sessions by ID. Only
+    /// tools whose output reports that ID are listed; the rest rely on
+    /// Polyglot's context transfer instead.
+    pub fn resume_flag(&self) -> Option<&'static str> {
+        match self {
+            Tool::Claude => Some("--resume"),
+            _ => None,
+        }
+    }
+
+    /// Whether earlier turns are sent to the tool as structured chat history.
+    pub fn accepts_history(&self) -> bool {
+        *self == Tool::Ollama
+    }
+
+    /// Default token budget for conversation context injected into prompts
+    /// for tools that keep no history of their own.
+    pub fn default_context_budget(&self) -> u32 {
+        match self {
+            Tool::Copilot => 1_000,
+            Tool::Perplexity | Tool::Ollama => 2_000,
+            Tool::Claude | Tool::Gemini | Tool::Codex | Tool::Cursor => 8_000,
+        }
+    }
+
+    /// Environment variable the tool's CLI reads its API key from, used
+    /// when a key is added to the vault without naming one.
+    pub fn api_key_env(&self) -> Option<&'static str> {
+        match self {
+            Tool::Claude => Some("ANTHROPIC_API_KEY"),
+            Tool::Gemini => Some("GEMINI_API_KEY"),
+            Tool::Codex => Some("OPENAI_API_KEY"),
+            Tool::Copilot => Some("GH_TOKEN"),
+            Tool::Perplexity => Some("PERPLEXITY_API_KEY"),
+            Tool::Cursor => Some("CURSOR_API_KEY"),
+            Tool::Ollama => None,
+        }
+    }
+
+    /// Arguments that resume `session_id`, or nothing if the tool can't.
+    pub fn resume_args(&self, session_id: Option<&str>) -> Vec<String> {
+        match (self.resume_flag(), session_id) {
+            (Some(flag), Some(id)) => vec![flag.to_string(), id.to_string()],
+            _ => Vec::new(),
+        }
+    }
 }
 
 impl std::fmt::Display for Tool {
@@ -149,6 +221,17 @@ pub struct ToolUsage {
     pub rate_limit_hits: u64,
     pub last_used: Option<DateTime<Utc>>,
     pub is_available: bool,
+    /// Split of `tokens_used`, for tools that report it
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+    /// Highest memory use of a single run, for runs measured through cgroups
+    #[serde(default)]
+    pub peak_memory_bytes: u64,
+    /// CPU time of all measured runs
+    #[serde(default)]
+    pub cpu_time_ms: u64,
 }
 
 impl ToolUsage {
@@ -161,8 +244,42 @@ impl ToolUsage {
             rate_limit_hits: 0,
             last_used: None,
             is_available: true,
+            input_tokens: 0,
+            output_tokens: 0,
+            peak_memory_bytes: 0,
+            cpu_time_ms: 0,
         }
     }
+
+    /// Adds exact usage reported by a tool. `tokens_used` includes cached input.
+    pub fn record_tokens(&mut self, usage: &TokenUsage) {
+        self.tokens_used += usage.total();
+        self.input_tokens += usage.input_tokens;
+        self.output_tokens += usage.output_tokens;
+    }
+
+    /// Adds the measured resource use of one run.
+    pub fn record_resources(&mut self, peak_memory_bytes: u64, cpu_time_ms: u64) {
+        self.peak_memory_bytes = self.peak_memory_bytes.max(peak_memory_bytes);
+        self.cpu_time_ms += cpu_time_ms;
+    }
+}
+
+/// Token counts reported by a tool for one request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cache_read_tokens: u64,
+    #[serde(default)]
+    pub cache_creation_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn total(&self) -> u64 {
+        self.input_tokens + self.output_tokens + self.cache_read_tokens + self.cache_creation_tokens
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -0,0 +1,252 @@
+//! Wire types for the Ollama REST API
+//! https://github.com/ollama/ollama/blob/main/docs/api.md
+//!
+//! Only the request/response shapes live here; the server and local
+//! binaries bring their own HTTP client.
+
+use serde::{Deserialize, Serialize};
+
+use crate::context::{Message, MessageRole};
+
+pub const DEFAULT_OLLAMA_HOST: &str = "http://localhost:11434";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OllamaMessage {
+    pub role: MessageRole,
+    pub content: String,
+}
+
+impl From<&Message> for OllamaMessage {
+    fn from(msg: &Message) -> Self {
+        Self {
+            role: msg.role,
+            content: msg.content.clone(),
+        }
+    }
+}
+
+/// Body of `POST /api/chat`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaChatRequest {
+    pub model: String,
+    pub messages: Vec<OllamaMessage>,
+    pub stream: bool,
+}
+
+impl OllamaChatRequest {
+    /// Builds a streaming chat request from prior conversation turns plus the new prompt.
+    pub fn new(model: &str, history: &[Message], prompt: &str) -> Self {
+        let mut messages: Vec<OllamaMessage> = history.iter().map(OllamaMessage::from).collect();
+        messages.push(OllamaMessage {
+            role: MessageRole::User,
+            content: prompt.to_string(),
+        });
+
+        Self {
+            model: model.to_string(),
+            messages,
+            stream: true,
+        }
+    }
+}
+
+/// One NDJSON line of a streaming `/api/chat` response
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OllamaChatChunk {
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub message: Option<OllamaMessage>,
+    #[serde(default)]
+    pub done: bool,
+    #[serde(default)]
+    pub prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    pub eval_count: Option<u64>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl OllamaChatChunk {
+    pub fn content(&self) -> &str {
+        self.message.as_ref().map(|m| m.content.as_str()).unwrap_or("")
+    }
+
+    /// Exact token usage reported on the final chunk.
+    pub fn total_tokens(&self) -> Option<u64> {
+        match (self.prompt_eval_count, self.eval_count) {
+            (None, None) => None,
+            (prompt, eval) => Some(prompt.unwrap_or(0) + eval.unwrap_or(0)),
+        }
+    }
+}
+
+/// Response of `GET /api/tags`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OllamaTags {
+    #[serde(default)]
+    pub models: Vec<OllamaModelInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaModelInfo {
+    pub name: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub modified_at: Option<String>,
+}
+
+impl OllamaTags {
+    /// Ollama reports untagged models as `name:latest`, so both spellings match.
+    pub fn has_model(&self, model: &str) -> bool {
+        self.models.iter().any(|m| {
+            m.name == model
+                || m.name.strip_suffix(":latest") == Some(model)
+                || model.strip_suffix(":latest") == Some(m.name.as_str())
+        })
+    }
+
+    pub fn model_names(&self) -> Vec<String> {
+        self.models.iter().map(|m| m.name.clone()).collect()
+    }
+}
+
+/// Body of `POST /api/pull`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaPullRequest {
+    pub model: String,
+    pub stream: bool,
+}
+
+/// One NDJSON line of a streaming `/api/pull` response
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OllamaPullProgress {
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub digest: Option<String>,
+    #[serde(default)]
+    pub total: Option<u64>,
+    #[serde(default)]
+    pub completed: Option<u64>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl OllamaPullProgress {
+    pub fn percent(&self) -> Option<u8> {
+        match (self.completed, self.total) {
+            (Some(done), Some(total)) if total > 0 => Some(((done.min(total) * 100) / total) as u8),
+            _ => None,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self.percent() {
+            Some(pct) => format!("{} ({}%)", self.status, pct),
+            None => self.status.clone(),
+        }
+    }
+}
+
+/// Splits a byte stream into complete newline-delimited JSON lines.
+#[derive(Debug, Default)]
+pub struct NdjsonBuffer {
+    pending: Vec<u8>,
+}
+
+impl NdjsonBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a chunk and returns every line it completed.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(bytes);
+
+        let mut lines = Vec::new();
+        while let Some(pos) = self.pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=pos).collect();
+            let text = String::from_utf8_lossy(&line).trim().to_string();
+            if !text.is_empty() {
+                lines.push(text);
+            }
+        }
+        lines
+    }
+
+    /// Returns whatever is left once the stream has ended.
+    pub fn finish(&mut self) -> Option<String> {
+        let text = String::from_utf8_lossy(&self.pending).trim().to_string();
+        self.pending.clear();
+        if text.is_empty() {
+            None
+        } else {
+            Some(text)
+        }
+    }
+}
+
+/// Joins a host entry and an API path, tolerating a missing scheme or trailing slash.
+pub fn ollama_url(host: &str, path: &str) -> String {
+    let host = host.trim_end_matches('/');
+    if host.starts_with("http://") || host.starts_with("https://") {
+        format!("{}{}", host, path)
+    } else {
+        format!("http://{}{}", host, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ndjson_buffer_splits_across_chunks() {
+        let mut buffer = NdjsonBuffer::new();
+        assert!(buffer.push(b"{\"done\":fal").is_empty());
+
+        let lines = buffer.push(b"se}\n{\"done\":true}\n{\"par");
+        assert_eq!(lines, vec!["{\"done\":false}", "{\"done\":true}"]);
+        assert_eq!(buffer.finish().as_deref(), Some("{\"par"));
+        assert_eq!(buffer.finish(), None);
+    }
+
+    #[test]
+    fn test_chat_chunk_token_counts() {
+        let line = r#"{"model":"llama3","message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":26,"eval_count":290}"#;
+        let chunk: OllamaChatChunk = serde_json::from_str(line).unwrap();
+        assert!(chunk.done);
+        assert_eq!(chunk.total_tokens(), Some(316));
+
+        let partial: OllamaChatChunk = serde_json::from_str(
+            r#"{"model":"llama3","message":{"role":"assistant","content":"Hi"},"done":false}"#,
+        ).unwrap();
+        assert_eq!(partial.content(), "Hi");
+        assert_eq!(partial.total_tokens(), None);
+    }
+
+    #[test]
+    fn test_chat_request_includes_history() {
+        let history = vec![Message::user("hello"), Message::assistant("hi there")];
+        let request = OllamaChatRequest::new("llama3", &history, "next question");
+        let json = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(json["messages"].as_array().unwrap().len(), 3);
+        assert_eq!(json["messages"][1]["role"], "assistant");
+        assert_eq!(json["messages"][2]["content"], "next question");
+        assert_eq!(json["stream"], true);
+    }
+
+    #[test]
+    fn test_tags_model_matching() {
+        let tags: OllamaTags = serde_json::from_str(
+            r#"{"models":[{"name":"codellama:latest","size":1},{"name":"llama3:8b","size":2}]}"#,
+        ).unwrap();
+        assert!(tags.has_model("codellama"));
+        assert!(tags.has_model("llama3:8b"));
+        assert!(!tags.has_model("llama3"));
+        assert_eq!(ollama_url("localhost:11434/", "/api/tags"), "http://localhost:11434/api/tags");
+    }
+}
@@ -22,6 +22,9 @@ pub enum ClientMessage {
         tool: Option<Tool>,
         message: String,
         working_dir: Option<String>,
+        /// Overrides the tool's configured default model
+        #[serde(default)]
+        model: Option<String>,
     },
 
     SyncRequest {
@@ -210,6 +213,10 @@ pub enum ServerMessage {
         monthly_limit: Option<u64>,
         monthly_used: u64,
         reset_at: Option<chrono::DateTime<chrono::Utc>>,
+        #[serde(default)]
+        daily_tokens_used: u64,
+        #[serde(default)]
+        monthly_tokens_used: u64,
     },
 
     /// Token refresh result
@@ -242,6 +249,38 @@ pub enum ServerMessage {
         sequence: u32,
         is_final: bool,
     },
+
+    /// Partial assistant text; not line-aligned, so append as-is
+    TextDelta {
+        tool: Tool,
+        content: String,
+    },
+
+    /// Something the tool did on its own, such as editing a file
+    ToolAction {
+        tool: Tool,
+        action: ToolAction,
+    },
+}
+
+/// Actions reported by tools with a structured output mode
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolAction {
+    FileEdit { path: String },
+    ShellCommand { command: String },
+    Other { name: String, detail: String },
+}
+
+impl std::fmt::Display for ToolAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ToolAction::FileEdit { path } => write!(f, "edit {}", path),
+            ToolAction::ShellCommand { command } => write!(f, "$ {}", command),
+            ToolAction::Other { name, detail } if detail.is_empty() => write!(f, "{}", name),
+            ToolAction::Other { name, detail } => write!(f, "{} {}", name, detail),
+        }
+    }
 }
 
 /// Export format for conversation history
@@ -299,6 +338,10 @@ pub struct ToolInfo {
     pub enabled: bool,
     pub available: bool,
     pub priority: u8,
+    #[serde(default)]
+    pub models: Vec<String>,
+    #[serde(default)]
+    pub default_model: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -391,15 +434,17 @@ mod tests {
             tool: Some(Tool::Claude),
             message: "Hello, world!".to_string(),
             working_dir: Some("/home/user/project".to_string()),
+            model: Some("opus".to_string()),
         };
 
         let encoded = encode_message(&msg).unwrap();
         let decoded: ClientMessage = decode_message(&encoded).unwrap();
 
         match decoded {
-            ClientMessage::Prompt { tool, message, .. } => {
+            ClientMessage::Prompt { tool, message, model, .. } => {
                 assert_eq!(tool, Some(Tool::Claude));
                 assert_eq!(message, "Hello, world!");
+                assert_eq!(model.as_deref(), Some("opus"));
             }
             _ => panic!("Wrong message type"),
         }
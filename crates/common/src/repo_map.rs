@@ -0,0 +1,568 @@
+//! A compact map of a repository, for tools that join a task midway
+//!
+//! The map lists the file tree, minus what `.gitignore` files exclude, along
+//! with the languages in use, the build manifests and the top-level symbols
+//! of common source files. Building it walks the whole tree, so maps are
+//! cached and only rebuilt when a directory, `.gitignore` or parsed source
+//! file has a newer modification time than when the map was built.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::file_refs::{language_for_path, SKIPPED_DIRS};
+
+/// Deeper directories are listed but not entered
+const MAX_DEPTH: usize = 8;
+
+/// Entries kept in the map; rendering trims further to the token budget
+const MAX_ENTRIES: usize = 5000;
+
+const MAX_SYMBOLS_PER_FILE: usize = 12;
+
+/// Larger source files are listed without symbols
+const MAX_SYMBOL_FILE_BYTES: u64 = 256 * 1024;
+
+/// Files that describe how a project is built
+const MANIFESTS: &[&str] = &[
+    "Cargo.toml", "package.json", "pyproject.toml", "setup.py", "requirements.txt",
+    "go.mod", "pom.xml", "build.gradle", "build.gradle.kts", "Gemfile", "composer.json",
+    "CMakeLists.txt", "Makefile", "Package.swift", "deno.json",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepoEntry {
+    /// Relative to the root, with `/` separators
+    pub path: String,
+    pub is_dir: bool,
+    pub symbols: Vec<String>,
+}
+
+impl RepoEntry {
+    fn depth(&self) -> usize {
+        self.path.matches('/').count()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoMap {
+    pub root: PathBuf,
+    /// Language and file count, most files first
+    pub languages: Vec<(String, usize)>,
+    pub manifests: Vec<String>,
+    /// In tree order
+    pub entries: Vec<RepoEntry>,
+    /// Entries past `MAX_ENTRIES`
+    pub omitted: usize,
+    /// Modification times, in nanoseconds, of everything the map was built from
+    stamps: Vec<(String, u128)>,
+}
+
+impl RepoMap {
+    pub fn build(root: &Path) -> io::Result<Self> {
+        let root = root.canonicalize()?;
+        let mut builder = Builder::default();
+        builder.walk(&root, "", 0, &[]);
+
+        let mut languages: Vec<(String, usize)> = builder.languages.into_iter().collect();
+        languages.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        Ok(Self {
+            root,
+            languages,
+            manifests: builder.manifests,
+            entries: builder.entries,
+            omitted: builder.omitted,
+            stamps: builder.stamps,
+        })
+    }
+
+    /// Whether nothing the map was built from has changed since.
+    pub fn is_fresh(&self) -> bool {
+        self.stamps.iter().all(|(path, stamp)| mtime_nanos(&self.root.join(path)) == Some(*stamp))
+    }
+
+    /// The map as prompt text of about `token_budget` tokens at most. When
+    /// the tree doesn't fit, shallower entries are kept over deeper ones.
+    pub fn render(&self, token_budget: u32) -> String {
+        let budget = token_budget as usize * 4;
+        let name = self.root.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.root.display().to_string());
+
+        let mut header = vec![format!("[Repository map: {}]", name)];
+        if !self.languages.is_empty() {
+            let languages: Vec<String> = self.languages.iter()
+                .map(|(language, count)| format!("{} ({})", language, count))
+                .collect();
+            header.push(format!("Languages: {}", languages.join(", ")));
+        }
+        if !self.manifests.is_empty() {
+            header.push(format!("Manifests: {}", self.manifests.join(", ")));
+        }
+
+        let mut used: usize = header.iter().map(|line| line.len() + 1).sum();
+        let lines: Vec<String> = self.entries.iter().map(render_entry).collect();
+
+        let mut order: Vec<usize> = (0..self.entries.len()).collect();
+        order.sort_by_key(|&i| self.entries[i].depth());
+        let mut keep = vec![false; self.entries.len()];
+        for i in order {
+            if used + lines[i].len() + 1 > budget {
+                break;
+            }
+            used += lines[i].len() + 1;
+            keep[i] = true;
+        }
+
+        let mut out = header;
+        out.extend(lines.into_iter().zip(keep.iter()).filter(|(_, keep)| **keep).map(|(line, _)| line));
+        let left_out = keep.iter().filter(|k| !**k).count() + self.omitted;
+        if left_out > 0 {
+            out.push(format!("... {} more entries", left_out));
+        }
+        out.join("\n")
+    }
+}
+
+fn render_entry(entry: &RepoEntry) -> String {
+    let indent = "  ".repeat(entry.depth());
+    let name = entry.path.rsplit('/').next().unwrap_or(&entry.path);
+    if entry.is_dir {
+        format!("{}{}/", indent, name)
+    } else if entry.symbols.is_empty() {
+        format!("{}{}", indent, name)
+    } else {
+        format!("{}{}: {}", indent, name, entry.symbols.join(", "))
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    languages: HashMap<String, usize>,
+    manifests: Vec<String>,
+    entries: Vec<RepoEntry>,
+    omitted: usize,
+    stamps: Vec<(String, u128)>,
+}
+
+impl Builder {
+    fn stamp(&mut self, path: &Path, rel: &str) {
+        if let Some(stamp) = mtime_nanos(path) {
+            self.stamps.push((rel.to_string(), stamp));
+        }
+    }
+
+    fn walk(&mut self, dir: &Path, rel: &str, depth: usize, inherited: &[IgnoreRule]) {
+        self.stamp(dir, rel);
+
+        let mut rules = inherited.to_vec();
+        let gitignore = dir.join(".gitignore");
+        if let Ok(text) = std::fs::read_to_string(&gitignore) {
+            self.stamp(&gitignore, &join(rel, ".gitignore"));
+            rules.extend(parse_gitignore(rel, &text));
+        }
+
+        let Ok(read) = std::fs::read_dir(dir) else {
+            return;
+        };
+        let mut children: Vec<_> = read.flatten().collect();
+        children.sort_by_key(|entry| entry.file_name());
+
+        for child in children {
+            let name = child.file_name().to_string_lossy().to_string();
+            if name == ".git" {
+                continue;
+            }
+            let Ok(file_type) = child.file_type() else {
+                continue;
+            };
+            let is_dir = file_type.is_dir();
+            let child_rel = join(rel, &name);
+            if is_ignored(&rules, &child_rel, is_dir) || (is_dir && SKIPPED_DIRS.contains(&name.as_str())) {
+                continue;
+            }
+
+            if is_dir {
+                self.push(RepoEntry { path: child_rel.clone(), is_dir: true, symbols: Vec::new() });
+                if depth + 1 < MAX_DEPTH {
+                    self.walk(&child.path(), &child_rel, depth + 1, &rules);
+                }
+                continue;
+            }
+
+            if MANIFESTS.contains(&name.as_str()) || name.ends_with(".csproj") {
+                self.manifests.push(child_rel.clone());
+            }
+
+            let path = child.path();
+            let language = language_for_path(&path);
+            if let Some(language) = language {
+                *self.languages.entry(language.to_string()).or_default() += 1;
+            }
+
+            let mut symbols = Vec::new();
+            if let Some(language) = language.filter(|_| self.entries.len() < MAX_ENTRIES) {
+                let small = child.metadata().is_ok_and(|m| m.len() <= MAX_SYMBOL_FILE_BYTES);
+                if small && has_symbols(language) {
+                    if let Ok(content) = std::fs::read_to_string(&path) {
+                        self.stamp(&path, &child_rel);
+                        symbols = top_level_symbols(language, &content);
+                    }
+                }
+            }
+            self.push(RepoEntry { path: child_rel, is_dir: false, symbols });
+        }
+    }
+
+    fn push(&mut self, entry: RepoEntry) {
+        if self.entries.len() < MAX_ENTRIES {
+            self.entries.push(entry);
+        } else {
+            self.omitted += 1;
+        }
+    }
+}
+
+fn join(rel: &str, name: &str) -> String {
+    if rel.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", rel, name)
+    }
+}
+
+fn mtime_nanos(path: &Path) -> Option<u128> {
+    let modified = std::fs::symlink_metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos())
+}
+
+// =============================================================================
+// .gitignore matching
+// =============================================================================
+
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    /// Directory of the `.gitignore`, relative to the root
+    base: String,
+    pattern: String,
+    negated: bool,
+    dir_only: bool,
+    /// Matched against the whole path below `base` rather than just the name
+    anchored: bool,
+}
+
+impl IgnoreRule {
+    fn matches(&self, rel: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let below_base = if self.base.is_empty() {
+            rel
+        } else {
+            match rel.strip_prefix(self.base.as_str()).and_then(|rest| rest.strip_prefix('/')) {
+                Some(rest) => rest,
+                None => return false,
+            }
+        };
+        let subject = if self.anchored {
+            below_base
+        } else {
+            below_base.rsplit('/').next().unwrap_or(below_base)
+        };
+        glob_match(self.pattern.as_bytes(), subject.as_bytes())
+    }
+}
+
+fn parse_gitignore(base: &str, text: &str) -> Vec<IgnoreRule> {
+    let mut rules = Vec::new();
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let pattern = line.strip_prefix('/').unwrap_or(line);
+        if pattern.is_empty() {
+            continue;
+        }
+        rules.push(IgnoreRule {
+            base: base.to_string(),
+            pattern: pattern.to_string(),
+            negated,
+            dir_only,
+            anchored,
+        });
+    }
+    rules
+}
+
+/// Later rules override earlier ones, as in git.
+fn is_ignored(rules: &[IgnoreRule], rel: &str, is_dir: bool) -> bool {
+    let mut ignored = false;
+    for rule in rules {
+        if rule.matches(rel, is_dir) {
+            ignored = !rule.negated;
+        }
+    }
+    ignored
+}
+
+/// Glob matching with `*`, `?`, `[...]` and `**` spanning directories.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            glob_match(rest, text)
+                || text.iter().enumerate().any(|(i, &c)| c == b'/' && glob_match(rest, &text[i + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        [b'*', rest @ ..] => {
+            let segment = text.iter().position(|&c| c == b'/').unwrap_or(text.len());
+            (0..=segment).any(|i| glob_match(rest, &text[i..]))
+        }
+        [b'?', rest @ ..] => matches!(text, [c, ..] if *c != b'/') && glob_match(rest, &text[1..]),
+        [b'[', class @ ..] => {
+            let Some(end) = class.iter().skip(1).position(|&c| c == b']').map(|p| p + 1) else {
+                return matches!(text, [b'[', ..]) && glob_match(class, &text[1..]);
+            };
+            let Some((&c, text_rest)) = text.split_first() else {
+                return false;
+            };
+            let (negated, set) = match class[..end].split_first() {
+                Some((b'!' | b'^', set)) => (true, set),
+                _ => (false, &class[..end]),
+            };
+            let mut found = false;
+            let mut i = 0;
+            while i < set.len() {
+                if i + 2 < set.len() && set[i + 1] == b'-' {
+                    found |= (set[i]..=set[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    found |= set[i] == c;
+                    i += 1;
+                }
+            }
+            found != negated && c != b'/' && glob_match(&class[end + 1..], text_rest)
+        }
+        [c, rest @ ..] => matches!(text, [t, ..] if t == c) && glob_match(rest, &text[1..]),
+    }
+}
+
+// =============================================================================
+// Symbols
+// =============================================================================
+
+fn has_symbols(language: &str) -> bool {
+    matches!(language, "rust" | "python" | "javascript" | "typescript" | "go" | "java" | "kotlin"
+        | "csharp" | "ruby" | "php" | "swift")
+}
+
+/// Declarations at the start of a line, which in most files are the top-level ones.
+fn top_level_symbols(language: &str, content: &str) -> Vec<String> {
+    let (modifiers, keywords): (&[&str], &[&str]) = match language {
+        "rust" => (
+            &["pub", "pub(crate)", "pub(super)", "async", "unsafe", "const", "extern"],
+            &["fn", "struct", "enum", "trait", "type", "union", "mod", "macro_rules!"],
+        ),
+        "python" => (&["async"], &["def", "class"]),
+        "javascript" | "typescript" => (
+            &["export", "default", "async", "declare", "abstract"],
+            &["function", "class", "interface", "type", "enum"],
+        ),
+        "go" => (&[], &["func", "type"]),
+        "ruby" => (&[], &["class", "module", "def"]),
+        "php" => (&["abstract", "final"], &["class", "interface", "trait", "function", "enum"]),
+        _ => (
+            &["public", "private", "protected", "internal", "abstract", "final", "static",
+              "sealed", "open", "data", "partial"],
+            &["class", "interface", "enum", "struct", "record", "object", "fun", "func", "protocol"],
+        ),
+    };
+
+    let mut symbols = Vec::new();
+    for line in content.lines() {
+        if line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        if let Some(symbol) = declaration(line, modifiers, keywords) {
+            symbols.push(symbol);
+            if symbols.len() == MAX_SYMBOLS_PER_FILE {
+                break;
+            }
+        }
+    }
+    symbols
+}
+
+fn declaration(line: &str, modifiers: &[&str], keywords: &[&str]) -> Option<String> {
+    let mut words = line.split_whitespace().peekable();
+    while words.peek().is_some_and(|word| modifiers.contains(word)) {
+        words.next();
+    }
+    let keyword = words.next().filter(|word| keywords.contains(word))?;
+    let mut rest = words.collect::<Vec<_>>().join(" ");
+
+    // Go methods: `func (s *Server) Start()`
+    if keyword == "func" && rest.starts_with('(') {
+        rest = rest.split_once(')')?.1.trim_start().to_string();
+    }
+
+    let name: String = rest.chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '$')
+        .collect();
+    if name.is_empty() {
+        return None;
+    }
+    Some(format!("{} {}", keyword.trim_end_matches('!'), name))
+}
+
+// =============================================================================
+// Cache
+// =============================================================================
+
+/// Repo maps by root, kept in memory and, with a directory, on disk
+pub struct RepoMapCache {
+    dir: Option<PathBuf>,
+    maps: Mutex<HashMap<PathBuf, RepoMap>>,
+}
+
+impl RepoMapCache {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            maps: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The map of `root`, rebuilt only when the cached one is stale.
+    pub fn get(&self, root: &Path) -> io::Result<RepoMap> {
+        let root = root.canonicalize()?;
+
+        if let Some(map) = self.maps.lock().get(&root).filter(|map| map.is_fresh()) {
+            return Ok(map.clone());
+        }
+
+        let file = self.dir.as_ref().map(|dir| dir.join(cache_file_name(&root)));
+        let saved = file.as_ref()
+            .and_then(|file| std::fs::read(file).ok())
+            .and_then(|bytes| serde_json::from_slice::<RepoMap>(&bytes).ok())
+            .filter(|map| map.root == root && map.is_fresh());
+
+        let map = match saved {
+            Some(map) => map,
+            None => {
+                let map = RepoMap::build(&root)?;
+                if let (Some(dir), Some(file)) = (&self.dir, &file) {
+                    // The cache only saves time, so failing to write it isn't an error.
+                    if std::fs::create_dir_all(dir).is_ok() {
+                        if let Ok(json) = serde_json::to_vec(&map) {
+                            let _ = std::fs::write(file, json);
+                        }
+                    }
+                }
+                map
+            }
+        };
+
+        self.maps.lock().insert(root, map.clone());
+        Ok(map)
+    }
+}
+
+fn cache_file_name(root: &Path) -> String {
+    let mut hasher = DefaultHasher::new();
+    root.hash(&mut hasher);
+    format!("{:016x}.json", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_gitignore_rules() {
+        let rules = parse_gitignore("", "*.log\n/build/\ndocs/**/*.tmp\n!keep.log\n# comment\n");
+        assert!(is_ignored(&rules, "app.log", false));
+        assert!(is_ignored(&rules, "src/deep/app.log", false));
+        assert!(!is_ignored(&rules, "keep.log", false));
+        assert!(is_ignored(&rules, "build", true));
+        assert!(!is_ignored(&rules, "build", false));
+        assert!(!is_ignored(&rules, "src/build", true));
+        assert!(is_ignored(&rules, "docs/a/b/x.tmp", false));
+        assert!(is_ignored(&rules, "docs/x.tmp", false));
+
+        let nested = parse_gitignore("web", "dist\n[ab]?.js\n");
+        assert!(is_ignored(&nested, "web/dist", true));
+        assert!(!is_ignored(&nested, "dist", true));
+        assert!(is_ignored(&nested, "web/src/a1.js", false));
+        assert!(!is_ignored(&nested, "web/src/c1.js", false));
+    }
+
+    #[test]
+    fn test_build_and_render() {
+        let root = tempdir().unwrap();
+        let path = root.path();
+        std::fs::create_dir_all(path.join("src/bin")).unwrap();
+        std::fs::create_dir_all(path.join("generated")).unwrap();
+        std::fs::write(path.join(".gitignore"), "generated/\n").unwrap();
+        std::fs::write(path.join("Cargo.toml"), "[package]\n").unwrap();
+        std::fs::write(path.join("generated/out.rs"), "fn hidden() {}\n").unwrap();
+        std::fs::write(path.join("src/lib.rs"),
+            "pub struct Map;\n\nimpl Map {\n    pub fn new() {}\n}\n\npub(crate) async fn load() {}\nmacro_rules! bail {}\n").unwrap();
+        std::fs::write(path.join("src/bin/tool.py"), "class Tool:\n    def run(self): ...\n\nasync def main(): ...\n").unwrap();
+
+        let map = RepoMap::build(path).unwrap();
+        assert_eq!(map.manifests, vec!["Cargo.toml"]);
+        assert_eq!(map.languages, vec![("python".to_string(), 1), ("rust".to_string(), 1), ("toml".to_string(), 1)]);
+
+        let rendered = map.render(1000);
+        assert!(rendered.contains("  lib.rs: struct Map, fn load, macro_rules bail"));
+        assert!(rendered.contains("    tool.py: class Tool, def main"));
+        assert!(!rendered.contains("generated"));
+
+        // A tight budget keeps the shallow entries.
+        let short = map.render(40);
+        assert!(short.contains("src/") && !short.contains("tool.py"));
+        assert!(short.ends_with("more entries"));
+    }
+
+    #[test]
+    fn test_cache_rebuilds_when_stale() {
+        let root = tempdir().unwrap();
+        let cache_dir = tempdir().unwrap();
+        std::fs::write(root.path().join("a.rs"), "fn one() {}\n").unwrap();
+
+        let cache = RepoMapCache::new(Some(cache_dir.path().to_path_buf()));
+        let first = cache.get(root.path()).unwrap();
+        assert_eq!(first.entries[0].symbols, vec!["fn one"]);
+        assert_eq!(std::fs::read_dir(cache_dir.path()).unwrap().count(), 1);
+
+        // A fresh cache reads the saved map back.
+        let reloaded = RepoMapCache::new(Some(cache_dir.path().to_path_buf())).get(root.path()).unwrap();
+        assert_eq!(reloaded.entries, first.entries);
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        std::fs::write(root.path().join("a.rs"), "fn two() {}\n").unwrap();
+        std::fs::write(root.path().join("b.rs"), "fn three() {}\n").unwrap();
+        let updated = cache.get(root.path()).unwrap();
+        let symbols: Vec<_> = updated.entries.iter().flat_map(|e| e.symbols.clone()).collect();
+        assert_eq!(symbols, vec!["fn two", "fn three"]);
+    }
+}
@@ -0,0 +1,396 @@
+//! Importing conversations into `ChatSession`s
+//!
+//! Accepts our own JSON export (one session or a list of them) and the
+//! session logs the CLIs keep on disk: Claude Code's JSONL transcripts under
+//! `~/.claude/projects/` and Codex's rollout files under `~/.codex/sessions/`.
+//! Tool calls in those logs are kept as one-line notes in the assistant's
+//! message; their output is left out.
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::claude_stream::action_for;
+use crate::context::{ChatSession, Message, MessageRole};
+use crate::protocol::ToolAction;
+use crate::Tool;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    /// `export_session` / `export_sessions` JSON
+    Polyglot,
+    ClaudeCode,
+    Codex,
+}
+
+impl TranscriptFormat {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Self::Polyglot => "Polyglot-AI export",
+            Self::ClaudeCode => "Claude Code session log",
+            Self::Codex => "Codex session log",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportError {
+    /// Neither our export nor a session log we know
+    UnknownFormat,
+    /// A JSON export that doesn't hold sessions
+    InvalidExport(String),
+    /// A session log with no user or assistant messages
+    Empty,
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownFormat => write!(
+                f,
+                "not a Polyglot-AI JSON export, Claude Code session log or Codex session log"
+            ),
+            Self::InvalidExport(message) => write!(f, "invalid JSON export: {}", message),
+            Self::Empty => write!(f, "the transcript has no messages"),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Sessions read from `content`, with the format they were in. Sessions
+/// from a CLI's log take the log's session ID when it is a UUID, so
+/// importing the same log again replaces the earlier import.
+pub fn import_sessions(content: &str) -> Result<(Vec<ChatSession>, TranscriptFormat), ImportError> {
+    let content = content.trim_start_matches('\u{feff}').trim();
+
+    // A JSONL log's first line is a complete object too, so only a
+    // document that parses whole is an export.
+    if let Ok(value) = serde_json::from_str::<Value>(content) {
+        let sessions = match value {
+            Value::Array(_) => serde_json::from_value::<Vec<ChatSession>>(value),
+            Value::Object(_) if value.get("messages").is_some() => {
+                serde_json::from_value::<ChatSession>(value).map(|s| vec![s])
+            }
+            _ => return Err(ImportError::UnknownFormat),
+        };
+        return sessions
+            .map(|s| (s, TranscriptFormat::Polyglot))
+            .map_err(|e| ImportError::InvalidExport(e.to_string()));
+    }
+
+    let lines: Vec<Value> = content.lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line.trim()).ok())
+        .filter(Value::is_object)
+        .collect();
+
+    let claude = lines.iter().filter(|l| is_claude_line(l)).count();
+    let codex = lines.iter().filter(|l| is_codex_line(l)).count();
+    let (session, format) = match (claude, codex) {
+        (0, 0) => return Err(ImportError::UnknownFormat),
+        (claude, codex) if claude >= codex => (parse_claude(&lines), TranscriptFormat::ClaudeCode),
+        _ => (parse_codex(&lines), TranscriptFormat::Codex),
+    };
+
+    if session.messages.is_empty() {
+        return Err(ImportError::Empty);
+    }
+    Ok((vec![session], format))
+}
+
+fn is_claude_line(line: &Value) -> bool {
+    matches!(line["type"].as_str(), Some("user" | "assistant")) && line["message"].is_object()
+}
+
+fn is_codex_line(line: &Value) -> bool {
+    match line["type"].as_str() {
+        Some("session_meta" | "response_item" | "turn_context") => true,
+        // Logs written before items were wrapped in `response_item`
+        Some("message") => line["role"].is_string(),
+        _ => false,
+    }
+}
+
+/// Builds the session as the log's messages are added, keeping
+/// consecutive assistant entries together as one reply.
+struct Builder {
+    session: ChatSession,
+    started: Option<DateTime<Utc>>,
+}
+
+impl Builder {
+    fn new(tool: Tool) -> Self {
+        let mut session = ChatSession::new(None);
+        session.tool = Some(tool);
+        Self { session, started: None }
+    }
+
+    fn set_id(&mut self, id: &str) {
+        if let Ok(id) = Uuid::parse_str(id) {
+            self.session.id = id;
+        }
+    }
+
+    fn set_project(&mut self, cwd: &Value) {
+        if let Some(cwd) = cwd.as_str().filter(|c| !c.is_empty()) {
+            self.session.project_path.get_or_insert_with(|| cwd.to_string());
+        }
+    }
+
+    fn push(&mut self, role: MessageRole, text: String, time: Option<DateTime<Utc>>, model: Option<String>) {
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+
+        if role == MessageRole::Assistant {
+            if let Some(last) = self.session.messages.last_mut().filter(|m| m.role == MessageRole::Assistant) {
+                last.content.push_str("\n\n");
+                last.content.push_str(text);
+                last.token_estimate = (last.content.len() / 4) as u32;
+                if let Some(time) = time {
+                    last.timestamp = time;
+                }
+                return;
+            }
+        }
+
+        let mut message = Message::new(role, text.to_string()).with_model(model);
+        if let Some(time) = time {
+            message.timestamp = time;
+            self.started.get_or_insert(time);
+        }
+        self.session.add_message(message);
+    }
+
+    fn finish(mut self) -> ChatSession {
+        self.session.total_tokens = self.session.messages.iter().map(|m| m.token_estimate).sum();
+        if let Some(started) = self.started {
+            self.session.created_at = started;
+        }
+        if let Some(last) = self.session.messages.last() {
+            self.session.updated_at = last.timestamp;
+        }
+        self.session.auto_title();
+        self.session
+    }
+}
+
+fn timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    value.as_str()
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.with_timezone(&Utc))
+}
+
+fn parse_claude(lines: &[Value]) -> ChatSession {
+    let mut builder = Builder::new(Tool::Claude);
+    let mut title = None;
+
+    for line in lines {
+        if line["type"] == "summary" {
+            // Later summaries describe more of the conversation.
+            title = line["summary"].as_str().map(|s| s.to_string());
+            continue;
+        }
+        // Subagent runs and command bookkeeping aren't part of the conversation.
+        if !is_claude_line(line) || line["isSidechain"] == true || line["isMeta"] == true {
+            continue;
+        }
+
+        if let Some(id) = line["sessionId"].as_str() {
+            builder.set_id(id);
+        }
+        builder.set_project(&line["cwd"]);
+
+        let message = &line["message"];
+        let time = timestamp(&line["timestamp"]);
+        match line["type"].as_str() {
+            Some("user") => {
+                let text = match &message["content"] {
+                    Value::String(text) => text.clone(),
+                    // Tool results come back as user entries; only typed text counts.
+                    Value::Array(blocks) => blocks.iter()
+                        .filter(|b| b["type"] == "text")
+                        .filter_map(|b| b["text"].as_str())
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    _ => String::new(),
+                };
+                if !text.trim_start().starts_with("<command-") && !text.trim_start().starts_with("<local-command-") {
+                    builder.push(MessageRole::User, text, time, None);
+                }
+            }
+            Some("assistant") => {
+                let mut parts = Vec::new();
+                for block in message["content"].as_array().into_iter().flatten() {
+                    match block["type"].as_str() {
+                        Some("text") => parts.extend(block["text"].as_str().map(|t| t.to_string())),
+                        Some("tool_use") => {
+                            let name = block["name"].as_str().unwrap_or("tool");
+                            parts.push(format!("[{}]", action_for(name, &block["input"])));
+                        }
+                        _ => {}
+                    }
+                }
+                let model = message["model"].as_str()
+                    .filter(|m| !m.starts_with('<'))
+                    .map(|m| m.to_string());
+                builder.push(MessageRole::Assistant, parts.join("\n"), time, model);
+            }
+            _ => {}
+        }
+    }
+
+    let mut session = builder.finish();
+    if let Some(title) = title {
+        session.set_title(title);
+    }
+    session
+}
+
+fn parse_codex(lines: &[Value]) -> ChatSession {
+    let mut builder = Builder::new(Tool::Codex);
+    let mut model = None;
+
+    for line in lines {
+        let time = timestamp(&line["timestamp"]);
+        let item = match line["type"].as_str() {
+            Some("session_meta") => {
+                let meta = &line["payload"];
+                if let Some(id) = meta["id"].as_str() {
+                    builder.set_id(id);
+                }
+                builder.set_project(&meta["cwd"]);
+                continue;
+            }
+            Some("turn_context") => {
+                model = line["payload"]["model"].as_str().map(|m| m.to_string());
+                builder.set_project(&line["payload"]["cwd"]);
+                continue;
+            }
+            Some("response_item") => &line["payload"],
+            Some("message") => line,
+            _ => continue,
+        };
+
+        match item["type"].as_str() {
+            Some("message") => {
+                let text = item["content"].as_array().into_iter().flatten()
+                    .filter(|b| matches!(b["type"].as_str(), Some("input_text" | "output_text" | "text")))
+                    .filter_map(|b| b["text"].as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                match item["role"].as_str() {
+                    // Codex sends its instructions and environment as user messages.
+                    Some("user") if !is_codex_context(&text) => {
+                        builder.push(MessageRole::User, text, time, None);
+                    }
+                    Some("assistant") => builder.push(MessageRole::Assistant, text, time, model.clone()),
+                    _ => {}
+                }
+            }
+            Some("function_call") | Some("local_shell_call") => {
+                let action = codex_action(item);
+                builder.push(MessageRole::Assistant, format!("[{}]", action), time, model.clone());
+            }
+            _ => {}
+        }
+    }
+
+    builder.finish()
+}
+
+fn is_codex_context(text: &str) -> bool {
+    let text = text.trim_start();
+    text.starts_with("<environment_context>") || text.starts_with("<user_instructions>")
+}
+
+fn codex_action(item: &Value) -> ToolAction {
+    // `arguments` is a JSON string; `local_shell_call` has an `action` object.
+    let arguments = item["arguments"].as_str()
+        .and_then(|a| serde_json::from_str::<Value>(a).ok())
+        .unwrap_or_else(|| item["action"].clone());
+    let command = arguments["command"].as_array().map(|parts| {
+        let parts: Vec<_> = parts.iter().filter_map(|p| p.as_str()).collect();
+        // Usually `["bash", "-lc", "<script>"]`
+        match parts.as_slice() {
+            [_, "-lc" | "-c", script] => script.to_string(),
+            _ => parts.join(" "),
+        }
+    });
+
+    match command {
+        Some(command) => ToolAction::ShellCommand { command },
+        None => action_for(item["name"].as_str().unwrap_or("tool"), &arguments),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{export_session, export_sessions, ExportFormat};
+
+    #[test]
+    fn test_own_exports_round_trip() {
+        let mut session = ChatSession::new(Some("/work".to_string()));
+        session.add_message(Message::user("Hello"));
+        session.add_message(Message::assistant("Hi"));
+
+        let (sessions, format) = import_sessions(&export_session(&session, ExportFormat::Json)).unwrap();
+        assert_eq!(format, TranscriptFormat::Polyglot);
+        assert_eq!(sessions[0].id, session.id);
+        assert_eq!(sessions[0].messages.len(), 2);
+
+        let all = export_sessions(&[session.clone(), session.fork(1)], ExportFormat::Json);
+        assert_eq!(import_sessions(&all).unwrap().0.len(), 2);
+        assert!(matches!(import_sessions("{\"foo\": 1}"), Err(ImportError::UnknownFormat)));
+        assert!(matches!(import_sessions("not json"), Err(ImportError::UnknownFormat)));
+    }
+
+    #[test]
+    fn test_claude_code_session_log() {
+        let log = r#"
+{"type":"summary","summary":"Fix the flaky test","leafUuid":"x"}
+{"type":"user","sessionId":"6f1c2a3e-8d5b-4c7a-9e2f-1a2b3c4d5e6f","cwd":"/work/app","timestamp":"2025-06-01T10:00:00Z","message":{"role":"user","content":"Why does test_sync fail?"}}
+{"type":"assistant","sessionId":"6f1c2a3e-8d5b-4c7a-9e2f-1a2b3c4d5e6f","timestamp":"2025-06-01T10:00:05Z","message":{"id":"m1","role":"assistant","model":"claude-sonnet-4","content":[{"type":"text","text":"Let me run it."},{"type":"tool_use","id":"t1","name":"Bash","input":{"command":"cargo test test_sync"}}]}}
+{"type":"user","sessionId":"6f1c2a3e-8d5b-4c7a-9e2f-1a2b3c4d5e6f","timestamp":"2025-06-01T10:00:09Z","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"t1","content":"1 failed"}]}}
+{"type":"assistant","sessionId":"6f1c2a3e-8d5b-4c7a-9e2f-1a2b3c4d5e6f","timestamp":"2025-06-01T10:00:12Z","message":{"id":"m2","role":"assistant","model":"claude-sonnet-4","content":[{"type":"text","text":"It races on the temp dir."}]}}
+{"type":"assistant","isSidechain":true,"message":{"role":"assistant","content":[{"type":"text","text":"subagent"}]}}
+"#;
+        let (sessions, format) = import_sessions(log).unwrap();
+        assert_eq!(format, TranscriptFormat::ClaudeCode);
+        let session = &sessions[0];
+        assert_eq!(session.id.to_string(), "6f1c2a3e-8d5b-4c7a-9e2f-1a2b3c4d5e6f");
+        assert_eq!(session.title.as_deref(), Some("Fix the flaky test"));
+        assert_eq!(session.project_path.as_deref(), Some("/work/app"));
+        assert_eq!(session.tool, Some(Tool::Claude));
+        assert_eq!(session.messages.len(), 2);
+        assert_eq!(
+            session.messages[1].content,
+            "Let me run it.\n[$ cargo test test_sync]\n\nIt races on the temp dir."
+        );
+        assert_eq!(session.messages[1].model.as_deref(), Some("claude-sonnet-4"));
+        assert_eq!(session.updated_at.to_rfc3339(), "2025-06-01T10:00:12+00:00");
+    }
+
+    #[test]
+    fn test_codex_session_log() {
+        let log = r#"{"timestamp":"2025-09-01T08:00:00Z","type":"session_meta","payload":{"id":"0199a0b1-2c3d-7e4f-8a9b-0c1d2e3f4a5b","cwd":"/work/cli"}}
+{"timestamp":"2025-09-01T08:00:00Z","type":"response_item","payload":{"type":"message","role":"user","content":[{"type":"input_text","text":"<environment_context>\n<cwd>/work/cli</cwd>\n</environment_context>"}]}}
+{"timestamp":"2025-09-01T08:00:01Z","type":"turn_context","payload":{"cwd":"/work/cli","model":"gpt-5-codex"}}
+{"timestamp":"2025-09-01T08:00:01Z","type":"response_item","payload":{"type":"message","role":"user","content":[{"type":"input_text","text":"List the crates"}]}}
+{"timestamp":"2025-09-01T08:00:03Z","type":"response_item","payload":{"type":"function_call","name":"shell","arguments":"{\"command\":[\"bash\",\"-lc\",\"ls crates\"]}","call_id":"c1"}}
+{"timestamp":"2025-09-01T08:00:04Z","type":"response_item","payload":{"type":"function_call_output","call_id":"c1","output":"common\nlocal"}}
+{"timestamp":"2025-09-01T08:00:06Z","type":"response_item","payload":{"type":"message","role":"assistant","content":[{"type":"output_text","text":"There are two: common and local."}]}}
+{"timestamp":"2025-09-01T08:00:06Z","type":"event_msg","payload":{"type":"agent_message","message":"There are two: common and local."}}"#;
+        let (sessions, format) = import_sessions(log).unwrap();
+        assert_eq!(format, TranscriptFormat::Codex);
+        let session = &sessions[0];
+        assert_eq!(session.tool, Some(Tool::Codex));
+        assert_eq!(session.project_path.as_deref(), Some("/work/cli"));
+        assert_eq!(session.messages.len(), 2);
+        assert_eq!(session.messages[0].content, "List the crates");
+        assert_eq!(session.messages[1].content, "[$ ls crates]\n\nThere are two: common and local.");
+        assert_eq!(session.messages[1].model.as_deref(), Some("gpt-5-codex"));
+    }
+}
@@ -0,0 +1,233 @@
+//! Encrypted store for tool API keys
+//!
+//! Keys are kept in the `api_keys` table of a `Database`, sealed by
+//! `ApiKeyManager` with a random master key from a key file next to it.
+//! Each key belongs to one tool and one environment variable, and is only
+//! decrypted to be handed to that tool's process or relayed to a server.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use thiserror::Error;
+
+use crate::crypto::{hex_decode, hex_encode};
+use crate::features::{ApiKeyError, ApiKeyManager};
+use crate::storage::{Database, StorageError, StoredApiKey};
+use crate::Tool;
+
+const DB_FILE: &str = "keys.db";
+const KEY_FILE: &str = "keys.key";
+
+#[derive(Debug, Error)]
+pub enum VaultError {
+    #[error("Key store error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("{0}")]
+    Crypto(#[from] ApiKeyError),
+    #[error("Key file {0}: {1}")]
+    KeyFile(PathBuf, String),
+    #[error("{0} has no default API key variable; name one with --env")]
+    NoDefaultEnv(Tool),
+    #[error("Invalid environment variable name: {0}")]
+    InvalidName(String),
+    #[error("{tool} already has a key in {env_var}; use `keys rotate` to replace it")]
+    Exists { tool: Tool, env_var: String },
+    #[error("{tool} has no key in {env_var}")]
+    NotFound { tool: Tool, env_var: String },
+}
+
+/// A stored key, without its value
+#[derive(Debug, Clone)]
+pub struct VaultEntry {
+    pub tool: Tool,
+    pub env_var: String,
+    pub created_at: String,
+}
+
+pub struct KeyVault {
+    db: Database,
+    sealer: ApiKeyManager,
+}
+
+impl KeyVault {
+    /// `polyglot-ai` in the user's data directory
+    pub fn default_dir() -> PathBuf {
+        directories::BaseDirs::new()
+            .map(|dirs| dirs.data_dir().to_path_buf())
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("polyglot-ai")
+    }
+
+    /// Opens the vault in `dir`, creating it and its master key if missing.
+    pub fn open(dir: &Path) -> Result<Self, VaultError> {
+        fs::create_dir_all(dir).map_err(|e| VaultError::KeyFile(dir.to_path_buf(), e.to_string()))?;
+        let key = read_or_create_key(&dir.join(KEY_FILE))?;
+        let db = Database::open(&dir.join(DB_FILE))?;
+        Ok(Self { db, sealer: ApiKeyManager::from_key(key) })
+    }
+
+    /// Whether `dir` holds a vault, so callers can skip creating one.
+    pub fn exists(dir: &Path) -> bool {
+        dir.join(DB_FILE).exists()
+    }
+
+    /// Environment variables for every stored key, by tool. Empty without
+    /// a vault in `dir`.
+    pub fn load_env(dir: &Path) -> Result<Vec<(Tool, String, String)>, VaultError> {
+        if !Self::exists(dir) {
+            return Ok(Vec::new());
+        }
+        Self::open(dir)?.env()
+    }
+
+    /// Stores a new key. `env_var` defaults to the tool's usual variable.
+    pub fn add(&self, tool: Tool, env_var: Option<&str>, value: &str) -> Result<VaultEntry, VaultError> {
+        let env_var = resolve_env(tool, env_var)?;
+        if self.find(tool, &env_var)?.is_some() {
+            return Err(VaultError::Exists { tool, env_var });
+        }
+        self.store(tool, env_var, value)
+    }
+
+    /// Replaces the value of an existing key.
+    pub fn rotate(&self, tool: Tool, env_var: Option<&str>, value: &str) -> Result<VaultEntry, VaultError> {
+        let env_var = resolve_env(tool, env_var)?;
+        if self.find(tool, &env_var)?.is_none() {
+            return Err(VaultError::NotFound { tool, env_var });
+        }
+        self.store(tool, env_var, value)
+    }
+
+    pub fn remove(&self, tool: Tool, env_var: Option<&str>) -> Result<(), VaultError> {
+        let env_var = resolve_env(tool, env_var)?;
+        if !self.db.delete_api_key(&key_id(tool, &env_var))? {
+            return Err(VaultError::NotFound { tool, env_var });
+        }
+        Ok(())
+    }
+
+    pub fn list(&self) -> Result<Vec<VaultEntry>, VaultError> {
+        Ok(self.db.list_api_keys()?.iter().filter_map(entry_from_stored).collect())
+    }
+
+    /// Decrypts every key into `(tool, variable, value)`.
+    pub fn env(&self) -> Result<Vec<(Tool, String, String)>, VaultError> {
+        let mut env = Vec::new();
+        for stored in self.db.list_api_keys()? {
+            if let Some(entry) = entry_from_stored(&stored) {
+                let value = self.sealer.decrypt(&stored.encrypted_key)?;
+                env.push((entry.tool, entry.env_var, value));
+            }
+        }
+        Ok(env)
+    }
+
+    fn find(&self, tool: Tool, env_var: &str) -> Result<Option<VaultEntry>, VaultError> {
+        let id = key_id(tool, env_var);
+        Ok(self.list()?.into_iter().find(|e| key_id(e.tool, &e.env_var) == id))
+    }
+
+    fn store(&self, tool: Tool, env_var: String, value: &str) -> Result<VaultEntry, VaultError> {
+        ApiKeyManager::validate_key_format(value)?;
+        let created_at = Utc::now().to_rfc3339();
+        self.db.save_api_key(&StoredApiKey {
+            key_id: key_id(tool, &env_var),
+            tool: tool.as_str().to_string(),
+            encrypted_key: self.sealer.encrypt(value)?,
+            created_at: created_at.clone(),
+            last_used: None,
+            is_active: true,
+        })?;
+        Ok(VaultEntry { tool, env_var, created_at })
+    }
+}
+
+/// Keys are stored once per tool and variable, so adding one again replaces it.
+fn key_id(tool: Tool, env_var: &str) -> String {
+    format!("{}/{}", tool.as_str(), env_var)
+}
+
+fn entry_from_stored(stored: &StoredApiKey) -> Option<VaultEntry> {
+    let (tool, env_var) = stored.key_id.split_once('/')?;
+    Some(VaultEntry {
+        tool: tool.parse().ok()?,
+        env_var: env_var.to_string(),
+        created_at: stored.created_at.clone(),
+    })
+}
+
+fn resolve_env(tool: Tool, env_var: Option<&str>) -> Result<String, VaultError> {
+    let name = match env_var {
+        Some(name) => name.trim(),
+        None => tool.api_key_env().ok_or(VaultError::NoDefaultEnv(tool))?,
+    };
+    let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(VaultError::InvalidName(name.to_string()));
+    }
+    Ok(name.to_string())
+}
+
+/// Reads the hex master key in `path`, writing a new one readable only by
+/// its owner if the file is missing.
+fn read_or_create_key(path: &Path) -> Result<[u8; 32], VaultError> {
+    let key_error = |e: String| VaultError::KeyFile(path.to_path_buf(), e);
+
+    if !path.exists() {
+        let key = ApiKeyManager::generate_key();
+        fs::write(path, hex_encode(&key) + "\n").map_err(|e| key_error(e.to_string()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+                .map_err(|e| key_error(e.to_string()))?;
+        }
+        return Ok(key);
+    }
+
+    let text = fs::read_to_string(path).map_err(|e| key_error(e.to_string()))?;
+    hex_decode(text.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| key_error("expected 64 hex digits".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_keys_are_sealed_and_listed_without_values() {
+        let dir = tempdir().unwrap();
+        assert!(KeyVault::load_env(dir.path()).unwrap().is_empty());
+
+        let vault = KeyVault::open(dir.path()).unwrap();
+        vault.add(Tool::Claude, None, "sk-ant-original-123").unwrap();
+        vault.add(Tool::Gemini, Some("GOOGLE_API_KEY"), "AIza-gemini-key").unwrap();
+        assert!(matches!(
+            vault.add(Tool::Claude, None, "sk-ant-another-456"),
+            Err(VaultError::Exists { .. })
+        ));
+        assert!(matches!(vault.add(Tool::Ollama, None, "token-123456"), Err(VaultError::NoDefaultEnv(_))));
+        assert!(matches!(vault.add(Tool::Codex, Some("BAD-NAME"), "sk-123456789"), Err(VaultError::InvalidName(_))));
+
+        vault.rotate(Tool::Claude, None, "sk-ant-rotated-789").unwrap();
+        let names: Vec<_> = vault.list().unwrap().into_iter().map(|e| e.env_var).collect();
+        assert_eq!(names, vec!["ANTHROPIC_API_KEY", "GOOGLE_API_KEY"]);
+
+        let raw = fs::read(dir.path().join(DB_FILE)).unwrap();
+        assert!(!raw.windows(6).any(|w| w == b"sk-ant"));
+
+        drop(vault);
+        let env = KeyVault::load_env(dir.path()).unwrap();
+        assert!(env.contains(&(Tool::Claude, "ANTHROPIC_API_KEY".to_string(), "sk-ant-rotated-789".to_string())));
+
+        let vault = KeyVault::open(dir.path()).unwrap();
+        vault.remove(Tool::Gemini, Some("GOOGLE_API_KEY")).unwrap();
+        assert!(matches!(vault.remove(Tool::Gemini, Some("GOOGLE_API_KEY")), Err(VaultError::NotFound { .. })));
+        assert_eq!(vault.list().unwrap().len(), 1);
+    }
+}
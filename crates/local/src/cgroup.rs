@@ -0,0 +1,314 @@
+//! Per-run cgroup v2 groups for sandboxed tools on Linux
+//!
+//! Each tool run gets a fresh group under polyglot's own cgroup (or the
+//! configured `cgroup_parent`) with CPU, memory, PID and I/O limits. The
+//! child joins the group between fork and exec, and the group's peak usage is
+//! read back once the tool exits.
+//!
+//! Polyglot never moves itself between groups. The parent must already hand
+//! the controllers to its children, or be able to, which usually means a
+//! delegated, empty `cgroup_parent` such as a systemd scope with
+//! `Delegate=yes`; otherwise runs fall back to rlimits.
+
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use tokio::process::Command;
+use uuid::Uuid;
+
+use crate::sandbox::{ResourceUsage, SandboxConfig};
+
+const CGROUP_MOUNT: &str = "/sys/fs/cgroup";
+const CPU_PERIOD_USEC: u64 = 100_000;
+const REQUIRED_CONTROLLERS: &[&str] = &["cpu", "memory", "pids"];
+
+pub struct ToolCgroup {
+    path: PathBuf,
+    /// The group's `cgroup.procs`, opened up front so the child can join
+    /// without allocating after fork
+    procs: File,
+}
+
+impl ToolCgroup {
+    /// A new group under the configured parent. I/O limits apply to the disk
+    /// holding `working_dir`.
+    pub fn create(config: &SandboxConfig, working_dir: &Path) -> Result<Self> {
+        let parent = match &config.cgroup_parent {
+            Some(parent) => parent.clone(),
+            None => own_cgroup()?,
+        };
+        if !parent.join("cgroup.controllers").exists() {
+            bail!("{} is not a cgroup v2 group", parent.display());
+        }
+
+        let io_enabled = enable_controllers(&parent)?;
+
+        let path = parent.join(format!("polyglot-{}", Uuid::new_v4().simple()));
+        fs::create_dir(&path)
+            .with_context(|| format!("Failed to create cgroup {}", path.display()))?;
+
+        let procs = match OpenOptions::new().write(true).create(true).truncate(false).open(path.join("cgroup.procs")) {
+            Ok(file) => file,
+            Err(e) => {
+                let _ = fs::remove_dir(&path);
+                return Err(e).context("Failed to open cgroup.procs");
+            }
+        };
+
+        let group = Self { path, procs };
+        group.write_limits(config, io_enabled, working_dir)?;
+        Ok(group)
+    }
+
+    /// Moves the child into this group before it execs.
+    pub fn attach(&self, cmd: &mut Command) {
+        let fd = self.procs.as_raw_fd();
+        unsafe {
+            cmd.pre_exec(move || {
+                // "0" moves the writing process.
+                if libc::write(fd, b"0".as_ptr().cast(), 1) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// Peak usage of the run so far. `memory.peak` needs Linux 5.19 and
+    /// `pids.peak` 6.1; on older kernels those read as zero.
+    pub fn usage(&self) -> ResourceUsage {
+        let cpu_usec = fs::read_to_string(self.path.join("cpu.stat"))
+            .ok()
+            .and_then(|stat| {
+                stat.lines()
+                    .find_map(|line| line.strip_prefix("usage_usec "))
+                    .and_then(|v| v.trim().parse::<u64>().ok())
+            })
+            .unwrap_or(0);
+
+        ResourceUsage {
+            peak_memory_bytes: read_number(&self.path.join("memory.peak")),
+            cpu_time_ms: cpu_usec / 1000,
+            peak_pids: read_number(&self.path.join("pids.peak")),
+        }
+    }
+
+    fn write_limits(&self, config: &SandboxConfig, io_enabled: bool, working_dir: &Path) -> Result<()> {
+        if let Some(percent) = config.max_cpu_percent {
+            let cpus = std::thread::available_parallelism().map(|n| n.get() as u64).unwrap_or(1);
+            let quota = (CPU_PERIOD_USEC * cpus * percent as u64 / 100).max(1000);
+            self.write("cpu.max", &format!("{} {}", quota, CPU_PERIOD_USEC))?;
+        }
+
+        if let Some(max_mem_mb) = config.max_memory_mb {
+            self.write("memory.max", &(max_mem_mb * 1024 * 1024).to_string())?;
+        }
+
+        if let Some(max_pids) = config.max_pids {
+            self.write("pids.max", &max_pids.to_string())?;
+        }
+
+        if let Some(mbps) = config.max_io_mbps {
+            if !io_enabled {
+                bail!("max_io_mbps is set but the io controller is not delegated");
+            }
+            let device = block_device(working_dir)
+                .with_context(|| format!("Could not find the disk holding {}", working_dir.display()))?;
+            let bytes = mbps * 1024 * 1024;
+            self.write("io.max", &format!("{} rbps={} wbps={}", device, bytes, bytes))?;
+        }
+
+        Ok(())
+    }
+
+    fn write(&self, file: &str, value: &str) -> Result<()> {
+        fs::write(self.path.join(file), value)
+            .with_context(|| format!("Failed to set {} to {}", file, value))
+    }
+}
+
+impl Drop for ToolCgroup {
+    fn drop(&mut self) {
+        // Stragglers the tool left behind would keep the group alive.
+        let _ = fs::write(self.path.join("cgroup.kill"), "1");
+        if !is_busy(fs::remove_dir(&self.path)) {
+            return;
+        }
+
+        // Killed processes take a moment to leave the group; wait for them
+        // off the runtime's worker threads.
+        let path = self.path.clone();
+        let remove = move || {
+            for _ in 0..20 {
+                std::thread::sleep(Duration::from_millis(10));
+                if !is_busy(fs::remove_dir(&path)) {
+                    return;
+                }
+            }
+            tracing::debug!("Leaving busy cgroup {}", path.display());
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(remove)),
+            Err(_) => remove(),
+        }
+    }
+}
+
+fn is_busy(result: io::Result<()>) -> bool {
+    matches!(result, Err(e) if e.raw_os_error() == Some(libc::EBUSY))
+}
+
+/// The cgroup this process was started in, from `/proc/self/cgroup`.
+fn own_cgroup() -> Result<PathBuf> {
+    let contents = fs::read_to_string("/proc/self/cgroup")
+        .context("Failed to read /proc/self/cgroup")?;
+    let relative = contents.lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .context("Not running under cgroup v2")?;
+    Ok(Path::new(CGROUP_MOUNT).join(relative.trim_start_matches('/')))
+}
+
+/// Enables the controllers tool groups need in `parent`. Returns whether `io`
+/// could be enabled too.
+fn enable_controllers(parent: &Path) -> Result<bool> {
+    let available = fs::read_to_string(parent.join("cgroup.controllers")).unwrap_or_default();
+    for controller in REQUIRED_CONTROLLERS {
+        if !available.split_whitespace().any(|c| c == *controller) {
+            bail!("The {} controller is not delegated to {}", controller, parent.display());
+        }
+    }
+
+    let enabled = fs::read_to_string(parent.join("cgroup.subtree_control")).unwrap_or_default();
+    let mut missing: Vec<&str> = REQUIRED_CONTROLLERS.iter()
+        .copied()
+        .filter(|c| !enabled.split_whitespace().any(|e| e == *c))
+        .collect();
+    let wants_io = available.split_whitespace().any(|c| c == "io");
+    if wants_io && !enabled.split_whitespace().any(|c| c == "io") {
+        missing.push("io");
+    }
+    if missing.is_empty() {
+        return Ok(wants_io);
+    }
+
+    // A group with processes in it, polyglot included, can't hand
+    // controllers to children, so these writes fail there and the caller
+    // falls back to rlimits.
+    let mut io_enabled = wants_io;
+    for controller in missing {
+        if let Err(e) = fs::write(parent.join("cgroup.subtree_control"), format!("+{}", controller)) {
+            if controller == "io" {
+                io_enabled = false;
+                continue;
+            }
+            return Err(e).with_context(|| {
+                format!("Failed to enable the {} controller in {}; set cgroup_parent to a delegated, empty group",
+                    controller, parent.display())
+            });
+        }
+    }
+    Ok(io_enabled)
+}
+
+/// `major:minor` of the whole disk `path` lives on, as `io.max` expects.
+fn block_device(path: &Path) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = fs::metadata(path).ok()?.dev();
+    let id = format!("{}:{}", libc::major(dev), libc::minor(dev));
+    let sys = Path::new("/sys/dev/block").join(&id);
+    if !sys.exists() {
+        return None;
+    }
+
+    // Partitions are throttled through their disk.
+    if sys.join("partition").exists() {
+        let disk = fs::read_to_string(sys.join("../dev")).ok()?;
+        return Some(disk.trim().to_string());
+    }
+    Some(id)
+}
+
+fn read_number(path: &Path) -> u64 {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_limits_written_to_group() {
+        // cgroupfs is plain files, so a directory can stand in for the parent.
+        let parent = tempdir().unwrap();
+        fs::write(parent.path().join("cgroup.controllers"), "cpu memory pids\n").unwrap();
+        fs::write(parent.path().join("cgroup.subtree_control"), "").unwrap();
+        fs::write(parent.path().join("cgroup.procs"), "").unwrap();
+
+        let config = SandboxConfig {
+            cgroups: true,
+            cgroup_parent: Some(parent.path().to_path_buf()),
+            max_memory_mb: Some(512),
+            max_cpu_percent: Some(100),
+            max_pids: Some(32),
+            ..Default::default()
+        };
+        let group = ToolCgroup::create(&config, Path::new(".")).unwrap();
+
+        let read = |file: &str| fs::read_to_string(group.path.join(file)).unwrap();
+        assert_eq!(read("memory.max"), (512u64 * 1024 * 1024).to_string());
+        assert_eq!(read("pids.max"), "32");
+        let cpus = std::thread::available_parallelism().unwrap().get() as u64;
+        assert_eq!(read("cpu.max"), format!("{} 100000", cpus * 100_000));
+
+        // Without the io controller an I/O limit can't be honoured.
+        let config = SandboxConfig { max_io_mbps: Some(50), ..config };
+        assert!(ToolCgroup::create(&config, Path::new(".")).is_err());
+
+        let config = SandboxConfig { cgroup_parent: Some(group.path.join("missing")), ..config };
+        assert!(ToolCgroup::create(&config, Path::new(".")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tool_runs_in_limited_cgroup() {
+        let config = SandboxConfig {
+            cgroups: true,
+            max_memory_mb: Some(256),
+            max_cpu_percent: Some(50),
+            max_pids: Some(64),
+            ..Default::default()
+        };
+
+        // Cgroups are often not delegated in CI containers.
+        let Ok(group) = ToolCgroup::create(&config, Path::new(".")) else {
+            return;
+        };
+
+        assert_eq!(fs::read_to_string(group.path.join("pids.max")).unwrap().trim(), "64");
+        assert_eq!(
+            fs::read_to_string(group.path.join("memory.max")).unwrap().trim(),
+            (256u64 * 1024 * 1024).to_string()
+        );
+
+        let mut cmd = Command::new("cat");
+        cmd.arg("/proc/self/cgroup");
+        group.attach(&mut cmd);
+        let output = cmd.output().await.unwrap();
+        let name = group.path.file_name().unwrap().to_string_lossy().to_string();
+        assert!(String::from_utf8_lossy(&output.stdout).contains(&name));
+
+        let path = group.path.clone();
+        let usage = group.usage();
+        assert!(usage.peak_pids <= 64);
+        drop(group);
+        assert!(!path.exists());
+    }
+}
@@ -1,11 +1,11 @@
 //! Configuration for Polyglot-AI Local
 
 use std::path::PathBuf;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use serde::{Deserialize, Serialize};
-use polyglot_common::{Tool, RotationStrategy};
+use polyglot_common::{Tool, RotationStrategy, SummarizerConfig, RedactionConfig};
 use crate::plugins::PluginConfig;
-use crate::sandbox::NetworkPolicy;
+use crate::sandbox::{NetworkPolicy, SeccompProfile};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LocalConfig {
@@ -26,6 +26,27 @@ pub struct LocalConfig {
 
     #[serde(default)]
     pub drive: DriveConfig,
+
+    #[serde(default)]
+    pub summarizer: SummarizerConfig,
+
+    #[serde(default)]
+    pub snapshots: SnapshotConfig,
+
+    #[serde(default)]
+    pub repo_map: RepoMapConfig,
+
+    #[serde(default)]
+    pub history: HistoryConfig,
+
+    #[serde(default)]
+    pub redaction: RedactionConfig,
+
+    #[serde(default)]
+    pub pipelines: BTreeMap<String, PipelineConfig>,
+
+    #[serde(default)]
+    pub judge: JudgeConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -86,6 +107,34 @@ pub struct ToolConfig {
 
     #[serde(default)]
     pub use_isolated: bool,
+
+    /// Default model; passed as `--model` to CLIs that support it, or requested from Ollama
+    #[serde(default)]
+    pub model: Option<String>,
+
+    /// Ollama base URLs, tried in order until one answers
+    #[serde(default)]
+    pub hosts: Vec<String>,
+
+    /// Tokens of conversation context added to each prompt; defaults per tool
+    #[serde(default)]
+    pub context_budget: Option<u32>,
+
+    /// Syscall filter: "off", "baseline", "strict", or "log" to only log what
+    /// "strict" would deny
+    #[serde(default = "default_seccomp")]
+    pub seccomp: String,
+
+    /// Run under a pseudo-terminal instead of pipes, for CLIs that only
+    /// behave interactively on a TTY
+    #[serde(default)]
+    pub pty: bool,
+}
+
+impl ToolConfig {
+    pub fn get_seccomp_profile(&self) -> anyhow::Result<SeccompProfile> {
+        self.seccomp.parse()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -123,11 +172,34 @@ pub struct SandboxConfig {
     #[serde(default = "default_max_cpu")]
     pub max_cpu_percent: Option<u8>,
 
+    /// "allow_all", "localhost" or "deny". The latter two run tools in their
+    /// own network namespace on Linux and refuse to run them elsewhere.
     #[serde(default = "default_network_policy")]
     pub network_access: String,
 
     #[serde(default = "default_env_whitelist")]
     pub env_whitelist: HashSet<String>,
+
+    /// Run each tool in its own cgroup v2 group, falling back to rlimits
+    /// when cgroups are not delegated to this user
+    #[serde(default)]
+    pub cgroups: bool,
+
+    /// Cgroup under which tool groups are created; defaults to polyglot's own.
+    /// It must be delegated and have no processes of its own.
+    #[serde(default)]
+    pub cgroup_parent: Option<PathBuf>,
+
+    #[serde(default = "default_max_pids")]
+    pub max_pids: Option<u32>,
+
+    /// Read and write limit, in MB/s, on the disk holding the working directory
+    #[serde(default)]
+    pub max_io_mbps: Option<u64>,
+
+    /// Confine tools to the allowed paths with Landlock, where the kernel supports it
+    #[serde(default = "default_true")]
+    pub landlock: bool,
 }
 
 fn default_tools_dir() -> std::path::PathBuf {
@@ -150,6 +222,14 @@ fn default_max_cpu() -> Option<u8> {
     Some(80)
 }
 
+fn default_seccomp() -> String {
+    "off".to_string()
+}
+
+fn default_max_pids() -> Option<u32> {
+    Some(1024)
+}
+
 fn default_network_policy() -> String {
     "allow_all".to_string()
 }
@@ -189,6 +269,11 @@ impl Default for SandboxConfig {
             max_cpu_percent: default_max_cpu(),
             network_access: default_network_policy(),
             env_whitelist: default_env_whitelist(),
+            cgroups: false,
+            cgroup_parent: None,
+            max_pids: default_max_pids(),
+            max_io_mbps: None,
+            landlock: true,
         }
     }
 }
@@ -203,6 +288,136 @@ impl SandboxConfig {
     }
 }
 
+/// Working tree snapshots taken before each prompt, for `/undo`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Larger files are tracked but not copied, so edits to them can't be undone
+    #[serde(default = "default_snapshot_max_file_kb")]
+    pub max_file_kb: u64,
+
+    /// Working directories with more files than this are not snapshotted
+    #[serde(default = "default_snapshot_max_files")]
+    pub max_files: usize,
+
+    /// Runs that can still be undone, across all projects
+    #[serde(default = "default_snapshot_keep")]
+    pub keep: usize,
+}
+
+fn default_snapshot_max_file_kb() -> u64 {
+    1024
+}
+
+fn default_snapshot_max_files() -> usize {
+    20_000
+}
+
+fn default_snapshot_keep() -> usize {
+    20
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_file_kb: default_snapshot_max_file_kb(),
+            max_files: default_snapshot_max_files(),
+            keep: default_snapshot_keep(),
+        }
+    }
+}
+
+/// The repository map sent with `/map` and `ask --repo-map`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoMapConfig {
+    /// Tokens the map may take up; deeper parts of the tree are left out first
+    #[serde(default = "default_repo_map_token_budget")]
+    pub token_budget: u32,
+}
+
+fn default_repo_map_token_budget() -> u32 {
+    1500
+}
+
+impl Default for RepoMapConfig {
+    fn default() -> Self {
+        Self {
+            token_budget: default_repo_map_token_budget(),
+        }
+    }
+}
+
+/// How chat history is stored
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryConfig {
+    /// Encrypt sessions at rest, unlocked with a passphrase or `key_file`
+    #[serde(default)]
+    pub encrypt: bool,
+
+    /// File holding the key, created on first use; without one a
+    /// passphrase is asked for
+    #[serde(default)]
+    pub key_file: Option<PathBuf>,
+}
+
+/// A tool that compares the answers to a multi-model prompt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JudgeConfig {
+    /// Tool that judges; without one, answers are only shown side by side
+    #[serde(default)]
+    pub tool: Option<Tool>,
+
+    #[serde(default)]
+    pub model: Option<String>,
+
+    /// "rank" orders the answers best first; "merge" combines them into one
+    #[serde(default = "default_judge_mode")]
+    pub mode: String,
+}
+
+fn default_judge_mode() -> String {
+    "rank".to_string()
+}
+
+impl Default for JudgeConfig {
+    fn default() -> Self {
+        Self {
+            tool: None,
+            model: None,
+            mode: default_judge_mode(),
+        }
+    }
+}
+
+/// A named chain of tools run by `/pipe` and `polyglot-local pipe`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineConfig {
+    /// Steps as `name: tool`, joined by `->`
+    pub steps: String,
+
+    /// Prompt templates by step name. `{input}` is the prompt the pipeline
+    /// was started with, `{previous}` the last good step's output and
+    /// `{<step>}` the output of an earlier step.
+    #[serde(default)]
+    pub prompts: HashMap<String, String>,
+
+    /// "stop" ends the run at the first failed step; "continue" runs the
+    /// remaining steps on the last good output
+    #[serde(default = "default_on_failure")]
+    pub on_failure: String,
+
+    /// Steps whose failure never stops the run
+    #[serde(default)]
+    pub optional: Vec<String>,
+}
+
+fn default_on_failure() -> String {
+    "stop".to_string()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UiConfig {
     #[serde(default = "default_true")]
@@ -240,6 +455,13 @@ impl Default for LocalConfig {
             sandbox: SandboxConfig::default(),
             plugins: Vec::new(),
             drive: DriveConfig::default(),
+            summarizer: SummarizerConfig::default(),
+            snapshots: SnapshotConfig::default(),
+            repo_map: RepoMapConfig::default(),
+            history: HistoryConfig::default(),
+            redaction: RedactionConfig::default(),
+            pipelines: BTreeMap::new(),
+            judge: JudgeConfig::default(),
         }
     }
 }
@@ -265,6 +487,11 @@ impl Default for ToolsConfig {
                 args: vec![],
                 env: vec![],
                 use_isolated: true,
+                model: None,
+                hosts: vec![],
+                context_budget: None,
+                seccomp: default_seccomp(),
+                pty: false,
             }),
             gemini: Some(ToolConfig {
                 enabled: true,
@@ -272,6 +499,11 @@ impl Default for ToolsConfig {
                 args: vec![],
                 env: vec![],
                 use_isolated: true,
+                model: None,
+                hosts: vec![],
+                context_budget: None,
+                seccomp: default_seccomp(),
+                pty: false,
             }),
             codex: Some(ToolConfig {
                 enabled: true,
@@ -279,6 +511,11 @@ impl Default for ToolsConfig {
                 args: vec![],
                 env: vec![],
                 use_isolated: true,
+                model: None,
+                hosts: vec![],
+                context_budget: None,
+                seccomp: default_seccomp(),
+                pty: false,
             }),
             copilot: Some(ToolConfig {
                 enabled: true,
@@ -286,6 +523,11 @@ impl Default for ToolsConfig {
                 args: vec!["copilot".to_string()],
                 env: vec![],
                 use_isolated: true,
+                model: None,
+                hosts: vec![],
+                context_budget: None,
+                seccomp: default_seccomp(),
+                pty: false,
             }),
             perplexity: Some(ToolConfig {
                 enabled: true,
@@ -293,6 +535,11 @@ impl Default for ToolsConfig {
                 args: vec![],
                 env: vec![],
                 use_isolated: true,
+                model: None,
+                hosts: vec![],
+                context_budget: None,
+                seccomp: default_seccomp(),
+                pty: false,
             }),
             cursor: Some(ToolConfig {
                 enabled: true,
@@ -300,6 +547,11 @@ impl Default for ToolsConfig {
                 args: vec![],
                 env: vec![],
                 use_isolated: true,
+                model: None,
+                hosts: vec![],
+                context_budget: None,
+                seccomp: default_seccomp(),
+                pty: false,
             }),
             ollama: Some(ToolConfig {
                 enabled: true,
@@ -307,6 +559,11 @@ impl Default for ToolsConfig {
                 args: vec![],
                 env: vec![],
                 use_isolated: true,
+                model: None,
+                hosts: vec![],
+                context_budget: None,
+                seccomp: default_seccomp(),
+                pty: false,
             }),
         }
     }
@@ -333,9 +590,32 @@ impl LocalConfig {
     pub fn load(path: &PathBuf) -> anyhow::Result<Self> {
         let content = std::fs::read_to_string(path)?;
         let config: Self = toml::from_str(&content)?;
+        config.validate()?;
         Ok(config)
     }
 
+    /// Rejects settings that would otherwise quietly fall back to a default,
+    /// such as a misspelled seccomp profile turning the filter off.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        let tools = &self.tools;
+        for (name, tool) in [
+            ("claude", &tools.claude),
+            ("gemini", &tools.gemini),
+            ("codex", &tools.codex),
+            ("copilot", &tools.copilot),
+            ("perplexity", &tools.perplexity),
+            ("cursor", &tools.cursor),
+            ("ollama", &tools.ollama),
+        ] {
+            if let Some(tool) = tool {
+                tool.get_seccomp_profile().with_context(|| format!("Invalid [tools.{}] settings", name))?;
+            }
+        }
+        Ok(())
+    }
+
     #[allow(dead_code)]
     pub fn save(&self, path: &PathBuf) -> anyhow::Result<()> {
         if let Some(parent) = path.parent() {
@@ -364,11 +644,23 @@ switch_delay = 3
 enabled = true
 path = "claude"
 args = []
+# Default model, overridable per chat with /model
+# model = "sonnet"
+# Tokens of earlier conversation added to prompts when the tool can't resume
+# its own session (see /context)
+# context_budget = 8000
+# Syscall filter: "off" (the default), "baseline", "strict", or "log" to
+# record what "strict" would block (see the kernel audit log) before enforcing it
+# seccomp = "baseline"
+# Run under a pseudo-terminal so the CLI sees a TTY; in the TUI, Enter then
+# types into the tool while it runs
+# pty = false
 
 [tools.gemini]
 enabled = true
 path = "gemini"
 args = []
+# model = "gemini-2.5-pro"
 
 [tools.codex]
 enabled = true
@@ -394,6 +686,15 @@ enabled = true
 # Unix/Mac: runs directly
 path = "cursor-agent"
 args = []
+
+# Ollama is reached over its HTTP API rather than the CLI.
+# Without a model, the first model installed on the host is used.
+[tools.ollama]
+enabled = true
+# model = "codellama"
+# Several Ollama boxes can be listed; the first one that answers is used
+hosts = ["http://localhost:11434"]
+
 [ui]
 # Enable terminal UI (set to false for simple CLI mode)
 tui_enabled = true
@@ -411,6 +712,77 @@ remote = ""
 # Local path to sync (defaults to the history folder)
 path = "~/.local/share/polyglot-ai/history"
 
+[summarizer]
+# Summarize long sessions once they pass this many tokens
+summarize_threshold = 4000
+# Recent messages kept verbatim rather than summarized
+keep_recent_messages = 4
+# Tool that writes the summaries; without one a quick heuristic is used
+# tool = "ollama"
+# model = "llama3.2"
+
+[snapshots]
+# Snapshot the working directory before each prompt so /undo can restore it
+enabled = true
+# Larger files are tracked but not copied, so edits to them can't be undone
+max_file_kb = 1024
+# Skip snapshots in directories with more files than this
+max_files = 20000
+# Runs that can still be undone. Snapshots hold full copies of files; they
+# are encrypted along with history when [history] encrypt is on
+keep = 20
+
+[repo_map]
+# Tokens the repository map may use when sent with /map or ask --repo-map
+token_budget = 1500
+
+[history]
+# Encrypt chat history at rest; it is unlocked with a passphrase at start
+encrypt = false
+# Use a key file instead of a passphrase (created if missing)
+# key_file = "~/.config/polyglot-ai/history.key"
+
+[redaction]
+# Replace secrets in prompts and saved history with [REDACTED:<kind>];
+# streamed tool output is shown as is
+enabled = true
+# Refuse to send prompts that contain secrets instead of redacting them
+block_prompts = false
+# Built-in detectors to skip: private_key, jwt, github_token, aws_access_key,
+# aws_secret_key, env_secret
+disabled = []
+# Detectors that also match ordinary code, off unless listed: email,
+# high_entropy (long random-looking strings)
+opt_in = []
+# Extra patterns; a (?P<secret>...) group limits what is replaced
+# [[redaction.patterns]]
+# name = "internal_token"
+# regex = "itk_[A-Za-z0-9]{32}"
+
+[judge]
+# After a multi-model prompt, this tool compares the answers in an extra
+# pane; /judge changes it for the session
+# tool = "claude"
+# model = "opus"
+# "rank" orders the answers best first; "merge" writes one combined answer
+mode = "rank"
+
+# Pipelines chain tools, templating each step's output into the next
+# step's prompt. Run one with /pipe <name> <prompt> or `polyglot-local pipe`.
+# [pipelines.ship]
+# steps = "plan: claude -> implement: codex -> review: gemini"
+# A step fails on an error, a rate limit or an empty reply; "stop" ends the
+# run there and "continue" carries on with the last good output
+# on_failure = "stop"
+# Steps that may fail without stopping the run
+# optional = ["review"]
+# {input} is your prompt, {previous} the last good step's output and
+# {<step>} the output of an earlier step. Steps without a template get your
+# prompt followed by the previous output.
+# [pipelines.ship.prompts]
+# implement = "Implement this plan:\n\n{plan}\n\nTask: {input}"
+# review = "Review the changes just made for this task: {input}\n\nPlan:\n{plan}"
+
 # Custom Plugins
 # ===============
 # Plugins allow you to add custom AI tools without modifying code.
@@ -0,0 +1,194 @@
+//! Line-based unified diffs for the change review view
+
+/// Lines of unchanged text shown around each change
+const CONTEXT_LINES: usize = 3;
+
+/// Above this many line pairs, the changed middle of a file is shown as
+/// removed and re-added rather than aligned line by line
+const MAX_ALIGNED_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// A unified diff from `old` to `new`, or an empty string if they match.
+/// `None` stands for a file that doesn't exist on that side.
+pub fn unified_diff(path: &str, old: Option<&[u8]>, new: Option<&[u8]>) -> String {
+    let (old_name, new_name) = match (old, new) {
+        (None, _) => ("/dev/null".to_string(), format!("b/{}", path)),
+        (_, None) => (format!("a/{}", path), "/dev/null".to_string()),
+        _ => (format!("a/{}", path), format!("b/{}", path)),
+    };
+    let header = format!("--- {}\n+++ {}\n", old_name, new_name);
+
+    let (Some(old), Some(new)) = (text(old.unwrap_or_default()), text(new.unwrap_or_default())) else {
+        return if old == new {
+            String::new()
+        } else {
+            format!("{}Binary files differ\n", header)
+        };
+    };
+
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+    if ops.iter().all(|op| matches!(op, Op::Equal(..))) {
+        return String::new();
+    }
+
+    let mut out = header;
+    for hunk in hunks(&ops) {
+        let ops = &ops[hunk.clone()];
+        let (old_start, old_len) = span(ops, |op| match op {
+            Op::Equal(i, _) | Op::Delete(i) => Some(*i),
+            Op::Insert(_) => None,
+        });
+        let (new_start, new_len) = span(ops, |op| match op {
+            Op::Equal(_, j) | Op::Insert(j) => Some(*j),
+            Op::Delete(_) => None,
+        });
+        out.push_str(&format!("@@ -{},{} +{},{} @@\n", old_start, old_len, new_start, new_len));
+
+        for op in ops {
+            match op {
+                Op::Equal(i, _) => out.push_str(&format!(" {}\n", old_lines[*i])),
+                Op::Delete(i) => out.push_str(&format!("-{}\n", old_lines[*i])),
+                Op::Insert(j) => out.push_str(&format!("+{}\n", new_lines[*j])),
+            }
+        }
+    }
+    out
+}
+
+/// The content as text, unless it looks binary.
+fn text(content: &[u8]) -> Option<&str> {
+    if content.contains(&0) {
+        return None;
+    }
+    std::str::from_utf8(content).ok()
+}
+
+/// Edit script turning `old` into `new`, from a longest common subsequence.
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Op> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut ops: Vec<Op> = (0..prefix).map(|i| Op::Equal(i, i)).collect();
+
+    if old_mid.len().saturating_mul(new_mid.len()) > MAX_ALIGNED_CELLS {
+        ops.extend((0..old_mid.len()).map(|i| Op::Delete(prefix + i)));
+        ops.extend((0..new_mid.len()).map(|j| Op::Insert(prefix + j)));
+    } else {
+        // lcs[i][j] is the LCS length of old_mid[i..] and new_mid[j..].
+        let width = new_mid.len() + 1;
+        let mut lcs = vec![0u32; (old_mid.len() + 1) * width];
+        for i in (0..old_mid.len()).rev() {
+            for j in (0..new_mid.len()).rev() {
+                lcs[i * width + j] = if old_mid[i] == new_mid[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < old_mid.len() || j < new_mid.len() {
+            if i < old_mid.len() && j < new_mid.len() && old_mid[i] == new_mid[j] {
+                ops.push(Op::Equal(prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if i < old_mid.len() && (j == new_mid.len() || lcs[(i + 1) * width + j] >= lcs[i * width + j + 1]) {
+                ops.push(Op::Delete(prefix + i));
+                i += 1;
+            } else {
+                ops.push(Op::Insert(prefix + j));
+                j += 1;
+            }
+        }
+    }
+
+    let old_offset = old.len() - suffix;
+    let new_offset = new.len() - suffix;
+    ops.extend((0..suffix).map(|k| Op::Equal(old_offset + k, new_offset + k)));
+    ops
+}
+
+/// Ranges of `ops` to print: each change with its surrounding context,
+/// merged where the context overlaps.
+fn hunks(ops: &[Op]) -> Vec<std::ops::Range<usize>> {
+    let mut ranges: Vec<std::ops::Range<usize>> = Vec::new();
+    for (index, op) in ops.iter().enumerate() {
+        if matches!(op, Op::Equal(..)) {
+            continue;
+        }
+        let start = index.saturating_sub(CONTEXT_LINES);
+        let end = (index + CONTEXT_LINES + 1).min(ops.len());
+        match ranges.last_mut() {
+            Some(last) if start <= last.end => last.end = end,
+            _ => ranges.push(start..end),
+        }
+    }
+    ranges
+}
+
+/// The 1-based start line and line count a hunk covers on one side.
+fn span(ops: &[Op], line: impl Fn(&Op) -> Option<usize>) -> (usize, usize) {
+    let lines: Vec<usize> = ops.iter().filter_map(line).collect();
+    match lines.first() {
+        Some(first) => (first + 1, lines.len()),
+        // Only a missing or empty file has no lines in a hunk.
+        None => (0, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff_hunks() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\n";
+
+        let diff = unified_diff("src/lib.rs", Some(old.as_bytes()), Some(new.as_bytes()));
+        assert_eq!(diff, "\
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,5 +1,5 @@
+ a
+-b
++B
+ c
+ d
+ e
+@@ -8,3 +8,4 @@
+ h
+ i
+ j
++k
+");
+        assert!(unified_diff("same.rs", Some(old.as_bytes()), Some(old.as_bytes())).is_empty());
+    }
+
+    #[test]
+    fn test_added_deleted_and_binary_files() {
+        let added = unified_diff("new.txt", None, Some(b"one\ntwo\n"));
+        assert_eq!(added, "--- /dev/null\n+++ b/new.txt\n@@ -0,0 +1,2 @@\n+one\n+two\n");
+
+        let deleted = unified_diff("old.txt", Some(b"gone\n"), None);
+        assert_eq!(deleted, "--- a/old.txt\n+++ /dev/null\n@@ -1,1 +0,0 @@\n-gone\n");
+
+        let binary = unified_diff("logo.png", Some(b"\x89PNG\0\x01"), Some(b"\x89PNG\0\x02"));
+        assert!(binary.ends_with("Binary files differ\n"));
+    }
+}
@@ -0,0 +1,820 @@
+//! SQLite storage for chat history
+//!
+//! Each session is stored whole as JSON, next to the columns the history
+//! views list and filter by. Titles, summaries and every message go into an
+//! FTS5 index, so search covers everything said in a session and ranks the
+//! hits with bm25.
+//!
+//! Encrypted history keeps only AES-GCM sealed sessions on disk. Once
+//! unlocked, they are decrypted into in-memory temp tables that shadow the
+//! on-disk ones, so listing and search work the same in both modes.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, NaiveDate, SecondsFormat, Utc};
+use rusqlite::types::Type;
+use rusqlite::{named_params, params, Connection, OptionalExtension, Row};
+use uuid::Uuid;
+
+use polyglot_common::crypto::{hex_decode, hex_encode};
+use polyglot_common::{ApiKeyManager, ChatSession, HistoryEntry, Tool};
+
+/// Where JSON sessions from earlier versions are moved once imported
+const JSON_BACKUP_DIR: &str = "json-backup";
+
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    project_path TEXT,
+    tool TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    preview TEXT NOT NULL,
+    message_count INTEGER NOT NULL,
+    data TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_sessions_updated ON sessions(updated_at);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS session_text USING fts5(
+    session_id UNINDEXED,
+    kind UNINDEXED,
+    content,
+    tokenize = 'porter unicode61'
+);
+
+CREATE TABLE IF NOT EXISTS sealed_sessions (
+    id TEXT PRIMARY KEY,
+    data BLOB NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS encryption (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    salt BLOB,
+    key_check BLOB NOT NULL
+);
+"#;
+
+/// Plaintext tables for encrypted history, which never leave memory
+const TEMP_SCHEMA: &str = r#"
+PRAGMA temp_store = MEMORY;
+
+CREATE TEMP TABLE sessions (
+    id TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    project_path TEXT,
+    tool TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    preview TEXT NOT NULL,
+    message_count INTEGER NOT NULL,
+    data TEXT NOT NULL,
+    parent_id TEXT
+);
+
+CREATE INDEX temp.idx_sessions_updated ON sessions(updated_at);
+
+CREATE VIRTUAL TABLE temp.session_text USING fts5(
+    session_id UNINDEXED,
+    kind UNINDEXED,
+    content,
+    tokenize = 'porter unicode61'
+);
+"#;
+
+/// Sealed with the key so a wrong key can be told apart from damaged data
+const KEY_CHECK: &str = "polyglot-ai history";
+
+/// Changes to `SCHEMA`, applied in order to older databases. The number
+/// applied is kept in `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &[
+    "ALTER TABLE sessions ADD COLUMN parent_id TEXT",
+];
+
+const ENTRY_COLUMNS: &str =
+    "s.id, s.title, s.project_path, s.tool, s.created_at, s.updated_at, s.preview, s.message_count, s.parent_id";
+
+/// Narrows a history search
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistoryFilter {
+    pub tool: Option<Tool>,
+    /// Matches sessions whose project path contains this
+    pub project: Option<String>,
+    /// Sessions still active at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Sessions started before this time
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl HistoryFilter {
+    /// Splits `tool:`, `project:`, `since:` and `until:` terms off a search
+    /// query, returning the remaining text. Dates are `YYYY-MM-DD`, and
+    /// `until` includes the whole day.
+    pub fn parse(query: &str) -> Result<(String, Self)> {
+        let mut filter = Self::default();
+        let mut words = Vec::new();
+
+        for word in query.split_whitespace() {
+            match word.split_once(':') {
+                Some(("tool", value)) => {
+                    filter.tool = Some(value.parse().map_err(|e: String| anyhow!(e))?);
+                }
+                Some(("project", value)) => filter.project = Some(value.to_string()),
+                Some(("since", value)) => filter.since = Some(parse_day(value)?),
+                Some(("until", value)) => filter.until = Some(parse_day(value)? + Duration::days(1)),
+                _ => words.push(word),
+            }
+        }
+
+        Ok((words.join(" "), filter))
+    }
+}
+
+fn parse_day(value: &str) -> Result<DateTime<Utc>> {
+    let day = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .with_context(|| format!("Invalid date '{}', expected YYYY-MM-DD", value))?;
+    Ok(day.and_hms_opt(0, 0, 0).expect("midnight is a valid time").and_utc())
+}
+
+/// What encrypted history is unlocked with
+pub enum HistoryKey {
+    /// Stretched with a random salt stored alongside the history
+    Passphrase(String),
+    /// Read from a key file
+    Raw([u8; 32]),
+}
+
+impl HistoryKey {
+    /// Reads the hex key in `path`, creating it with a random key if missing.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Self::write_file(path);
+        }
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read history key file {}", path.display()))?;
+        let key = hex_decode(text.trim())
+            .ok()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .ok_or_else(|| anyhow!("History key file {} should hold 64 hex digits", path.display()))?;
+        Ok(Self::Raw(key))
+    }
+
+    /// Writes a new random key to `path`, readable only by its owner.
+    pub fn write_file(path: &Path) -> Result<Self> {
+        let key = ApiKeyManager::generate_key();
+        let hex = hex_encode(&key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, hex + "\n")
+            .with_context(|| format!("Failed to write history key file {}", path.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(Self::Raw(key))
+    }
+
+    fn cipher(&self, salt: Option<&[u8]>) -> ApiKeyManager {
+        match self {
+            Self::Passphrase(passphrase) => ApiKeyManager::with_salt(passphrase, salt.unwrap_or_default()),
+            Self::Raw(key) => ApiKeyManager::from_key(*key),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Wrong history passphrase or key")]
+pub struct WrongKey;
+
+/// A session found by a search
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub entry: HistoryEntry,
+    /// The best matching passage, with matched words in [brackets]
+    pub snippet: String,
+}
+
+pub struct HistoryDb {
+    conn: Connection,
+    /// Set while encrypted history is unlocked
+    sealer: Option<ApiKeyManager>,
+}
+
+impl HistoryDb {
+    /// Opens the history at `path`. With a key, history is encrypted, and
+    /// sessions stored in plaintext before are encrypted now.
+    pub fn open(path: &Path, key: Option<&HistoryKey>) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open history database {}", path.display()))?;
+        conn.execute_batch(SCHEMA)
+            .context("Failed to create the history tables")?;
+
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for migration in MIGRATIONS.iter().skip(version) {
+            conn.execute_batch(migration)
+                .context("Failed to upgrade the history database")?;
+        }
+        conn.pragma_update(None, "user_version", MIGRATIONS.len())?;
+
+        let stored: Option<(Option<Vec<u8>>, Vec<u8>)> = conn
+            .query_row("SELECT salt, key_check FROM encryption WHERE id = 1", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()?;
+
+        let mut db = Self { conn, sealer: None };
+        let Some(key) = key else {
+            if stored.is_some() {
+                return Err(anyhow!("History is encrypted; set encrypt = true under [history] to unlock it"));
+            }
+            return Ok(db);
+        };
+
+        // Overwrite deleted rows, so removed plaintext doesn't linger in the file.
+        db.conn.pragma_update(None, "secure_delete", true)?;
+        match stored {
+            Some((salt, check)) => {
+                let sealer = key.cipher(salt.as_deref());
+                if sealer.decrypt(&check).ok().as_deref() != Some(KEY_CHECK) {
+                    return Err(WrongKey.into());
+                }
+                db.sealer = Some(sealer);
+            }
+            None => {
+                db.sealer = Some(db.store_key(key)?);
+                db.seal_plaintext()?;
+            }
+        }
+        db.unseal()?;
+        Ok(db)
+    }
+
+    /// The cipher sessions are sealed with, while the history is unlocked.
+    pub fn cipher(&self) -> Option<&ApiKeyManager> {
+        self.sealer.as_ref()
+    }
+
+    /// Whether the history at `path` is encrypted
+    pub fn is_encrypted(path: &Path) -> Result<bool> {
+        if !path.exists() {
+            return Ok(false);
+        }
+        let conn = Connection::open(path)?;
+        let has_table: bool = conn.query_row(
+            "SELECT count(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'encryption'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_table {
+            return Ok(false);
+        }
+        Ok(conn.query_row("SELECT count(*) > 0 FROM encryption", [], |row| row.get(0))?)
+    }
+
+    /// Re-encrypts every session with `key`.
+    pub fn rekey(&mut self, key: &HistoryKey) -> Result<()> {
+        if self.sealer.is_none() {
+            return Err(anyhow!("History isn't encrypted; set encrypt = true under [history] first"));
+        }
+        // Sessions skipped at unlock would stay sealed with the old key.
+        let unreadable: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM main.sealed_sessions WHERE id NOT IN (SELECT id FROM temp.sessions)",
+            [],
+            |row| row.get(0),
+        )?;
+        if unreadable > 0 {
+            return Err(anyhow!(
+                "{} history sessions couldn't be decrypted, and a new key would lose them for good; not re-keying",
+                unreadable
+            ));
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        let sealer = self.store_key(key)?;
+        {
+            let mut sessions = tx.prepare("SELECT id, data FROM temp.sessions")?;
+            let mut rows = sessions.query([])?;
+            while let Some(row) = rows.next()? {
+                let (id, data): (String, String) = (row.get(0)?, row.get(1)?);
+                tx.execute(
+                    "INSERT OR REPLACE INTO main.sealed_sessions (id, data) VALUES (?1, ?2)",
+                    params![id, sealer.encrypt(&data)?],
+                )?;
+            }
+        }
+        tx.commit()?;
+        self.sealer = Some(sealer);
+        Ok(())
+    }
+
+    /// Saves a fresh salt and key check for `key`, returning its cipher.
+    fn store_key(&self, key: &HistoryKey) -> Result<ApiKeyManager> {
+        let salt = match key {
+            HistoryKey::Passphrase(_) => Some(ApiKeyManager::generate_key().to_vec()),
+            HistoryKey::Raw(_) => None,
+        };
+        let sealer = key.cipher(salt.as_deref());
+        self.conn.execute(
+            "INSERT OR REPLACE INTO encryption (id, salt, key_check) VALUES (1, ?1, ?2)",
+            params![salt, sealer.encrypt(KEY_CHECK)?],
+        )?;
+        Ok(sealer)
+    }
+
+    /// Encrypts sessions stored before encryption was turned on.
+    fn seal_plaintext(&self) -> Result<()> {
+        let sealer = self.sealer.as_ref().expect("called once a key is set");
+        let rows: Vec<(String, String)> = {
+            let mut stmt = self.conn.prepare("SELECT id, data FROM main.sessions")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            rows
+        };
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        for (id, data) in &rows {
+            tx.execute(
+                "INSERT OR REPLACE INTO main.sealed_sessions (id, data) VALUES (?1, ?2)",
+                params![id, sealer.encrypt(data)?],
+            )?;
+        }
+        // A rebuild drops the old terms, which deletes alone leave in the index.
+        tx.execute_batch(
+            "DELETE FROM main.sessions; DELETE FROM main.session_text;
+             INSERT INTO main.session_text(session_text) VALUES ('rebuild');",
+        )?;
+        tx.commit()?;
+        // Rebuilds the file, so no free page still holds the plaintext.
+        self.conn.execute_batch("VACUUM")?;
+        Ok(())
+    }
+
+    /// Decrypts the sealed sessions into the in-memory tables.
+    fn unseal(&self) -> Result<()> {
+        let sealer = self.sealer.as_ref().expect("called once a key is set");
+        self.conn.execute_batch(TEMP_SCHEMA)?;
+
+        let mut stmt = self.conn.prepare("SELECT id, data FROM main.sealed_sessions")?;
+        let mut rows = stmt.query([])?;
+        let tx = self.conn.unchecked_transaction()?;
+        while let Some(row) = rows.next()? {
+            let (id, sealed): (String, Vec<u8>) = (row.get(0)?, row.get(1)?);
+            let session = sealer.decrypt(&sealed)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| Ok((serde_json::from_str::<ChatSession>(&data)?, data)));
+            match session {
+                Ok((session, data)) => write_session(&tx, &session, &data)?,
+                Err(e) => tracing::warn!("Skipping history session {}: {}", id, e),
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Stores `session`, replacing any earlier copy, and reindexes its text.
+    pub fn save(&self, session: &ChatSession) -> Result<()> {
+        let data = serde_json::to_string(session).context("Failed to serialize session")?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        write_session(&tx, session, &data)?;
+        if let Some(ref sealer) = self.sealer {
+            tx.execute(
+                "INSERT OR REPLACE INTO main.sealed_sessions (id, data) VALUES (?1, ?2)",
+                params![session.id.to_string(), sealer.encrypt(&data)?],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn load(&self, session_id: Uuid) -> Result<Option<ChatSession>> {
+        let data: Option<String> = self.conn
+            .query_row(
+                "SELECT data FROM sessions WHERE id = ?1",
+                params![session_id.to_string()],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        data.map(|d| serde_json::from_str(&d).context("Failed to parse session"))
+            .transpose()
+    }
+
+    pub fn contains(&self, session_id: Uuid) -> Result<bool> {
+        Ok(self.conn
+            .query_row(
+                "SELECT 1 FROM sessions WHERE id = ?1",
+                params![session_id.to_string()],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some())
+    }
+
+    /// Every session, most recently updated first
+    pub fn entries(&self) -> Result<Vec<HistoryEntry>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM sessions s ORDER BY s.updated_at DESC",
+            ENTRY_COLUMNS
+        ))?;
+        let entries = stmt.query_map([], entry_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(entries)
+    }
+
+    /// Sessions matching `text` and `filter`, best match first. With no
+    /// text, every session the filter allows, most recent first.
+    pub fn search(&self, text: &str, filter: &HistoryFilter, limit: usize) -> Result<Vec<SearchHit>> {
+        let tool = filter.tool.map(|t| t.as_str());
+        let since = filter.since.map(timestamp);
+        let until = filter.until.map(timestamp);
+        let conditions = "(:tool IS NULL OR s.tool = :tool)
+              AND (:project IS NULL OR instr(s.project_path, :project) > 0)
+              AND (:since IS NULL OR s.updated_at >= :since)
+              AND (:until IS NULL OR s.created_at < :until)";
+
+        let query = fts_query(text);
+        if query.is_empty() {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT {} FROM sessions s
+                 WHERE {}
+                 ORDER BY s.updated_at DESC
+                 LIMIT :limit",
+                ENTRY_COLUMNS, conditions
+            ))?;
+            let hits = stmt
+                .query_map(
+                    named_params! {
+                        ":tool": tool,
+                        ":project": filter.project,
+                        ":since": since,
+                        ":until": until,
+                        ":limit": limit as i64,
+                    },
+                    |row| {
+                        let entry = entry_from_row(row)?;
+                        Ok(SearchHit { snippet: entry.preview.clone(), entry })
+                    },
+                )?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            return Ok(hits);
+        }
+
+        // One row per matching message; keep each session's best.
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {}, snippet(session_text, 2, '[', ']', '...', 12)
+             FROM session_text JOIN sessions s ON s.id = session_text.session_id
+             WHERE session_text MATCH :query AND {}
+             ORDER BY bm25(session_text)",
+            ENTRY_COLUMNS, conditions
+        ))?;
+        let mut rows = stmt.query(named_params! {
+            ":query": query,
+            ":tool": tool,
+            ":project": filter.project,
+            ":since": since,
+            ":until": until,
+        })?;
+
+        let mut seen = HashSet::new();
+        let mut hits = Vec::new();
+        while hits.len() < limit {
+            let Some(row) = rows.next()? else { break };
+            let entry = entry_from_row(row)?;
+            if seen.insert(entry.session_id) {
+                hits.push(SearchHit { entry, snippet: row.get(9)? });
+            }
+        }
+        Ok(hits)
+    }
+
+    pub fn delete(&self, session_id: Uuid) -> Result<()> {
+        let id = session_id.to_string();
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM sessions WHERE id = ?1", params![id])?;
+        tx.execute("DELETE FROM session_text WHERE session_id = ?1", params![id])?;
+        tx.execute("DELETE FROM main.sealed_sessions WHERE id = ?1", params![id])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "DELETE FROM sessions; DELETE FROM session_text; DELETE FROM main.sealed_sessions;",
+        )?;
+        Ok(())
+    }
+
+    /// Deletes all but the `keep` most recently updated sessions.
+    pub fn prune(&self, keep: usize) -> Result<usize> {
+        let old: Vec<String> = {
+            let mut stmt = self.conn.prepare(
+                "SELECT id FROM sessions ORDER BY updated_at DESC LIMIT -1 OFFSET ?1",
+            )?;
+            let ids = stmt.query_map(params![keep as i64], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            ids
+        };
+
+        let tx = self.conn.unchecked_transaction()?;
+        for id in &old {
+            tx.execute("DELETE FROM sessions WHERE id = ?1", params![id])?;
+            tx.execute("DELETE FROM session_text WHERE session_id = ?1", params![id])?;
+            tx.execute("DELETE FROM main.sealed_sessions WHERE id = ?1", params![id])?;
+        }
+        tx.commit()?;
+        Ok(old.len())
+    }
+
+    /// Imports the one-file-per-session JSON history of earlier versions
+    /// from `dir`, moving the files into `json-backup/` once stored.
+    /// Sessions already in the database are kept as they are. Encrypted
+    /// history deletes the files instead, as the backup would be plaintext.
+    pub fn migrate_json(&self, dir: &Path) -> Result<usize> {
+        let mut files: Vec<_> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_file() && path.extension().is_some_and(|e| e == "json"))
+            .collect();
+        if files.is_empty() {
+            return Ok(0);
+        }
+        files.sort();
+
+        let backup = dir.join(JSON_BACKUP_DIR);
+        if self.sealer.is_none() {
+            fs::create_dir_all(&backup).context("Failed to create the JSON backup directory")?;
+        }
+
+        let mut imported = 0;
+        for path in files {
+            let is_index = path.file_name().is_some_and(|n| n == "index.json");
+            if !is_index {
+                let session = fs::read_to_string(&path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|s| serde_json::from_str::<ChatSession>(&s).map_err(anyhow::Error::from));
+                match session {
+                    Ok(session) => {
+                        if !self.contains(session.id)? {
+                            self.save(&session)?;
+                            imported += 1;
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!("Skipping history file {}: {}", path.display(), e);
+                        continue;
+                    }
+                }
+            }
+            if self.sealer.is_some() {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            } else if let Some(name) = path.file_name() {
+                fs::rename(&path, backup.join(name))
+                    .with_context(|| format!("Failed to move {} into {}", path.display(), JSON_BACKUP_DIR))?;
+            }
+        }
+        Ok(imported)
+    }
+}
+
+/// Writes a session's row and indexed text. In encrypted history these
+/// land in the in-memory tables.
+fn write_session(conn: &Connection, session: &ChatSession, data: &str) -> Result<()> {
+    let entry = HistoryEntry::from(session);
+    let id = session.id.to_string();
+
+    conn.execute(
+        "INSERT OR REPLACE INTO sessions
+            (id, title, project_path, tool, created_at, updated_at, preview, message_count, parent_id, data)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            id,
+            entry.title,
+            entry.project_path,
+            entry.tool.map(|t| t.as_str()),
+            timestamp(entry.created_at),
+            timestamp(entry.updated_at),
+            entry.preview,
+            entry.message_count,
+            entry.parent_id.map(|p| p.to_string()),
+            data,
+        ],
+    )?;
+
+    conn.execute("DELETE FROM session_text WHERE session_id = ?1", params![id])?;
+    let mut insert = conn.prepare_cached(
+        "INSERT INTO session_text (session_id, kind, content) VALUES (?1, ?2, ?3)",
+    )?;
+    insert.execute(params![id, "title", entry.title])?;
+    if let Some(ref summary) = session.summary {
+        insert.execute(params![id, "summary", summary])?;
+    }
+    for message in &session.messages {
+        let kind = serde_json::to_value(message.role)?;
+        insert.execute(params![id, kind.as_str(), message.content])?;
+    }
+    Ok(())
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    // Fixed precision and a `Z` suffix, so the text sorts by time.
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn entry_from_row(row: &Row) -> rusqlite::Result<HistoryEntry> {
+    let id: String = row.get(0)?;
+    let tool: Option<String> = row.get(3)?;
+    let parent_id: Option<String> = row.get(8)?;
+    Ok(HistoryEntry {
+        session_id: Uuid::parse_str(&id).map_err(|e| conversion_error(0, e))?,
+        title: row.get(1)?,
+        project_path: row.get(2)?,
+        tool: tool.and_then(|t| t.parse().ok()),
+        created_at: parse_timestamp(row, 4)?,
+        updated_at: parse_timestamp(row, 5)?,
+        preview: row.get(6)?,
+        message_count: row.get(7)?,
+        parent_id: parent_id.map(|p| Uuid::parse_str(&p).map_err(|e| conversion_error(8, e))).transpose()?,
+    })
+}
+
+fn parse_timestamp(row: &Row, index: usize) -> rusqlite::Result<DateTime<Utc>> {
+    let text: String = row.get(index)?;
+    DateTime::parse_from_rfc3339(&text)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| conversion_error(index, e))
+}
+
+fn conversion_error(index: usize, e: impl std::error::Error + Send + Sync + 'static) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(e))
+}
+
+/// Quotes each word, so punctuation in a query isn't read as FTS5 syntax.
+/// The last word also matches as a prefix, as it may be half typed.
+fn fts_query(text: &str) -> String {
+    let words: Vec<_> = text.split_whitespace()
+        .map(|w| format!("\"{}\"", w.replace('"', "\"\"")))
+        .collect();
+    match words.split_last() {
+        Some((last, rest)) => {
+            let mut query = rest.join(" ");
+            if !query.is_empty() {
+                query.push(' ');
+            }
+            query.push_str(last);
+            query.push('*');
+            query
+        }
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polyglot_common::Message;
+    use tempfile::tempdir;
+
+    fn session(tool: Tool, project: &str, messages: &[&str]) -> ChatSession {
+        let mut session = ChatSession::new(Some(project.to_string()));
+        session.tool = Some(tool);
+        for (i, text) in messages.iter().enumerate() {
+            if i % 2 == 0 {
+                session.add_message(Message::user(*text));
+            } else {
+                session.add_message(Message::assistant(*text));
+            }
+        }
+        session.auto_title();
+        session
+    }
+
+    #[test]
+    fn test_search_ranks_message_bodies_and_filters() {
+        let dir = tempdir().unwrap();
+        let db = HistoryDb::open(&dir.path().join("history.db"), None).unwrap();
+
+        let borrow = session(Tool::Claude, "/work/api", &[
+            "Why won't this compile?",
+            "The borrow checker rejects the second mutable borrow of `self.cache`.",
+        ]);
+        let tokio = session(Tool::Gemini, "/work/cli", &[
+            "Set up a runtime",
+            "Use #[tokio::main]; a mutable borrow isn't involved here.",
+        ]);
+        db.save(&borrow).unwrap();
+        db.save(&tokio).unwrap();
+
+        let hits = db.search("mutable borrow", &HistoryFilter::default(), 10).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().any(|h| h.snippet.contains("[borrow]")));
+
+        let (text, filter) = HistoryFilter::parse("tool:claude borrow-checker").unwrap();
+        assert_eq!(text, "borrow-checker");
+        let hits = db.search(&text, &filter, 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].entry.session_id, borrow.id);
+
+        let filter = HistoryFilter { project: Some("cli".into()), ..Default::default() };
+        let hits = db.search("", &filter, 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].entry.session_id, tokio.id);
+
+        let (_, filter) = HistoryFilter::parse("until:2000-01-01").unwrap();
+        assert!(db.search("borrow", &filter, 10).unwrap().is_empty());
+
+        // Resaving replaces the indexed text.
+        let mut edited = borrow.clone();
+        edited.messages.truncate(1);
+        db.save(&edited).unwrap();
+        assert_eq!(db.search("checker", &HistoryFilter::default(), 10).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_json_sessions_are_migrated() {
+        let dir = tempdir().unwrap();
+        let old = session(Tool::Codex, "/work", &["Explain lifetimes", "They bound references."]);
+        fs::write(dir.path().join(format!("{}.json", old.id)), serde_json::to_string(&old).unwrap()).unwrap();
+        fs::write(dir.path().join("index.json"), "[]").unwrap();
+        fs::write(dir.path().join("broken.json"), "{").unwrap();
+
+        let db = HistoryDb::open(&dir.path().join("history.db"), None).unwrap();
+        assert_eq!(db.migrate_json(dir.path()).unwrap(), 1);
+        assert_eq!(db.load(old.id).unwrap().unwrap().messages.len(), 2);
+        assert!(dir.path().join(JSON_BACKUP_DIR).join("index.json").exists());
+        // Unreadable files stay where they are.
+        assert!(dir.path().join("broken.json").exists());
+        assert_eq!(db.migrate_json(dir.path()).unwrap(), 0);
+        assert_eq!(db.search("references", &HistoryFilter::default(), 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_encrypted_history_seals_sessions_and_rekeys() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let plain = session(Tool::Claude, "/work", &["Rotate the AWS secret", "Use the console."]);
+        HistoryDb::open(&path, None).unwrap().save(&plain).unwrap();
+
+        let key = HistoryKey::Passphrase("hunter2".into());
+        let mut db = HistoryDb::open(&path, Some(&key)).unwrap();
+        let sealed = session(Tool::Gemini, "/work", &["What about the token?", "Keep it in the vault."]);
+        db.save(&sealed).unwrap();
+        assert_eq!(db.search("secret", &HistoryFilter::default(), 10).unwrap().len(), 1);
+        assert!(HistoryDb::is_encrypted(&path).unwrap());
+        let raw = fs::read(&path).unwrap();
+        assert!(!raw.windows(6).any(|w| w == b"secret" || w == b"consol"));
+
+        let new_key = HistoryKey::Raw(ApiKeyManager::generate_key());
+        db.rekey(&new_key).unwrap();
+        drop(db);
+
+        assert!(HistoryDb::open(&path, None).is_err());
+        let err = HistoryDb::open(&path, Some(&key)).err().unwrap();
+        assert!(err.is::<WrongKey>());
+        let db = HistoryDb::open(&path, Some(&new_key)).unwrap();
+        assert_eq!(db.entries().unwrap().len(), 2);
+        assert_eq!(db.load(plain.id).unwrap().unwrap().messages.len(), 2);
+    }
+
+    #[test]
+    fn test_rekey_refuses_to_drop_unreadable_sessions() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let key = HistoryKey::Raw(ApiKeyManager::generate_key());
+        let db = HistoryDb::open(&path, Some(&key)).unwrap();
+        db.save(&session(Tool::Claude, "/work", &["Hello", "Hi"])).unwrap();
+        db.conn.execute(
+            "INSERT INTO main.sealed_sessions (id, data) VALUES ('damaged', ?1)",
+            params![b"not sealed".to_vec()],
+        ).unwrap();
+        drop(db);
+
+        let mut db = HistoryDb::open(&path, Some(&key)).unwrap();
+        assert_eq!(db.entries().unwrap().len(), 1);
+        assert!(db.rekey(&HistoryKey::Raw(ApiKeyManager::generate_key())).is_err());
+        drop(db);
+        // The old key still opens everything.
+        assert_eq!(HistoryDb::open(&path, Some(&key)).unwrap().entries().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_key_file_is_created_and_read_back() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("keys").join("history.key");
+        let HistoryKey::Raw(created) = HistoryKey::from_file(&path).unwrap() else { panic!() };
+        let HistoryKey::Raw(read) = HistoryKey::from_file(&path).unwrap() else { panic!() };
+        assert_eq!(created, read);
+
+        fs::write(&path, "not hex").unwrap();
+        assert!(HistoryKey::from_file(&path).is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! A judge tool that ranks or merges the answers to a multi-model prompt
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+
+use polyglot_common::Tool;
+use crate::config::JudgeConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JudgeMode {
+    /// Order the answers best first, with reasons
+    Rank,
+    /// Combine the answers into one
+    Merge,
+}
+
+impl FromStr for JudgeMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rank" => Ok(JudgeMode::Rank),
+            "merge" => Ok(JudgeMode::Merge),
+            other => bail!("judge mode must be \"rank\" or \"merge\", not {:?}", other),
+        }
+    }
+}
+
+impl fmt::Display for JudgeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            JudgeMode::Rank => "rank",
+            JudgeMode::Merge => "merge",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Judge {
+    pub tool: Tool,
+    /// Model for the judge; `/model` choices apply without one
+    pub model: Option<String>,
+    pub mode: JudgeMode,
+}
+
+/// One tool's answer, as put before the judge
+pub struct Candidate<'a> {
+    pub tool: Tool,
+    pub model: Option<&'a str>,
+    pub answer: &'a str,
+}
+
+impl Judge {
+    /// The judge under `[judge]`, or `None` without a tool.
+    pub fn from_config(config: &JudgeConfig) -> Result<Option<Self>> {
+        let mode = config.mode.parse()?;
+        Ok(config.tool.map(|tool| Self { tool, model: config.model.clone(), mode }))
+    }
+
+    /// The prompt asking the judge for its verdict on `candidates`, which
+    /// answered `question`.
+    pub fn prompt(&self, question: &str, candidates: &[Candidate]) -> String {
+        let task = match self.mode {
+            JudgeMode::Rank => "Compare them for correctness, completeness and clarity. \
+                Rank them from best to worst as a numbered list, naming each answer's assistant \
+                with a one-line reason, then point out any mistakes in the best one.",
+            JudgeMode::Merge => "Write one answer to the request that keeps what each gets right \
+                and drops their mistakes. Where they disagree, say which side you took and why. \
+                Don't describe the individual answers otherwise.",
+        };
+
+        let mut prompt = format!(
+            "Several AI assistants answered the same request. {}\n\n=== Request ===\n{}\n",
+            task,
+            question.trim()
+        );
+        for candidate in candidates {
+            let name = match candidate.model {
+                Some(model) => format!("{} ({})", candidate.tool.display_name(), model),
+                None => candidate.tool.display_name().to_string(),
+            };
+            prompt.push_str(&format!("\n=== Answer from {} ===\n{}\n", name, candidate.answer.trim()));
+        }
+        prompt
+    }
+
+    /// The verdict as it's kept in chat history.
+    pub fn record(&self, verdict: &str) -> String {
+        let heading = match self.mode {
+            JudgeMode::Rank => "Ranking",
+            JudgeMode::Merge => "Merged answer",
+        };
+        format!("--- {} by {} ---\n{}", heading, self.tool.display_name(), verdict.trim())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_judge_prompt_lists_each_candidate() {
+        let mut config = JudgeConfig::default();
+        assert!(Judge::from_config(&config).unwrap().is_none());
+
+        config.tool = Some(Tool::Claude);
+        config.mode = "merge".to_string();
+        let judge = Judge::from_config(&config).unwrap().unwrap();
+        assert_eq!(judge.mode, JudgeMode::Merge);
+
+        let prompt = judge.prompt("How do I sort a Vec?", &[
+            Candidate { tool: Tool::Gemini, model: Some("gemini-2.5-pro"), answer: "Use sort().\n" },
+            Candidate { tool: Tool::Codex, model: None, answer: "v.sort_unstable()" },
+        ]);
+        assert!(prompt.contains("keeps what each gets right"));
+        assert!(prompt.contains("=== Request ===\nHow do I sort a Vec?\n"));
+        assert!(prompt.contains("=== Answer from Gemini CLI (gemini-2.5-pro) ===\nUse sort().\n"));
+        assert!(prompt.ends_with("=== Answer from Codex CLI ===\nv.sort_unstable()\n"));
+
+        assert_eq!(judge.record("Use sort()\n"), "--- Merged answer by Claude Code ---\nUse sort()");
+
+        config.mode = "vote".to_string();
+        assert!(Judge::from_config(&config).is_err());
+    }
+}
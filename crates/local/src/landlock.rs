@@ -0,0 +1,175 @@
+//! Filesystem confinement for tool processes with Landlock (Linux 5.13+)
+//!
+//! The ruleset is built in the parent, where opening paths and allocating is
+//! safe, and enforced by the child just before exec. Anything not covered by
+//! a rule is off limits, including reads.
+
+use std::fs::OpenOptions;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use tokio::process::Command;
+
+const CREATE_RULESET_VERSION: u32 = 1 << 0;
+const RULE_PATH_BENEATH: libc::c_int = 1;
+
+const ACCESS_EXECUTE: u64 = 1 << 0;
+const ACCESS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_READ_FILE: u64 = 1 << 2;
+const ACCESS_READ_DIR: u64 = 1 << 3;
+/// Everything up to `MAKE_SYM`, the rights of ABI 1
+const ACCESS_ABI_1: u64 = (1 << 13) - 1;
+const ACCESS_REFER: u64 = 1 << 13;
+const ACCESS_TRUNCATE: u64 = 1 << 14;
+const ACCESS_IOCTL_DEV: u64 = 1 << 15;
+
+const READ_ACCESS: u64 = ACCESS_EXECUTE | ACCESS_READ_FILE | ACCESS_READ_DIR;
+/// Rights that apply to a single file; directory rights on a file rule are rejected
+const FILE_ACCESS: u64 =
+    ACCESS_EXECUTE | ACCESS_WRITE_FILE | ACCESS_READ_FILE | ACCESS_TRUNCATE | ACCESS_IOCTL_DEV;
+
+/// Paths every tool needs to read just to start
+const SYSTEM_READ_PATHS: &[&str] = &[
+    "/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/etc", "/opt", "/proc", "/sys", "/nix/store",
+];
+/// Device nodes such as `/dev/null` and `/dev/tty` are opened for writing
+const SYSTEM_WRITE_PATHS: &[&str] = &["/dev"];
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+/// The kernel's Landlock ABI version, or `None` if Landlock is unavailable or disabled.
+pub fn abi_version() -> Option<u32> {
+    let version = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<RulesetAttr>(),
+            0usize,
+            CREATE_RULESET_VERSION,
+        )
+    };
+    (version > 0).then_some(version as u32)
+}
+
+pub struct Ruleset {
+    fd: OwnedFd,
+    handled: u64,
+}
+
+impl Ruleset {
+    /// A ruleset that already allows the system paths tools need to run.
+    pub fn new() -> io::Result<Self> {
+        let abi = abi_version().ok_or_else(|| io::Error::from_raw_os_error(libc::EOPNOTSUPP))?;
+
+        let mut handled = ACCESS_ABI_1;
+        if abi >= 2 {
+            handled |= ACCESS_REFER;
+        }
+        if abi >= 3 {
+            handled |= ACCESS_TRUNCATE;
+        }
+        if abi >= 5 {
+            handled |= ACCESS_IOCTL_DEV;
+        }
+
+        let attr = RulesetAttr { handled_access_fs: handled };
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0u32,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut ruleset = Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd as RawFd) },
+            handled,
+        };
+        for path in SYSTEM_READ_PATHS {
+            ruleset.allow_read(Path::new(path))?;
+        }
+        for path in SYSTEM_WRITE_PATHS {
+            ruleset.allow_write(Path::new(path))?;
+        }
+        Ok(ruleset)
+    }
+
+    pub fn allow_read(&mut self, path: &Path) -> io::Result<()> {
+        self.allow(path, READ_ACCESS)
+    }
+
+    pub fn allow_write(&mut self, path: &Path) -> io::Result<()> {
+        self.allow(path, self.handled)
+    }
+
+    /// Grants `access` beneath `path`. Paths that don't exist are skipped.
+    fn allow(&mut self, path: &Path, access: u64) -> io::Result<()> {
+        let file = match OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_PATH | libc::O_CLOEXEC)
+            .open(path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        let mut allowed = access & self.handled;
+        if !file.metadata()?.is_dir() {
+            allowed &= FILE_ACCESS;
+        }
+
+        let attr = PathBeneathAttr {
+            allowed_access: allowed,
+            parent_fd: file.as_raw_fd(),
+        };
+        let rc = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                self.fd.as_raw_fd(),
+                RULE_PATH_BENEATH,
+                &attr as *const PathBeneathAttr,
+                0u32,
+            )
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Enforces the ruleset in the child right before exec. The ruleset must
+    /// stay alive until the command has been spawned.
+    pub fn apply_to(&self, cmd: &mut Command) {
+        let fd = self.fd.as_raw_fd();
+        unsafe {
+            cmd.pre_exec(move || restrict_self(fd));
+        }
+    }
+}
+
+fn restrict_self(ruleset_fd: RawFd) -> io::Result<()> {
+    unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::syscall(libc::SYS_landlock_restrict_self, ruleset_fd, 0u32) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
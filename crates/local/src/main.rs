@@ -2,25 +2,46 @@ mod config;
 mod tools;
 mod tui;
 mod history;
+mod history_db;
 mod plugins;
 mod environment;
 mod sandbox;
-
-use std::path::PathBuf;
+#[cfg(target_os = "linux")]
+mod cgroup;
+#[cfg(target_os = "linux")]
+mod landlock;
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod seccomp;
+mod ollama;
+mod snapshot;
+mod diff;
+mod pty;
+mod pipeline;
+mod judge;
+mod race;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::io::{self, Write};
+use std::time::Instant;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use tokio::process::Command;
 use tokio::sync::mpsc;
 use tracing::error;
 use tracing_subscriber::EnvFilter;
 
-use polyglot_common::Tool;
+use polyglot_common::{Tool, FileRefLimits, KeyVault, Redactor, RepoMapCache, expand_file_refs, import_sessions};
 use config::LocalConfig;
-use tools::{LocalToolManager, ToolOutput, TaggedOutput};
+use tools::{LocalToolManager, MultiMode, ToolOutput, TaggedOutput};
 use tui::{App, AppAction, OutputType};
-use history::HistoryManager;
+use history::{HistoryManager, SummaryJob};
+use history_db::{HistoryDb, HistoryKey, WrongKey};
+use judge::Judge;
+use pipeline::{Pipeline, PipelineEvent, PipelineRun, StepResult};
+use sandbox::FilesystemConfinement;
+use snapshot::{FileChange, Revert, SnapshotStore};
 
 #[derive(Parser)]
 #[command(name = "polyglot-local")]
@@ -65,8 +86,16 @@ enum Commands {
         #[arg(short, long)]
         tool: Option<String>,
 
+        /// Model to use instead of the tool's configured default
+        #[arg(short, long)]
+        model: Option<String>,
+
         #[arg(long)]
         with_context: bool,
+
+        /// Include a map of the project's files and symbols
+        #[arg(long)]
+        repo_map: bool,
     },
 
     Tools,
@@ -74,11 +103,27 @@ enum Commands {
     Usage,
 
     History {
+        #[command(subcommand)]
+        action: Option<HistoryAction>,
+
         #[arg(short, long, default_value = "10")]
         limit: usize,
 
+        /// Search every message; `project:<name>` narrows to matching projects
         #[arg(short, long)]
         search: Option<String>,
+
+        /// Only sessions with this tool
+        #[arg(long)]
+        tool: Option<String>,
+
+        /// Only sessions active on or after this date (YYYY-MM-DD)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only sessions started on or before this date (YYYY-MM-DD)
+        #[arg(long)]
+        until: Option<String>,
     },
 
     Init {
@@ -97,6 +142,22 @@ enum Commands {
 
     DriveStatus,
 
+    /// Restore the files changed by the last prompt in this directory
+    Undo,
+
+    /// Run a pipeline from [pipelines], or list them without a name
+    Pipe {
+        name: Option<String>,
+
+        prompt: Option<String>,
+    },
+
+    /// Manage the encrypted API keys handed to tools
+    Keys {
+        #[command(subcommand)]
+        action: KeysAction,
+    },
+
     /// Check for updates and optionally install them
     Update {
         /// Just check for updates without installing
@@ -109,6 +170,44 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+enum HistoryAction {
+    /// Import a JSON export, or a Claude Code or Codex session log
+    Import {
+        file: PathBuf,
+    },
+    /// Re-encrypt history with a new passphrase or key file
+    Rekey,
+}
+
+#[derive(Subcommand)]
+enum KeysAction {
+    /// Store a key for a tool; the value is read from the terminal or stdin
+    Add {
+        tool: String,
+
+        /// Variable to set instead of the tool's usual one
+        #[arg(long)]
+        env: Option<String>,
+    },
+    /// List stored keys without their values
+    List,
+    /// Delete a stored key
+    Remove {
+        tool: String,
+
+        #[arg(long)]
+        env: Option<String>,
+    },
+    /// Replace a stored key with a new value
+    Rotate {
+        tool: String,
+
+        #[arg(long)]
+        env: Option<String>,
+    },
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
@@ -133,27 +232,44 @@ async fn main() -> Result<()> {
 
     apply_env_overrides(&mut config, &cli.env);
 
-    let mut history_manager = HistoryManager::new(None)?;
-    if let Some(ref project) = cli.project {
-        history_manager.set_project(Some(project.to_string_lossy().to_string()));
-    } else {
-        if let Ok(cwd) = std::env::current_dir() {
-            history_manager.set_project(Some(cwd.to_string_lossy().to_string()));
-        }
+    // Keys live apart from history, so managing them needn't unlock it.
+    if let Some(Commands::Keys { ref action }) = cli.command {
+        return manage_keys(action);
     }
 
-    let tool_manager = LocalToolManager::new(&config);
+    let redactor = config.redaction.enabled
+        .then(|| Redactor::new(&config.redaction))
+        .transpose()
+        .context("Invalid [redaction] settings")?;
+
+    let cwd = std::env::current_dir()?;
+    let project_dir = match &cli.project {
+        Some(project) => resolve_dir(&cwd, &project.to_string_lossy())?,
+        None => cwd,
+    };
+
+    let mut tool_manager = LocalToolManager::new(&config, &project_dir);
+    tool_manager.set_redactor(redactor.clone(), config.redaction.block_prompts);
+    // Only commands that read history open it, and so ask for its passphrase.
+    let history = || open_project_history(&config, redactor.clone(), &project_dir, &tool_manager);
 
     match cli.command {
         None | Some(Commands::Chat) => {
             if cli.no_tui {
-                run_simple_cli(tool_manager, &config, history_manager).await
+                let history_manager = history()?;
+                run_simple_cli(tool_manager, &config, history_manager, &project_dir).await
             } else {
-                run_tui(tool_manager, &config, history_manager).await
+                let history_manager = history()?;
+                run_tui(tool_manager, &config, history_manager, project_dir).await
             }
         }
-        Some(Commands::Ask { prompt, tool, with_context }) => {
-            run_single_prompt(tool_manager, &prompt, tool, with_context, &mut history_manager).await
+        Some(Commands::Ask { prompt, tool, model, with_context, repo_map }) => {
+            let context = AskContext {
+                history: with_context,
+                repo_map_budget: repo_map.then_some(config.repo_map.token_budget),
+            };
+            let mut history_manager = history()?;
+            run_single_prompt(tool_manager, &prompt, tool, model, context, &mut history_manager, &project_dir).await
         }
         Some(Commands::Tools) => {
             list_tools(tool_manager).await
@@ -161,8 +277,21 @@ async fn main() -> Result<()> {
         Some(Commands::Usage) => {
             show_usage(&tool_manager)
         }
-        Some(Commands::History { limit, search }) => {
-            show_history(&history_manager, limit, search)
+        Some(Commands::History { action: Some(HistoryAction::Import { file }), .. }) => {
+            import_history(&history()?, &file)
+        }
+        Some(Commands::History { action: Some(HistoryAction::Rekey), .. }) => {
+            rekey_history(&mut history()?, &tool_manager, &config)
+        }
+        Some(Commands::History { action: None, limit, search, tool, since, until }) => {
+            let filters = [("tool", tool), ("since", since), ("until", until)]
+                .into_iter()
+                .filter_map(|(key, value)| value.map(|v| format!("{}:{}", key, v)));
+            let search = match search {
+                Some(query) => Some(std::iter::once(query).chain(filters).collect::<Vec<_>>().join(" ")),
+                None => Some(filters.collect::<Vec<_>>().join(" ")).filter(|q| !q.is_empty()),
+            };
+            show_history(&history()?, limit, search)
         }
         Some(Commands::Init { output }) => {
             generate_config(&output)
@@ -179,6 +308,31 @@ async fn main() -> Result<()> {
         Some(Commands::DriveStatus) => {
             show_drive_status(&config)
         }
+        Some(Commands::Keys { .. }) => unreachable!("handled before the tools are set up"),
+        Some(Commands::Undo) => {
+            // Encrypted snapshots are unlocked with the history key.
+            history()?;
+            for line in undo_last_run(tool_manager.snapshots(), &project_dir)? {
+                println!("{}", line);
+            }
+            Ok(())
+        }
+        Some(Commands::Pipe { name: None, .. }) => {
+            for line in describe_pipelines(&config) {
+                println!("{}", line);
+            }
+            Ok(())
+        }
+        Some(Commands::Pipe { name: Some(name), prompt }) => {
+            let prompt = prompt.ok_or_else(|| anyhow::anyhow!("Usage: polyglot-local pipe <name> <prompt>"))?;
+            let pipeline = Pipeline::named(&config, &name)?;
+            let mut history_manager = history()?;
+            let run = run_pipeline(&tool_manager, pipeline, &prompt, &mut history_manager, &project_dir).await?;
+            match run.failure() {
+                Some((step, reason)) => anyhow::bail!("Pipeline {} failed at {}: {}", name, step.name, reason),
+                None => Ok(()),
+            }
+        }
         Some(Commands::Update { check_only, force }) => {
             run_update(check_only, force).await
         }
@@ -369,7 +523,12 @@ async fn show_splash_screen<B: ratatui::backend::Backend>(terminal: &mut ratatui
     Ok(())
 }
 
-async fn run_tui(tool_manager: LocalToolManager, _config: &LocalConfig, mut history_manager: HistoryManager) -> Result<()> {
+async fn run_tui(
+    tool_manager: LocalToolManager,
+    config: &LocalConfig,
+    mut history_manager: HistoryManager,
+    project_dir: PathBuf,
+) -> Result<()> {
     use std::time::Duration;
     use crossterm::{
         event::{self, DisableMouseCapture, EnableMouseCapture, Event},
@@ -378,6 +537,8 @@ async fn run_tui(tool_manager: LocalToolManager, _config: &LocalConfig, mut hist
     };
     use ratatui::{backend::CrosstermBackend, Terminal};
 
+    let judge = Judge::from_config(&config.judge).context("Invalid [judge] settings")?;
+
     enable_raw_mode()?;
 
     #[cfg(windows)]
@@ -407,6 +568,9 @@ async fn run_tui(tool_manager: LocalToolManager, _config: &LocalConfig, mut hist
     show_splash_screen(&mut terminal).await?;
 
     let mut app = App::new();
+    app.project_dir = project_dir;
+    app.multi_model.judge = judge;
+    let repo_maps = repo_map_cache();
     app.add_output(OutputType::System, "Welcome to Polyglot-AI Local!".to_string());
     app.add_output(OutputType::System, "Type your message and press Enter to send.".to_string());
     app.add_output(OutputType::System, "Use /help for commands, /update to check for updates, Ctrl+Q to quit.".to_string());
@@ -438,6 +602,9 @@ async fn run_tui(tool_manager: LocalToolManager, _config: &LocalConfig, mut hist
 
     let (response_tx, mut response_rx) = mpsc::channel::<ToolOutput>(100);
     let (multi_tx, mut multi_rx) = mpsc::channel::<TaggedOutput>(100);
+    let (pipe_tx, mut pipe_rx) = mpsc::channel::<PipelineEvent>(100);
+    let (judge_tx, mut judge_rx) = mpsc::channel::<ToolOutput>(100);
+    let (summary_tx, mut summary_rx) = mpsc::channel::<(SummaryJob, Result<String>)>(4);
 
     let result = async {
         loop {
@@ -454,25 +621,34 @@ async fn run_tui(tool_manager: LocalToolManager, _config: &LocalConfig, mut hist
                                 match action {
                                     AppAction::Quit => break,
                                     AppAction::SendPrompt(message) => {
+                                        let prior_turns = history_manager.current_session().messages.clone();
                                         history_manager.add_user_message(message.clone());
                                         app.current_response.clear();
+                                        app.review.clear();
 
-                                        let prompt_with_context = if let Some(context) = history_manager.get_transfer_context() {
-                                            if !context.summary.is_empty() && history_manager.current_session().messages.len() <= 2 {
-                                                context.as_prompt_prefix()
-                                            } else {
-                                                message.clone()
-                                            }
-                                        } else {
-                                            message.clone()
-                                        };
+                                        let (message, problems) = expand_references(
+                                            &message, &app.project_dir, &tool_manager, &mut history_manager);
+                                        for problem in problems {
+                                            app.add_output(OutputType::Error, problem);
+                                        }
 
-                                        let tx = response_tx.clone();
                                         let tool = app.current_tool;
+                                        let target = tool.unwrap_or(tool_manager.default_tool());
+                                        let builder = tool_manager.context_builder(target);
+                                        let mut prompt_with_context = history_manager.prompt_for(target, &message, &builder);
+                                        if let Some(map) = app.pending_repo_map.take() {
+                                            prompt_with_context = format!("{}\n\n{}", map, prompt_with_context);
+                                        }
+
+                                        let tx = response_tx.clone();
 
                                         let mut tm = tool_manager.clone();
+                                        tm.set_history(prior_turns);
+                                        tm.set_models(app.models.clone());
+                                        tm.set_native_sessions(history_manager.current_session().native_sessions.clone());
+                                        let working_dir = app.project_dir.clone();
                                         tokio::spawn(async move {
-                                            if let Err(e) = tm.execute_streaming(&prompt_with_context, tool, tx.clone()).await {
+                                            if let Err(e) = tm.execute_streaming(&prompt_with_context, tool, &working_dir, tx.clone()).await {
                                                 let _ = tx.send(ToolOutput::Error(format!("Tool execution error: {}", e))).await;
                                             }
                                         });
@@ -482,7 +658,7 @@ async fn run_tui(tool_manager: LocalToolManager, _config: &LocalConfig, mut hist
                                             app.current_tool = Some(tool);
                                             history_manager.set_tool(tool);
 
-                                            history_manager.auto_summarize();
+                                            spawn_summary(&tool_manager, history_manager.auto_summarize(), &summary_tx);
 
                                             app.add_output(OutputType::System,
                                                 format!("Switched to {} (context preserved)", tool.display_name()));
@@ -503,6 +679,104 @@ async fn run_tui(tool_manager: LocalToolManager, _config: &LocalConfig, mut hist
                                             .collect();
                                         app.view = tui::View::Tools;
                                     }
+                                    AppAction::ListModels(tool) => {
+                                        let current = app.models.get(&tool).cloned()
+                                            .or_else(|| tool_manager.model_for(tool));
+                                        app.add_output(OutputType::System, format!("{} model: {}",
+                                            tool.display_name(), current.as_deref().unwrap_or("tool default")));
+
+                                        let models = tool_manager.list_models(tool).await;
+                                        if models.is_empty() {
+                                            app.add_output(OutputType::System,
+                                                "No model list available; /model <name> still passes the name through.".to_string());
+                                        } else {
+                                            app.add_output(OutputType::System, format!("Available: {}", models.join(", ")));
+                                        }
+                                    }
+                                    AppAction::ChangeDirectory(target) => {
+                                        let dir = resolve_dir(&app.project_dir, &target).and_then(|dir| {
+                                            tool_manager.sandbox().validate_path_read(&dir)?;
+                                            tool_manager.sandbox().validate_path_write(&dir)?;
+                                            Ok(dir)
+                                        });
+                                        match dir {
+                                            Ok(dir) => {
+                                                history_manager.set_project(Some(dir.to_string_lossy().to_string()));
+                                                app.add_output(OutputType::System, format!("Working in {}", dir.display()));
+                                                app.project_dir = dir;
+                                            }
+                                            Err(e) => app.add_output(OutputType::Error, format!("Can't switch to {}: {}", target, e)),
+                                        }
+                                    }
+                                    AppAction::Undo => {
+                                        match undo_last_run(tool_manager.snapshots(), &app.project_dir) {
+                                            Ok(lines) => {
+                                                for line in lines {
+                                                    app.add_output(OutputType::System, line);
+                                                }
+                                            }
+                                            Err(e) => app.add_output(OutputType::Error, format!("Undo failed: {}", e)),
+                                        }
+                                    }
+                                    AppAction::ShowDiff(snapshot, path) => {
+                                        let diff = match tool_manager.snapshots() {
+                                            Some(store) => store.diff(snapshot, &path)
+                                                .unwrap_or_else(|e| format!("No diff available: {}", e)),
+                                            None => "Snapshots are disabled.".to_string(),
+                                        };
+                                        let diff = if diff.is_empty() { "No differences left.".to_string() } else { diff };
+                                        app.review.set_diff(&diff);
+                                    }
+                                    AppAction::AcceptChange(snapshot, path) => {
+                                        let result = tool_manager.snapshots()
+                                            .ok_or_else(|| anyhow::anyhow!("Snapshots are disabled"))
+                                            .and_then(|store| store.accept(snapshot, &path));
+                                        app.review.notice = match result {
+                                            Ok(()) => {
+                                                app.review.set_status(snapshot, &path, tui::ReviewStatus::Accepted);
+                                                format!("Accepted {}", path.display())
+                                            }
+                                            Err(e) => format!("Accept failed: {}", e),
+                                        };
+                                    }
+                                    AppAction::RevertChange(snapshot, path) => {
+                                        let result = tool_manager.snapshots()
+                                            .ok_or_else(|| anyhow::anyhow!("Snapshots are disabled"))
+                                            .and_then(|store| store.revert(snapshot, &path));
+                                        app.review.notice = match result {
+                                            Ok(Revert::Restored) => {
+                                                app.review.set_status(snapshot, &path, tui::ReviewStatus::Reverted);
+                                                format!("Reverted {}", path.display())
+                                            }
+                                            Ok(Revert::ChangedSince) => format!("{} was edited after the run; left alone", path.display()),
+                                            Ok(Revert::NotKept) => format!("{} was too large to snapshot", path.display()),
+                                            Err(e) => format!("Revert failed: {}", e),
+                                        };
+                                        if let Some(store) = tool_manager.snapshots() {
+                                            let diff = store.diff(snapshot, &path).unwrap_or_default();
+                                            app.review.set_diff(if diff.is_empty() { "No differences left." } else { &diff });
+                                        }
+                                    }
+                                    AppAction::ShowRepoMap => {
+                                        match repo_maps.get(&app.project_dir) {
+                                            Ok(map) => {
+                                                let rendered = map.render(config.repo_map.token_budget);
+                                                for line in rendered.lines() {
+                                                    app.add_output(OutputType::System, line.to_string());
+                                                }
+                                                app.add_output(OutputType::System,
+                                                    "The map will be sent with your next prompt.".to_string());
+                                                app.pending_repo_map = Some(rendered);
+                                            }
+                                            Err(e) => app.add_output(OutputType::Error, format!("Failed to map {}: {}", app.project_dir.display(), e)),
+                                        }
+                                    }
+                                    AppAction::ShowContext => {
+                                        let tool = app.current_tool.unwrap_or(tool_manager.default_tool());
+                                        for line in describe_context(&mut history_manager, &tool_manager, tool) {
+                                            app.add_output(OutputType::System, line);
+                                        }
+                                    }
                                     AppAction::RequestHistory => {
                                         app.history_search.clear();
                                         if let Ok(history) = history_manager.get_accessible_history() {
@@ -512,11 +786,17 @@ async fn run_tui(tool_manager: LocalToolManager, _config: &LocalConfig, mut hist
                                         app.view = tui::View::History;
                                     }
                                     AppAction::SearchHistory(query) => {
-                                        if let Ok(results) = history_manager.search(&query) {
-                                            app.history = results;
-                                            app.history_selected = 0;
+                                        match history_manager.search(&query) {
+                                            Ok(results) => {
+                                                app.history = results;
+                                                app.history_selected = 0;
+                                                app.view = tui::View::History;
+                                            }
+                                            Err(e) => {
+                                                app.add_output(OutputType::Error, format!("Search failed: {}", e));
+                                                app.view = tui::View::Chat;
+                                            }
                                         }
-                                        app.view = tui::View::History;
                                     }
                                     AppAction::NewChat => {
                                         if let Some(context) = history_manager.transfer_to_new_session() {
@@ -565,6 +845,48 @@ async fn run_tui(tool_manager: LocalToolManager, _config: &LocalConfig, mut hist
                                         }
                                         app.view = tui::View::Chat;
                                     }
+                                    AppAction::Fork(messages) => {
+                                        match history_manager.fork_current(messages) {
+                                            Ok(session) => {
+                                                let kept = session.messages.len();
+                                                app.output.clear();
+                                                app.scroll_offset = 0;
+                                                app.add_output(OutputType::System, format!(
+                                                    "Forked after message {}. The original is kept; /branches lists both.",
+                                                    kept
+                                                ));
+                                                for msg in session.last_messages(6) {
+                                                    let output_type = match msg.role {
+                                                        polyglot_common::MessageRole::User => OutputType::User,
+                                                        polyglot_common::MessageRole::Assistant => OutputType::Assistant,
+                                                        polyglot_common::MessageRole::System => OutputType::System,
+                                                    };
+                                                    app.add_output(output_type, msg.content.clone());
+                                                }
+                                            }
+                                            Err(e) => {
+                                                app.add_output(OutputType::Error, format!("Cannot fork: {}", e));
+                                            }
+                                        }
+                                        app.view = tui::View::Chat;
+                                    }
+                                    AppAction::ShowBranches => {
+                                        app.history_search.clear();
+                                        match history_manager.branches() {
+                                            Ok(branches) if branches.len() > 1 => {
+                                                app.history = branches.into_iter().map(|(_, e)| e).collect();
+                                                app.history_selected = 0;
+                                                app.view = tui::View::History;
+                                            }
+                                            Ok(_) => {
+                                                app.add_output(OutputType::System,
+                                                    "This conversation has no branches. Use /fork to start one.".to_string());
+                                            }
+                                            Err(e) => {
+                                                app.add_output(OutputType::Error, format!("Failed to list branches: {}", e));
+                                            }
+                                        }
+                                    }
                                     AppAction::EnableMultiModel(tools) => {
                                         app.multi_model.enable(tools.clone());
                                         let names: Vec<_> = tools.iter().map(|t| t.display_name()).collect();
@@ -580,14 +902,28 @@ async fn run_tui(tool_manager: LocalToolManager, _config: &LocalConfig, mut hist
                                     AppAction::SendMultiPrompt(message, tools) => {
                                         history_manager.add_user_message(message.clone());
                                         app.multi_model.clear_responses();
+                                        app.review.clear();
+                                        app.multi_model.prompt = message.clone();
 
                                         let tx = multi_tx.clone();
                                         let mut tm = tool_manager.clone();
-                                        let prompt = message.clone();
+                                        tm.set_models(app.models.clone());
+                                        tm.set_native_sessions(history_manager.current_session().native_sessions.clone());
+                                        let (mut prompt, problems) = expand_references(
+                                            &message, &app.project_dir, &tool_manager, &mut history_manager);
+                                        for problem in problems {
+                                            app.add_output(OutputType::Error, problem);
+                                        }
+                                        if let Some(map) = app.pending_repo_map.take() {
+                                            prompt = format!("{}\n\n{}", map, prompt);
+                                        }
                                         let selected_tools = tools.clone();
+                                        let working_dir = app.project_dir.clone();
+                                        let mode = if app.multi_model.race { MultiMode::Race } else { MultiMode::All };
+                                        app.multi_model.started = Some(Instant::now());
 
                                         tokio::spawn(async move {
-                                            if let Err(e) = tm.execute_multi_streaming(&prompt, selected_tools.clone(), tx.clone()).await {
+                                            if let Err(e) = tm.execute_multi_streaming(&prompt, selected_tools.clone(), &working_dir, tx.clone(), mode).await {
                                                 for tool in selected_tools {
                                                     let _ = tx.send(TaggedOutput {
                                                         tool,
@@ -597,6 +933,42 @@ async fn run_tui(tool_manager: LocalToolManager, _config: &LocalConfig, mut hist
                                             }
                                         });
                                     }
+                                    AppAction::ListPipelines => {
+                                        app.add_output(OutputType::System, "Pipelines:".to_string());
+                                        for line in describe_pipelines(config) {
+                                            app.add_output(OutputType::System, line);
+                                        }
+                                    }
+                                    AppAction::RunPipeline(name, message) => {
+                                        let pipeline = match Pipeline::named(config, &name) {
+                                            Ok(pipeline) => pipeline,
+                                            Err(e) => {
+                                                app.add_output(OutputType::Error, e.to_string());
+                                                continue;
+                                            }
+                                        };
+                                        history_manager.add_user_message(message.clone());
+                                        app.current_response.clear();
+                                        app.review.clear();
+
+                                        let (mut input, problems) = expand_references(
+                                            &message, &app.project_dir, &tool_manager, &mut history_manager);
+                                        for problem in problems {
+                                            app.add_output(OutputType::Error, problem);
+                                        }
+                                        if let Some(map) = app.pending_repo_map.take() {
+                                            input = format!("{}\n\n{}", map, input);
+                                        }
+                                        app.add_output(OutputType::System, format!("Running pipeline {}: {}", name, pipeline));
+
+                                        let tx = pipe_tx.clone();
+                                        let mut tm = tool_manager.clone();
+                                        tm.set_models(app.models.clone());
+                                        let working_dir = app.project_dir.clone();
+                                        tokio::spawn(async move {
+                                            pipeline.run(tm, &input, &working_dir, tx).await;
+                                        });
+                                    }
                                     AppAction::PerformUpdate => {
                                         match check_for_updates_github("polyglot-local").await {
                                             Ok(info) => {
@@ -632,41 +1004,206 @@ async fn run_tui(tool_manager: LocalToolManager, _config: &LocalConfig, mut hist
 
                 Some(tagged) = multi_rx.recv() => {
                     let tool = tagged.tool;
+                    let was_done = app.multi_model.all_done();
                     match tagged.output {
                         ToolOutput::Stdout(line) => {
-                            app.multi_model.add_line(tool, line);
+                            app.multi_model.add_answer(tool, line);
                         }
                         ToolOutput::Stderr(line) => {
                             app.multi_model.add_line(tool, format!("[stderr] {}", line));
                         }
-                        ToolOutput::Done { tool, tokens: _ } => {
-                            app.multi_model.mark_done(tool);
-
-                            if app.multi_model.all_done() {
-                                let mut combined = String::new();
-                                for t in &app.multi_model.selected_tools {
-                                    if let Some(lines) = app.multi_model.responses.get(t) {
-                                        combined.push_str(&format!("\n--- {} ---\n", t.display_name()));
-                                        combined.push_str(&lines.join("\n"));
-                                    }
-                                }
-                                if !combined.is_empty() {
-                                    history_manager.add_assistant_message(combined.trim().to_string());
-                                }
-                                app.add_output(OutputType::System, "All models completed.".to_string());
+                        ToolOutput::Action(action) => {
+                            app.multi_model.add_line(tool, format!("> {}", action));
+                        }
+                        ToolOutput::FilesChanged { tool, snapshot, changes } => {
+                            app.multi_model.add_line(tool, format!("{} (F8 to review)", describe_changes(&changes)));
+                            history_manager.add_changed_files(&changes);
+                            app.review.add_run(tool, snapshot, changes);
+                        }
+                        ToolOutput::NativeSession { tool, session_id } => {
+                            history_manager.set_native_session(tool, session_id);
+                        }
+                        ToolOutput::Done { tool, tokens: _, model } => {
+                            if let Some(model) = model {
+                                app.multi_model.set_model(tool, model);
+                            }
+                            if app.multi_model.race && app.multi_model.winner.is_none() {
+                                let elapsed = app.multi_model.started.map(|s| s.elapsed()).unwrap_or_default();
+                                app.multi_model.winner = Some((tool, elapsed));
                             }
+                            app.multi_model.mark_done(tool);
+                        }
+                        ToolOutput::Cancelled { tool } => {
+                            app.multi_model.add_line(tool, "[Stopped: another tool answered first]".to_string());
+                            app.multi_model.mark_failed(tool);
+                        }
+                        ToolOutput::Redacted(report) => {
+                            app.multi_model.add_line(tool, format!("[Redacted from the prompt: {}]", report));
                         }
                         ToolOutput::Error(e) => {
                             app.multi_model.add_line(tool, format!("[ERROR] {}", e));
-                            app.multi_model.mark_done(tool);
+                            app.multi_model.mark_failed(tool);
                         }
                         ToolOutput::RateLimited { tool, next_tool: _ } => {
                             app.multi_model.add_line(tool, "[Rate limited]".to_string());
-                            app.multi_model.mark_done(tool);
+                            app.multi_model.mark_failed(tool);
+                        }
+                        ToolOutput::Terminal { tool, .. } => {
+                            // Input can't be routed to one of several tools.
+                            app.multi_model.add_line(tool, "[Running in a terminal; input is not forwarded in multi-model mode]".to_string());
+                        }
+                    }
+
+                    if !was_done && app.multi_model.all_done() && app.multi_model.race {
+                        match app.multi_model.winner {
+                            Some((winner, elapsed)) => {
+                                if let Some(answer) = app.multi_model.answers.get(&winner).filter(|a| !a.trim().is_empty()) {
+                                    history_manager.add_assistant_message_with_model(
+                                        answer.trim().to_string(), app.multi_model.models.get(&winner).cloned());
+                                    spawn_summary(&tool_manager, history_manager.auto_summarize(), &summary_tx);
+                                }
+                                app.add_output(OutputType::System,
+                                    format!("{} won the race in {:.1}s.", winner.display_name(), elapsed.as_secs_f64()));
+                            }
+                            None => app.add_output(OutputType::Error, "No tool answered successfully.".to_string()),
+                        }
+                    } else if !was_done && app.multi_model.all_done() {
+                        let mut combined = String::new();
+                        for t in &app.multi_model.selected_tools {
+                            if let Some(lines) = app.multi_model.responses.get(t) {
+                                let header = match app.multi_model.models.get(t) {
+                                    Some(model) => format!("{} ({})", t.display_name(), model),
+                                    None => t.display_name().to_string(),
+                                };
+                                combined.push_str(&format!("\n--- {} ---\n", header));
+                                combined.push_str(&lines.join("\n"));
+                            }
+                        }
+                        if !combined.is_empty() {
+                            history_manager.add_assistant_message(combined.trim().to_string());
+                        }
+                        app.add_output(OutputType::System, "All models completed.".to_string());
+
+                        if let Some(judge) = app.multi_model.judge.clone() {
+                            let candidates = app.multi_model.candidates();
+                            if candidates.len() < 2 {
+                                app.add_output(OutputType::System,
+                                    format!("{} has nothing to compare; fewer than two tools answered.", judge.tool.display_name()));
+                            } else {
+                                let prompt = judge.prompt(&app.multi_model.prompt, &candidates);
+                                let model = judge.model.clone().or_else(|| app.models.get(&judge.tool).cloned());
+                                let tool = judge.tool;
+                                app.multi_model.start_verdict(judge);
+
+                                let tm = tool_manager.clone();
+                                let tx = judge_tx.clone();
+                                tokio::spawn(async move {
+                                    if let Err(e) = tm.execute_oneoff(&prompt, tool, model, tx.clone()).await {
+                                        let _ = tx.send(ToolOutput::Error(format!("Judge error: {}", e))).await;
+                                    }
+                                });
+                            }
+                        }
+                    }
+                }
+
+                Some(output) = judge_rx.recv() => {
+                    if let Some(verdict) = app.multi_model.verdict.as_mut().filter(|v| !v.done) {
+                        match output {
+                            ToolOutput::Stdout(line) => {
+                                verdict.text.push_str(&line);
+                                verdict.text.push('\n');
+                                verdict.lines.push(line);
+                            }
+                            ToolOutput::Stderr(line) => verdict.lines.push(format!("[stderr] {}", line)),
+                            ToolOutput::Action(action) => verdict.lines.push(format!("> {}", action)),
+                            ToolOutput::Redacted(report) => {
+                                verdict.lines.push(format!("[Redacted from the prompt: {}]", report));
+                            }
+                            ToolOutput::Done { model, .. } => {
+                                verdict.done = true;
+                                verdict.model = model.clone();
+                                if !verdict.text.trim().is_empty() {
+                                    history_manager.add_assistant_message_with_model(verdict.judge.record(&verdict.text), model);
+                                }
+                            }
+                            ToolOutput::Error(e) => {
+                                verdict.lines.push(format!("[ERROR] {}", e));
+                                verdict.done = true;
+                            }
+                            ToolOutput::RateLimited { .. } => {
+                                verdict.lines.push("[Rate limited]".to_string());
+                                verdict.done = true;
+                            }
+                            ToolOutput::Terminal { .. } => {
+                                verdict.lines.push("[Running in a terminal; input is not forwarded to the judge]".to_string());
+                            }
+                            // Only races cancel runs.
+                            ToolOutput::FilesChanged { .. } | ToolOutput::NativeSession { .. } | ToolOutput::Cancelled { .. } => {}
+                        }
+                    }
+                }
+
+                Some(event) = pipe_rx.recv() => {
+                    match event {
+                        PipelineEvent::StepStarted { index, name, tool } => {
+                            app.add_output(OutputType::System,
+                                format!("Step {}: {} ({})", index + 1, name, tool.display_name()));
+                        }
+                        PipelineEvent::Output(output) => match output {
+                            ToolOutput::Stdout(line) => app.add_output(OutputType::Assistant, line),
+                            ToolOutput::Stderr(line) => app.add_output(OutputType::System, format!("[stderr] {}", line)),
+                            ToolOutput::Action(action) => app.add_output(OutputType::System, format!("> {}", action)),
+                            ToolOutput::FilesChanged { tool, snapshot, changes } => {
+                                app.add_output(OutputType::System,
+                                    format!("{} (F8 to review, /undo to revert)", describe_changes(&changes)));
+                                history_manager.add_changed_files(&changes);
+                                app.review.add_run(tool, snapshot, changes);
+                            }
+                            ToolOutput::Done { tool, tokens: Some(t), .. } => {
+                                app.add_output(OutputType::System, format!("({} - {} tokens)", tool.display_name(), t));
+                            }
+                            ToolOutput::Redacted(report) => {
+                                app.add_output(OutputType::System, format!("Redacted from the prompt: {}", report));
+                            }
+                            ToolOutput::Terminal { tool, input } => {
+                                app.terminal_input = Some(input);
+                                app.add_output(OutputType::System,
+                                    format!("{} is running in a terminal; Enter sends your input to it.", tool.display_name()));
+                            }
+                            ToolOutput::RateLimited { tool, .. } => {
+                                app.add_output(OutputType::Error, format!("{} rate limited", tool.display_name()));
+                            }
+                            // Failures are reported once the step finishes; only races cancel runs.
+                            ToolOutput::Error(_) | ToolOutput::Done { .. } | ToolOutput::NativeSession { .. }
+                                | ToolOutput::Cancelled { .. } => {}
+                        },
+                        PipelineEvent::StepFinished { index, result } => {
+                            app.terminal_input = None;
+                            if let StepResult::Failed(reason) = result {
+                                app.add_output(OutputType::Error, format!("Step {} failed: {}", index + 1, reason));
+                            }
+                        }
+                        PipelineEvent::Finished(run) => {
+                            history_manager.add_assistant_message(run.transcript());
+                            spawn_summary(&tool_manager, history_manager.auto_summarize(), &summary_tx);
+                            match run.failure() {
+                                Some((step, reason)) => app.add_output(OutputType::Error,
+                                    format!("Pipeline {} failed at {}: {}", run.pipeline.name, step.name, reason)),
+                                None => app.add_output(OutputType::System,
+                                    format!("Pipeline {} finished.", run.pipeline.name)),
+                            }
                         }
                     }
                 }
 
+                Some((job, reply)) = summary_rx.recv() => {
+                    let tool = job.tool;
+                    if let Err(e) = history_manager.finish_summary(job, reply) {
+                        app.add_output(OutputType::System,
+                            format!("{} could not summarize the session, using a quick summary: {}", tool.display_name(), e));
+                    }
+                }
                 Some(output) = response_rx.recv() => {
                     match output {
                         ToolOutput::Stdout(line) => {
@@ -677,23 +1214,51 @@ async fn run_tui(tool_manager: LocalToolManager, _config: &LocalConfig, mut hist
                         ToolOutput::Stderr(line) => {
                             app.add_output(OutputType::System, format!("[stderr] {}", line));
                         }
-                        ToolOutput::Done { tool, tokens } => {
+                        ToolOutput::Action(action) => {
+                            app.add_output(OutputType::System, format!("> {}", action));
+                        }
+                        ToolOutput::FilesChanged { tool, snapshot, changes } => {
+                            app.add_output(OutputType::System,
+                                format!("{} (F8 to review, /undo to revert)", describe_changes(&changes)));
+                            history_manager.add_changed_files(&changes);
+                            app.review.add_run(tool, snapshot, changes);
+                        }
+                        ToolOutput::NativeSession { tool, session_id } => {
+                            history_manager.set_native_session(tool, session_id);
+                        }
+                        ToolOutput::Done { tool, tokens, model } => {
+                            app.terminal_input = None;
                             if !app.current_response.is_empty() {
-                                history_manager.add_assistant_message(app.current_response.trim().to_string());
+                                history_manager.add_assistant_message_with_model(
+                                    app.current_response.trim().to_string(), model.clone());
                                 app.current_response.clear();
 
-                                history_manager.auto_summarize();
+                                spawn_summary(&tool_manager, history_manager.auto_summarize(), &summary_tx);
                             }
 
                             if let Some(t) = tokens {
-                                app.add_output(OutputType::System, format!("({} - {} tokens)", tool.display_name(), t));
+                                let name = match &model {
+                                    Some(model) => format!("{} {}", tool.display_name(), model),
+                                    None => tool.display_name().to_string(),
+                                };
+                                app.add_output(OutputType::System, format!("({} - {} tokens)", name, t));
                             }
                         }
                         ToolOutput::Error(e) => {
+                            app.terminal_input = None;
                             app.add_output(OutputType::Error, e);
                         }
+                        ToolOutput::Redacted(report) => {
+                            app.add_output(OutputType::System, format!("Redacted from the prompt: {}", report));
+                        }
+                        ToolOutput::Terminal { tool, input } => {
+                            app.terminal_input = Some(input);
+                            app.add_output(OutputType::System,
+                                format!("{} is running in a terminal; Enter sends your input to it.", tool.display_name()));
+                        }
                         ToolOutput::RateLimited { tool, next_tool } => {
-                            history_manager.auto_summarize();
+                            app.terminal_input = None;
+                            spawn_summary(&tool_manager, history_manager.auto_summarize(), &summary_tx);
 
                             app.add_output(OutputType::System,
                                 format!("{} rate limited. Switching to {} (context preserved)...",
@@ -704,6 +1269,8 @@ async fn run_tui(tool_manager: LocalToolManager, _config: &LocalConfig, mut hist
                                 history_manager.set_tool(next);
                             }
                         }
+                        // Only races cancel runs.
+                        ToolOutput::Cancelled { .. } => {}
                     }
                 }
             }
@@ -729,7 +1296,43 @@ async fn run_tui(tool_manager: LocalToolManager, _config: &LocalConfig, mut hist
     result
 }
 
-async fn run_simple_cli(tool_manager: LocalToolManager, _config: &LocalConfig, mut history_manager: HistoryManager) -> Result<()> {
+/// Runs a summary job in the background; the reply comes back on `tx`.
+fn spawn_summary(
+    tool_manager: &LocalToolManager,
+    job: Option<SummaryJob>,
+    tx: &mpsc::Sender<(SummaryJob, Result<String>)>,
+) {
+    let Some(job) = job else {
+        return;
+    };
+
+    let tm = tool_manager.clone();
+    let tx = tx.clone();
+    tokio::spawn(async move {
+        let reply = tm.complete(&job.prompt, job.tool, job.model.clone()).await;
+        let _ = tx.send((job, reply)).await;
+    });
+}
+
+/// Brings the session summary up to date, waiting for the summarizer tool if one is configured.
+async fn update_summary(tool_manager: &LocalToolManager, history_manager: &mut HistoryManager) {
+    let Some(job) = history_manager.auto_summarize() else {
+        return;
+    };
+
+    let tool = job.tool;
+    let reply = tool_manager.complete(&job.prompt, job.tool, job.model.clone()).await;
+    if let Err(e) = history_manager.finish_summary(job, reply) {
+        eprintln!("{} could not summarize the session, using a quick summary: {}", tool.display_name(), e);
+    }
+}
+
+async fn run_simple_cli(
+    tool_manager: LocalToolManager,
+    config: &LocalConfig,
+    mut history_manager: HistoryManager,
+    project_dir: &Path,
+) -> Result<()> {
     println!("Polyglot-AI Local v{}", env!("CARGO_PKG_VERSION"));
     println!("Type your message and press Enter. Use /quit to exit.\n");
 
@@ -811,6 +1414,22 @@ async fn run_simple_cli(tool_manager: LocalToolManager, _config: &LocalConfig, m
                     }
                     println!();
                 }
+                "/undo" => {
+                    println!();
+                    match undo_last_run(tool_manager.snapshots(), project_dir) {
+                        Ok(lines) => lines.iter().for_each(|line| println!("{}", line)),
+                        Err(e) => println!("Undo failed: {}", e),
+                    }
+                    println!();
+                }
+                "/context" => {
+                    let tool = current_tool.unwrap_or(tool_manager.default_tool());
+                    println!();
+                    for line in describe_context(&mut history_manager, &tool_manager, tool) {
+                        println!("{}", line);
+                    }
+                    println!();
+                }
                 "/help" => {
                     println!("\nCommands:");
                     println!("  /tools          - List available tools");
@@ -820,16 +1439,20 @@ async fn run_simple_cli(tool_manager: LocalToolManager, _config: &LocalConfig, m
                     println!("  /search <query> - Search chat history");
                     println!("  /title <name>   - Set current session title");
                     println!("  /usage          - Show usage statistics");
+                    println!("  /context        - Show context sent with the next prompt");
+                    println!("  /undo           - Restore the files changed by the last prompt");
+                    println!("  /pipe <name> <prompt> - Run a pipeline; /pipe alone lists them");
                     println!("  /quit           - Exit");
                     println!();
                 }
                 _ if input.starts_with("/search ") => {
                     let query = input.strip_prefix("/search ").unwrap().trim();
                     println!("\nSearch results for: \"{}\"\n", query);
-                    if let Ok(results) = history_manager.search(query) {
-                        if results.is_empty() {
+                    match history_manager.search(query) {
+                        Ok(results) if results.is_empty() => {
                             println!("  No matching sessions found.");
-                        } else {
+                        }
+                        Ok(results) => {
                             for (i, entry) in results.iter().take(10).enumerate() {
                                 let tool_name = entry.tool.map(|t| t.as_str()).unwrap_or("?");
                                 println!("  {}. [{}] {} ({} msgs)",
@@ -837,11 +1460,41 @@ async fn run_simple_cli(tool_manager: LocalToolManager, _config: &LocalConfig, m
                                     tool_name,
                                     polyglot_common::truncate_smart(&entry.title, 40),
                                     entry.message_count);
+                                println!("     {}", polyglot_common::truncate_smart(&entry.preview, 80));
                             }
                         }
+                        Err(e) => println!("  Search failed: {}", e),
+                    }
+                    println!();
+                }
+                "/pipe" => {
+                    println!("\nPipelines:");
+                    for line in describe_pipelines(config) {
+                        println!("{}", line);
                     }
                     println!();
                 }
+                _ if input.starts_with("/pipe ") => {
+                    let args = input.strip_prefix("/pipe ").unwrap().trim();
+                    let Some((name, prompt)) = args.split_once(char::is_whitespace) else {
+                        println!("Usage: /pipe <name> <prompt>\n");
+                        continue;
+                    };
+                    match Pipeline::named(config, name) {
+                        Ok(pipeline) => {
+                            match run_pipeline(&tool_manager, pipeline, prompt.trim(), &mut history_manager, project_dir).await {
+                                Ok(run) => {
+                                    if let Some((step, reason)) = run.failure() {
+                                        println!("\nPipeline {} failed at {}: {}", name, step.name, reason);
+                                    }
+                                }
+                                Err(e) => println!("\nPipeline {}: {}", name, e),
+                            }
+                            println!();
+                        }
+                        Err(e) => println!("Error: {}\n", e),
+                    }
+                }
                 _ if input.starts_with("/title ") => {
                     let title = input.strip_prefix("/title ").unwrap().trim();
                     history_manager.set_session_title(title.to_string());
@@ -854,7 +1507,7 @@ async fn run_simple_cli(tool_manager: LocalToolManager, _config: &LocalConfig, m
                             if tool_manager.is_available(tool).await {
                                 current_tool = Some(tool);
                                 history_manager.set_tool(tool);
-                                history_manager.auto_summarize();
+                                update_summary(&tool_manager, &mut history_manager).await;
                                 println!("Switched to {} (context preserved)\n", tool.display_name());
                             } else {
                                 println!("Error: {} is not available\n", tool.display_name());
@@ -868,15 +1521,24 @@ async fn run_simple_cli(tool_manager: LocalToolManager, _config: &LocalConfig, m
             continue;
         }
 
+        let prior_turns = history_manager.current_session().messages.clone();
         history_manager.add_user_message(input.to_string());
+        let (input, problems) = expand_references(input, project_dir, &tool_manager, &mut history_manager);
+        for problem in problems {
+            eprintln!("Warning: {}", problem);
+        }
 
         let (tx, mut rx) = mpsc::channel(100);
-        let prompt = input.to_string();
         let tool = current_tool;
+        let target = tool.unwrap_or(tool_manager.default_tool());
+        let prompt = history_manager.prompt_for(target, &input, &tool_manager.context_builder(target));
 
         let mut tm = tool_manager.clone();
+        tm.set_history(prior_turns);
+        tm.set_native_sessions(history_manager.current_session().native_sessions.clone());
+        let working_dir = project_dir.to_path_buf();
         let handle = tokio::spawn(async move {
-            tm.execute_streaming(&prompt, tool, tx).await
+            tm.execute_streaming(&prompt, tool, &working_dir, tx).await
         });
 
         let mut response_buffer = String::new();
@@ -891,10 +1553,20 @@ async fn run_simple_cli(tool_manager: LocalToolManager, _config: &LocalConfig, m
                 ToolOutput::Stderr(line) => {
                     eprintln!("[stderr] {}", line);
                 }
-                ToolOutput::Done { tool, tokens } => {
+                ToolOutput::Action(action) => {
+                    eprintln!("> {}", action);
+                }
+                ToolOutput::FilesChanged { changes, .. } => {
+                    eprintln!("{} (/undo to revert)", describe_changes(&changes));
+                    history_manager.add_changed_files(&changes);
+                }
+                ToolOutput::NativeSession { tool, session_id } => {
+                    history_manager.set_native_session(tool, session_id);
+                }
+                ToolOutput::Done { tool, tokens, model } => {
                     if !response_buffer.is_empty() {
-                        history_manager.add_assistant_message(response_buffer.trim().to_string());
-                        history_manager.auto_summarize();
+                        history_manager.add_assistant_message_with_model(response_buffer.trim().to_string(), model);
+                        update_summary(&tool_manager, &mut history_manager).await;
                     }
 
                     if let Some(t) = tokens {
@@ -904,8 +1576,16 @@ async fn run_simple_cli(tool_manager: LocalToolManager, _config: &LocalConfig, m
                 ToolOutput::Error(e) => {
                     eprintln!("Error: {}", e);
                 }
+                ToolOutput::Redacted(report) => {
+                    eprintln!("(Redacted from the prompt: {})", report);
+                }
+                ToolOutput::Terminal { tool, .. } => {
+                    eprintln!("({} is running in a terminal; use the TUI to answer its prompts)", tool.display_name());
+                }
+                // Only races cancel runs.
+                ToolOutput::Cancelled { .. } => {}
                 ToolOutput::RateLimited { tool, next_tool } => {
-                    history_manager.auto_summarize();
+                    update_summary(&tool_manager, &mut history_manager).await;
                     println!("\n{} rate limited.", tool.display_name());
                     if let Some(next) = next_tool {
                         println!("Switching to {} (context preserved)...", next.display_name());
@@ -924,12 +1604,129 @@ async fn run_simple_cli(tool_manager: LocalToolManager, _config: &LocalConfig, m
     Ok(())
 }
 
+/// One line naming the files a run changed.
+fn describe_changes(changes: &[FileChange]) -> String {
+    const SHOWN: usize = 5;
+
+    let mut names: Vec<String> = changes.iter().take(SHOWN).map(|c| c.to_string()).collect();
+    if changes.len() > SHOWN {
+        names.push(format!("and {} more", changes.len() - SHOWN));
+    }
+    format!(
+        "Changed {} file{}: {}",
+        changes.len(),
+        if changes.len() == 1 { "" } else { "s" },
+        names.join(", ")
+    )
+}
+
+/// Inlines the `@file` references in `message`, read from `project_dir`
+/// with the sandbox's read rules, and records each file in the session.
+/// Returns the expanded prompt and why any reference was left out.
+fn expand_references(
+    message: &str,
+    project_dir: &Path,
+    tool_manager: &LocalToolManager,
+    history_manager: &mut HistoryManager,
+) -> (String, Vec<String>) {
+    let sandbox = tool_manager.sandbox();
+    let expanded = expand_file_refs(message, project_dir, &FileRefLimits::default(), |path| {
+        sandbox.validate_path_read(path).map_err(|e| e.to_string())
+    });
+    history_manager.add_referenced_files(&expanded.files);
+    (expanded.prompt, expanded.problems.iter().map(|p| p.to_string()).collect())
+}
+
+/// Repository maps, cached on disk across runs.
+fn repo_map_cache() -> RepoMapCache {
+    RepoMapCache::new(dirs::cache_dir().map(|dir| dir.join("polyglot-ai").join("repo-maps")))
+}
+
+/// Resolves `target` against `base`, expanding a leading `~`, to an existing directory.
+fn resolve_dir(base: &Path, target: &str) -> Result<PathBuf> {
+    let path = match target.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => dirs::home_dir()
+            .ok_or_else(|| anyhow::anyhow!("No home directory"))?
+            .join(rest.trim_start_matches('/')),
+        _ => PathBuf::from(target),
+    };
+    let dir = base.join(path).canonicalize()
+        .map_err(|e| anyhow::anyhow!("{}: {}", target, e))?;
+    if !dir.is_dir() {
+        anyhow::bail!("{} is not a directory", dir.display());
+    }
+    Ok(dir)
+}
+
+/// Undoes the last prompt's changes in `root`.
+fn undo_last_run(store: Option<&SnapshotStore>, root: &Path) -> Result<Vec<String>> {
+    let store = store.ok_or_else(|| anyhow::anyhow!("Snapshots are disabled; set enabled = true under [snapshots]"))?;
+    let Some(report) = store.undo(root)? else {
+        return Ok(vec![format!("Nothing to undo in {}", root.display())]);
+    };
+
+    let mut lines = vec![format!(
+        "Undid {}'s changes from {} ({} file{} restored)",
+        report.tool.display_name(),
+        report.created_at.with_timezone(&chrono::Local).format("%H:%M"),
+        report.restored.len(),
+        if report.restored.len() == 1 { "" } else { "s" },
+    )];
+    lines.extend(report.restored.iter().map(|change| format!("  {}", change)));
+
+    let list = |paths: &[PathBuf]| paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ");
+    if !report.changed_since.is_empty() {
+        lines.push(format!("Left alone, edited after the run: {}", list(&report.changed_since)));
+    }
+    if !report.not_kept.is_empty() {
+        lines.push(format!("Could not restore, too large to snapshot: {}", list(&report.not_kept)));
+    }
+    Ok(lines)
+}
+
+/// Explains what conversation context `tool` will receive with the next prompt.
+fn describe_context(history_manager: &mut HistoryManager, tool_manager: &LocalToolManager, tool: Tool) -> Vec<String> {
+    let builder = tool_manager.context_builder(tool);
+    let Some(context) = history_manager.context_for(tool, "", &builder) else {
+        let how = if tool.accepts_history() { "as chat history" } else { "through its own session" };
+        return vec![format!("{} receives the conversation {}; nothing is injected.", tool.display_name(), how)];
+    };
+
+    if context.is_empty() {
+        return vec![format!("No context to send to {} yet.", tool.display_name())];
+    }
+
+    let mut lines = vec![format!(
+        "Context for {} (~{} of {} tokens{}):",
+        tool.display_name(),
+        context.tokens,
+        builder.budget(),
+        if context.omitted_turns > 0 {
+            format!(", {} earlier messages left out", context.omitted_turns)
+        } else {
+            String::new()
+        }
+    )];
+    lines.extend(context.render().lines().map(|l| l.to_string()));
+    lines
+}
+
+/// What `ask` sends along with the prompt
+struct AskContext {
+    /// The current session's transfer context
+    history: bool,
+    /// A repository map of up to this many tokens
+    repo_map_budget: Option<u32>,
+}
+
 async fn run_single_prompt(
     mut tool_manager: LocalToolManager,
     prompt: &str,
     tool: Option<String>,
-    with_context: bool,
+    model: Option<String>,
+    context: AskContext,
     history_manager: &mut HistoryManager,
+    project_dir: &Path,
 ) -> Result<()> {
     let tool = match tool {
         Some(name) => Some(name.parse::<Tool>()
@@ -937,14 +1734,35 @@ async fn run_single_prompt(
         None => None,
     };
 
-    let full_prompt = if with_context {
-        if let Some(context) = history_manager.get_transfer_context() {
+    if let Some(model) = model {
+        let target = tool.unwrap_or(tool_manager.default_tool());
+        if !target.supports_model_selection() {
+            anyhow::bail!("{} does not support choosing a model", target.display_name());
+        }
+        tool_manager.set_models(HashMap::from([(target, model)]));
+    }
+
+    let (expanded, problems) = expand_references(prompt, project_dir, &tool_manager, history_manager);
+    for problem in problems {
+        eprintln!("Warning: {}", problem);
+    }
+
+    let repo_map = match context.repo_map_budget {
+        Some(budget) => Some((repo_map_cache().get(project_dir)?, budget)),
+        None => None,
+    };
+
+    let transfer = if context.history { history_manager.get_transfer_context() } else { None };
+    let full_prompt = match (transfer, &repo_map) {
+        (Some(mut context), _) => {
+            context.current_question = expanded;
+            if let Some((map, budget)) = &repo_map {
+                context.include_repo_map(map, *budget);
+            }
             context.as_prompt_prefix()
-        } else {
-            prompt.to_string()
         }
-    } else {
-        prompt.to_string()
+        (None, Some((map, budget))) => format!("{}\n\n{}", map.render(*budget), expanded),
+        (None, None) => expanded,
     };
 
     history_manager.add_user_message(prompt.to_string());
@@ -955,8 +1773,9 @@ async fn run_single_prompt(
     let (tx, mut rx) = mpsc::channel(100);
 
     let prompt_clone = full_prompt.clone();
+    let working_dir = project_dir.to_path_buf();
     let handle = tokio::spawn(async move {
-        tool_manager.execute_streaming(&prompt_clone, tool, tx).await
+        tool_manager.execute_streaming(&prompt_clone, tool, &working_dir, tx).await
     });
 
     let mut response_buffer = String::new();
@@ -971,21 +1790,39 @@ async fn run_single_prompt(
             ToolOutput::Stderr(line) => {
                 eprintln!("{}", line);
             }
-            ToolOutput::Done { .. } => {
+            ToolOutput::Action(action) => {
+                eprintln!("> {}", action);
+            }
+            ToolOutput::FilesChanged { changes, .. } => {
+                eprintln!("{} (polyglot-local undo to revert)", describe_changes(&changes));
+                history_manager.add_changed_files(&changes);
+            }
+            ToolOutput::NativeSession { tool, session_id } => {
+                history_manager.set_native_session(tool, session_id);
+            }
+            ToolOutput::Done { model, .. } => {
                 if !response_buffer.is_empty() {
-                    history_manager.add_assistant_message(response_buffer.trim().to_string());
+                    history_manager.add_assistant_message_with_model(response_buffer.trim().to_string(), model);
                 }
             }
             ToolOutput::Error(e) => {
                 eprintln!("Error: {}", e);
                 std::process::exit(1);
             }
+            ToolOutput::Redacted(report) => {
+                eprintln!("(Redacted from the prompt: {})", report);
+            }
+            ToolOutput::Terminal { tool, .. } => {
+                eprintln!("({} is running in a terminal; use the TUI to answer its prompts)", tool.display_name());
+            }
             ToolOutput::RateLimited { tool, next_tool } => {
                 eprintln!("\n{} rate limited.", tool.display_name());
                 if let Some(next) = next_tool {
                     eprintln!("Consider switching to: {}", next.display_name());
                 }
             }
+            // Only races cancel runs.
+            ToolOutput::Cancelled { .. } => {}
         }
     }
 
@@ -993,6 +1830,271 @@ async fn run_single_prompt(
     Ok(())
 }
 
+/// One line per configured pipeline, or why there are none.
+fn describe_pipelines(config: &LocalConfig) -> Vec<String> {
+    if config.pipelines.is_empty() {
+        return vec!["No pipelines are configured; add one under [pipelines].".to_string()];
+    }
+    config.pipelines.iter()
+        .map(|(name, pipeline)| match Pipeline::from_config(name, pipeline) {
+            Ok(pipeline) => format!("  {} - {}", name, pipeline),
+            Err(e) => format!("  {} - invalid: {}", name, e),
+        })
+        .collect()
+}
+
+/// Runs `pipeline` on `input`, printing each step as it streams, and records
+/// the run in the current session as one reply.
+async fn run_pipeline(
+    tool_manager: &LocalToolManager,
+    pipeline: Pipeline,
+    input: &str,
+    history_manager: &mut HistoryManager,
+    project_dir: &Path,
+) -> Result<PipelineRun> {
+    history_manager.add_user_message(input.to_string());
+    let (expanded, problems) = expand_references(input, project_dir, tool_manager, history_manager);
+    for problem in problems {
+        eprintln!("Warning: {}", problem);
+    }
+
+    let (tx, mut rx) = mpsc::channel(100);
+    let tm = tool_manager.clone();
+    let working_dir = project_dir.to_path_buf();
+    tokio::spawn(async move {
+        pipeline.run(tm, &expanded, &working_dir, tx).await;
+    });
+
+    while let Some(event) = rx.recv().await {
+        match event {
+            PipelineEvent::StepStarted { index, name, tool } => {
+                println!("\n=== Step {}: {} ({}) ===", index + 1, name, tool.display_name());
+            }
+            PipelineEvent::Output(output) => match output {
+                ToolOutput::Stdout(line) => println!("{}", line),
+                ToolOutput::Stderr(line) => eprintln!("[stderr] {}", line),
+                ToolOutput::Action(action) => eprintln!("> {}", action),
+                ToolOutput::FilesChanged { changes, .. } => {
+                    eprintln!("{} (polyglot-local undo to revert)", describe_changes(&changes));
+                    history_manager.add_changed_files(&changes);
+                }
+                ToolOutput::Done { tool, tokens: Some(t), .. } => {
+                    eprintln!("({} - {} tokens)", tool.display_name(), t);
+                }
+                ToolOutput::Redacted(report) => eprintln!("(Redacted from the prompt: {})", report),
+                ToolOutput::Terminal { tool, .. } => {
+                    eprintln!("({} is running in a terminal; use the TUI to answer its prompts)", tool.display_name());
+                }
+                ToolOutput::RateLimited { tool, .. } => eprintln!("{} rate limited.", tool.display_name()),
+                ToolOutput::Error(_) | ToolOutput::Done { .. } | ToolOutput::NativeSession { .. }
+                    | ToolOutput::Cancelled { .. } => {}
+            },
+            PipelineEvent::StepFinished { index, result: StepResult::Failed(reason) } => {
+                eprintln!("Step {} failed: {}", index + 1, reason);
+            }
+            PipelineEvent::StepFinished { .. } => {}
+            PipelineEvent::Finished(run) => {
+                history_manager.add_assistant_message(run.transcript());
+                update_summary(tool_manager, history_manager).await;
+                return Ok(run);
+            }
+        }
+    }
+    Err(anyhow::anyhow!("pipeline ended without a result"))
+}
+
+/// Opens the chat history, asking for its passphrase when `[history]`
+/// encrypts it without a key file.
+fn open_history(config: &LocalConfig) -> Result<HistoryManager> {
+    if !config.history.encrypt {
+        return HistoryManager::new(None);
+    }
+    if let Some(ref key_file) = config.history.key_file {
+        let key = HistoryKey::from_file(&expand_home(key_file))?;
+        return HistoryManager::open(None, Some(&key));
+    }
+
+    let db_path = HistoryManager::db_path(&HistoryManager::default_dir());
+    if !HistoryDb::is_encrypted(&db_path)? {
+        println!("Chat history will be encrypted.");
+        let key = new_passphrase()?;
+        return HistoryManager::open(None, Some(&key));
+    }
+
+    let mut attempts = 0;
+    loop {
+        let key = HistoryKey::Passphrase(read_secret("History passphrase: ")?);
+        attempts += 1;
+        match HistoryManager::open(None, Some(&key)) {
+            Err(e) if e.is::<WrongKey>() && attempts < 3 => eprintln!("Wrong passphrase, try again."),
+            result => return result,
+        }
+    }
+}
+
+/// Opens history for `project_dir` and unlocks the snapshots with its key.
+fn open_project_history(
+    config: &LocalConfig,
+    redactor: Option<Redactor>,
+    project_dir: &Path,
+    tool_manager: &LocalToolManager,
+) -> Result<HistoryManager> {
+    let mut history_manager = open_history(config)?;
+    history_manager.set_config(config.summarizer.clone());
+    history_manager.set_redactor(redactor);
+    history_manager.set_project(Some(project_dir.to_string_lossy().to_string()));
+    if let (Some(store), Some(cipher)) = (tool_manager.snapshots(), history_manager.cipher()) {
+        if let Err(e) = store.unlock(cipher) {
+            eprintln!("Warning: Failed to unlock snapshots, /undo is unavailable: {:#}", e);
+        }
+    }
+    Ok(history_manager)
+}
+
+fn rekey_history(history_manager: &mut HistoryManager, tool_manager: &LocalToolManager, config: &LocalConfig) -> Result<()> {
+    match config.history.key_file {
+        Some(ref key_file) => {
+            // The old key stays in place until the history uses the new one.
+            let path = expand_home(key_file);
+            let staged = path.with_extension("new");
+            let key = HistoryKey::write_file(&staged)?;
+            history_manager.rekey(&key)?;
+            std::fs::rename(&staged, &path)
+                .with_context(|| format!("History now uses the key in {}; move it to {}", staged.display(), path.display()))?;
+            println!("History re-encrypted with a new key in {}", path.display());
+        }
+        None => {
+            let key = new_passphrase()?;
+            history_manager.rekey(&key)?;
+            println!("History re-encrypted with the new passphrase.");
+        }
+    }
+
+    // The snapshot key is sealed with the history key, so it follows along
+    // once the new history key is in place.
+    if let (Some(store), Some(cipher)) = (tool_manager.snapshots(), history_manager.cipher()) {
+        store.rekey(cipher).context("Snapshots still use the old key, so earlier runs can't be undone")?;
+    }
+    Ok(())
+}
+
+/// Asks for a new passphrase twice, until both match.
+fn new_passphrase() -> Result<HistoryKey> {
+    loop {
+        let passphrase = read_secret("New history passphrase: ")?;
+        if passphrase.is_empty() {
+            eprintln!("The passphrase can't be empty.");
+            continue;
+        }
+        if read_secret("Repeat the passphrase: ")? == passphrase {
+            return Ok(HistoryKey::Passphrase(passphrase));
+        }
+        eprintln!("The passphrases don't match.");
+    }
+}
+
+/// Reads a line from the terminal without echoing it, or from stdin when
+/// it is piped.
+fn read_secret(prompt: &str) -> Result<String> {
+    use std::io::IsTerminal;
+    use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+    use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+
+    if !io::stdin().is_terminal() {
+        let mut line = String::new();
+        io::stdin().read_line(&mut line)?;
+        return Ok(line.trim_end_matches(['\r', '\n']).to_string());
+    }
+
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+    enable_raw_mode()?;
+    let mut passphrase = String::new();
+    let result = loop {
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => match key.code {
+                KeyCode::Enter => break Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    break Err(anyhow::anyhow!("Cancelled"));
+                }
+                KeyCode::Char(c) => passphrase.push(c),
+                KeyCode::Backspace => {
+                    passphrase.pop();
+                }
+                _ => {}
+            },
+            Ok(_) => {}
+            Err(e) => break Err(e.into()),
+        }
+    };
+    disable_raw_mode()?;
+    eprintln!();
+    result.map(|()| passphrase)
+}
+
+fn manage_keys(action: &KeysAction) -> Result<()> {
+    let vault = KeyVault::open(&KeyVault::default_dir())?;
+    let parse_tool = |name: &str| name.parse::<Tool>()
+        .map_err(|_| anyhow::anyhow!("Unknown tool: {}", name));
+
+    match action {
+        KeysAction::Add { tool, env } => {
+            let tool = parse_tool(tool)?;
+            let value = read_secret(&format!("{} API key: ", tool.display_name()))?;
+            let entry = vault.add(tool, env.as_deref(), &value)?;
+            println!("Stored {} for {}", entry.env_var, tool.display_name());
+        }
+        KeysAction::Rotate { tool, env } => {
+            let tool = parse_tool(tool)?;
+            let value = read_secret(&format!("New {} API key: ", tool.display_name()))?;
+            let entry = vault.rotate(tool, env.as_deref(), &value)?;
+            println!("Replaced {} for {}", entry.env_var, tool.display_name());
+        }
+        KeysAction::Remove { tool, env } => {
+            let tool = parse_tool(tool)?;
+            vault.remove(tool, env.as_deref())?;
+            println!("Removed the {} key", tool.display_name());
+        }
+        KeysAction::List => {
+            let entries = vault.list()?;
+            if entries.is_empty() {
+                println!("No keys stored. Add one with 'polyglot-local keys add <tool>'.");
+            }
+            for entry in entries {
+                let added = chrono::DateTime::parse_from_rfc3339(&entry.created_at)
+                    .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or(entry.created_at);
+                println!("  {:<20} {:<24} {}", entry.tool.display_name(), entry.env_var, added);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn import_history(history_manager: &HistoryManager, file: &Path) -> Result<()> {
+    let content = std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    let (sessions, format) = import_sessions(&content)
+        .with_context(|| format!("Cannot import {}", file.display()))?;
+
+    for session in &sessions {
+        history_manager.import_session(session)?;
+        println!("Imported \"{}\" ({} messages) from a {}",
+            polyglot_common::truncate_smart(&session.display_title(), 50),
+            session.messages.len(),
+            format.display_name());
+    }
+    println!("Resume with /history in 'polyglot-local chat'.");
+    Ok(())
+}
+
 fn show_history(history_manager: &HistoryManager, limit: usize, search: Option<String>) -> Result<()> {
     println!("Polyglot-AI Local - Chat History\n");
 
@@ -1017,6 +2119,7 @@ fn show_history(history_manager: &HistoryManager, limit: usize, search: Option<S
     if search.is_some() {
         for entry in history.iter().take(limit) {
             print_history_entry(entry);
+            println!("    {}", polyglot_common::truncate_smart(&entry.preview, 100));
         }
         println!();
         return Ok(());
@@ -1095,8 +2198,21 @@ fn show_usage(tool_manager: &LocalToolManager) -> Result<()> {
         println!("{}:", stat.tool.display_name());
         println!("  Requests:    {}", stat.requests);
         println!("  Tokens:      {}", stat.tokens_used);
+        if stat.input_tokens + stat.output_tokens > 0 {
+            println!("    In / Out:  {} / {}", stat.input_tokens, stat.output_tokens);
+        }
         println!("  Errors:      {}", stat.errors);
         println!("  Rate Limits: {}", stat.rate_limit_hits);
+        if stat.peak_memory_bytes > 0 || stat.cpu_time_ms > 0 {
+            println!("  Peak Memory: {}", tui::format_bytes(stat.peak_memory_bytes));
+            println!("  CPU Time:    {:.1}s", stat.cpu_time_ms as f64 / 1000.0);
+        }
+        if let Some(race) = tool_manager.race_record(stat.tool) {
+            match race.average_win() {
+                Some(avg) => println!("  Races:       won {} of {} (avg {:.1}s)", race.wins, race.races, avg.as_secs_f64()),
+                None => println!("  Races:       won 0 of {}", race.races),
+            }
+        }
         if let Some(last) = stat.last_used {
             println!("  Last Used:   {}", last.format("%Y-%m-%d %H:%M:%S"));
         }
@@ -1134,6 +2250,17 @@ async fn run_doctor(tool_manager: LocalToolManager) -> Result<()> {
 
     println!();
 
+    print!("Filesystem confinement ... ");
+    match sandbox::filesystem_confinement(tool_manager.sandbox()) {
+        FilesystemConfinement::Active { abi } => println!("\x1b[32mActive\x1b[0m (Landlock ABI {})", abi),
+        FilesystemConfinement::Disabled => println!("\x1b[33mDisabled\x1b[0m (sandbox.landlock = false)"),
+        FilesystemConfinement::Unsupported => {
+            println!("\x1b[33mUnavailable\x1b[0m (kernel has no Landlock; tools can reach the whole filesystem)")
+        }
+    }
+
+    println!();
+
     if all_ok {
         println!("\x1b[32mAt least one tool is available. You're ready to go!\x1b[0m");
     } else {
@@ -0,0 +1,210 @@
+//! Ollama HTTP client for local mode
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::mpsc;
+
+use polyglot_common::{
+    Message, NdjsonBuffer, OllamaChatChunk, OllamaChatRequest, OllamaPullProgress,
+    OllamaPullRequest, OllamaTags, ollama_url, DEFAULT_OLLAMA_HOST,
+};
+use crate::tools::ToolOutput;
+
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub struct OllamaClient {
+    hosts: Vec<String>,
+    model: Option<String>,
+    client: reqwest::Client,
+}
+
+impl OllamaClient {
+    pub fn new(hosts: &[String], model: Option<String>) -> Self {
+        let hosts = if hosts.is_empty() {
+            vec![DEFAULT_OLLAMA_HOST.to_string()]
+        } else {
+            hosts.to_vec()
+        };
+
+        Self {
+            hosts,
+            model,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn list_models(&self, host: &str) -> Result<OllamaTags> {
+        let response = self.client
+            .get(ollama_url(host, "/api/tags"))
+            .timeout(DISCOVERY_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.json::<OllamaTags>().await?)
+    }
+
+    pub async fn installed_models(&self) -> Vec<String> {
+        for host in &self.hosts {
+            if let Ok(tags) = self.list_models(host).await {
+                return tags.model_names();
+            }
+        }
+        Vec::new()
+    }
+
+    pub async fn is_available(&self) -> bool {
+        for host in &self.hosts {
+            if self.list_models(host).await.is_ok() {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns the host to talk to, the model to use and whether it still has to be pulled.
+    async fn resolve_target(&self) -> Result<(String, String, bool)> {
+        let mut fallback_host = None;
+
+        for host in &self.hosts {
+            let Ok(tags) = self.list_models(host).await else {
+                continue;
+            };
+
+            match &self.model {
+                Some(model) if tags.has_model(model) => return Ok((host.clone(), model.clone(), false)),
+                None => {
+                    if let Some(installed) = tags.models.first() {
+                        return Ok((host.clone(), installed.name.clone(), false));
+                    }
+                }
+                _ => {}
+            }
+
+            if fallback_host.is_none() {
+                fallback_host = Some(host.clone());
+            }
+        }
+
+        match (fallback_host, &self.model) {
+            (Some(host), Some(model)) => Ok((host, model.clone(), true)),
+            (Some(host), None) => Err(anyhow!(
+                "No models installed on {}. Pull one or set `model` under [tools.ollama].", host
+            )),
+            (None, _) => Err(anyhow!("No Ollama server reachable at {}", self.hosts.join(", "))),
+        }
+    }
+
+    async fn pull(&self, host: &str, model: &str, output_tx: &mpsc::Sender<ToolOutput>) -> Result<()> {
+        output_tx.send(ToolOutput::Stderr(format!(
+            "Model '{}' is not on {}; pulling it now (this may take a while)...", model, host
+        ))).await.ok();
+
+        let mut response = self.client
+            .post(ollama_url(host, "/api/pull"))
+            .json(&OllamaPullRequest { model: model.to_string(), stream: true })
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let mut buffer = NdjsonBuffer::new();
+        let mut last_status = String::new();
+
+        loop {
+            let chunk = response.chunk().await?;
+            let finished = chunk.is_none();
+            let lines = match chunk {
+                Some(bytes) => buffer.push(&bytes),
+                None => buffer.finish().into_iter().collect(),
+            };
+
+            for line in lines {
+                let Ok(progress) = serde_json::from_str::<OllamaPullProgress>(&line) else {
+                    continue;
+                };
+                if let Some(err) = progress.error {
+                    return Err(anyhow!("Pulling '{}' failed: {}", model, err));
+                }
+                let status = progress.describe();
+                if status != last_status {
+                    output_tx.send(ToolOutput::Stderr(status.clone())).await.ok();
+                    last_status = status;
+                }
+            }
+
+            if finished {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Streams a chat completion line by line and returns the exact token
+    /// count together with the model that answered.
+    pub async fn chat(
+        &self,
+        history: &[Message],
+        prompt: &str,
+        output_tx: &mpsc::Sender<ToolOutput>,
+    ) -> Result<(Option<u64>, String)> {
+        let (host, model, needs_pull) = self.resolve_target().await?;
+        if needs_pull {
+            self.pull(&host, &model, output_tx).await?;
+        }
+
+        let body = OllamaChatRequest::new(&model, history, prompt);
+        let mut response = self.client
+            .post(ollama_url(&host, "/api/chat"))
+            .json(&body)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(anyhow!("Ollama returned {}: {}", status, response.text().await.unwrap_or_default()));
+        }
+
+        let mut buffer = NdjsonBuffer::new();
+        let mut line = String::new();
+        let mut tokens = None;
+
+        loop {
+            let chunk = response.chunk().await?;
+            let finished = chunk.is_none();
+            let lines = match chunk {
+                Some(bytes) => buffer.push(&bytes),
+                None => buffer.finish().into_iter().collect(),
+            };
+
+            for raw in lines {
+                let Ok(parsed) = serde_json::from_str::<OllamaChatChunk>(&raw) else {
+                    continue;
+                };
+                if let Some(err) = parsed.error {
+                    return Err(anyhow!(err));
+                }
+
+                line.push_str(parsed.content());
+                while let Some(pos) = line.find('\n') {
+                    let complete: String = line.drain(..=pos).collect();
+                    if output_tx.send(ToolOutput::Stdout(complete.trim_end_matches('\n').to_string())).await.is_err() {
+                        return Ok((tokens, model));
+                    }
+                }
+
+                if parsed.done {
+                    tokens = parsed.total_tokens();
+                }
+            }
+
+            if finished {
+                break;
+            }
+        }
+
+        if !line.is_empty() {
+            output_tx.send(ToolOutput::Stdout(line)).await.ok();
+        }
+
+        Ok((tokens, model))
+    }
+}
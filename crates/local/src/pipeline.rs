@@ -0,0 +1,381 @@
+//! Named chains of tools, each step prompted with the output of earlier ones
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use tokio::sync::mpsc;
+
+use polyglot_common::Tool;
+use crate::config::{LocalConfig, PipelineConfig};
+use crate::tools::{LocalToolManager, ToolOutput};
+
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub name: String,
+    pub tool: Tool,
+    template: String,
+    /// Failing this step never stops the run
+    optional: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    pub name: String,
+    pub steps: Vec<Step>,
+    /// Run the remaining steps after a failure instead of stopping
+    keep_going: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepResult {
+    Done { output: String, model: Option<String> },
+    Failed(String),
+    /// An earlier step failed and stopped the run
+    Skipped,
+}
+
+#[derive(Debug)]
+pub enum PipelineEvent {
+    StepStarted { index: usize, name: String, tool: Tool },
+    /// Streamed output of the running step
+    Output(ToolOutput),
+    StepFinished { index: usize, result: StepResult },
+    /// Always the last event of a run
+    Finished(PipelineRun),
+}
+
+/// A finished run, with one result per step
+#[derive(Debug, Clone)]
+pub struct PipelineRun {
+    pub pipeline: Pipeline,
+    pub results: Vec<StepResult>,
+}
+
+impl Pipeline {
+    /// The pipeline called `name` under `[pipelines]`.
+    pub fn named(config: &LocalConfig, name: &str) -> Result<Self> {
+        match config.pipelines.get(name) {
+            Some(pipeline) => Self::from_config(name, pipeline),
+            None if config.pipelines.is_empty() => bail!("No pipelines are configured; add one under [pipelines]"),
+            None => bail!(
+                "No pipeline named {}; configured: {}",
+                name,
+                config.pipelines.keys().cloned().collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
+
+    pub fn from_config(name: &str, config: &PipelineConfig) -> Result<Self> {
+        let mut steps: Vec<Step> = Vec::new();
+        for spec in config.steps.split("->").map(str::trim) {
+            let (step_name, tool_name) = match spec.split_once(':') {
+                Some((step_name, tool_name)) => (step_name.trim(), tool_name.trim()),
+                None => (spec, spec),
+            };
+            if step_name.is_empty() || !step_name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+                bail!("Pipeline {}: invalid step {:?}", name, spec);
+            }
+            if matches!(step_name, "input" | "previous") {
+                bail!("Pipeline {}: {} is reserved for templates and can't name a step", name, step_name);
+            }
+            if steps.iter().any(|s| s.name == step_name) {
+                bail!("Pipeline {}: step {} appears twice", name, step_name);
+            }
+            let tool = tool_name.parse::<Tool>()
+                .map_err(|_| anyhow::anyhow!("Pipeline {}: unknown tool {} in step {}", name, tool_name, step_name))?;
+
+            let template = match (config.prompts.get(step_name), steps.last()) {
+                (Some(template), _) => template.clone(),
+                (None, None) => "{input}".to_string(),
+                (None, Some(prev)) => format!("{{input}}\n\nOutput of the {} step:\n\n{{previous}}", prev.name),
+            };
+
+            steps.push(Step {
+                name: step_name.to_string(),
+                tool,
+                template,
+                optional: config.optional.iter().any(|o| o == step_name),
+            });
+        }
+
+        for step_name in config.prompts.keys().chain(&config.optional) {
+            if !steps.iter().any(|s| &s.name == step_name) {
+                bail!("Pipeline {}: there is no step named {}", name, step_name);
+            }
+        }
+
+        // A step's output only exists once it has run.
+        for (index, step) in steps.iter().enumerate() {
+            let later = placeholders(&step.template)
+                .find(|key| steps[index..].iter().any(|s| s.name == *key));
+            if let Some(later) = later {
+                bail!("Pipeline {}: the prompt for {} uses {{{}}}, which hasn't run yet", name, step.name, later);
+            }
+        }
+
+        let keep_going = match config.on_failure.as_str() {
+            "stop" => false,
+            "continue" => true,
+            other => bail!("Pipeline {}: on_failure must be \"stop\" or \"continue\", not {:?}", name, other),
+        };
+
+        Ok(Self { name: name.to_string(), steps, keep_going })
+    }
+
+    /// The prompt for step `index`, given the results of the steps before it.
+    fn prompt_for(&self, index: usize, input: &str, results: &[StepResult]) -> String {
+        let mut values: HashMap<&str, &str> = HashMap::from([("input", input), ("previous", input)]);
+        for (step, result) in self.steps.iter().zip(results) {
+            let output = match result {
+                StepResult::Done { output, .. } => {
+                    values.insert("previous", output);
+                    output.as_str()
+                }
+                _ => "",
+            };
+            values.insert(&step.name, output);
+        }
+        render(&self.steps[index].template, &values)
+    }
+
+    /// Whether `result` of step `index` ends the run.
+    fn stops_after(&self, index: usize, result: &StepResult) -> bool {
+        matches!(result, StepResult::Failed(_)) && !self.keep_going && !self.steps[index].optional
+    }
+
+    /// Runs each step in turn in `working_dir`, reporting progress on
+    /// `events`. Steps start fresh rather than resuming the chat's tool
+    /// sessions; models chosen on `tool_manager` are kept.
+    pub async fn run(
+        &self,
+        mut tool_manager: LocalToolManager,
+        input: &str,
+        working_dir: &Path,
+        events: mpsc::Sender<PipelineEvent>,
+    ) {
+        tool_manager.set_history(Vec::new());
+        tool_manager.set_native_sessions(HashMap::new());
+
+        let mut results: Vec<StepResult> = Vec::new();
+        let mut stopped = false;
+        for (index, step) in self.steps.iter().enumerate() {
+            if stopped {
+                results.push(StepResult::Skipped);
+                continue;
+            }
+
+            let _ = events.send(PipelineEvent::StepStarted { index, name: step.name.clone(), tool: step.tool }).await;
+            let prompt = self.prompt_for(index, input, &results);
+            let result = run_step(&mut tool_manager, step.tool, &prompt, working_dir, &events).await;
+            stopped = self.stops_after(index, &result);
+            let _ = events.send(PipelineEvent::StepFinished { index, result: result.clone() }).await;
+            results.push(result);
+        }
+
+        let run = PipelineRun { pipeline: self.clone(), results };
+        let _ = events.send(PipelineEvent::Finished(run)).await;
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let steps: Vec<String> = self.steps.iter()
+            .map(|s| format!("{}: {}", s.name, s.tool.as_str()))
+            .collect();
+        write!(f, "{}", steps.join(" -> "))
+    }
+}
+
+impl PipelineRun {
+    /// The first step that failed and wasn't optional, if any.
+    pub fn failure(&self) -> Option<(&Step, &str)> {
+        self.pipeline.steps.iter().zip(&self.results).find_map(|(step, result)| match result {
+            StepResult::Failed(reason) if !step.optional => Some((step, reason.as_str())),
+            _ => None,
+        })
+    }
+
+    /// Every step's output in one message, for the chat history.
+    pub fn transcript(&self) -> String {
+        let mut text = format!("Pipeline {}: {}\n", self.pipeline.name, self.pipeline);
+        for (step, result) in self.pipeline.steps.iter().zip(&self.results) {
+            let header = match result {
+                StepResult::Done { model: Some(model), .. } => format!("{} ({}, {})", step.name, step.tool.display_name(), model),
+                StepResult::Done { model: None, .. } => format!("{} ({})", step.name, step.tool.display_name()),
+                StepResult::Failed(reason) => format!("{} ({}) failed: {}", step.name, step.tool.display_name(), reason),
+                StepResult::Skipped => format!("{} ({}) skipped", step.name, step.tool.display_name()),
+            };
+            text.push_str(&format!("\n--- {} ---\n", header));
+            if let StepResult::Done { output, .. } = result {
+                text.push_str(output);
+                text.push('\n');
+            }
+        }
+        text.trim_end().to_string()
+    }
+}
+
+/// Runs one step, forwarding its output on `events`. A step fails on an
+/// error, a rate limit or an empty reply.
+async fn run_step(
+    tool_manager: &mut LocalToolManager,
+    tool: Tool,
+    prompt: &str,
+    working_dir: &Path,
+    events: &mpsc::Sender<PipelineEvent>,
+) -> StepResult {
+    let (tx, mut rx) = mpsc::channel::<ToolOutput>(100);
+
+    let mut reply = String::new();
+    let mut model = None;
+    let mut failure = None;
+    let forward = async {
+        while let Some(output) = rx.recv().await {
+            match &output {
+                ToolOutput::Stdout(line) => {
+                    reply.push_str(line);
+                    reply.push('\n');
+                }
+                ToolOutput::Done { model: m, .. } => model = m.clone(),
+                ToolOutput::Error(e) => failure = Some(e.clone()),
+                ToolOutput::RateLimited { tool, .. } => {
+                    failure = Some(format!("{} is rate limited", tool.display_name()));
+                }
+                // The step's session isn't the chat's, so it isn't resumed.
+                ToolOutput::NativeSession { .. } => continue,
+                _ => {}
+            }
+            let _ = events.send(PipelineEvent::Output(output)).await;
+        }
+    };
+
+    let (executed, ()) = tokio::join!(tool_manager.execute_streaming(prompt, Some(tool), working_dir, tx), forward);
+    if let Err(e) = executed {
+        failure.get_or_insert(e.to_string());
+    }
+
+    match failure {
+        Some(reason) => StepResult::Failed(reason),
+        None if reply.trim().is_empty() => StepResult::Failed("no output".to_string()),
+        None => StepResult::Done { output: reply.trim().to_string(), model },
+    }
+}
+
+/// The `{name}` placeholders in `template`.
+fn placeholders(template: &str) -> impl Iterator<Item = &str> {
+    template.split('{').skip(1).filter_map(|part| part.split_once('}').map(|(key, _)| key))
+}
+
+/// Fills in the placeholders of `template` named in `values`, in one pass so
+/// that braces in the substituted text are left alone. Other braces are
+/// kept as written.
+fn render(template: &str, values: &HashMap<&str, &str>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.split_once('}').and_then(|(key, tail)| Some((values.get(key)?, tail))) {
+            Some((value, tail)) => {
+                out.push_str(value);
+                rest = tail;
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(steps: &str) -> PipelineConfig {
+        PipelineConfig {
+            steps: steps.to_string(),
+            prompts: HashMap::new(),
+            on_failure: "stop".to_string(),
+            optional: Vec::new(),
+        }
+    }
+
+    fn done(output: &str) -> StepResult {
+        StepResult::Done { output: output.to_string(), model: None }
+    }
+
+    #[test]
+    fn test_steps_are_parsed_and_templated() {
+        let mut cfg = config("plan: claude -> implement: codex -> review: gemini");
+        cfg.prompts.insert("review".to_string(), "Review {implement} against {plan} for: {input} {unknown}".to_string());
+        let pipeline = Pipeline::from_config("ship", &cfg).unwrap();
+        assert_eq!(pipeline.to_string(), "plan: claude -> implement: codex -> review: gemini");
+
+        assert_eq!(pipeline.prompt_for(0, "add login", &[]), "add login");
+        assert_eq!(
+            pipeline.prompt_for(1, "add login", &[done("1. {input} form")]),
+            "add login\n\nOutput of the plan step:\n\n1. {input} form"
+        );
+        assert_eq!(
+            pipeline.prompt_for(2, "add login", &[done("P"), done("I")]),
+            "Review I against P for: add login {unknown}"
+        );
+    }
+
+    #[test]
+    fn test_invalid_pipelines_are_rejected() {
+        let unknown_tool = Pipeline::from_config("p", &config("plan: nope"));
+        assert!(unknown_tool.unwrap_err().to_string().contains("unknown tool nope"));
+        assert!(Pipeline::from_config("p", &config("a: claude -> a: codex")).is_err());
+        assert!(Pipeline::from_config("p", &config("input: claude")).is_err());
+
+        let mut forward_ref = config("plan: claude -> review: gemini");
+        forward_ref.prompts.insert("plan".to_string(), "Plan, then {review}".to_string());
+        assert!(Pipeline::from_config("p", &forward_ref).unwrap_err().to_string().contains("hasn't run yet"));
+
+        let mut bad_optional = config("plan: claude");
+        bad_optional.optional.push("review".to_string());
+        assert!(Pipeline::from_config("p", &bad_optional).is_err());
+
+        let mut bad_rule = config("plan: claude");
+        bad_rule.on_failure = "retry".to_string();
+        assert!(Pipeline::from_config("p", &bad_rule).is_err());
+
+        let bare = Pipeline::from_config("p", &config("claude -> gemini")).unwrap();
+        assert_eq!(bare.steps[1].name, "gemini");
+    }
+
+    #[test]
+    fn test_failures_stop_unless_allowed() {
+        let failed = StepResult::Failed("boom".to_string());
+
+        let mut cfg = config("plan: claude -> implement: codex -> review: gemini");
+        cfg.optional.push("review".to_string());
+        let pipeline = Pipeline::from_config("ship", &cfg).unwrap();
+        assert!(pipeline.stops_after(0, &failed));
+        assert!(!pipeline.stops_after(0, &done("ok")));
+        assert!(!pipeline.stops_after(2, &failed));
+
+        cfg.on_failure = "continue".to_string();
+        let pipeline = Pipeline::from_config("ship", &cfg).unwrap();
+        assert!(!pipeline.stops_after(0, &failed));
+        // Later steps see the last good output as {previous}.
+        assert!(pipeline.prompt_for(2, "task", &[done("plan"), failed.clone()]).ends_with("\n\nplan"));
+
+        let run = PipelineRun {
+            pipeline,
+            results: vec![done("the plan"), failed, StepResult::Skipped],
+        };
+        assert_eq!(run.failure().map(|(step, reason)| (step.name.as_str(), reason)), Some(("implement", "boom")));
+        let transcript = run.transcript();
+        assert!(transcript.starts_with("Pipeline ship: plan: claude"));
+        assert!(transcript.contains("--- plan (Claude Code) ---\nthe plan\n"));
+        assert!(transcript.contains("--- implement (Codex CLI) failed: boom ---"));
+        assert!(transcript.ends_with("--- review (Gemini CLI) skipped ---"));
+    }
+}
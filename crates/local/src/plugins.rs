@@ -309,7 +309,8 @@ impl PluginManager {
         if status.success() {
             output_tx.send(ToolOutput::Done {
                 tool: polyglot_common::Tool::Claude,
-                tokens: None
+                tokens: None,
+                model: None,
             }).await.ok();
         } else {
             if let Some(usage) = self.usage.get_mut(&plugin_name) {
@@ -376,7 +377,8 @@ impl PluginManager {
         if output.status.success() {
             output_tx.send(ToolOutput::Done {
                 tool: polyglot_common::Tool::Claude,
-                tokens: None
+                tokens: None,
+                model: None,
             }).await.ok();
         } else {
             let plugin_name = name.to_string();
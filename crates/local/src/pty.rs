@@ -0,0 +1,365 @@
+//! Pseudo-terminal mode for tools that need a TTY
+//!
+//! The tool is spawned with the terminal's slave side as its stdin, stdout,
+//! stderr and controlling terminal, through the same `Command` as pipe mode
+//! so every sandbox hook still applies. Output is read from the master side,
+//! stripped of escape sequences and split into lines; keystrokes from the
+//! user are written back to it.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use portable_pty::{native_pty_system, MasterPty, PtySize};
+use tokio::process::Command;
+use tokio::sync::{mpsc, oneshot};
+
+/// Wide enough that tools don't wrap lines themselves
+const TERMINAL_SIZE: PtySize = PtySize { rows: 50, cols: 200, pixel_width: 0, pixel_height: 0 };
+
+/// A partial line left this long is most likely a prompt waiting for input
+const PROMPT_IDLE: Duration = Duration::from_millis(200);
+
+/// How long to keep reading after the tool exits, for output still in flight
+/// and for children that inherited the terminal
+const EXIT_GRACE: Duration = Duration::from_millis(500);
+
+pub struct Terminal {
+    master: Box<dyn MasterPty + Send>,
+}
+
+impl Terminal {
+    /// Opens a pseudo-terminal and makes it `cmd`'s stdio and controlling terminal.
+    #[cfg(unix)]
+    pub fn attach(cmd: &mut Command) -> io::Result<Self> {
+        use std::fs::OpenOptions;
+        use std::os::unix::fs::OpenOptionsExt;
+        use std::process::Stdio;
+
+        let pair = native_pty_system().openpty(TERMINAL_SIZE).map_err(io::Error::other)?;
+        let tty = pair.master.tty_name()
+            .ok_or_else(|| io::Error::other("The pseudo-terminal has no device path"))?;
+        let slave = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY | libc::O_CLOEXEC)
+            .open(&tty)?;
+
+        cmd.stdin(Stdio::from(slave.try_clone()?));
+        cmd.stdout(Stdio::from(slave.try_clone()?));
+        cmd.stderr(Stdio::from(slave));
+
+        unsafe {
+            cmd.pre_exec(|| {
+                // A new session, so the terminal can become the controlling one.
+                if libc::setsid() < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if libc::ioctl(0, libc::TIOCSCTTY as _, 0) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        Ok(Self { master: pair.master })
+    }
+
+    #[cfg(not(unix))]
+    pub fn attach(_cmd: &mut Command) -> io::Result<Self> {
+        let _ = (native_pty_system, TERMINAL_SIZE);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "PTY mode is only available on Unix; set pty = false for this tool",
+        ))
+    }
+
+    /// Starts relaying the terminal once the tool is spawned. `exited` fires
+    /// when the tool exits. With `flush_prompts`, a partial line that sits
+    /// idle is passed on, so prompts show up before the user answers them.
+    pub fn start(self, flush_prompts: bool, exited: oneshot::Receiver<()>) -> io::Result<(PtyInput, TerminalLines)> {
+        let mut reader = self.master.try_clone_reader().map_err(io::Error::other)?;
+        let mut writer = self.master.take_writer().map_err(io::Error::other)?;
+
+        let (chunk_tx, chunk_rx) = mpsc::channel::<Vec<u8>>(64);
+        let master = self.master;
+        std::thread::spawn(move || {
+            let _master = master;
+            let mut buf = [0u8; 4096];
+            // Reads fail with EIO once every process has closed the slave side.
+            while let Ok(n) = reader.read(&mut buf) {
+                if n == 0 || chunk_tx.blocking_send(buf[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let (input_tx, input_rx) = std::sync::mpsc::channel::<Vec<u8>>();
+        std::thread::spawn(move || {
+            for bytes in input_rx {
+                if writer.write_all(&bytes).and_then(|_| writer.flush()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let lines = TerminalLines {
+            chunks: chunk_rx,
+            exited,
+            exit_seen: false,
+            done: false,
+            flush_prompts,
+            buffer: LineBuffer::default(),
+            ready: VecDeque::new(),
+        };
+        Ok((PtyInput { tx: input_tx }, lines))
+    }
+}
+
+/// Types into a tool running under a pseudo-terminal
+#[derive(Debug, Clone)]
+pub struct PtyInput {
+    tx: std::sync::mpsc::Sender<Vec<u8>>,
+}
+
+impl PtyInput {
+    /// Types `line` and presses Enter. Returns false once the tool is gone.
+    pub fn send_line(&self, line: &str) -> bool {
+        self.tx.send(format!("{}\r", line).into_bytes()).is_ok()
+    }
+}
+
+/// The terminal's output as plain lines
+pub struct TerminalLines {
+    chunks: mpsc::Receiver<Vec<u8>>,
+    exited: oneshot::Receiver<()>,
+    exit_seen: bool,
+    done: bool,
+    flush_prompts: bool,
+    buffer: LineBuffer,
+    ready: VecDeque<String>,
+}
+
+impl TerminalLines {
+    /// The next line, or `None` once the tool has exited and its output is drained.
+    pub async fn next_line(&mut self) -> Option<String> {
+        loop {
+            if let Some(line) = self.ready.pop_front() {
+                return Some(line);
+            }
+            if self.done {
+                return None;
+            }
+
+            let wait = if self.exit_seen {
+                EXIT_GRACE
+            } else if self.flush_prompts && self.buffer.has_partial() {
+                PROMPT_IDLE
+            } else {
+                Duration::from_secs(3600)
+            };
+
+            tokio::select! {
+                chunk = self.chunks.recv() => match chunk {
+                    Some(bytes) => self.buffer.feed(&bytes, &mut self.ready),
+                    None => self.finish(),
+                },
+                _ = &mut self.exited, if !self.exit_seen => self.exit_seen = true,
+                _ = tokio::time::sleep(wait) => {
+                    if self.exit_seen {
+                        self.finish();
+                    } else {
+                        self.ready.extend(self.buffer.take_partial());
+                    }
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        self.ready.extend(self.buffer.take_partial());
+        self.done = true;
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    #[default]
+    None,
+    Esc,
+    /// `ESC [` up to a final byte
+    Csi,
+    /// `ESC ]` and other strings ended by BEL or `ESC \`
+    Str,
+    StrEsc,
+}
+
+/// Turns a terminal byte stream into the lines a user would read, dropping
+/// colors, cursor movement and lines overwritten with a carriage return.
+#[derive(Debug, Default)]
+struct LineBuffer {
+    line: String,
+    escape: Escape,
+    carriage_return: bool,
+    /// The start of a UTF-8 character split across reads
+    partial_char: Vec<u8>,
+}
+
+impl LineBuffer {
+    fn feed(&mut self, bytes: &[u8], out: &mut VecDeque<String>) {
+        let mut data = std::mem::take(&mut self.partial_char);
+        data.extend_from_slice(bytes);
+
+        let mut rest = data.as_slice();
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(text) => {
+                    self.feed_str(text, out);
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    self.feed_str(std::str::from_utf8(valid).unwrap_or_default(), out);
+                    match e.error_len() {
+                        Some(len) => {
+                            self.feed_str("\u{FFFD}", out);
+                            rest = &after[len..];
+                        }
+                        None => {
+                            self.partial_char = after.to_vec();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn feed_str(&mut self, text: &str, out: &mut VecDeque<String>) {
+        for c in text.chars() {
+            match self.escape {
+                Escape::Esc => {
+                    self.escape = match c {
+                        '[' => Escape::Csi,
+                        ']' | 'P' | '_' | '^' => Escape::Str,
+                        _ => Escape::None,
+                    };
+                    continue;
+                }
+                Escape::Csi => {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        self.escape = Escape::None;
+                    }
+                    continue;
+                }
+                Escape::Str => {
+                    match c {
+                        '\x07' => self.escape = Escape::None,
+                        '\x1b' => self.escape = Escape::StrEsc,
+                        _ => {}
+                    }
+                    continue;
+                }
+                Escape::StrEsc => {
+                    self.escape = if c == '\\' { Escape::None } else { Escape::Str };
+                    continue;
+                }
+                Escape::None => {}
+            }
+
+            if c == '\n' {
+                self.carriage_return = false;
+                out.push_back(self.line.trim_end().to_string());
+                self.line.clear();
+                continue;
+            }
+            if self.carriage_return {
+                // Redrawn from the start, as progress bars do.
+                self.carriage_return = false;
+                if c != '\r' {
+                    self.line.clear();
+                }
+            }
+
+            match c {
+                '\x1b' => self.escape = Escape::Esc,
+                '\r' => self.carriage_return = true,
+                '\x08' => {
+                    self.line.pop();
+                }
+                '\t' => self.line.push(c),
+                c if c.is_control() => {}
+                c => self.line.push(c),
+            }
+        }
+    }
+
+    fn has_partial(&self) -> bool {
+        !self.line.trim().is_empty()
+    }
+
+    fn take_partial(&mut self) -> Option<String> {
+        let line = std::mem::take(&mut self.line);
+        self.carriage_return = false;
+        let line = line.trim_end();
+        (!line.is_empty()).then(|| line.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clean(chunks: &[&[u8]]) -> Vec<String> {
+        let mut buffer = LineBuffer::default();
+        let mut out = VecDeque::new();
+        for chunk in chunks {
+            buffer.feed(chunk, &mut out);
+        }
+        out.extend(buffer.take_partial());
+        out.into_iter().collect()
+    }
+
+    #[test]
+    fn test_escapes_and_redraws_are_cleaned() {
+        assert_eq!(
+            clean(&[b"\x1b[1;32mok\x1b[0m done\r\n", b"\x1b]0;title\x07plain\r\n"]),
+            vec!["ok done", "plain"]
+        );
+        // Only the last redraw of a progress bar survives.
+        assert_eq!(clean(&[b" 10%\r 50%\r", b"\x1b[2K100%\r\n"]), vec!["100%"]);
+        assert_eq!(clean(&[b"abd\x08c\n"]), vec!["abc"]);
+        // A character split across reads.
+        assert_eq!(clean(&["héllo\n".as_bytes()[..2].as_ref(), &"héllo\n".as_bytes()[2..]]), vec!["héllo"]);
+        assert_eq!(clean(&[b"Proceed? [y/N] "]), vec!["Proceed? [y/N]"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_tool_sees_a_terminal_and_gets_input() {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg("test -t 0 && test -t 1 && echo tty; printf 'Name? '; read name; echo \"hi $name\"");
+        cmd.env("TERM", "dumb");
+        let terminal = Terminal::attach(&mut cmd).unwrap();
+        let mut child = cmd.spawn().unwrap();
+        drop(cmd);
+
+        let (exit_tx, exit_rx) = oneshot::channel();
+        let (input, mut lines) = terminal.start(true, exit_rx).unwrap();
+
+        assert_eq!(lines.next_line().await.as_deref(), Some("tty"));
+        assert_eq!(lines.next_line().await.as_deref(), Some("Name?"));
+        assert!(input.send_line("polyglot"));
+
+        let status = child.wait().await.unwrap();
+        exit_tx.send(()).unwrap();
+        assert!(status.success());
+
+        let mut rest = Vec::new();
+        while let Some(line) = lines.next_line().await {
+            rest.push(line);
+        }
+        // The terminal echoes what was typed.
+        assert_eq!(rest, vec!["polyglot", "hi polyglot"]);
+    }
+}
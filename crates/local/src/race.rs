@@ -0,0 +1,132 @@
+//! Race mode results, kept across runs so that fallback tools are tried in
+//! the order they tend to answer
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use polyglot_common::Tool;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RaceRecord {
+    /// Races the tool was entered in
+    pub races: u64,
+    pub wins: u64,
+    /// Time to answer summed over the tool's wins
+    pub win_ms: u64,
+}
+
+impl RaceRecord {
+    /// Share of races won, pulled towards one half until there are a few
+    /// races to go on, so one lucky win doesn't put a tool first.
+    pub fn win_rate(&self) -> f64 {
+        (self.wins as f64 + 1.0) / (self.races as f64 + 2.0)
+    }
+
+    pub fn average_win(&self) -> Option<Duration> {
+        (self.wins > 0).then(|| Duration::from_millis(self.win_ms / self.wins))
+    }
+}
+
+#[derive(Default)]
+pub struct RaceStats {
+    /// Where the stats are saved; `None` keeps them in memory
+    path: Option<PathBuf>,
+    records: HashMap<Tool, RaceRecord>,
+}
+
+impl RaceStats {
+    pub fn default_path() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("polyglot-ai")
+            .join("race-stats.json")
+    }
+
+    /// Loads the stats saved at `path`. Missing or unreadable stats start
+    /// over, since they only affect the order tools are tried in.
+    pub fn load(path: PathBuf) -> Self {
+        let records = match fs::read_to_string(&path) {
+            Ok(data) => serde_json::from_str(&data).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring race stats in {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        Self { path: Some(path), records }
+    }
+
+    pub fn get(&self, tool: Tool) -> Option<&RaceRecord> {
+        self.records.get(&tool)
+    }
+
+    /// Counts a race between `entrants`, won by the tool in `winner` after
+    /// the given time. Nobody wins when every tool failed.
+    pub fn record(&mut self, entrants: &[Tool], winner: Option<(Tool, Duration)>) {
+        for tool in entrants {
+            self.records.entry(*tool).or_default().races += 1;
+        }
+        if let Some((tool, elapsed)) = winner {
+            let record = self.records.entry(tool).or_default();
+            record.wins += 1;
+            record.win_ms += elapsed.as_millis() as u64;
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let Some(ref path) = self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(&self.records)?)
+            .with_context(|| format!("Failed to save race stats to {}", path.display()))
+    }
+
+    /// Puts tools that have raced first, best win rate and then fastest
+    /// wins first. Tools that never raced keep their order after them.
+    pub fn rank(&self, tools: &mut [Tool]) {
+        tools.sort_by(|a, b| match (self.records.get(a), self.records.get(b)) {
+            (Some(a), Some(b)) => b.win_rate().total_cmp(&a.win_rate())
+                .then_with(|| a.average_win().unwrap_or(Duration::MAX).cmp(&b.average_win().unwrap_or(Duration::MAX))),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_races_are_recorded_and_rank_tools() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("race-stats.json");
+        let mut stats = RaceStats::load(path.clone());
+
+        let entrants = [Tool::Claude, Tool::Gemini, Tool::Codex];
+        stats.record(&entrants, Some((Tool::Gemini, Duration::from_millis(1200))));
+        stats.record(&entrants, Some((Tool::Gemini, Duration::from_millis(800))));
+        stats.record(&entrants, Some((Tool::Codex, Duration::from_millis(500))));
+        stats.record(&entrants, None);
+        stats.save().unwrap();
+
+        let stats = RaceStats::load(path);
+        let gemini = stats.get(Tool::Gemini).unwrap();
+        assert_eq!((gemini.races, gemini.wins), (4, 2));
+        assert_eq!(gemini.average_win(), Some(Duration::from_millis(1000)));
+        assert_eq!(stats.get(Tool::Claude).unwrap().average_win(), None);
+
+        let mut tools = [Tool::Cursor, Tool::Claude, Tool::Codex, Tool::Perplexity, Tool::Gemini];
+        stats.rank(&mut tools);
+        assert_eq!(tools, [Tool::Gemini, Tool::Codex, Tool::Claude, Tool::Cursor, Tool::Perplexity]);
+    }
+}
@@ -13,6 +13,11 @@ pub struct SandboxConfig {
     pub max_cpu_percent: Option<u8>,
     pub network_access: NetworkPolicy,
     pub env_whitelist: HashSet<String>,
+    pub cgroups: bool,
+    pub cgroup_parent: Option<PathBuf>,
+    pub max_pids: Option<u32>,
+    pub max_io_mbps: Option<u64>,
+    pub landlock: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -22,6 +27,158 @@ pub enum NetworkPolicy {
     AllowAll,
 }
 
+/// Syscall filter applied to a tool's process
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SeccompProfile {
+    #[default]
+    Off,
+    /// Denies debugging, mounts, kernel modules, raw sockets and similar calls
+    Baseline,
+    /// Baseline plus namespaces, io_uring and userfaultfd
+    Strict,
+    /// Allows everything but logs the calls `Strict` would deny, for tuning
+    Log,
+}
+
+impl std::str::FromStr for SeccompProfile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "off" => Ok(SeccompProfile::Off),
+            "baseline" => Ok(SeccompProfile::Baseline),
+            "strict" => Ok(SeccompProfile::Strict),
+            "log" => Ok(SeccompProfile::Log),
+            other => bail!("seccomp must be one of off|baseline|strict|log, not {:?}", other),
+        }
+    }
+}
+
+impl NetworkPolicy {
+    /// The `network_access` config value for this policy
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NetworkPolicy::Deny => "deny",
+            NetworkPolicy::AllowLocalhost => "localhost",
+            NetworkPolicy::AllowAll => "allow_all",
+        }
+    }
+
+    /// Whether tools have to be started in their own network namespace
+    pub fn needs_isolation(&self) -> bool {
+        *self != NetworkPolicy::AllowAll
+    }
+}
+
+/// What a tool run used, as measured by its cgroup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    pub peak_memory_bytes: u64,
+    pub cpu_time_ms: u64,
+    pub peak_pids: u64,
+}
+
+/// Keeps a tool run's resource limits in place until the run is over
+#[derive(Default)]
+pub struct ResourceGuard {
+    #[cfg(target_os = "linux")]
+    cgroup: Option<crate::cgroup::ToolCgroup>,
+    /// Held until the child has been spawned and has applied it
+    #[cfg(target_os = "linux")]
+    landlock: Option<crate::landlock::Ruleset>,
+}
+
+/// Paths a tool needs beyond the sandbox's own
+#[derive(Debug, Clone, Default)]
+pub struct ToolPaths {
+    /// Where the tool and its runtime are installed; read-only
+    pub install: Vec<PathBuf>,
+    /// Credentials and session state the tool keeps under `$HOME`
+    pub state: Vec<PathBuf>,
+}
+
+impl ToolPaths {
+    pub fn for_tool(tool: Tool, executable: &str) -> Self {
+        let mut install = Vec::new();
+        if let Ok(path) = which::which(executable) {
+            if let Some(bin) = path.parent() {
+                install.push(bin.to_path_buf());
+                // A `bin` dir usually sits in a prefix with the runtime, e.g. Node's.
+                if bin.ends_with("bin") {
+                    if let Some(prefix) = bin.parent() {
+                        install.push(prefix.to_path_buf());
+                    }
+                }
+            }
+            // npm installs link `bin/claude` to the package's entry point.
+            if let Some(target) = path.canonicalize().ok().and_then(|p| p.parent().map(Path::to_path_buf)) {
+                install.push(target);
+            }
+        }
+
+        let state = match directories::BaseDirs::new() {
+            Some(dirs) => {
+                let home = dirs.home_dir();
+                let relative: &[&str] = match tool {
+                    Tool::Claude => &[".claude", ".claude.json", ".config/claude"],
+                    Tool::Gemini => &[".gemini"],
+                    Tool::Codex => &[".codex"],
+                    Tool::Copilot => &[".config/gh", ".config/github-copilot"],
+                    Tool::Cursor => &[".cursor", ".config/cursor"],
+                    Tool::Perplexity | Tool::Ollama => &[],
+                };
+                relative.iter().map(|r| home.join(r)).collect()
+            }
+            None => Vec::new(),
+        };
+
+        Self { install, state }
+    }
+}
+
+/// Whether tool processes get filesystem confinement, for `doctor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilesystemConfinement {
+    Active { abi: u32 },
+    Disabled,
+    Unsupported,
+}
+
+pub fn filesystem_confinement(config: &SandboxConfig) -> FilesystemConfinement {
+    if !config.enabled || !config.landlock {
+        return FilesystemConfinement::Disabled;
+    }
+
+    #[cfg(target_os = "linux")]
+    return match crate::landlock::abi_version() {
+        Some(abi) => FilesystemConfinement::Active { abi },
+        None => FilesystemConfinement::Unsupported,
+    };
+
+    #[cfg(not(target_os = "linux"))]
+    FilesystemConfinement::Unsupported
+}
+
+impl ResourceGuard {
+    /// Whether the run is limited through a cgroup rather than rlimits
+    pub fn is_cgroup(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        return self.cgroup.is_some();
+
+        #[cfg(not(target_os = "linux"))]
+        false
+    }
+
+    /// Releases the limits, returning what the run used if it was measured.
+    pub fn finish(self) -> Option<ResourceUsage> {
+        #[cfg(target_os = "linux")]
+        return self.cgroup.map(|group| group.usage());
+
+        #[cfg(not(target_os = "linux"))]
+        None
+    }
+}
+
 impl Default for SandboxConfig {
     fn default() -> Self {
         let sandbox_root = directories::BaseDirs::new()
@@ -49,6 +206,11 @@ impl Default for SandboxConfig {
             max_cpu_percent: Some(80),
             network_access: NetworkPolicy::AllowAll,
             env_whitelist,
+            cgroups: false,
+            cgroup_parent: None,
+            max_pids: Some(1024),
+            max_io_mbps: None,
+            landlock: true,
         }
     }
 }
@@ -167,16 +329,14 @@ impl SandboxConfig {
             .collect()
     }
 
-    pub fn add_tool_env_vars(&self, env: &mut Vec<(String, String)>, tool: Tool) {
+    pub fn add_tool_env_vars(&self, env: &mut Vec<(String, String)>, tool: Tool, project_dir: &Path) {
         env.push(("POLYGLOT_SANDBOX".to_string(), "1".to_string()));
         env.push(("POLYGLOT_TOOL".to_string(), tool.as_str().to_string()));
         env.push(("POLYGLOT_WORKSPACE".to_string(), self.get_workspace_dir().to_string_lossy().to_string()));
         env.push(("POLYGLOT_TOOLS_DIR".to_string(), self.get_tools_dir().to_string_lossy().to_string()));
         env.push(("POLYGLOT_CACHE_DIR".to_string(), self.get_cache_dir().to_string_lossy().to_string()));
 
-        if let Ok(current_dir) = std::env::current_dir() {
-            env.push(("POLYGLOT_PROJECT_DIR".to_string(), current_dir.to_string_lossy().to_string()));
-        }
+        env.push(("POLYGLOT_PROJECT_DIR".to_string(), project_dir.to_string_lossy().to_string()));
 
         env.push(("TMPDIR".to_string(), self.get_temp_dir().to_string_lossy().to_string()));
         env.push(("TEMP".to_string(), self.get_temp_dir().to_string_lossy().to_string()));
@@ -190,25 +350,235 @@ pub mod unix {
     use std::os::unix::process::CommandExt;
     use tokio::process::Command;
 
-    pub fn apply_resource_limits(cmd: &mut Command, config: &SandboxConfig) {
+    /// Applies CPU, memory, network and filesystem limits to `cmd`. Fails
+    /// rather than run the tool unrestricted when the network policy cannot
+    /// be enforced. Filesystem confinement is skipped on kernels without
+    /// Landlock; `doctor` reports that. I/O limits apply to the disk
+    /// holding `working_dir`.
+    pub fn apply_resource_limits(
+        cmd: &mut Command,
+        config: &SandboxConfig,
+        tool_paths: &ToolPaths,
+        working_dir: &Path,
+        seccomp: SeccompProfile,
+    ) -> Result<ResourceGuard> {
         if !config.enabled {
-            return;
+            return Ok(ResourceGuard::default());
         }
 
-        let _ = (config.max_cpu_percent, config.network_access);
+        let mut guard = cgroup_limits(cmd, config, working_dir);
+
+        // RLIMIT_AS is the fallback only: it counts reserved address space,
+        // which JIT runtimes such as Node reserve far more of than they use.
+        if !guard.is_cgroup() {
+            if let Some(max_mem_mb) = config.max_memory_mb {
+                let max_mem_bytes = max_mem_mb * 1024 * 1024;
+                unsafe {
+                    use libc::{setrlimit, rlimit, RLIMIT_AS};
+                    let limit = rlimit {
+                        rlim_cur: max_mem_bytes,
+                        rlim_max: max_mem_bytes,
+                    };
+                    cmd.pre_exec(move || {
+                        setrlimit(RLIMIT_AS, &limit);
+                        Ok(())
+                    });
+                }
+            }
+        }
 
-        if let Some(max_mem_mb) = config.max_memory_mb {
-            let max_mem_bytes = max_mem_mb * 1024 * 1024;
-            unsafe {
-                use libc::{setrlimit, rlimit, RLIMIT_AS};
-                let limit = rlimit {
-                    rlim_cur: max_mem_bytes,
-                    rlim_max: max_mem_bytes,
-                };
-                cmd.pre_exec(move || {
-                    setrlimit(RLIMIT_AS, &limit);
-                    Ok(())
+        isolate_network(cmd, config.network_access)?;
+
+        // After the hooks above, so they can still write to /proc and cgroupfs.
+        confine_filesystem(cmd, config, tool_paths, &mut guard)?;
+        filter_syscalls(cmd, seccomp)?;
+
+        Ok(guard)
+    }
+
+    #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+    fn filter_syscalls(cmd: &mut Command, profile: SeccompProfile) -> Result<()> {
+        if let Some(filter) = crate::seccomp::Filter::new(profile) {
+            filter.apply_to(cmd);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+    fn filter_syscalls(_cmd: &mut Command, profile: SeccompProfile) -> Result<()> {
+        if profile != SeccompProfile::Off {
+            bail!("seccomp profiles need Linux on x86_64 or aarch64");
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn confine_filesystem(
+        cmd: &mut Command,
+        config: &SandboxConfig,
+        tool_paths: &ToolPaths,
+        guard: &mut ResourceGuard,
+    ) -> Result<()> {
+        if !config.landlock || crate::landlock::abi_version().is_none() {
+            return Ok(());
+        }
+
+        let ruleset = landlock_ruleset(config, tool_paths)
+            .context("Failed to set up Landlock filesystem confinement")?;
+        ruleset.apply_to(cmd);
+        guard.landlock = Some(ruleset);
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn confine_filesystem(
+        _cmd: &mut Command,
+        _config: &SandboxConfig,
+        _tool_paths: &ToolPaths,
+        _guard: &mut ResourceGuard,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn landlock_ruleset(config: &SandboxConfig, tool_paths: &ToolPaths) -> std::io::Result<crate::landlock::Ruleset> {
+        let mut ruleset = crate::landlock::Ruleset::new()?;
+        for path in config.allowed_read_paths.iter().chain(&tool_paths.install) {
+            ruleset.allow_read(path)?;
+        }
+        for path in config.allowed_write_paths.iter().chain(&tool_paths.state) {
+            ruleset.allow_write(path)?;
+        }
+        Ok(ruleset)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn cgroup_limits(cmd: &mut Command, config: &SandboxConfig, working_dir: &Path) -> ResourceGuard {
+        use std::sync::Once;
+        static FALLBACK_WARNING: Once = Once::new();
+
+        if !config.cgroups {
+            return ResourceGuard::default();
+        }
+
+        match crate::cgroup::ToolCgroup::create(config, working_dir) {
+            Ok(group) => {
+                group.attach(cmd);
+                ResourceGuard { cgroup: Some(group), ..Default::default() }
+            }
+            Err(e) => {
+                FALLBACK_WARNING.call_once(|| {
+                    tracing::warn!("cgroup limits unavailable, falling back to rlimits: {:#}", e);
                 });
+                ResourceGuard::default()
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn cgroup_limits(_cmd: &mut Command, _config: &SandboxConfig, _working_dir: &Path) -> ResourceGuard {
+        ResourceGuard::default()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn isolate_network(cmd: &mut Command, policy: NetworkPolicy) -> Result<()> {
+        if policy.needs_isolation() {
+            netns::isolate(cmd, policy);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn isolate_network(_cmd: &mut Command, policy: NetworkPolicy) -> Result<()> {
+        if policy.needs_isolation() {
+            bail!(
+                "network_access = \"{}\" needs Linux network namespaces, which this platform lacks",
+                policy.as_str()
+            );
+        }
+        Ok(())
+    }
+
+    /// Runs tools in a fresh network namespace. The namespace has only a
+    /// loopback interface, which is brought up for `AllowLocalhost` and left
+    /// down for `Deny`. Loopback there is the namespace's own, so services on
+    /// the host's localhost are not reachable either way.
+    #[cfg(target_os = "linux")]
+    mod netns {
+        use super::*;
+        use std::ffi::CStr;
+        use std::io;
+
+        pub fn isolate(cmd: &mut Command, policy: NetworkPolicy) {
+            let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+            // Without root, a user namespace is needed to own the network namespace.
+            let user_namespace = uid != 0;
+            // Formatted here because the child must not allocate after fork.
+            let uid_map = format!("{} {} 1", uid, uid);
+            let gid_map = format!("{} {} 1", gid, gid);
+            let loopback = policy == NetworkPolicy::AllowLocalhost;
+
+            unsafe {
+                cmd.pre_exec(move || enter(user_namespace, &uid_map, &gid_map, loopback));
+            }
+        }
+
+        fn enter(user_namespace: bool, uid_map: &str, gid_map: &str, loopback: bool) -> io::Result<()> {
+            let flags = if user_namespace {
+                libc::CLONE_NEWUSER | libc::CLONE_NEWNET
+            } else {
+                libc::CLONE_NEWNET
+            };
+            if unsafe { libc::unshare(flags) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            if user_namespace {
+                // Kernels before 3.19 have no setgroups file and don't need it.
+                match write_proc(c"/proc/self/setgroups", b"deny") {
+                    Err(e) if e.raw_os_error() != Some(libc::ENOENT) => return Err(e),
+                    _ => {}
+                }
+                write_proc(c"/proc/self/uid_map", uid_map.as_bytes())?;
+                write_proc(c"/proc/self/gid_map", gid_map.as_bytes())?;
+            }
+
+            if loopback {
+                loopback_up()?;
+            }
+            Ok(())
+        }
+
+        fn write_proc(path: &CStr, contents: &[u8]) -> io::Result<()> {
+            unsafe {
+                let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let written = libc::write(fd, contents.as_ptr().cast(), contents.len());
+                let result = if written < 0 { Err(io::Error::last_os_error()) } else { Ok(()) };
+                libc::close(fd);
+                result
+            }
+        }
+
+        fn loopback_up() -> io::Result<()> {
+            unsafe {
+                let fd = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                let mut request: libc::ifreq = std::mem::zeroed();
+                for (dst, src) in request.ifr_name.iter_mut().zip(b"lo") {
+                    *dst = *src as libc::c_char;
+                }
+                request.ifr_ifru.ifru_flags = (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short;
+
+                let rc = libc::ioctl(fd, libc::SIOCSIFFLAGS as _, &request);
+                let result = if rc != 0 { Err(io::Error::last_os_error()) } else { Ok(()) };
+                libc::close(fd);
+                result
             }
         }
     }
@@ -219,11 +589,144 @@ pub mod windows {
     use super::*;
     use tokio::process::Command;
 
-    pub fn apply_resource_limits(_cmd: &mut Command, config: &SandboxConfig) {
+    pub fn apply_resource_limits(
+        _cmd: &mut Command,
+        config: &SandboxConfig,
+        _tool_paths: &ToolPaths,
+        _working_dir: &Path,
+        seccomp: SeccompProfile,
+    ) -> Result<ResourceGuard> {
         if !config.enabled {
+            return Ok(ResourceGuard::default());
+        }
+
+        let _ = (config.max_memory_mb, config.max_cpu_percent);
+
+        if config.network_access.needs_isolation() {
+            bail!(
+                "network_access = \"{}\" is not supported on Windows",
+                config.network_access.as_str()
+            );
+        }
+        if seccomp != SeccompProfile::Off {
+            bail!("seccomp profiles are not supported on Windows");
+        }
+        Ok(ResourceGuard::default())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::time::Duration;
+    use tokio::process::Command;
+
+    const PROBE_ENV: &str = "POLYGLOT_NETNS_PROBE_ADDR";
+
+    /// Run by the tests below in a child process. Connects to the address in
+    /// `PROBE_ENV`, or to a listener of its own on loopback if that is unset,
+    /// and fails if it can't.
+    #[test]
+    #[ignore]
+    fn network_probe() {
+        let _listener;
+        let addr: SocketAddr = match std::env::var(PROBE_ENV) {
+            Ok(addr) => addr.parse().unwrap(),
+            Err(_) => {
+                _listener = TcpListener::bind("127.0.0.1:0").unwrap();
+                _listener.local_addr().unwrap()
+            }
+        };
+        TcpStream::connect_timeout(&addr, Duration::from_secs(2)).unwrap();
+    }
+
+    fn sandboxed(program: impl AsRef<std::ffi::OsStr>, policy: NetworkPolicy) -> Command {
+        let config = SandboxConfig {
+            max_memory_mb: None,
+            network_access: policy,
+            landlock: false,
+            ..Default::default()
+        };
+
+        let mut cmd = Command::new(program);
+        cmd.stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null());
+        unix::apply_resource_limits(&mut cmd, &config, &ToolPaths::default(), Path::new("."), SeccompProfile::Off).unwrap();
+        cmd
+    }
+
+    /// Whether the probe connected under `policy`. A spawn failure counts as
+    /// not connecting, since the tool would not have run at all.
+    async fn probe_connects(policy: NetworkPolicy, addr: Option<SocketAddr>) -> bool {
+        let mut cmd = sandboxed(std::env::current_exe().unwrap(), policy);
+        cmd.args(["--ignored", "--exact", "sandbox::tests::network_probe", "--quiet"]);
+        match addr {
+            Some(addr) => cmd.env(PROBE_ENV, addr.to_string()),
+            None => cmd.env_remove(PROBE_ENV),
+        };
+
+        match cmd.status().await {
+            Ok(status) => status.success(),
+            Err(_) => false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_network_policy_blocks_outbound_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host_addr = listener.local_addr().unwrap();
+        let external: SocketAddr = "192.0.2.1:443".parse().unwrap();
+
+        assert!(probe_connects(NetworkPolicy::AllowAll, Some(host_addr)).await);
+
+        assert!(!probe_connects(NetworkPolicy::Deny, Some(host_addr)).await);
+        assert!(!probe_connects(NetworkPolicy::Deny, Some(external)).await);
+        // Loopback is the namespace's own, so the host's listener stays out of reach.
+        assert!(!probe_connects(NetworkPolicy::AllowLocalhost, Some(host_addr)).await);
+        assert!(!probe_connects(NetworkPolicy::AllowLocalhost, Some(external)).await);
+    }
+
+    #[tokio::test]
+    async fn test_loopback_only_up_for_allow_localhost() {
+        assert!(!probe_connects(NetworkPolicy::Deny, None).await);
+
+        // Without namespace support the spawn fails closed; nothing more to check.
+        if sandboxed("true", NetworkPolicy::Deny).status().await.is_ok() {
+            assert!(probe_connects(NetworkPolicy::AllowLocalhost, None).await);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_landlock_blocks_writes_outside_allowed_paths() {
+        if crate::landlock::abi_version().is_none() {
             return;
         }
 
-        let _ = (config.max_memory_mb, config.max_cpu_percent, config.network_access);
+        let allowed = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let config = SandboxConfig {
+            max_memory_mb: None,
+            allowed_read_paths: vec![allowed.path().to_path_buf()],
+            allowed_write_paths: vec![allowed.path().to_path_buf()],
+            ..Default::default()
+        };
+
+        // The guard holds the ruleset, so it has to outlive the spawn.
+        let run = |script: String| async {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg(script).stderr(std::process::Stdio::null());
+            let _guard = unix::apply_resource_limits(&mut cmd, &config, &ToolPaths::default(), Path::new("."), SeccompProfile::Off).unwrap();
+            cmd.status().await.unwrap().success()
+        };
+
+        assert!(run(format!("echo hi > {}/file", allowed.path().display())).await);
+        assert!(allowed.path().join("file").exists());
+
+        assert!(!run(format!("echo hi > {}/file", outside.path().display())).await);
+        assert!(!outside.path().join("file").exists());
+
+        // Reads outside the allowed paths are refused too.
+        assert!(!run(format!("ls {}", outside.path().display())).await);
     }
 }
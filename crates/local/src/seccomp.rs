@@ -0,0 +1,301 @@
+//! Seccomp-bpf syscall filters for tool processes
+//!
+//! Filters are deny lists: AI CLIs run arbitrary build tooling, so an allow
+//! list would break them. Denied calls fail with `EPERM`, or are only logged
+//! to the kernel audit log in `Log` mode.
+
+use std::io;
+
+use tokio::process::Command;
+
+use crate::sandbox::SeccompProfile;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xC000_003E;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xC000_00B7;
+
+/// Set on x32 syscall numbers, which would otherwise slip past the filter
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+// Offsets into `struct seccomp_data`
+const DATA_NR: u32 = 0;
+const DATA_ARCH: u32 = 4;
+const DATA_ARG0: u32 = 16;
+const DATA_ARG1: u32 = 24;
+
+/// Calls no coding tool needs: debugging other processes, mounts, kernel
+/// modules, kexec, eBPF and other kernel-wide state.
+const BASELINE_DENIED: &[libc::c_long] = &[
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_fsopen,
+    libc::SYS_fsconfig,
+    libc::SYS_fsmount,
+    libc::SYS_fspick,
+    libc::SYS_move_mount,
+    libc::SYS_open_tree,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_kexec_load,
+    libc::SYS_kexec_file_load,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_open_by_handle_at,
+    libc::SYS_reboot,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_acct,
+    libc::SYS_quotactl,
+    libc::SYS_syslog,
+    libc::SYS_settimeofday,
+    libc::SYS_clock_settime,
+    libc::SYS_adjtimex,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_keyctl,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_iopl,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_ioperm,
+];
+
+/// On top of the baseline: new namespaces, io_uring (which bypasses
+/// syscall filtering) and userfaultfd, a common exploit primitive.
+const STRICT_DENIED: &[libc::c_long] = &[
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_chroot,
+    libc::SYS_mount_setattr,
+    libc::SYS_io_uring_setup,
+    libc::SYS_io_uring_enter,
+    libc::SYS_io_uring_register,
+    libc::SYS_userfaultfd,
+    libc::SYS_personality,
+];
+
+/// A compiled filter, ready to be installed in a child
+pub struct Filter {
+    program: Vec<libc::sock_filter>,
+    flags: libc::c_ulong,
+}
+
+impl Filter {
+    /// Compiles `profile`, or returns `None` for `Off`.
+    pub fn new(profile: SeccompProfile) -> Option<Self> {
+        let (denied, action, flags): (Vec<libc::c_long>, u32, libc::c_ulong) = match profile {
+            SeccompProfile::Off => return None,
+            SeccompProfile::Baseline => (
+                BASELINE_DENIED.to_vec(),
+                libc::SECCOMP_RET_ERRNO | libc::EPERM as u32,
+                0,
+            ),
+            SeccompProfile::Strict => (
+                [BASELINE_DENIED, STRICT_DENIED].concat(),
+                libc::SECCOMP_RET_ERRNO | libc::EPERM as u32,
+                0,
+            ),
+            // Logs everything `Strict` would deny.
+            SeccompProfile::Log => (
+                [BASELINE_DENIED, STRICT_DENIED].concat(),
+                libc::SECCOMP_RET_LOG,
+                libc::SECCOMP_FILTER_FLAG_LOG,
+            ),
+        };
+
+        Some(Self { program: compile(&denied, action), flags })
+    }
+
+    /// Installs the filter in the child right before exec. Registered after
+    /// the other sandbox hooks, since it may deny calls they make.
+    pub fn apply_to(self, cmd: &mut Command) {
+        unsafe {
+            cmd.pre_exec(move || install(&self.program, self.flags));
+        }
+    }
+}
+
+fn compile(denied: &[libc::c_long], action: u32) -> Vec<libc::sock_filter> {
+    let mut program = vec![
+        load(DATA_ARCH),
+        jump_if(AUDIT_ARCH, 1, 0),
+        ret(libc::SECCOMP_RET_KILL_PROCESS),
+        load(DATA_NR),
+    ];
+
+    #[cfg(target_arch = "x86_64")]
+    program.extend([
+        stmt(libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K, X32_SYSCALL_BIT, 0, 1),
+        ret(action),
+    ]);
+
+    for nr in denied {
+        program.push(jump_if(*nr as u32, 0, 1));
+        program.push(ret(action));
+    }
+
+    // Packet sockets and raw IP sockets can sniff or forge traffic. Raw
+    // netlink sockets stay allowed: `getifaddrs` and resolvers use them.
+    program.extend([
+        jump_if(libc::SYS_socket as u32, 0, 9),
+        load(DATA_ARG0),
+        jump_if(libc::AF_PACKET as u32, 0, 1),
+        ret(action),
+        jump_if(libc::AF_INET as u32, 1, 0),
+        jump_if(libc::AF_INET6 as u32, 0, 4),
+        load(DATA_ARG1),
+        stmt(libc::BPF_ALU | libc::BPF_AND | libc::BPF_K, 0xf, 0, 0),
+        jump_if(libc::SOCK_RAW as u32, 0, 1),
+        ret(action),
+        ret(libc::SECCOMP_RET_ALLOW),
+    ]);
+
+    program.push(ret(libc::SECCOMP_RET_ALLOW));
+    program
+}
+
+fn install(program: &[libc::sock_filter], flags: libc::c_ulong) -> io::Result<()> {
+    let prog = libc::sock_fprog {
+        len: program.len() as libc::c_ushort,
+        filter: program.as_ptr() as *mut libc::sock_filter,
+    };
+
+    unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::syscall(libc::SYS_seccomp, libc::SECCOMP_SET_MODE_FILTER, flags, &prog) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn stmt(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter { code: code as u16, jt, jf, k }
+}
+
+fn load(offset: u32) -> libc::sock_filter {
+    stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, offset, 0, 0)
+}
+
+fn jump_if(value: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    stmt(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, value, jt, jf)
+}
+
+fn ret(action: u32) -> libc::sock_filter {
+    stmt(libc::BPF_RET | libc::BPF_K, action, 0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static TARGET: u8 = 7;
+
+    /// Reads this process's own memory with `process_vm_readv`, which any
+    /// process may do unless a filter denies it.
+    fn read_own_memory() -> io::Result<()> {
+        let mut byte = 0u8;
+        let local = libc::iovec { iov_base: (&mut byte as *mut u8).cast(), iov_len: 1 };
+        let remote = libc::iovec { iov_base: (&TARGET as *const u8).cast_mut().cast(), iov_len: 1 };
+        if unsafe { libc::process_vm_readv(libc::getpid(), &local, 1, &remote, 1, 0) } != 1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn query_personality() -> io::Result<()> {
+        if unsafe { libc::personality(0xffff_ffff) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn raw_socket() -> io::Result<()> {
+        let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_RAW, libc::IPPROTO_ICMP) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        unsafe { libc::close(fd) };
+        Ok(())
+    }
+
+    fn netlink_socket() -> io::Result<()> {
+        let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::NETLINK_ROUTE) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        unsafe { libc::close(fd) };
+        Ok(())
+    }
+
+    fn tcp_socket() -> io::Result<()> {
+        let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        unsafe { libc::close(fd) };
+        Ok(())
+    }
+
+    /// Runs `check` in a child under `profile`, right after the filter is
+    /// installed; its error fails the spawn.
+    async fn run_under(profile: SeccompProfile, check: fn() -> io::Result<()>) -> io::Result<()> {
+        let mut cmd = Command::new("true");
+        if let Some(filter) = Filter::new(profile) {
+            filter.apply_to(&mut cmd);
+        }
+        unsafe {
+            cmd.pre_exec(check);
+        }
+        cmd.status().await.map(|_| ())
+    }
+
+    fn is_denied(result: io::Result<()>) -> bool {
+        matches!(result, Err(e) if e.raw_os_error() == Some(libc::EPERM))
+    }
+
+    #[tokio::test]
+    async fn test_baseline_denies_listed_calls() {
+        assert!(run_under(SeccompProfile::Off, read_own_memory).await.is_ok());
+        assert!(is_denied(run_under(SeccompProfile::Baseline, read_own_memory).await));
+        assert!(is_denied(run_under(SeccompProfile::Baseline, raw_socket).await));
+
+        assert!(run_under(SeccompProfile::Baseline, tcp_socket).await.is_ok());
+        assert!(run_under(SeccompProfile::Strict, netlink_socket).await.is_ok());
+        assert!(run_under(SeccompProfile::Baseline, query_personality).await.is_ok());
+        assert!(is_denied(run_under(SeccompProfile::Strict, query_personality).await));
+    }
+
+    #[tokio::test]
+    async fn test_log_mode_allows_everything() {
+        assert!(run_under(SeccompProfile::Log, read_own_memory).await.is_ok());
+        assert!(run_under(SeccompProfile::Log, query_personality).await.is_ok());
+    }
+
+    #[test]
+    fn test_misspelled_profile_is_rejected() {
+        assert_eq!("strict".parse::<SeccompProfile>().unwrap(), SeccompProfile::Strict);
+
+        let mut config = crate::config::LocalConfig::default();
+        assert!(config.validate().is_ok());
+        config.tools.claude.as_mut().unwrap().seccomp = "strcit".to_string();
+        let err = format!("{:#}", config.validate().unwrap_err());
+        assert!(err.contains("[tools.claude]") && err.contains("off|baseline|strict|log"), "{}", err);
+    }
+
+    #[test]
+    fn test_program_fits_kernel_limit() {
+        let filter = Filter::new(SeccompProfile::Strict).unwrap();
+        assert!(filter.program.len() < libc::BPF_MAXINSNS as usize);
+        assert_eq!(filter.program.last().unwrap().k, libc::SECCOMP_RET_ALLOW);
+        assert!(Filter::new(SeccompProfile::Off).is_none());
+    }
+}
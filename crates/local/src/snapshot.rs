@@ -0,0 +1,919 @@
+//! Working tree snapshots taken before each prompt, so tool edits can be undone
+//!
+//! A snapshot records every file under the working directory with a content
+//! hash, and keeps the content in a shared object store keyed by that hash.
+//! Unchanged files are stored once, so after the first prompt only files
+//! that were modified since cost a copy. No git repository is needed.
+//!
+//! Stored content is a full copy of each file, so it can't be redacted. When
+//! history is encrypted, content is sealed with a key of its own, which is in
+//! turn sealed with the history's key, and can't be read while history is
+//! locked. Only the newest `keep` snapshots are kept either way.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use walkdir::WalkDir;
+use xxhash_rust::xxh3::xxh3_64;
+
+use polyglot_common::{ApiKeyManager, Tool};
+use polyglot_common::crypto::{hex_decode, hex_encode};
+use crate::config::SnapshotConfig;
+use crate::diff::unified_diff;
+
+/// Build output, dependencies and VCS metadata, which tools rarely edit by hand
+const SKIPPED_DIRS: &[&str] = &[
+    ".git", ".hg", ".svn", "target", "node_modules", ".venv", "venv", "__pycache__",
+];
+
+/// Objects no snapshot refers to are kept this long, since a snapshot of a
+/// run still in progress may be using them
+const OBJECT_GRACE: Duration = Duration::from_secs(60 * 60);
+
+/// Files modified this close to a snapshot are rehashed rather than trusted
+/// by size and mtime, since timestamps are only as fine as the filesystem's
+const RACY_WINDOW_NS: u64 = 2_000_000_000;
+
+/// Starts every object stored encrypted
+const SEALED_MAGIC: &[u8] = b"PGSEAL1\0";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+}
+
+impl ChangeKind {
+    pub fn marker(&self) -> char {
+        match self {
+            ChangeKind::Added => 'A',
+            ChangeKind::Modified => 'M',
+            ChangeKind::Deleted => 'D',
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileChange {
+    /// Relative to the snapshot root
+    pub path: PathBuf,
+    pub kind: ChangeKind,
+    /// State right after the run, so undo can tell if the file was edited since
+    after: Option<FileEntry>,
+}
+
+impl FileChange {
+    /// The language of the file, judged by its extension.
+    pub fn language(&self) -> Option<&'static str> {
+        polyglot_common::language_for_path(&self.path)
+    }
+}
+
+impl fmt::Display for FileChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind.marker(), self.path.display())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct FileEntry {
+    len: u64,
+    modified_ns: u64,
+    /// Hash of the content, or `None` if it was too large or unreadable to keep
+    hash: Option<u64>,
+    #[serde(default)]
+    mode: u32,
+}
+
+impl FileEntry {
+    fn from_metadata(metadata: &Metadata) -> Self {
+        #[cfg(unix)]
+        let mode = std::os::unix::fs::PermissionsExt::mode(&metadata.permissions());
+        #[cfg(not(unix))]
+        let mode = 0;
+
+        Self {
+            len: metadata.len(),
+            modified_ns: metadata.modified().ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(0),
+            hash: None,
+            mode,
+        }
+    }
+
+    fn same_metadata(&self, other: &FileEntry) -> bool {
+        self.len == other.len && self.modified_ns == other.modified_ns
+    }
+
+    /// Whether matching metadata is enough to call the file unchanged since
+    /// a snapshot taken at `taken_ns`.
+    fn settled_before(&self, taken_ns: u64) -> bool {
+        self.modified_ns.saturating_add(RACY_WINDOW_NS) < taken_ns
+    }
+
+    fn same_content(&self, other: &FileEntry) -> bool {
+        match (self.hash, other.hash) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.same_metadata(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub id: Uuid,
+    pub tool: Tool,
+    pub root: PathBuf,
+    pub created_at: DateTime<Utc>,
+    files: BTreeMap<PathBuf, FileEntry>,
+    /// Filled in once the run is over
+    #[serde(default)]
+    pub changes: Vec<FileChange>,
+}
+
+impl Snapshot {
+    fn file_name(&self) -> String {
+        // Sorts by age.
+        format!("{}-{}.json", self.created_at.format("%Y%m%dT%H%M%S%.9f"), self.id.simple())
+    }
+}
+
+/// The outcome of reverting one file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Revert {
+    Restored,
+    /// Edited again after the run, so it was left alone
+    ChangedSince,
+    /// Too large or unreadable to have been kept
+    NotKept,
+}
+
+/// What `SnapshotStore::undo` did
+#[derive(Debug)]
+pub struct UndoReport {
+    pub tool: Tool,
+    pub created_at: DateTime<Utc>,
+    pub restored: Vec<FileChange>,
+    /// Files edited again after the run, which were left alone
+    pub changed_since: Vec<PathBuf>,
+    /// Files too large or unreadable to have been kept
+    pub not_kept: Vec<PathBuf>,
+}
+
+/// What the last snapshot of a directory found, so files that haven't
+/// changed since needn't be read and hashed again
+#[derive(Default, Serialize, Deserialize)]
+struct Index {
+    taken_ns: u64,
+    files: BTreeMap<PathBuf, FileEntry>,
+}
+
+/// The key stored content is sealed with
+struct ContentKey {
+    raw: [u8; 32],
+    cipher: ApiKeyManager,
+}
+
+#[derive(Clone)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+    config: SnapshotConfig,
+    /// Whether content has to be sealed, because history is encrypted
+    encrypt: bool,
+    /// Set by `unlock`
+    key: Arc<RwLock<Option<Arc<ContentKey>>>>,
+}
+
+impl SnapshotStore {
+    /// Opens the store in `dir`. With `encrypt`, nothing is snapshotted
+    /// until `unlock` is given the history's key.
+    pub fn new(dir: Option<PathBuf>, config: SnapshotConfig, encrypt: bool) -> Result<Self> {
+        let dir = dir.unwrap_or_else(|| {
+            dirs::data_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join("polyglot-ai")
+                .join("snapshots")
+        });
+
+        fs::create_dir_all(dir.join("objects"))
+            .and_then(|_| fs::create_dir_all(dir.join("index")))
+            .context("Failed to create snapshot directory")?;
+
+        Ok(Self { dir, config, encrypt, key: Arc::default() })
+    }
+
+    /// Loads the content key sealed with `history`, the cipher encrypted
+    /// history is unlocked with, creating the key on first use.
+    pub fn unlock(&self, history: &ApiKeyManager) -> Result<()> {
+        let path = self.dir.join("key");
+        let raw = match fs::read(&path) {
+            Ok(sealed) => {
+                let hex = history.decrypt(&sealed)
+                    .map_err(|_| anyhow!("{} wasn't sealed with the history key", path.display()))?;
+                hex_decode(&hex).ok()
+                    .and_then(|raw| <[u8; 32]>::try_from(raw).ok())
+                    .with_context(|| format!("{} is damaged", path.display()))?
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let raw = ApiKeyManager::generate_key();
+                write_key(&path, &raw, history)?;
+                raw
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        *self.key.write() = Some(Arc::new(ContentKey { raw, cipher: ApiKeyManager::from_key(raw) }));
+        Ok(())
+    }
+
+    /// Seals the content key with `history` again, after history was
+    /// re-encrypted with it.
+    pub fn rekey(&self, history: &ApiKeyManager) -> Result<()> {
+        match self.key.read().as_ref() {
+            Some(key) => write_key(&self.dir.join("key"), &key.raw, history),
+            None => Ok(()),
+        }
+    }
+
+    /// The key new content is sealed with, or `None` when it is stored as is.
+    fn content_key(&self) -> Result<Option<Arc<ContentKey>>> {
+        if !self.encrypt {
+            return Ok(None);
+        }
+        self.key.read().clone()
+            .map(Some)
+            .context("Snapshots are encrypted with the history key, which isn't unlocked")
+    }
+
+    /// Records the state of every file under `root` before `tool` runs there.
+    pub fn take(&self, root: &Path, tool: Tool) -> Result<Snapshot> {
+        let root = root.canonicalize()
+            .with_context(|| format!("Failed to resolve {}", root.display()))?;
+        let created_at = Utc::now();
+        let key = self.content_key()?;
+        let index = self.load_index(&root);
+
+        let mut files = BTreeMap::new();
+        for (relative, metadata) in walk(&root, self.config.max_files)? {
+            let known = index.files.get(&relative).filter(|entry| entry.settled_before(index.taken_ns));
+            let entry = match self.reuse(known, &metadata, key.is_some()) {
+                Some(entry) => entry,
+                None => self.store_file(&root.join(&relative), &metadata, key.as_deref())?,
+            };
+            files.insert(relative, entry);
+        }
+
+        let index = Index { taken_ns: created_at.timestamp_nanos_opt().unwrap_or(0) as u64, files };
+        if let Err(e) = self.save_index(&root, &index) {
+            tracing::debug!("Failed to save the snapshot index: {}", e);
+        }
+        let files = index.files;
+
+        Ok(Snapshot {
+            id: Uuid::new_v4(),
+            tool,
+            root,
+            created_at,
+            files,
+            changes: Vec::new(),
+        })
+    }
+
+    /// Compares the working tree with `snapshot` and keeps the snapshot for
+    /// `undo` if anything changed. Returns the changed files.
+    pub fn record(&self, mut snapshot: Snapshot) -> Result<Vec<FileChange>> {
+        snapshot.changes = self.changes(&snapshot)?;
+        if snapshot.changes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let content = serde_json::to_string(&snapshot)?;
+        fs::write(self.dir.join(snapshot.file_name()), content)
+            .context("Failed to save snapshot")?;
+
+        self.prune()?;
+        Ok(snapshot.changes)
+    }
+
+    /// Restores the files changed by the last recorded run in `root` to
+    /// their state before it, then forgets that run. Returns `None` if there
+    /// is nothing to undo.
+    pub fn undo(&self, root: &Path) -> Result<Option<UndoReport>> {
+        let root = root.canonicalize()
+            .with_context(|| format!("Failed to resolve {}", root.display()))?;
+        let Some((file, snapshot)) = self.latest(&root)? else {
+            return Ok(None);
+        };
+
+        let mut report = UndoReport {
+            tool: snapshot.tool,
+            created_at: snapshot.created_at,
+            restored: Vec::new(),
+            changed_since: Vec::new(),
+            not_kept: Vec::new(),
+        };
+
+        for change in &snapshot.changes {
+            match self.revert_change(&snapshot, change)? {
+                Revert::Restored => report.restored.push(change.clone()),
+                Revert::ChangedSince => report.changed_since.push(change.path.clone()),
+                Revert::NotKept => report.not_kept.push(change.path.clone()),
+            }
+        }
+
+        fs::remove_file(&file).context("Failed to remove snapshot")?;
+        self.collect_garbage()?;
+        Ok(Some(report))
+    }
+
+    /// A unified diff of one file the run `id` changed, from before the run to now.
+    pub fn diff(&self, id: Uuid, path: &Path) -> Result<String> {
+        let (_, snapshot) = self.find(id)?;
+        let before = match snapshot.files.get(path) {
+            Some(entry) => {
+                let hash = entry.hash
+                    .with_context(|| format!("{} was too large to snapshot", path.display()))?;
+                Some(self.read_object(hash)?)
+            }
+            None => None,
+        };
+        let now = match fs::read(snapshot.root.join(path)) {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Ok(unified_diff(&path.to_string_lossy(), before.as_deref(), now.as_deref()))
+    }
+
+    /// Keeps the run's change to `path`, so undoing the run leaves it alone.
+    pub fn accept(&self, id: Uuid, path: &Path) -> Result<()> {
+        let (file, snapshot) = self.find(id)?;
+        self.forget(&file, snapshot, path)
+    }
+
+    /// Restores `path` to its state before the run `id`. Once restored, the
+    /// file is no longer part of the run.
+    pub fn revert(&self, id: Uuid, path: &Path) -> Result<Revert> {
+        let (file, snapshot) = self.find(id)?;
+        let change = snapshot.changes.iter()
+            .find(|c| c.path == path)
+            .with_context(|| format!("{} was not changed by this run", path.display()))?;
+
+        let outcome = self.revert_change(&snapshot, change)?;
+        if outcome == Revert::Restored {
+            self.forget(&file, snapshot, path)?;
+        }
+        Ok(outcome)
+    }
+
+    fn revert_change(&self, snapshot: &Snapshot, change: &FileChange) -> Result<Revert> {
+        let path = snapshot.root.join(&change.path);
+        if !self.unchanged_since(&path, change.after.as_ref()) {
+            return Ok(Revert::ChangedSince);
+        }
+
+        match change.kind {
+            ChangeKind::Added => {
+                remove_file(&path)?;
+                remove_empty_parents(&path, &snapshot.root);
+            }
+            ChangeKind::Modified | ChangeKind::Deleted => {
+                let Some((hash, mode)) = snapshot.files.get(&change.path)
+                    .and_then(|before| Some((before.hash?, before.mode)))
+                else {
+                    return Ok(Revert::NotKept);
+                };
+                self.restore_file(&path, hash, mode)
+                    .with_context(|| format!("Failed to restore {}", change.path.display()))?;
+            }
+        }
+        Ok(Revert::Restored)
+    }
+
+    /// Drops `path` from the recorded run, and the run once nothing is left.
+    fn forget(&self, file: &Path, mut snapshot: Snapshot, path: &Path) -> Result<()> {
+        snapshot.changes.retain(|c| c.path != path);
+        if snapshot.changes.is_empty() {
+            fs::remove_file(file).context("Failed to remove snapshot")?;
+            return self.collect_garbage();
+        }
+        fs::write(file, serde_json::to_string(&snapshot)?)
+            .context("Failed to save snapshot")
+    }
+
+    fn find(&self, id: Uuid) -> Result<(PathBuf, Snapshot)> {
+        let suffix = format!("-{}.json", id.simple());
+        self.snapshot_files()?
+            .into_iter()
+            .find(|file| file.to_string_lossy().ends_with(&suffix))
+            .and_then(|file| load(&file).map(|snapshot| (file, snapshot)))
+            .context("That run is gone; it was undone or is too old to keep")
+    }
+
+    fn changes(&self, snapshot: &Snapshot) -> Result<Vec<FileChange>> {
+        let mut changes = Vec::new();
+        let mut seen = HashSet::new();
+        let taken_ns = snapshot.created_at.timestamp_nanos_opt().unwrap_or(i64::MAX) as u64;
+
+        for (relative, metadata) in walk(&snapshot.root, usize::MAX)? {
+            let before = snapshot.files.get(&relative)
+                .filter(|entry| entry.settled_before(taken_ns));
+            let now = self.current_entry(&snapshot.root.join(&relative), &metadata, before);
+            let before = snapshot.files.get(&relative);
+            match before {
+                None => changes.push(FileChange {
+                    path: relative.clone(),
+                    kind: ChangeKind::Added,
+                    after: Some(now),
+                }),
+                Some(before) if !before.same_content(&now) => changes.push(FileChange {
+                    path: relative.clone(),
+                    kind: ChangeKind::Modified,
+                    after: Some(now),
+                }),
+                Some(_) => {}
+            }
+            seen.insert(relative);
+        }
+
+        for path in snapshot.files.keys().filter(|p| !seen.contains(*p)) {
+            changes.push(FileChange {
+                path: path.clone(),
+                kind: ChangeKind::Deleted,
+                after: None,
+            });
+        }
+
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(changes)
+    }
+
+    /// Whether `path` is still in the state the run left it in.
+    fn unchanged_since(&self, path: &Path, after: Option<&FileEntry>) -> bool {
+        match (fs::symlink_metadata(path), after) {
+            (Err(e), None) => e.kind() == io::ErrorKind::NotFound,
+            (Ok(metadata), Some(after)) if metadata.is_file() => {
+                after.same_content(&self.current_entry(path, &metadata, None))
+            }
+            _ => false,
+        }
+    }
+
+    /// The entry for a file as it is now, hashing it unless its metadata
+    /// matches `known`.
+    fn current_entry(&self, path: &Path, metadata: &Metadata, known: Option<&FileEntry>) -> FileEntry {
+        let mut entry = FileEntry::from_metadata(metadata);
+        if let Some(known) = known {
+            if known.same_metadata(&entry) {
+                return *known;
+            }
+        }
+        if entry.len <= self.max_file_bytes() {
+            entry.hash = fs::read(path).ok().map(|content| xxh3_64(&content));
+        }
+        entry
+    }
+
+    /// `known`, if the file still matches it and its content is still
+    /// stored the way new content would be.
+    fn reuse(&self, known: Option<&FileEntry>, metadata: &Metadata, sealed: bool) -> Option<FileEntry> {
+        let known = known?;
+        let now = FileEntry::from_metadata(metadata);
+        if !known.same_metadata(&now) || known.mode != now.mode {
+            return None;
+        }
+        self.keep_object(&self.object_path(known.hash?), sealed).then_some(*known)
+    }
+
+    fn store_file(&self, path: &Path, metadata: &Metadata, key: Option<&ContentKey>) -> Result<FileEntry> {
+        let mut entry = FileEntry::from_metadata(metadata);
+        if entry.len > self.max_file_bytes() {
+            return Ok(entry);
+        }
+        let Ok(content) = fs::read(path) else {
+            return Ok(entry);
+        };
+
+        let hash = xxh3_64(&content);
+        let object = self.object_path(hash);
+        if !self.keep_object(&object, key.is_some()) {
+            let data = match key {
+                Some(key) => {
+                    let sealed = key.cipher.seal(&content)
+                        .map_err(|e| anyhow!("Failed to encrypt {}: {}", path.display(), e))?;
+                    [SEALED_MAGIC, &sealed].concat()
+                }
+                None => content,
+            };
+            let partial = object.with_extension(format!("{}.tmp", Uuid::new_v4().simple()));
+            fs::write(&partial, &data)
+                .and_then(|_| fs::rename(&partial, &object))
+                .with_context(|| format!("Failed to copy {} into the snapshot", path.display()))?;
+        }
+
+        entry.hash = Some(hash);
+        Ok(entry)
+    }
+
+    /// Whether `object` is stored, sealed or not as asked, marking it used.
+    fn keep_object(&self, object: &Path, sealed: bool) -> bool {
+        let Ok(mut file) = File::options().read(true).write(true).open(object) else {
+            return false;
+        };
+        let mut magic = [0u8; SEALED_MAGIC.len()];
+        let is_sealed = file.read_exact(&mut magic).is_ok() && magic == SEALED_MAGIC;
+        // Keeps it clear of garbage collection while this run is going.
+        is_sealed == sealed && file.set_modified(SystemTime::now()).is_ok()
+    }
+
+    fn read_object(&self, hash: u64) -> Result<Vec<u8>> {
+        let data = fs::read(self.object_path(hash))?;
+        let Some(sealed) = data.strip_prefix(SEALED_MAGIC) else {
+            return Ok(data);
+        };
+        let key = self.key.read().clone()
+            .context("This snapshot is encrypted; set encrypt = true under [history] to use it")?;
+        key.cipher.open(sealed).map_err(|e| anyhow!("Failed to decrypt a snapshot: {}", e))
+    }
+
+    fn restore_file(&self, path: &Path, hash: u64, mode: u32) -> Result<()> {
+        let content = self.read_object(hash)?;
+
+        // Never write through a link the tool left in place of the file.
+        if fs::symlink_metadata(path).map(|m| m.file_type().is_symlink()).unwrap_or(false) {
+            fs::remove_file(path)?;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+
+        #[cfg(unix)]
+        if mode != 0 {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+
+        Ok(())
+    }
+
+    /// The newest recorded snapshot of `root`, with the file it is stored in.
+    fn latest(&self, root: &Path) -> Result<Option<(PathBuf, Snapshot)>> {
+        for file in self.snapshot_files()?.into_iter().rev() {
+            let Some(snapshot) = load(&file) else {
+                continue;
+            };
+            if snapshot.root == root {
+                return Ok(Some((file, snapshot)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Drops all but the newest `keep` snapshots.
+    fn prune(&self) -> Result<()> {
+        let files = self.snapshot_files()?;
+        if files.len() <= self.config.keep {
+            return Ok(());
+        }
+        for file in &files[..files.len() - self.config.keep] {
+            fs::remove_file(file)?;
+        }
+        self.collect_garbage()
+    }
+
+    /// Removes stored content no snapshot refers to any more.
+    fn collect_garbage(&self) -> Result<()> {
+        let mut referenced = HashSet::new();
+        for file in self.snapshot_files()? {
+            if let Some(snapshot) = load(&file) {
+                referenced.extend(snapshot.files.values().filter_map(|e| e.hash));
+            }
+        }
+
+        let now = SystemTime::now();
+        for entry in fs::read_dir(self.dir.join("objects"))?.flatten() {
+            let name = entry.file_name();
+            let in_use = name.to_str()
+                .and_then(|n| u64::from_str_radix(n, 16).ok())
+                .is_some_and(|hash| referenced.contains(&hash));
+            let recent = entry.metadata().and_then(|m| m.modified()).ok()
+                .and_then(|modified| now.duration_since(modified).ok())
+                .is_none_or(|age| age < OBJECT_GRACE);
+            if !in_use && !recent {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+        Ok(())
+    }
+
+    fn snapshot_files(&self) -> Result<Vec<PathBuf>> {
+        let mut files: Vec<PathBuf> = fs::read_dir(&self.dir)
+            .context("Failed to read snapshot directory")?
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+
+    fn index_path(&self, root: &Path) -> PathBuf {
+        let id = xxh3_64(root.as_os_str().as_encoded_bytes());
+        self.dir.join("index").join(format!("{:016x}.json", id))
+    }
+
+    fn load_index(&self, root: &Path) -> Index {
+        fs::read_to_string(self.index_path(root)).ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_index(&self, root: &Path, index: &Index) -> Result<()> {
+        let path = self.index_path(root);
+        let partial = path.with_extension(format!("{}.tmp", Uuid::new_v4().simple()));
+        fs::write(&partial, serde_json::to_string(index)?)?;
+        fs::rename(&partial, &path)?;
+        Ok(())
+    }
+
+    fn object_path(&self, hash: u64) -> PathBuf {
+        self.dir.join("objects").join(format!("{:016x}", hash))
+    }
+
+    fn max_file_bytes(&self) -> u64 {
+        self.config.max_file_kb * 1024
+    }
+}
+
+/// Regular files under `root`, relative to it. Symlinks are not followed.
+fn walk(root: &Path, max_files: usize) -> Result<Vec<(PathBuf, Metadata)>> {
+    let mut files = Vec::new();
+    let entries = WalkDir::new(root)
+        .follow_links(false)
+        .into_iter()
+        .filter_entry(|e| {
+            e.depth() == 0
+                || !e.file_type().is_dir()
+                || !e.file_name().to_str().is_some_and(|name| SKIPPED_DIRS.contains(&name))
+        });
+
+    // Unreadable directories are skipped the same way on every walk.
+    for entry in entries.flatten() {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if files.len() == max_files {
+            bail!("More than {} files under {}", max_files, root.display());
+        }
+        let relative = entry.path().strip_prefix(root)?.to_path_buf();
+        files.push((relative, metadata));
+    }
+    Ok(files)
+}
+
+/// Writes the content key to `path`, sealed with the history's cipher.
+fn write_key(path: &Path, raw: &[u8; 32], history: &ApiKeyManager) -> Result<()> {
+    let sealed = history.encrypt(&hex_encode(raw))
+        .map_err(|e| anyhow!("Failed to seal the snapshot key: {}", e))?;
+    fs::write(path, sealed)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+fn load(file: &Path) -> Option<Snapshot> {
+    let content = fs::read_to_string(file).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn remove_file(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Removes directories the run created, up to `root`, once they are empty.
+fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        if current == root || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn store(dir: &Path, config: SnapshotConfig) -> SnapshotStore {
+        SnapshotStore::new(Some(dir.to_path_buf()), config, false).unwrap()
+    }
+
+    #[test]
+    fn test_undo_restores_pre_prompt_state() {
+        let work = tempdir().unwrap();
+        let data = tempdir().unwrap();
+        let store = store(data.path(), SnapshotConfig::default());
+        let root = work.path();
+
+        fs::write(root.join("main.rs"), "fn main() {}\n").unwrap();
+        fs::write(root.join("old.rs"), "// old\n").unwrap();
+        fs::create_dir(root.join("target")).unwrap();
+        fs::write(root.join("target").join("out.o"), "build output").unwrap();
+
+        let snapshot = store.take(root, Tool::Claude).unwrap();
+
+        fs::write(root.join("main.rs"), "fn main() { println!(\"hi\"); }\n").unwrap();
+        fs::remove_file(root.join("old.rs")).unwrap();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(root.join("src").join("new.rs"), "// new\n").unwrap();
+        fs::write(root.join("target").join("out.o"), "rebuilt").unwrap();
+
+        let changes = store.record(snapshot).unwrap();
+        let summary: Vec<String> = changes.iter().map(|c| c.to_string()).collect();
+        assert_eq!(summary, vec!["M main.rs", "D old.rs", "A src/new.rs"]);
+
+        let report = store.undo(root).unwrap().unwrap();
+        assert_eq!(report.tool, Tool::Claude);
+        assert_eq!(report.restored.len(), 3);
+        assert_eq!(fs::read_to_string(root.join("main.rs")).unwrap(), "fn main() {}\n");
+        assert_eq!(fs::read_to_string(root.join("old.rs")).unwrap(), "// old\n");
+        assert!(!root.join("src").exists());
+        assert_eq!(fs::read_to_string(root.join("target").join("out.o")).unwrap(), "rebuilt");
+
+        // Each run is undone once.
+        assert!(store.undo(root).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_undo_leaves_later_edits_and_large_files() {
+        let work = tempdir().unwrap();
+        let data = tempdir().unwrap();
+        let store = store(data.path(), SnapshotConfig { max_file_kb: 1, ..Default::default() });
+        let root = work.path();
+
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("big.bin"), vec![0u8; 4096]).unwrap();
+
+        let snapshot = store.take(root, Tool::Codex).unwrap();
+        fs::write(root.join("a.txt"), "from the tool").unwrap();
+        fs::write(root.join("big.bin"), vec![1u8; 8192]).unwrap();
+        assert_eq!(store.record(snapshot).unwrap().len(), 2);
+
+        // The user kept working on the file after the run.
+        fs::write(root.join("a.txt"), "from the user, after").unwrap();
+
+        let report = store.undo(root).unwrap().unwrap();
+        assert!(report.restored.is_empty());
+        assert_eq!(report.changed_since, vec![PathBuf::from("a.txt")]);
+        assert_eq!(report.not_kept, vec![PathBuf::from("big.bin")]);
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "from the user, after");
+    }
+
+    #[test]
+    fn test_review_accepts_and_reverts_single_files() {
+        let work = tempdir().unwrap();
+        let data = tempdir().unwrap();
+        let store = store(data.path(), SnapshotConfig::default());
+        let root = work.path();
+        fs::write(root.join("keep.rs"), "one\n").unwrap();
+        fs::write(root.join("revert.rs"), "two\n").unwrap();
+
+        let snapshot = store.take(root, Tool::Claude).unwrap();
+        let id = snapshot.id;
+        fs::write(root.join("keep.rs"), "one, edited\n").unwrap();
+        fs::write(root.join("revert.rs"), "two, edited\n").unwrap();
+        store.record(snapshot).unwrap();
+
+        let diff = store.diff(id, Path::new("revert.rs")).unwrap();
+        assert!(diff.contains("-two\n+two, edited\n"));
+
+        assert_eq!(store.revert(id, Path::new("revert.rs")).unwrap(), Revert::Restored);
+        assert_eq!(fs::read_to_string(root.join("revert.rs")).unwrap(), "two\n");
+
+        // Accepting the last file leaves nothing for undo.
+        store.accept(id, Path::new("keep.rs")).unwrap();
+        assert!(store.undo(root).unwrap().is_none());
+        assert_eq!(fs::read_to_string(root.join("keep.rs")).unwrap(), "one, edited\n");
+        assert!(store.diff(id, Path::new("keep.rs")).is_err());
+    }
+
+    #[test]
+    fn test_encrypted_content_needs_the_history_key() {
+        let work = tempdir().unwrap();
+        let data = tempdir().unwrap();
+        let root = work.path();
+        let open = || SnapshotStore::new(Some(data.path().to_path_buf()), SnapshotConfig::default(), true).unwrap();
+        let history = ApiKeyManager::from_key(ApiKeyManager::generate_key());
+        fs::write(root.join(".env"), "PASSWORD=hunter2\n").unwrap();
+
+        let store = open();
+        assert!(store.take(root, Tool::Claude).is_err());
+        store.unlock(&history).unwrap();
+        let snapshot = store.take(root, Tool::Claude).unwrap();
+        fs::write(root.join(".env"), "PASSWORD=changed\n").unwrap();
+        store.record(snapshot).unwrap();
+
+        for object in fs::read_dir(data.path().join("objects")).unwrap() {
+            let content = fs::read(object.unwrap().path()).unwrap();
+            assert!(content.starts_with(SEALED_MAGIC));
+            assert!(!content.windows(7).any(|w| w == b"hunter2"));
+        }
+        let wrong = ApiKeyManager::from_key(ApiKeyManager::generate_key());
+        assert!(open().unlock(&wrong).is_err());
+
+        // Re-encrypting history carries the snapshot key along.
+        let rekeyed = ApiKeyManager::from_key(ApiKeyManager::generate_key());
+        store.rekey(&rekeyed).unwrap();
+        let store = open();
+        assert!(store.undo(root).is_err());
+        store.unlock(&rekeyed).unwrap();
+        store.undo(root).unwrap().unwrap();
+        assert_eq!(fs::read_to_string(root.join(".env")).unwrap(), "PASSWORD=hunter2\n");
+    }
+
+    #[test]
+    fn test_settled_files_are_not_read_again() {
+        let work = tempdir().unwrap();
+        let data = tempdir().unwrap();
+        let store = store(data.path(), SnapshotConfig::default());
+        let root = work.path();
+        let path = root.join("lib.rs");
+        let settled = SystemTime::now() - Duration::from_secs(60);
+        let write = |content: &str| {
+            fs::write(&path, content).unwrap();
+            File::options().write(true).open(&path).unwrap().set_modified(settled).unwrap();
+        };
+
+        write("one");
+        let hash = |snapshot: &Snapshot| snapshot.files[Path::new("lib.rs")].hash.unwrap();
+        let first = hash(&store.take(root, Tool::Claude).unwrap());
+
+        // Only reading the file again would notice this edit.
+        write("two");
+        assert_eq!(hash(&store.take(root, Tool::Claude).unwrap()), first);
+
+        // Once its stored copy is gone, the file is read again.
+        fs::remove_file(store.object_path(first)).unwrap();
+        assert_eq!(hash(&store.take(root, Tool::Claude).unwrap()), xxh3_64(b"two"));
+    }
+
+    #[test]
+    fn test_unchanged_runs_are_not_kept_and_old_ones_pruned() {
+        let work = tempdir().unwrap();
+        let data = tempdir().unwrap();
+        let store = store(data.path(), SnapshotConfig { keep: 2, ..Default::default() });
+        let root = work.path();
+        fs::write(root.join("file.txt"), "0").unwrap();
+
+        let snapshot = store.take(root, Tool::Gemini).unwrap();
+        assert!(store.record(snapshot).unwrap().is_empty());
+        assert!(store.snapshot_files().unwrap().is_empty());
+
+        for i in 1..=3 {
+            let snapshot = store.take(root, Tool::Gemini).unwrap();
+            fs::write(root.join("file.txt"), i.to_string()).unwrap();
+            store.record(snapshot).unwrap();
+        }
+        assert_eq!(store.snapshot_files().unwrap().len(), 2);
+
+        // Stale content nothing refers to is removed.
+        let stale = store.object_path(xxh3_64(b"0"));
+        File::options().write(true).open(&stale).unwrap()
+            .set_modified(SystemTime::now() - OBJECT_GRACE * 2).unwrap();
+        store.collect_garbage().unwrap();
+        assert!(!stale.exists());
+
+        store.undo(root).unwrap();
+        assert_eq!(fs::read_to_string(root.join("file.txt")).unwrap(), "2");
+    }
+}
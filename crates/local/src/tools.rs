@@ -1,27 +1,60 @@
 //! Local tool execution without network
 
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::sync::Arc;
+use std::time::Instant;
 
 use parking_lot::RwLock;
-use tokio::process::Command;
-use tokio::io::{BufReader, AsyncBufReadExt};
-use tokio::sync::mpsc;
+use tokio::process::{Child, Command};
+use tokio::io::{BufReader, AsyncBufReadExt, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot, watch};
 use chrono::Utc;
 
-use polyglot_common::{Tool, ToolUsage, RotationStrategy};
+use polyglot_common::{
+    Tool, ToolUsage, RotationStrategy, Message, ToolAction, TokenUsage,
+    ClaudeStreamParser, ClaudeEvent, CLAUDE_STREAM_ARGS, ContextBuilder, KeyVault,
+    Redactor, RedactionReport,
+};
 use crate::config::{LocalConfig, ToolConfig};
 use crate::environment::EnvironmentManager;
-use crate::sandbox::{SandboxConfig as SandboxSettings};
+use crate::ollama::OllamaClient;
+use crate::sandbox::{SandboxConfig as SandboxSettings, ToolPaths};
+use crate::pty::{PtyInput, Terminal};
+use crate::race::{RaceRecord, RaceStats};
+use crate::snapshot::{FileChange, Snapshot, SnapshotStore};
 
 #[derive(Debug, Clone)]
 pub enum ToolOutput {
     Stdout(String),
     Stderr(String),
-    Done { tool: Tool, tokens: Option<u64> },
+    /// A file edit, shell command or other action the tool reported
+    Action(ToolAction),
+    /// The tool's own session ID, to be resumed on the next prompt
+    NativeSession { tool: Tool, session_id: String },
+    Done { tool: Tool, tokens: Option<u64>, model: Option<String> },
+    /// Files the run added, modified or deleted in the working directory,
+    /// reviewable through `SnapshotStore` under `snapshot`
+    FilesChanged { tool: Tool, snapshot: uuid::Uuid, changes: Vec<FileChange> },
     Error(String),
+    /// Secrets were removed from the prompt before it was sent
+    Redacted(RedactionReport),
     RateLimited { tool: Tool, next_tool: Option<Tool> },
+    /// The tool runs under a pseudo-terminal and reads what is typed into `input`
+    Terminal { tool: Tool, input: PtyInput },
+    /// Another tool won the race; this one was stopped, or its answer set aside
+    Cancelled { tool: Tool },
+}
+
+/// How `execute_multi_streaming` treats the tools it runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MultiMode {
+    /// Every tool answers
+    All,
+    /// The first tool to answer successfully wins, and the rest are stopped
+    Race,
 }
 
 #[derive(Debug, Clone)]
@@ -41,15 +74,39 @@ struct LocalToolManagerInner {
     environment: EnvironmentManager,
     sandbox: SandboxSettings,
     force_isolated: bool,
+    /// Where working tree snapshots go, unless they are turned off
+    snapshots: Option<SnapshotStore>,
+    /// The project given at startup, where internal prompts run
+    project_dir: PathBuf,
+    /// API keys from the vault, added to each tool's environment
+    keys: HashMap<Tool, Vec<(String, String)>>,
+    /// Race mode wins and times, which order fallback tools
+    races: RwLock<RaceStats>,
 }
 
 #[derive(Clone)]
 pub struct LocalToolManager {
     inner: Arc<LocalToolManagerInner>,
+    /// Prior conversation turns, passed to tools that accept chat history
+    history: Vec<Message>,
+    /// Per-tool model overrides chosen with `/model`
+    models: HashMap<Tool, String>,
+    /// Native session IDs of the current chat, passed to the tool's resume flag
+    native_sessions: HashMap<Tool, String>,
+    /// Off for internal prompts, such as summaries, that shouldn't be undoable
+    take_snapshots: bool,
+    /// Applied to prompts and chat history before they reach a tool
+    redactor: Option<Redactor>,
+    /// Refuse prompts with secrets in them rather than redact them
+    block_secrets: bool,
+    /// Set once another tool wins the race this run is part of
+    cancel: Option<watch::Receiver<bool>>,
 }
 
 impl LocalToolManager {
-    pub fn new(config: &LocalConfig) -> Self {
+    /// `project_dir` is granted read and write access like the configured
+    /// sandbox paths.
+    pub fn new(config: &LocalConfig, project_dir: &Path) -> Self {
         let mut configs = HashMap::new();
         let mut usage = HashMap::new();
 
@@ -62,15 +119,19 @@ impl LocalToolManager {
             max_cpu_percent: config.sandbox.max_cpu_percent,
             network_access: config.sandbox.get_network_policy(),
             env_whitelist: config.sandbox.env_whitelist.clone(),
+            cgroups: config.sandbox.cgroups,
+            cgroup_parent: config.sandbox.cgroup_parent.clone(),
+            max_pids: config.sandbox.max_pids,
+            max_io_mbps: config.sandbox.max_io_mbps,
+            landlock: config.sandbox.landlock,
         };
 
-        if let Ok(current_dir) = std::env::current_dir() {
-            if !sandbox_settings.allowed_read_paths.contains(&current_dir) {
-                sandbox_settings.allowed_read_paths.push(current_dir.clone());
-            }
-            if !sandbox_settings.allowed_write_paths.contains(&current_dir) {
-                sandbox_settings.allowed_write_paths.push(current_dir);
-            }
+        let project_dir = project_dir.to_path_buf();
+        if !sandbox_settings.allowed_read_paths.contains(&project_dir) {
+            sandbox_settings.allowed_read_paths.push(project_dir.clone());
+        }
+        if !sandbox_settings.allowed_write_paths.contains(&project_dir) {
+            sandbox_settings.allowed_write_paths.push(project_dir.clone());
         }
 
         if sandbox_settings.allowed_read_paths.is_empty() {
@@ -91,6 +152,24 @@ impl LocalToolManager {
             config.isolation.auto_install,
         );
 
+        let snapshots = if config.snapshots.enabled {
+            SnapshotStore::new(None, config.snapshots.clone(), config.history.encrypt)
+                .map_err(|e| eprintln!("Warning: Failed to initialize snapshots, /undo is unavailable: {}", e))
+                .ok()
+        } else {
+            None
+        };
+
+        let mut keys: HashMap<Tool, Vec<(String, String)>> = HashMap::new();
+        match KeyVault::load_env(&KeyVault::default_dir()) {
+            Ok(entries) => {
+                for (tool, name, value) in entries {
+                    keys.entry(tool).or_default().push((name, value));
+                }
+            }
+            Err(e) => eprintln!("Warning: Failed to read the key vault, stored API keys are unavailable: {}", e),
+        }
+
         if let Some(ref c) = config.tools.claude {
             if c.enabled {
                 configs.insert(Tool::Claude, c.clone());
@@ -150,8 +229,64 @@ impl LocalToolManager {
                 environment,
                 sandbox: sandbox_settings,
                 force_isolated: config.isolation.force_isolated,
+                snapshots,
+                project_dir,
+                keys,
+                races: RwLock::new(RaceStats::load(RaceStats::default_path())),
             }),
+            history: Vec::new(),
+            models: HashMap::new(),
+            native_sessions: HashMap::new(),
+            take_snapshots: true,
+            redactor: None,
+            block_secrets: false,
+            cancel: None,
+        }
+    }
+
+    pub fn set_history(&mut self, history: Vec<Message>) {
+        self.history = history;
+    }
+
+    pub fn set_models(&mut self, models: HashMap<Tool, String>) {
+        self.models = models;
+    }
+
+    pub fn set_redactor(&mut self, redactor: Option<Redactor>, block_secrets: bool) {
+        self.redactor = redactor;
+        self.block_secrets = block_secrets;
+    }
+
+    pub fn set_native_sessions(&mut self, sessions: HashMap<Tool, String>) {
+        self.native_sessions = sessions;
+    }
+
+    /// The `/model` override if one is set, otherwise the tool's configured default.
+    pub fn model_for(&self, tool: Tool) -> Option<String> {
+        self.models.get(&tool).cloned()
+            .or_else(|| self.inner.configs.get(&tool).and_then(|c| c.model.clone()))
+    }
+
+    pub fn context_builder(&self, tool: Tool) -> ContextBuilder {
+        let budget = self.inner.configs.get(&tool)
+            .and_then(|c| c.context_budget)
+            .unwrap_or_else(|| tool.default_context_budget());
+        ContextBuilder::new(budget)
+    }
+
+    fn ollama_client(&self) -> Option<OllamaClient> {
+        self.inner.configs.get(&Tool::Ollama)
+            .map(|c| OllamaClient::new(&c.hosts, self.model_for(Tool::Ollama)))
+    }
+
+    pub async fn list_models(&self, tool: Tool) -> Vec<String> {
+        if tool == Tool::Ollama {
+            return match self.ollama_client() {
+                Some(client) => client.installed_models().await,
+                None => Vec::new(),
+            };
         }
+        tool.known_models().iter().map(|m| m.to_string()).collect()
     }
 
     fn get_tool_path(&self, tool: Tool) -> String {
@@ -167,6 +302,14 @@ impl LocalToolManager {
         }
     }
 
+    pub fn sandbox(&self) -> &SandboxSettings {
+        &self.inner.sandbox
+    }
+
+    pub fn snapshots(&self) -> Option<&SnapshotStore> {
+        self.inner.snapshots.as_ref()
+    }
+
     pub fn environment(&self) -> &EnvironmentManager {
         &self.inner.environment
     }
@@ -176,6 +319,13 @@ impl LocalToolManager {
             return false;
         }
 
+        if tool == Tool::Ollama {
+            return match self.ollama_client() {
+                Some(client) => client.is_available().await,
+                None => false,
+            };
+        }
+
         let tool_path = self.get_tool_path(tool);
 
         let result = Command::new(&tool_path)
@@ -204,10 +354,13 @@ impl LocalToolManager {
         available
     }
 
+    /// Runs `prompt` on `tool` in `working_dir`, which must be readable and
+    /// writable under the sandbox.
     pub async fn execute_streaming(
         &mut self,
         prompt: &str,
         tool: Option<Tool>,
+        working_dir: &Path,
         output_tx: mpsc::Sender<ToolOutput>,
     ) -> anyhow::Result<()> {
         let tool = tool.unwrap_or(self.inner.default_tool);
@@ -216,6 +369,11 @@ impl LocalToolManager {
             .ok_or_else(|| anyhow::anyhow!("{} is not configured", tool.display_name()))?
             .clone();
 
+        let Some(prompt) = self.redact_prompt(prompt, &output_tx).await else {
+            return Ok(());
+        };
+        let prompt = prompt.as_ref();
+
         {
             let mut usage = self.inner.usage.write();
             if let Some(stats) = usage.get_mut(&tool) {
@@ -224,6 +382,16 @@ impl LocalToolManager {
             }
         }
 
+        if tool == Tool::Ollama {
+            return tokio::select! {
+                result = self.execute_ollama(prompt, output_tx.clone()) => result,
+                _ = self.cancelled() => {
+                    output_tx.send(ToolOutput::Cancelled { tool }).await.ok();
+                    Ok(())
+                }
+            };
+        }
+
         let tool_path = self.get_tool_path(tool);
         let mut cmd = Command::new(&tool_path);
 
@@ -231,9 +399,17 @@ impl LocalToolManager {
             cmd.arg(arg);
         }
 
+        let model = self.model_for(tool);
+        if let (Some(flag), Some(model)) = (tool.model_flag(), &model) {
+            cmd.arg(flag);
+            cmd.arg(model);
+        }
+        cmd.args(tool.resume_args(self.native_sessions.get(&tool).map(|s| s.as_str())));
+
         match tool {
             Tool::Claude => {
                 cmd.arg("--print");
+                cmd.args(CLAUDE_STREAM_ARGS);
                 cmd.arg(prompt);
             }
             Tool::Gemini => {
@@ -255,25 +431,23 @@ impl LocalToolManager {
                 cmd.arg("-p");
                 cmd.arg(prompt);
             }
-            Tool::Ollama => {
-                if config.args.is_empty() {
-                    cmd.arg("run");
-                    cmd.arg("codellama");
-                }
-                cmd.arg(prompt);
-            }
+            Tool::Ollama => unreachable!("Ollama is served over HTTP"),
         }
 
         let mut filtered_env = self.inner.sandbox.filter_env_vars(&config.env);
-        self.inner.sandbox.add_tool_env_vars(&mut filtered_env, tool);
+        // Vault keys are whitelisted for their own tool, like `--env` entries.
+        if let Some(keys) = self.inner.keys.get(&tool) {
+            filtered_env.retain(|(key, _)| !keys.iter().any(|(name, _)| name == key));
+            filtered_env.extend(keys.iter().cloned());
+        }
+        self.inner.sandbox.add_tool_env_vars(&mut filtered_env, tool, working_dir);
 
         cmd.env_clear();
         for (key, value) in &filtered_env {
             cmd.env(key, value);
         }
 
-        let working_dir = std::env::current_dir().unwrap_or_else(|_| self.inner.sandbox.get_workspace_dir());
-        if let Err(e) = self.inner.sandbox.validate_path_read(&working_dir) {
+        if let Err(e) = self.inner.sandbox.validate_path_read(working_dir) {
             let _ = output_tx.send(ToolOutput::Error(format!(
                 "Sandbox read access denied for {}: {}",
                 working_dir.display(),
@@ -281,7 +455,7 @@ impl LocalToolManager {
             ))).await;
             return Err(e);
         }
-        if let Err(e) = self.inner.sandbox.validate_path_write(&working_dir) {
+        if let Err(e) = self.inner.sandbox.validate_path_write(working_dir) {
             let _ = output_tx.send(ToolOutput::Error(format!(
                 "Sandbox write access denied for {}: {}",
                 working_dir.display(),
@@ -289,89 +463,196 @@ impl LocalToolManager {
             ))).await;
             return Err(e);
         }
-        cmd.current_dir(&working_dir);
+        cmd.current_dir(working_dir);
+
+        let snapshot = self.take_snapshot(working_dir, tool).await;
+
+        let terminal = if config.pty {
+            if !filtered_env.iter().any(|(key, _)| key == "TERM") {
+                cmd.env("TERM", "xterm-256color");
+            }
+            match Terminal::attach(&mut cmd) {
+                Ok(terminal) => Some(terminal),
+                Err(e) => {
+                    let message = format!("Failed to open a terminal for {}: {}", tool.display_name(), e);
+                    output_tx.send(ToolOutput::Error(message.clone())).await.ok();
+                    return Err(anyhow::anyhow!(message));
+                }
+            }
+        } else {
+            cmd.stdout(Stdio::piped());
+            cmd.stderr(Stdio::piped());
+            cmd.stdin(Stdio::null());
+            // Its own group, so a lost race can stop whatever it started.
+            // The terminal's new session already is one.
+            #[cfg(unix)]
+            cmd.process_group(0);
+            None
+        };
 
-        cmd.stdout(Stdio::piped());
-        cmd.stderr(Stdio::piped());
-        cmd.stdin(Stdio::null());
+        let tool_paths = ToolPaths::for_tool(tool, &tool_path);
+
+        let seccomp = config.get_seccomp_profile();
 
         #[cfg(unix)]
-        crate::sandbox::unix::apply_resource_limits(&mut cmd, &self.inner.sandbox);
+        let limits = seccomp.and_then(|seccomp| crate::sandbox::unix::apply_resource_limits(
+            &mut cmd, &self.inner.sandbox, &tool_paths, working_dir, seccomp));
 
         #[cfg(windows)]
-        crate::sandbox::windows::apply_resource_limits(&mut cmd, &self.inner.sandbox);
+        let limits = seccomp.and_then(|seccomp| crate::sandbox::windows::apply_resource_limits(
+            &mut cmd, &self.inner.sandbox, &tool_paths, working_dir, seccomp));
+
+        let limits = match limits {
+            Ok(guard) => guard,
+            Err(e) => {
+                output_tx.send(ToolOutput::Error(format!("Not starting {}: {}", tool.display_name(), e))).await.ok();
+                return Err(e);
+            }
+        };
+
+        if self.cancel.as_ref().is_some_and(|cancel| *cancel.borrow()) {
+            output_tx.send(ToolOutput::Cancelled { tool }).await.ok();
+            return Ok(());
+        }
 
         let mut child = match cmd.spawn() {
             Ok(c) => c,
             Err(e) => {
-                output_tx.send(ToolOutput::Error(format!("Failed to start {}: {}", tool.display_name(), e))).await.ok();
-                return Err(e.into());
+                let policy = self.inner.sandbox.network_access;
+                let message = if self.inner.sandbox.enabled && policy.needs_isolation() && e.kind() != std::io::ErrorKind::NotFound {
+                    format!(
+                        "Failed to start {} in an isolated network namespace (network_access = \"{}\"): {}. \
+                         Network namespaces may be disabled on this system; the tool was not run without isolation.",
+                        tool.display_name(), policy.as_str(), e
+                    )
+                } else {
+                    format!("Failed to start {}: {}", tool.display_name(), e)
+                };
+                output_tx.send(ToolOutput::Error(message.clone())).await.ok();
+                return Err(anyhow::anyhow!(message));
             }
         };
 
-        let stdout = child.stdout.take()
-            .ok_or_else(|| anyhow::anyhow!("Failed to capture stdout"))?;
-        let stderr = child.stderr.take()
-            .ok_or_else(|| anyhow::anyhow!("Failed to capture stderr"))?;
+        // The terminal's slave side must only stay open in the child, or
+        // reading the master never ends.
+        drop(cmd);
 
-        let output_tx_stdout = output_tx.clone();
-        let output_tx_stderr = output_tx.clone();
         let inner = self.inner.clone();
+        let mut exited = None;
+
+        let (stdout_handle, stderr_handle) = match terminal {
+            Some(terminal) => {
+                let (exit_tx, exit_rx) = oneshot::channel();
+                exited = Some(exit_tx);
+                // Claude's stream-json lines must not be split at idle points.
+                let (input, mut lines) = terminal.start(tool != Tool::Claude, exit_rx)?;
+                output_tx.send(ToolOutput::Terminal { tool, input }).await.ok();
+
+                // Cleaned lines go through the same reader as pipe mode.
+                let (reader, mut writer) = tokio::io::duplex(64 * 1024);
+                let stdout_handle = tokio::spawn(forward_stdout(tool, reader, output_tx.clone()));
+                let output_tx = output_tx.clone();
+                let stderr_handle = tokio::spawn(async move {
+                    let mut rate_limited = false;
+                    while let Some(line) = lines.next_line().await {
+                        if is_rate_limit_message(&line) {
+                            rate_limited = true;
+                            report_rate_limit(&inner, tool, &output_tx).await;
+                        } else if writer.write_all(format!("{}\n", line).as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                    rate_limited
+                });
+                (stdout_handle, stderr_handle)
+            }
+            None => {
+                let stdout = child.stdout.take()
+                    .ok_or_else(|| anyhow::anyhow!("Failed to capture stdout"))?;
+                let stderr = child.stderr.take()
+                    .ok_or_else(|| anyhow::anyhow!("Failed to capture stderr"))?;
+
+                let stdout_handle = tokio::spawn(forward_stdout(tool, stdout, output_tx.clone()));
+                let output_tx = output_tx.clone();
+                let stderr_handle = tokio::spawn(async move {
+                    let reader = BufReader::new(stderr);
+                    let mut lines = reader.lines();
+                    let mut rate_limited = false;
+
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        if is_rate_limit_message(&line) {
+                            rate_limited = true;
+                            report_rate_limit(&inner, tool, &output_tx).await;
+                        } else {
+                            output_tx.send(ToolOutput::Stderr(line)).await.ok();
+                        }
+                    }
 
-        let stdout_handle = tokio::spawn(async move {
-            let reader = BufReader::new(stdout);
-            let mut lines = reader.lines();
+                    rate_limited
+                });
+                (stdout_handle, stderr_handle)
+            }
+        };
 
-            while let Ok(Some(line)) = lines.next_line().await {
-                if output_tx_stdout.send(ToolOutput::Stdout(line)).await.is_err() {
-                    break;
-                }
+        let (status, cancelled) = tokio::select! {
+            status = child.wait() => (status?, false),
+            _ = self.cancelled() => {
+                kill_tool(&mut child);
+                (child.wait().await?, true)
             }
-        });
+        };
+        if let Some(exited) = exited {
+            exited.send(()).ok();
+        }
 
-        let stderr_handle = tokio::spawn(async move {
-            let reader = BufReader::new(stderr);
-            let mut lines = reader.lines();
-            let mut rate_limited = false;
-
-            while let Ok(Some(line)) = lines.next_line().await {
-                let lower = line.to_lowercase();
-                if lower.contains("rate limit") ||
-                   lower.contains("too many requests") ||
-                   lower.contains("quota exceeded") ||
-                   lower.contains("429")
-                {
-                    rate_limited = true;
-                    let next_tool = get_next_tool(&inner, tool);
-                    output_tx_stderr.send(ToolOutput::RateLimited {
-                        tool,
-                        next_tool,
-                    }).await.ok();
-
-                    let mut usage = inner.usage.write();
-                    if let Some(stats) = usage.get_mut(&tool) {
-                        stats.rate_limit_hits += 1;
-                        stats.is_available = false;
-                    }
-                } else {
-                    output_tx_stderr.send(ToolOutput::Stderr(line)).await.ok();
-                }
+        if let Some(resources) = limits.finish() {
+            let mut usage = self.inner.usage.write();
+            if let Some(stats) = usage.get_mut(&tool) {
+                stats.record_resources(resources.peak_memory_bytes, resources.cpu_time_ms);
             }
+        }
 
-            rate_limited
-        });
+        if let Some(snapshot) = snapshot {
+            let id = snapshot.id;
+            let changes = self.record_snapshot(snapshot).await;
+            if !changes.is_empty() {
+                output_tx.send(ToolOutput::FilesChanged { tool, snapshot: id, changes }).await.ok();
+            }
+        }
 
-        let status = child.wait().await?;
+        if cancelled {
+            // Anything the tool started may still hold its output open.
+            stdout_handle.abort();
+            stderr_handle.abort();
+            output_tx.send(ToolOutput::Cancelled { tool }).await.ok();
+            return Ok(());
+        }
 
-        let _ = stdout_handle.await;
+        let (reported_usage, reported_error) = stdout_handle.await.unwrap_or((None, None));
         let rate_limited = stderr_handle.await.unwrap_or(false);
 
         if rate_limited {
             return Ok(());
         }
 
-        if status.success() {
-            output_tx.send(ToolOutput::Done { tool, tokens: None }).await.ok();
+        if let Some(reported) = &reported_usage {
+            let mut usage = self.inner.usage.write();
+            if let Some(stats) = usage.get_mut(&tool) {
+                stats.record_tokens(reported);
+            }
+        }
+
+        if let Some(error) = reported_error {
+            {
+                let mut usage = self.inner.usage.write();
+                if let Some(stats) = usage.get_mut(&tool) {
+                    stats.errors += 1;
+                }
+            }
+            output_tx.send(ToolOutput::Error(format!("{}: {}", tool.display_name(), error))).await.ok();
+        } else if status.success() {
+            let tokens = reported_usage.map(|u| u.total());
+            output_tx.send(ToolOutput::Done { tool, tokens, model }).await.ok();
         } else {
             {
                 let mut usage = self.inner.usage.write();
@@ -387,6 +668,151 @@ impl LocalToolManager {
         Ok(())
     }
 
+    /// Resolves once another tool wins the race this run is part of, and
+    /// never outside a race.
+    async fn cancelled(&self) {
+        if let Some(mut cancel) = self.cancel.clone() {
+            if cancel.wait_for(|&cancelled| cancelled).await.is_ok() {
+                return;
+            }
+        }
+        std::future::pending().await
+    }
+
+    /// Removes secrets from `prompt` and reports what went. `None` when
+    /// secrets block the prompt instead.
+    async fn redact_prompt<'a>(&self, prompt: &'a str, output_tx: &mpsc::Sender<ToolOutput>) -> Option<Cow<'a, str>> {
+        let Some(ref redactor) = self.redactor else {
+            return Some(Cow::Borrowed(prompt));
+        };
+        let redacted = redactor.redact(prompt);
+        if redacted.report.is_empty() {
+            return Some(redacted.text);
+        }
+        if self.block_secrets {
+            output_tx.send(ToolOutput::Error(format!(
+                "Prompt not sent, it contains secrets ({}). Remove them, or set block_prompts = false under [redaction] to send it redacted.",
+                redacted.report
+            ))).await.ok();
+            return None;
+        }
+        output_tx.send(ToolOutput::Redacted(redacted.report)).await.ok();
+        Some(redacted.text)
+    }
+
+    /// Snapshots `working_dir` before `tool` runs in it. Failures only cost
+    /// the ability to undo the run, so they are logged rather than returned.
+    async fn take_snapshot(&self, working_dir: &Path, tool: Tool) -> Option<Snapshot> {
+        let store = self.inner.snapshots.clone().filter(|_| self.take_snapshots)?;
+        let root = working_dir.to_path_buf();
+        match tokio::task::spawn_blocking(move || store.take(&root, tool)).await {
+            Ok(Ok(snapshot)) => Some(snapshot),
+            Ok(Err(e)) => {
+                tracing::warn!("Not snapshotting {}, this run can't be undone: {}", working_dir.display(), e);
+                None
+            }
+            Err(_) => None,
+        }
+    }
+
+    async fn record_snapshot(&self, snapshot: Snapshot) -> Vec<FileChange> {
+        let Some(store) = self.inner.snapshots.clone() else {
+            return Vec::new();
+        };
+        match tokio::task::spawn_blocking(move || store.record(snapshot)).await {
+            Ok(Ok(changes)) => changes,
+            Ok(Err(e)) => {
+                tracing::warn!("Failed to record changed files: {}", e);
+                Vec::new()
+            }
+            Err(_) => Vec::new(),
+        }
+    }
+
+    async fn execute_ollama(&self, prompt: &str, output_tx: mpsc::Sender<ToolOutput>) -> anyhow::Result<()> {
+        let client = self.ollama_client()
+            .ok_or_else(|| anyhow::anyhow!("Ollama is not configured"))?;
+
+        let history: Vec<Message> = match self.redactor {
+            Some(ref redactor) => self.history.iter()
+                .map(|m| Message { content: redactor.redact(&m.content).text.into_owned(), ..m.clone() })
+                .collect(),
+            None => self.history.clone(),
+        };
+
+        match client.chat(&history, prompt, &output_tx).await {
+            Ok((tokens, model)) => {
+                if let Some(count) = tokens {
+                    let mut usage = self.inner.usage.write();
+                    if let Some(stats) = usage.get_mut(&Tool::Ollama) {
+                        stats.tokens_used += count;
+                    }
+                }
+                output_tx.send(ToolOutput::Done { tool: Tool::Ollama, tokens, model: Some(model) }).await.ok();
+            }
+            Err(e) => {
+                {
+                    let mut usage = self.inner.usage.write();
+                    if let Some(stats) = usage.get_mut(&Tool::Ollama) {
+                        stats.errors += 1;
+                    }
+                }
+                output_tx.send(ToolOutput::Error(format!("Ollama: {}", e))).await.ok();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Streams a one-off prompt on `tool` in the startup project, without
+    /// chat history, a resumed session or a snapshot.
+    pub async fn execute_oneoff(
+        &self,
+        prompt: &str,
+        tool: Tool,
+        model: Option<String>,
+        output_tx: mpsc::Sender<ToolOutput>,
+    ) -> anyhow::Result<()> {
+        let mut tm = self.clone();
+        tm.history.clear();
+        tm.native_sessions.clear();
+        tm.take_snapshots = false;
+        if let Some(model) = model {
+            tm.models.insert(tool, model);
+        }
+
+        let project_dir = tm.inner.project_dir.clone();
+        tm.execute_streaming(prompt, Some(tool), &project_dir, output_tx).await
+    }
+
+    /// Runs a one-off prompt on `tool` and returns its whole reply.
+    pub async fn complete(&self, prompt: &str, tool: Tool, model: Option<String>) -> anyhow::Result<String> {
+        let (tx, mut rx) = mpsc::channel(100);
+        let tm = self.clone();
+        let prompt = prompt.to_string();
+        let handle = tokio::spawn(async move {
+            tm.execute_oneoff(&prompt, tool, model, tx).await
+        });
+
+        let mut reply = String::new();
+        while let Some(output) = rx.recv().await {
+            match output {
+                ToolOutput::Stdout(line) => {
+                    reply.push_str(&line);
+                    reply.push('\n');
+                }
+                ToolOutput::Error(e) => anyhow::bail!(e),
+                ToolOutput::RateLimited { tool, .. } => {
+                    anyhow::bail!("{} is rate limited", tool.display_name())
+                }
+                _ => {}
+            }
+        }
+
+        handle.await??;
+        Ok(reply)
+    }
+
     pub fn get_usage(&self) -> Vec<ToolUsage> {
         self.inner.usage.read().values().cloned().collect()
     }
@@ -396,15 +822,25 @@ impl LocalToolManager {
         self.inner.default_tool
     }
 
+    /// Runs `prompt` on each of `tools` at once. In a race, only the first
+    /// tool to finish successfully reports `Done`; the others are stopped
+    /// and report `Cancelled`, and the result is added to the race stats.
     pub async fn execute_multi_streaming(
         &mut self,
         prompt: &str,
         tools: Vec<Tool>,
+        working_dir: &Path,
         output_tx: mpsc::Sender<TaggedOutput>,
+        mode: MultiMode,
     ) -> anyhow::Result<()> {
         use tokio::task::JoinSet;
 
         let mut join_set: JoinSet<anyhow::Result<()>> = JoinSet::new();
+        let (cancel_tx, cancel_rx) = watch::channel(false);
+        let cancel_tx = Arc::new(cancel_tx);
+        let started = Instant::now();
+        let winner: Arc<RwLock<Option<(Tool, std::time::Duration)>>> = Arc::default();
+        let mut entrants = Vec::new();
 
         for tool in tools {
             if !self.inner.configs.contains_key(&tool) {
@@ -415,10 +851,18 @@ impl LocalToolManager {
                 }).await;
                 continue;
             }
+            entrants.push(tool);
 
             let tx = output_tx.clone();
             let prompt = prompt.to_string();
+            let working_dir = working_dir.to_path_buf();
             let mut tm = self.clone();
+            let race = mode == MultiMode::Race;
+            if race {
+                tm.cancel = Some(cancel_rx.clone());
+            }
+            let cancel_tx = cancel_tx.clone();
+            let winner = winner.clone();
 
             join_set.spawn(async move {
                 let (tool_tx, mut tool_rx) = mpsc::channel::<ToolOutput>(100);
@@ -426,10 +870,19 @@ impl LocalToolManager {
                 let exec_tool = tool;
                 let exec_prompt = prompt.clone();
                 let exec_handle = tokio::spawn(async move {
-                    tm.execute_streaming(&exec_prompt, Some(exec_tool), tool_tx).await
+                    tm.execute_streaming(&exec_prompt, Some(exec_tool), &working_dir, tool_tx).await
                 });
 
-                while let Some(output) = tool_rx.recv().await {
+                while let Some(mut output) = tool_rx.recv().await {
+                    if race && matches!(output, ToolOutput::Done { .. }) {
+                        // Two tools can finish together; only the first to get here wins.
+                        let won = cancel_tx.send_if_modified(|cancelled| !std::mem::replace(cancelled, true));
+                        if won {
+                            *winner.write() = Some((tool, started.elapsed()));
+                        } else {
+                            output = ToolOutput::Cancelled { tool };
+                        }
+                    }
                     if tx.send(TaggedOutput { tool, output }).await.is_err() {
                         break;
                     }
@@ -444,21 +897,126 @@ impl LocalToolManager {
             let _ = result;
         }
 
+        if mode == MultiMode::Race && !entrants.is_empty() {
+            let mut races = self.inner.races.write();
+            races.record(&entrants, *winner.read());
+            if let Err(e) = races.save() {
+                tracing::warn!("{}", e);
+            }
+        }
+
         Ok(())
     }
 
+    /// How `tool` has done in races, if it has raced.
+    pub fn race_record(&self, tool: Tool) -> Option<RaceRecord> {
+        self.inner.races.read().get(tool).copied()
+    }
+
     #[allow(dead_code)]
     pub fn configured_tools(&self) -> Vec<Tool> {
         self.inner.configs.keys().copied().collect()
     }
 }
 
+/// Forwards a tool's standard output, returning what `forward_claude_stream`
+/// reports for Claude.
+async fn forward_stdout<R>(
+    tool: Tool,
+    stdout: R,
+    output_tx: mpsc::Sender<ToolOutput>,
+) -> (Option<TokenUsage>, Option<String>)
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    let reader = BufReader::new(stdout);
+    let mut lines = reader.lines();
+
+    if tool == Tool::Claude {
+        return forward_claude_stream(tool, &mut lines, &output_tx).await;
+    }
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        if output_tx.send(ToolOutput::Stdout(line)).await.is_err() {
+            break;
+        }
+    }
+    (None, None)
+}
+
+fn is_rate_limit_message(line: &str) -> bool {
+    let lower = line.to_lowercase();
+    lower.contains("rate limit") ||
+        lower.contains("too many requests") ||
+        lower.contains("quota exceeded") ||
+        lower.contains("429")
+}
+
+async fn report_rate_limit(inner: &LocalToolManagerInner, tool: Tool, output_tx: &mpsc::Sender<ToolOutput>) {
+    let next_tool = get_next_tool(inner, tool);
+    output_tx.send(ToolOutput::RateLimited { tool, next_tool }).await.ok();
+
+    let mut usage = inner.usage.write();
+    if let Some(stats) = usage.get_mut(&tool) {
+        stats.rate_limit_hits += 1;
+        stats.is_available = false;
+    }
+}
+
+/// Turns Claude's stream-json events into line-based output, returning the
+/// reported token usage and any error the CLI reported in its result.
+async fn forward_claude_stream<R>(
+    tool: Tool,
+    lines: &mut tokio::io::Lines<BufReader<R>>,
+    output_tx: &mpsc::Sender<ToolOutput>,
+) -> (Option<TokenUsage>, Option<String>)
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    let mut parser = ClaudeStreamParser::new();
+    let mut pending = String::new();
+    let mut usage = None;
+    let mut error = None;
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        for event in parser.parse_line(&line) {
+            match event {
+                ClaudeEvent::Text(text) => {
+                    pending.push_str(&text);
+                    while let Some(pos) = pending.find('\n') {
+                        let complete: String = pending.drain(..=pos).collect();
+                        if output_tx.send(ToolOutput::Stdout(complete.trim_end_matches('\n').to_string())).await.is_err() {
+                            return (usage, error);
+                        }
+                    }
+                }
+                ClaudeEvent::Action(action) => {
+                    output_tx.send(ToolOutput::Action(action)).await.ok();
+                }
+                ClaudeEvent::Usage(reported) => usage = Some(reported),
+                ClaudeEvent::Session(session_id) => {
+                    output_tx.send(ToolOutput::NativeSession { tool, session_id }).await.ok();
+                }
+                ClaudeEvent::Error(message) => error = Some(message),
+            }
+        }
+    }
+
+    if !pending.is_empty() {
+        output_tx.send(ToolOutput::Stdout(pending)).await.ok();
+    }
+
+    (usage, error)
+}
+
 fn get_next_tool(inner: &LocalToolManagerInner, current: Tool) -> Option<Tool> {
     let usage = inner.usage.read();
 
     match inner.rotation_strategy {
         RotationStrategy::OnLimit | RotationStrategy::Priority => {
-            let priorities = [Tool::Claude, Tool::Gemini, Tool::Codex, Tool::Copilot, Tool::Perplexity, Tool::Cursor];
+            let mut priorities = [Tool::Claude, Tool::Gemini, Tool::Codex, Tool::Copilot, Tool::Perplexity, Tool::Cursor];
+            // Tools that win races are tried first.
+            inner.races.read().rank(&mut priorities);
             for tool in priorities {
                 if tool != current {
                     if let Some(stats) = usage.get(&tool) {
@@ -489,3 +1047,71 @@ fn get_next_tool(inner: &LocalToolManagerInner, current: Tool) -> Option<Tool> {
 
     None
 }
+
+/// Kills a tool and every process it started. Tools lead their own process
+/// group, so node or shell helpers go along with it.
+fn kill_tool(child: &mut Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // SAFETY: killpg only sends a signal.
+        unsafe {
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+    child.start_kill().ok();
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Gone, or a zombie waiting for init to reap it
+    fn is_dead(pid: u32) -> bool {
+        match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+            Ok(stat) => stat.rsplit_once(") ").is_some_and(|(_, rest)| rest.starts_with('Z')),
+            Err(_) => true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_kill_tool_stops_grandchildren() {
+        for pty in [false, true] {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg("sleep 30 & echo $!; wait");
+            let terminal = if pty {
+                Some(Terminal::attach(&mut cmd).unwrap())
+            } else {
+                cmd.stdout(Stdio::piped());
+                cmd.process_group(0);
+                None
+            };
+            let mut child = cmd.spawn().unwrap();
+            drop(cmd);
+
+            let grandchild = match terminal {
+                Some(terminal) => {
+                    let (_exit_tx, exit_rx) = oneshot::channel();
+                    let (_input, mut lines) = terminal.start(true, exit_rx).unwrap();
+                    lines.next_line().await.unwrap()
+                }
+                None => {
+                    let stdout = child.stdout.take().unwrap();
+                    BufReader::new(stdout).lines().next_line().await.unwrap().unwrap()
+                }
+            };
+            let grandchild: u32 = grandchild.trim().parse().unwrap();
+            assert!(!is_dead(grandchild));
+
+            kill_tool(&mut child);
+            child.wait().await.unwrap();
+
+            let mut waited = 0;
+            while !is_dead(grandchild) && waited < 50 {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                waited += 1;
+            }
+            assert!(is_dead(grandchild), "sleep outlived its tool (pty = {})", pty);
+        }
+    }
+}
@@ -1,6 +1,8 @@
 //! Terminal UI for Polyglot-AI Local
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use crossterm::event::{KeyCode, KeyModifiers};
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
@@ -12,6 +14,9 @@ use ratatui::{
 use unicode_width::UnicodeWidthChar;
 
 use polyglot_common::{Tool, ToolUsage, HistoryEntry};
+use crate::judge::{Candidate, Judge, JudgeMode};
+use crate::pty::PtyInput;
+use crate::snapshot::FileChange;
 
 #[derive(Clone, Default)]
 pub struct MultiModelState {
@@ -19,6 +24,32 @@ pub struct MultiModelState {
     pub selected_tools: Vec<Tool>,
     pub responses: HashMap<Tool, Vec<String>>,
     pub completed: HashMap<Tool, bool>,
+    pub models: HashMap<Tool, String>,
+    /// Each tool's reply, without the notes shown alongside it
+    pub answers: HashMap<Tool, String>,
+    /// Tools that errored or were rate limited
+    pub failed: HashSet<Tool>,
+    /// The prompt being answered
+    pub prompt: String,
+    /// Compares the answers once all are in, from `[judge]` or `/judge`
+    pub judge: Option<Judge>,
+    pub verdict: Option<Verdict>,
+    /// Take the first good answer and stop the other tools
+    pub race: bool,
+    /// When the prompt was sent, for the race winner's time
+    pub started: Option<Instant>,
+    pub winner: Option<(Tool, Duration)>,
+}
+
+/// The judge's pane in the multi-model view
+#[derive(Clone)]
+pub struct Verdict {
+    pub judge: Judge,
+    pub lines: Vec<String>,
+    /// The judge's reply, without notes
+    pub text: String,
+    pub model: Option<String>,
+    pub done: bool,
 }
 
 impl MultiModelState {
@@ -34,6 +65,7 @@ impl MultiModelState {
 
     pub fn disable(&mut self) {
         self.enabled = false;
+        self.race = false;
         self.selected_tools.clear();
         self.clear_responses();
     }
@@ -41,6 +73,11 @@ impl MultiModelState {
     pub fn clear_responses(&mut self) {
         self.responses.clear();
         self.completed.clear();
+        self.models.clear();
+        self.answers.clear();
+        self.failed.clear();
+        self.verdict = None;
+        self.winner = None;
         for tool in &self.selected_tools {
             self.responses.insert(*tool, Vec::new());
             self.completed.insert(*tool, false);
@@ -53,10 +90,42 @@ impl MultiModelState {
         }
     }
 
+    /// Adds a line of the tool's reply.
+    pub fn add_answer(&mut self, tool: Tool, line: String) {
+        let answer = self.answers.entry(tool).or_default();
+        answer.push_str(&line);
+        answer.push('\n');
+        self.add_line(tool, line);
+    }
+
     pub fn mark_done(&mut self, tool: Tool) {
         self.completed.insert(tool, true);
     }
 
+    pub fn mark_failed(&mut self, tool: Tool) {
+        self.failed.insert(tool);
+        self.mark_done(tool);
+    }
+
+    /// The replies worth judging, in the order the tools were selected.
+    pub fn candidates(&self) -> Vec<Candidate<'_>> {
+        self.selected_tools.iter()
+            .filter(|tool| !self.failed.contains(tool))
+            .filter_map(|tool| {
+                let answer = self.answers.get(tool).filter(|a| !a.trim().is_empty())?;
+                Some(Candidate { tool: *tool, model: self.models.get(tool).map(|m| m.as_str()), answer })
+            })
+            .collect()
+    }
+
+    pub fn start_verdict(&mut self, judge: Judge) {
+        self.verdict = Some(Verdict { judge, lines: Vec::new(), text: String::new(), model: None, done: false });
+    }
+
+    pub fn set_model(&mut self, tool: Tool, model: String) {
+        self.models.insert(tool, model);
+    }
+
     pub fn all_done(&self) -> bool {
         !self.selected_tools.is_empty() && self.completed.values().all(|&v| v)
     }
@@ -70,6 +139,65 @@ impl MultiModelState {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewStatus {
+    Pending,
+    Accepted,
+    Reverted,
+}
+
+#[derive(Clone)]
+pub struct ReviewFile {
+    pub tool: Tool,
+    /// The run that changed the file
+    pub snapshot: uuid::Uuid,
+    pub change: FileChange,
+    pub status: ReviewStatus,
+}
+
+/// Files changed by the last prompt, for the review view
+#[derive(Clone, Default)]
+pub struct ReviewState {
+    pub files: Vec<ReviewFile>,
+    pub selected: usize,
+    /// Diff of the selected file
+    pub diff: Vec<String>,
+    pub diff_scroll: usize,
+    /// Outcome of the last accept or revert
+    pub notice: String,
+}
+
+impl ReviewState {
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Adds the files one run changed; several tools may run for one prompt.
+    pub fn add_run(&mut self, tool: Tool, snapshot: uuid::Uuid, changes: Vec<FileChange>) {
+        self.files.extend(changes.into_iter().map(|change| ReviewFile {
+            tool,
+            snapshot,
+            change,
+            status: ReviewStatus::Pending,
+        }));
+    }
+
+    pub fn selected_file(&self) -> Option<&ReviewFile> {
+        self.files.get(self.selected)
+    }
+
+    pub fn set_status(&mut self, snapshot: uuid::Uuid, path: &std::path::Path, status: ReviewStatus) {
+        if let Some(file) = self.files.iter_mut().find(|f| f.snapshot == snapshot && f.change.path == path) {
+            file.status = status;
+        }
+    }
+
+    pub fn set_diff(&mut self, diff: &str) {
+        self.diff = diff.lines().map(|l| l.to_string()).collect();
+        self.diff_scroll = 0;
+    }
+}
+
 pub struct App {
     pub input: String,
     pub cursor_position: usize,
@@ -86,6 +214,15 @@ pub struct App {
     pub scroll_offset: usize,
     pub current_response: String,
     pub multi_model: MultiModelState,
+    /// Models chosen with `/model`, per tool
+    pub models: HashMap<Tool, String>,
+    pub review: ReviewState,
+    /// Where Enter sends the input line while a tool runs under a terminal
+    pub terminal_input: Option<PtyInput>,
+    /// The directory tools run in, changed with `/cd`
+    pub project_dir: PathBuf,
+    /// Repository map from `/map`, sent with the next prompt
+    pub pending_repo_map: Option<String>,
 }
 
 #[derive(Clone)]
@@ -113,6 +250,7 @@ pub enum View {
     Help,
     MultiSelect,
     About,
+    Review,
 }
 
 #[derive(Debug, Clone)]
@@ -128,9 +266,23 @@ pub enum AppAction {
     SearchHistory(String),
     NewChat,
     ResumeSession(uuid::Uuid),
+    /// Fork the current session after this many messages, or all of them
+    Fork(Option<usize>),
+    ShowBranches,
     EnableMultiModel(Vec<Tool>),
     DisableMultiModel,
     ToggleMultiTool(Tool),
+    ListModels(Tool),
+    ShowContext,
+    ShowRepoMap,
+    Undo,
+    ShowDiff(uuid::Uuid, PathBuf),
+    AcceptChange(uuid::Uuid, PathBuf),
+    RevertChange(uuid::Uuid, PathBuf),
+    ChangeDirectory(String),
+    /// Run the named pipeline on a prompt
+    RunPipeline(String, String),
+    ListPipelines,
     PerformUpdate,
 }
 
@@ -152,6 +304,11 @@ impl Default for App {
             scroll_offset: 0,
             current_response: String::new(),
             multi_model: MultiModelState::new(),
+            models: HashMap::new(),
+            review: ReviewState::default(),
+            terminal_input: None,
+            project_dir: std::env::current_dir().unwrap_or_default(),
+            pending_repo_map: None,
         }
     }
 }
@@ -195,6 +352,7 @@ impl App {
             (KeyCode::F(5), _) => { self.view = View::Help; None }
             (KeyCode::F(6), _) => { self.view = View::MultiSelect; Some(AppAction::RequestTools) }
             (KeyCode::F(7), _) => { self.view = View::About; None }
+            (KeyCode::F(8), _) => self.open_review(),
 
             (KeyCode::Char('n'), KeyModifiers::CONTROL) => {
                 Some(AppAction::NewChat)
@@ -211,6 +369,8 @@ impl App {
                 }
             }
 
+            _ if self.view == View::Review => self.handle_review_key(code),
+
             (KeyCode::PageUp, _) => {
                 if self.view == View::History {
                     self.history_selected = self.history_selected.saturating_sub(1);
@@ -309,6 +469,89 @@ impl App {
         }
     }
 
+    fn open_review(&mut self) -> Option<AppAction> {
+        self.view = View::Review;
+        self.show_selected_diff()
+    }
+
+    fn show_selected_diff(&self) -> Option<AppAction> {
+        self.review.selected_file()
+            .map(|file| AppAction::ShowDiff(file.snapshot, file.change.path.clone()))
+    }
+
+    fn handle_review_key(&mut self, code: KeyCode) -> Option<AppAction> {
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.review.selected = self.review.selected.saturating_sub(1);
+                self.show_selected_diff()
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.review.selected = (self.review.selected + 1).min(self.review.files.len().saturating_sub(1));
+                self.show_selected_diff()
+            }
+            KeyCode::PageUp => {
+                self.review.diff_scroll = self.review.diff_scroll.saturating_sub(10);
+                None
+            }
+            KeyCode::PageDown => {
+                let last = self.review.diff.len().saturating_sub(1);
+                self.review.diff_scroll = (self.review.diff_scroll + 10).min(last);
+                None
+            }
+            KeyCode::Char('a') => {
+                let file = self.review.selected_file().filter(|f| f.status == ReviewStatus::Pending)?;
+                Some(AppAction::AcceptChange(file.snapshot, file.change.path.clone()))
+            }
+            KeyCode::Char('r') => {
+                let file = self.review.selected_file().filter(|f| f.status == ReviewStatus::Pending)?;
+                Some(AppAction::RevertChange(file.snapshot, file.change.path.clone()))
+            }
+            KeyCode::Esc => {
+                self.view = View::Chat;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Completes the `@path` before the cursor against the project directory,
+    /// as far as the candidates agree, and lists them when that adds nothing.
+    fn complete_file_ref(&mut self) {
+        let cursor_byte = self.input.char_indices()
+            .nth(self.cursor_position)
+            .map(|(i, _)| i)
+            .unwrap_or(self.input.len());
+        let before = &self.input[..cursor_byte];
+        let word_start = before.rfind(char::is_whitespace).map(|i| i + 1).unwrap_or(0);
+        let Some(partial) = before[word_start..].strip_prefix('@') else {
+            return;
+        };
+
+        let candidates = polyglot_common::complete_file_ref(&self.project_dir, partial);
+        let Some(first) = candidates.first() else {
+            return;
+        };
+        let common: String = first.chars().enumerate()
+            .take_while(|(i, c)| candidates.iter().all(|other| other.chars().nth(*i) == Some(*c)))
+            .map(|(_, c)| c)
+            .collect();
+
+        if common.len() > partial.len() {
+            let start = word_start + 1;
+            self.input.replace_range(start..cursor_byte, &common);
+            self.cursor_position = self.input[..start + common.len()].chars().count();
+            self.update_cursor_display_pos();
+        } else if candidates.len() > 1 {
+            let shown: Vec<_> = candidates.iter().take(20).map(|c| c.as_str()).collect();
+            let more = candidates.len().saturating_sub(shown.len());
+            let mut listing = shown.join("  ");
+            if more > 0 {
+                listing.push_str(&format!("  (+{} more)", more));
+            }
+            self.add_output(OutputType::System, listing);
+        }
+    }
+
     fn handle_chat_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Option<AppAction> {
         match code {
             KeyCode::Esc => {
@@ -317,6 +560,17 @@ impl App {
                 None
             }
 
+            KeyCode::Enter if self.terminal_input.is_some() && !self.input.starts_with('/') => {
+                let input = std::mem::take(&mut self.input);
+                self.cursor_position = 0;
+                // The terminal echoes what the tool reads, so it isn't added to the output here.
+                if !self.terminal_input.as_ref().is_some_and(|terminal| terminal.send_line(&input)) {
+                    self.terminal_input = None;
+                    self.add_output(OutputType::Error, "The tool is no longer reading input.".to_string());
+                }
+                None
+            }
+
             KeyCode::Enter => {
                 if !self.input.is_empty() {
                     let input = self.input.clone();
@@ -346,6 +600,11 @@ impl App {
                 None
             }
 
+            KeyCode::Tab => {
+                self.complete_file_ref();
+                None
+            }
+
             KeyCode::Char(c) => {
                 let byte_pos = self.input.char_indices()
                     .nth(self.cursor_position)
@@ -419,6 +678,71 @@ impl App {
                 self.view = View::Tools;
                 AppAction::RequestTools
             }
+            Some("context") => AppAction::ShowContext,
+            Some("map") => AppAction::ShowRepoMap,
+            Some("undo") => AppAction::Undo,
+            Some("cd") => {
+                let path = command_str["cd".len()..].trim();
+                if path.is_empty() {
+                    self.add_output(OutputType::System, format!("Working in {}", self.project_dir.display()));
+                    return AppAction::None;
+                }
+                AppAction::ChangeDirectory(path.to_string())
+            }
+            Some("review") => self.open_review().unwrap_or(AppAction::None),
+            Some("judge") => {
+                match parts.get(1).copied() {
+                    None => {
+                        let status = match &self.multi_model.judge {
+                            Some(judge) => format!("Multi-model answers are judged by {} ({})", judge.tool.display_name(), judge.mode),
+                            None => "No judge; /judge <tool> [rank|merge] sets one".to_string(),
+                        };
+                        self.add_output(OutputType::System, status);
+                    }
+                    Some("off") => {
+                        self.multi_model.judge = None;
+                        self.add_output(OutputType::System, "Multi-model answers will no longer be judged.".to_string());
+                    }
+                    Some(name) => {
+                        let Ok(tool) = name.parse::<Tool>() else {
+                            self.add_output(OutputType::Error, "Usage: /judge <tool> [rank|merge] or /judge off".to_string());
+                            return AppAction::None;
+                        };
+                        let current = self.multi_model.judge.take();
+                        let mode = match parts.get(2).map(|m| m.parse::<JudgeMode>()) {
+                            Some(Ok(mode)) => mode,
+                            Some(Err(e)) => {
+                                self.multi_model.judge = current;
+                                self.add_output(OutputType::Error, e.to_string());
+                                return AppAction::None;
+                            }
+                            None => current.as_ref().map_or(JudgeMode::Rank, |j| j.mode),
+                        };
+                        // A configured model only fits the tool it was configured for.
+                        let model = current.filter(|j| j.tool == tool).and_then(|j| j.model);
+                        self.add_output(OutputType::System,
+                            format!("Multi-model answers will be judged by {} ({})", tool.display_name(), mode));
+                        self.multi_model.judge = Some(Judge { tool, model, mode });
+                    }
+                }
+                AppAction::None
+            }
+            Some("pipe") => {
+                let args = command_str["pipe".len()..].trim();
+                if args.is_empty() {
+                    return AppAction::ListPipelines;
+                }
+                match args.split_once(char::is_whitespace) {
+                    Some((name, prompt)) => {
+                        self.add_output(OutputType::User, input.to_string());
+                        AppAction::RunPipeline(name.to_string(), prompt.trim().to_string())
+                    }
+                    None => {
+                        self.add_output(OutputType::Error, "Usage: /pipe <name> <prompt>".to_string());
+                        AppAction::None
+                    }
+                }
+            }
             Some("switch") => {
                 if let Some(tool_name) = parts.get(1) {
                     if let Ok(tool) = tool_name.parse::<Tool>() {
@@ -428,6 +752,32 @@ impl App {
                 self.add_output(OutputType::Error, "Usage: /switch <claude|gemini|codex|copilot>".to_string());
                 AppAction::None
             }
+            Some("model") | Some("m") => {
+                let Some(tool) = self.current_tool else {
+                    self.add_output(OutputType::Error, "Select a tool before choosing a model.".to_string());
+                    return AppAction::None;
+                };
+                match parts.get(1).copied() {
+                    None => AppAction::ListModels(tool),
+                    Some("default") => {
+                        self.models.remove(&tool);
+                        self.add_output(OutputType::System,
+                            format!("{} will use its configured model", tool.display_name()));
+                        AppAction::None
+                    }
+                    Some(_) if !tool.supports_model_selection() => {
+                        self.add_output(OutputType::Error,
+                            format!("{} does not support choosing a model", tool.display_name()));
+                        AppAction::None
+                    }
+                    Some(model) => {
+                        self.models.insert(tool, model.to_string());
+                        self.add_output(OutputType::System,
+                            format!("{} model set to {}", tool.display_name(), model));
+                        AppAction::None
+                    }
+                }
+            }
             Some("help") => {
                 self.view = View::Help;
                 AppAction::None
@@ -450,6 +800,15 @@ impl App {
             Some("new") | Some("n") => {
                 AppAction::NewChat
             }
+            Some("fork") => match parts.get(1).map(|n| n.parse::<usize>()) {
+                None => AppAction::Fork(None),
+                Some(Ok(n)) => AppAction::Fork(Some(n)),
+                Some(Err(_)) => {
+                    self.add_output(OutputType::Error, "Usage: /fork [message number]".to_string());
+                    AppAction::None
+                }
+            },
+            Some("branches") => AppAction::ShowBranches,
             Some("title") => {
                 if parts.len() > 1 {
                     let title = parts[1..].join(" ");
@@ -481,6 +840,7 @@ impl App {
                     }
                     if tools.len() >= 2 {
                         self.multi_model.enable(tools.clone());
+                        self.multi_model.race = false;
                         let names: Vec<_> = tools.iter().map(|t| t.display_name()).collect();
                         self.add_output(OutputType::System,
                             format!("Multi-model mode enabled with: {}", names.join(", ")));
@@ -493,6 +853,44 @@ impl App {
                 self.view = View::MultiSelect;
                 AppAction::RequestTools
             }
+            Some("race") => {
+                match parts.get(1).copied() {
+                    Some("off") => {
+                        self.multi_model.race = false;
+                        self.add_output(OutputType::System, "Race mode off; every tool answers.".to_string());
+                    }
+                    None if self.multi_model.enabled && self.multi_model.selected_tools.len() >= 2 => {
+                        self.multi_model.race = true;
+                        self.add_output(OutputType::System,
+                            "Race mode on; the first good answer wins and the other tools are stopped.".to_string());
+                    }
+                    None => {
+                        self.add_output(OutputType::Error, "Usage: /race <t1> <t2> ... or /race off".to_string());
+                    }
+                    Some(_) => {
+                        let mut tools = Vec::new();
+                        for name in &parts[1..] {
+                            match name.parse::<Tool>() {
+                                Ok(tool) => tools.push(tool),
+                                Err(_) => {
+                                    self.add_output(OutputType::Error, format!("Unknown tool: {}", name));
+                                    return AppAction::None;
+                                }
+                            }
+                        }
+                        if tools.len() < 2 {
+                            self.add_output(OutputType::Error, "Specify at least 2 tools.".to_string());
+                            return AppAction::None;
+                        }
+                        let names: Vec<_> = tools.iter().map(|t| t.display_name()).collect();
+                        self.add_output(OutputType::System,
+                            format!("Racing {}; the first good answer wins.", names.join(", ")));
+                        self.multi_model.enable(tools);
+                        self.multi_model.race = true;
+                    }
+                }
+                AppAction::None
+            }
             Some("single") => {
                 self.multi_model.disable();
                 self.add_output(OutputType::System, "Multi-model mode disabled. Back to single tool mode.".to_string());
@@ -541,6 +939,7 @@ pub fn draw_ui(f: &mut Frame, app: &App) {
         View::Help => draw_help_view(f, chunks[1], app),
         View::MultiSelect => draw_multi_select_view(f, chunks[1], app),
         View::About => draw_about_view(f, chunks[1], app),
+        View::Review => draw_review_view(f, chunks[1], app),
     }
 
     if app.view == View::Chat || app.view == View::MultiSelect {
@@ -562,9 +961,12 @@ fn draw_header(f: &mut Frame, area: Rect, app: &App) {
         if app.view == View::Help { "[F5 Help]" } else { " F5 Help " },
         if app.view == View::MultiSelect { "[F6 Multi]" } else { " F6 Multi " },
         if app.view == View::About { "[F7 About]" } else { " F7 About " },
+        if app.view == View::Review { "[F8 Review]" } else { " F8 Review " },
     ];
 
-    let multi_indicator = if app.multi_model.enabled {
+    let multi_indicator = if app.multi_model.enabled && app.multi_model.race {
+        format!(" [RACE: {}]", app.multi_model.selected_tools.len())
+    } else if app.multi_model.enabled {
         format!(" [MULTI: {}]", app.multi_model.selected_tools.len())
     } else {
         String::new()
@@ -788,6 +1190,18 @@ fn draw_usage_view(f: &mut Frame, area: Rect, app: &App) {
                 ),
             ]));
 
+            if stat.peak_memory_bytes > 0 || stat.cpu_time_ms > 0 {
+                text.push(Line::from(vec![
+                    Span::raw("  Peak Memory:         "),
+                    Span::styled(format_bytes(stat.peak_memory_bytes), Style::default().fg(Color::Cyan)),
+                ]));
+
+                text.push(Line::from(vec![
+                    Span::raw("  CPU Time:            "),
+                    Span::styled(format!("{:.1}s", stat.cpu_time_ms as f64 / 1000.0), Style::default().fg(Color::Cyan)),
+                ]));
+            }
+
             text.push(Line::from(vec![
                 Span::raw("  Last Used:           "),
                 Span::styled(last_used_str, Style::default().fg(Color::Gray)),
@@ -842,6 +1256,15 @@ fn format_number(n: u64) -> String {
     }
 }
 
+pub fn format_bytes(n: u64) -> String {
+    const MB: f64 = 1024.0 * 1024.0;
+    if n as f64 >= 1024.0 * MB {
+        format!("{:.2} GB", n as f64 / (1024.0 * MB))
+    } else {
+        format!("{:.1} MB", n as f64 / MB)
+    }
+}
+
 fn draw_help_view(f: &mut Frame, area: Rect, app: &App) {
     let help_text = vec![
         Line::from(Span::styled("Commands:", Style::default().add_modifier(Modifier::BOLD))),
@@ -849,10 +1272,22 @@ fn draw_help_view(f: &mut Frame, area: Rect, app: &App) {
         Line::from("  /tools      - Show available tools"),
         Line::from("  /history    - Show chat history"),
         Line::from("  /new        - Start new chat (with context transfer)"),
+        Line::from("  /fork [n]   - Branch the chat after message n (default: the last)"),
+        Line::from("  /branches   - Show the current chat's branches"),
         Line::from("  /switch <t> - Switch tool (claude, gemini, codex, copilot, perplexity, cursor, ollama)"),
+        Line::from("  /model [m]  - Show models for the current tool, or pick one (default resets)"),
+        Line::from("  /context    - Show the conversation context sent with the next prompt"),
+        Line::from("  /map        - Show the repository map and send it with the next prompt"),
+        Line::from("  /cd <dir>   - Run tools in another project directory"),
+        Line::from("  @path       - Inline a file (@file:10-40 for lines, @dir/ for a directory); Tab completes"),
+        Line::from("  /review     - Review the files changed by the last prompt"),
+        Line::from("  /undo       - Restore the files changed by the last prompt"),
+        Line::from("  /pipe <name> <prompt> - Run a pipeline of tools; /pipe alone lists them"),
         Line::from("  /multi      - Open multi-model selection (query multiple AIs at once)"),
         Line::from("  /multi <t1> <t2> ... - Enable multi-model with specific tools"),
+        Line::from("  /race <t1> <t2> ... - Take the first good answer and stop the other tools (off to stop)"),
         Line::from("  /single     - Return to single-tool mode"),
+        Line::from("  /judge <t> [rank|merge] - Have a tool rank or merge multi-model answers (off to stop)"),
         Line::from("  /update     - Check for updates"),
         Line::from("  /clear      - Clear chat output"),
         Line::from("  /about      - About Polyglot-AI"),
@@ -860,7 +1295,8 @@ fn draw_help_view(f: &mut Frame, area: Rect, app: &App) {
         Line::from("  /quit       - Exit"),
         Line::from(""),
         Line::from(Span::styled("Keyboard Shortcuts:", Style::default().add_modifier(Modifier::BOLD))),
-        Line::from("  F1-F7         - Switch views (Chat, Tools, Usage, History, Help, Multi, About)"),
+        Line::from("  F1-F8         - Switch views (Chat, Tools, Usage, History, Help, Multi, About, Review)"),
+        Line::from("  a / r         - Accept or revert the selected file in Review"),
         Line::from("  Ctrl+M        - Toggle multi-model mode"),
         Line::from("  Ctrl+N        - New chat with context transfer"),
         Line::from("  Ctrl+C/Q      - Quit"),
@@ -1013,11 +1449,30 @@ fn draw_history_view(f: &mut Frame, area: Rect, app: &App) {
         return;
     }
 
+    // Forks are listed under their parent; search results are ranked instead.
+    let parents: HashMap<uuid::Uuid, Option<uuid::Uuid>> = app.history.iter()
+        .map(|e| (e.session_id, e.parent_id))
+        .collect();
+    let depth = |entry: &HistoryEntry| {
+        let mut depth = 0;
+        let mut parent = entry.parent_id;
+        while let Some(id) = parent.filter(|id| parents.contains_key(id) && depth < parents.len()) {
+            depth += 1;
+            parent = parents[&id];
+        }
+        depth
+    };
+
     let items: Vec<ListItem> = app.history
         .iter()
         .enumerate()
         .map(|(i, entry)| {
             let is_selected = i == app.history_selected;
+            let branch = match depth(entry) {
+                0 => String::new(),
+                _ if !app.history_search.is_empty() => "⑂ ".to_string(),
+                d => format!("{}└ ", "  ".repeat(d - 1)),
+            };
             let tool_name = entry.tool.map(|t| t.as_str()).unwrap_or("?");
             let project = entry.project_path.as_deref()
                 .map(|p| {
@@ -1037,7 +1492,8 @@ fn draw_history_view(f: &mut Frame, area: Rect, app: &App) {
                 Style::default()
             };
 
-            ListItem::new(Line::from(vec![
+            let mut lines = vec![Line::from(vec![
+                Span::raw(branch),
                 Span::styled(
                     format!("[{}] ", tool_name),
                     Style::default().fg(Color::Cyan)
@@ -1054,7 +1510,15 @@ fn draw_history_view(f: &mut Frame, area: Rect, app: &App) {
                     format!("{} • {} msgs", time_ago, entry.message_count),
                     Style::default().fg(Color::Gray)
                 ),
-            ])).style(style)
+            ])];
+            if !app.history_search.is_empty() {
+                // Search results carry the best matching passage as their preview.
+                lines.push(Line::from(Span::styled(
+                    format!("    {}", polyglot_common::truncate_smart(&entry.preview, 100)),
+                    Style::default().fg(Color::Gray),
+                )));
+            }
+            ListItem::new(lines).style(style)
         })
         .collect();
 
@@ -1065,6 +1529,90 @@ fn draw_history_view(f: &mut Frame, area: Rect, app: &App) {
     f.render_widget(list, area);
 }
 
+fn draw_review_view(f: &mut Frame, area: Rect, app: &App) {
+    let review = &app.review;
+    if review.files.is_empty() {
+        let text = vec![
+            Line::from(""),
+            Line::from(Span::styled("The last prompt didn't change any files.", Style::default().fg(Color::Gray))),
+        ];
+        let paragraph = Paragraph::new(text)
+            .block(Block::default().borders(Borders::ALL).title("Review"));
+        f.render_widget(paragraph, area);
+        return;
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
+        .split(area);
+
+    let several_tools = review.files.iter().any(|f| f.tool != review.files[0].tool);
+    let items: Vec<ListItem> = review.files
+        .iter()
+        .enumerate()
+        .map(|(i, file)| {
+            let kind_color = match file.change.kind {
+                crate::snapshot::ChangeKind::Added => Color::Green,
+                crate::snapshot::ChangeKind::Modified => Color::Yellow,
+                crate::snapshot::ChangeKind::Deleted => Color::Red,
+            };
+            let status = match file.status {
+                ReviewStatus::Pending => "",
+                ReviewStatus::Accepted => " [accepted]",
+                ReviewStatus::Reverted => " [reverted]",
+            };
+            let mut spans = vec![
+                Span::styled(format!("{} ", file.change.kind.marker()), Style::default().fg(kind_color)),
+                Span::raw(file.change.path.display().to_string()),
+                Span::styled(status, Style::default().fg(Color::Gray)),
+            ];
+            if several_tools {
+                spans.push(Span::styled(format!(" ({})", file.tool.as_str()), Style::default().fg(Color::Cyan)));
+            }
+
+            let style = if i == review.selected {
+                Style::default().bg(Color::DarkGray).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            ListItem::new(Line::from(spans)).style(style)
+        })
+        .collect();
+
+    let title = if review.notice.is_empty() {
+        "Changed files - a=accept r=revert Esc=back".to_string()
+    } else {
+        review.notice.clone()
+    };
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(list, chunks[0]);
+
+    let lines: Vec<Line> = review.diff
+        .iter()
+        .skip(review.diff_scroll)
+        .map(|line| {
+            let style = if line.starts_with("+++") || line.starts_with("---") {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else if line.starts_with('+') {
+                Style::default().fg(Color::Green)
+            } else if line.starts_with('-') {
+                Style::default().fg(Color::Red)
+            } else if line.starts_with("@@") {
+                Style::default().fg(Color::Cyan)
+            } else {
+                Style::default()
+            };
+            Line::from(Span::styled(line.as_str(), style))
+        })
+        .collect();
+
+    let diff = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title("Diff - PgUp/PgDn to scroll"));
+    f.render_widget(diff, chunks[1]);
+}
+
 fn draw_multi_select_view(f: &mut Frame, area: Rect, app: &App) {
     let mut items = vec![
         ListItem::new(Line::from(vec![
@@ -1133,8 +1681,9 @@ fn draw_multi_model_view(f: &mut Frame, area: Rect, app: &App) {
         return;
     }
 
-    let constraints: Vec<Constraint> = (0..num_tools)
-        .map(|_| Constraint::Ratio(1, num_tools as u32))
+    let panes = num_tools + usize::from(app.multi_model.verdict.is_some());
+    let constraints: Vec<Constraint> = (0..panes)
+        .map(|_| Constraint::Ratio(1, panes as u32))
         .collect();
 
     let chunks = Layout::default()
@@ -1149,14 +1698,21 @@ fn draw_multi_model_view(f: &mut Frame, area: Rect, app: &App) {
 
         let is_done = app.multi_model.completed.get(tool).copied().unwrap_or(false);
 
-        let status = if is_done { " ✓" } else { " ..." };
+        let status = match app.multi_model.winner {
+            Some((winner, elapsed)) if winner == *tool => format!(" ✓ won in {:.1}s", elapsed.as_secs_f64()),
+            _ if is_done => " ✓".to_string(),
+            _ => " ...".to_string(),
+        };
         let title_style = if is_done {
             Style::default().fg(Color::Green)
         } else {
             Style::default().fg(Color::Yellow)
         };
 
-        let title = format!("{}{}", tool.display_name(), status);
+        let title = match app.multi_model.models.get(tool) {
+            Some(model) => format!("{} ({}){}", tool.display_name(), model, status),
+            None => format!("{}{}", tool.display_name(), status),
+        };
 
         let content: Vec<Line> = responses.iter()
             .flat_map(|line| {
@@ -1187,6 +1743,29 @@ fn draw_multi_model_view(f: &mut Frame, area: Rect, app: &App) {
 
         f.render_widget(paragraph, chunks[i]);
     }
+
+    if let Some(verdict) = &app.multi_model.verdict {
+        let heading = match verdict.judge.mode {
+            JudgeMode::Rank => "Ranking",
+            JudgeMode::Merge => "Merged",
+        };
+        let title = match &verdict.model {
+            Some(model) => format!("{} by {} ({})", heading, verdict.judge.tool.display_name(), model),
+            None => format!("{} by {}", heading, verdict.judge.tool.display_name()),
+        };
+        let title = format!("{}{}", title, if verdict.done { " ✓" } else { " ..." });
+        let style = if verdict.done { Style::default().fg(Color::Magenta) } else { Style::default().fg(Color::Yellow) };
+
+        let content: Vec<Line> = verdict.lines.iter().map(|line| Line::from(line.clone())).collect();
+        let paragraph = Paragraph::new(content)
+            .block(Block::default()
+                .borders(Borders::ALL)
+                .title(Span::styled(title, style))
+                .border_style(style))
+            .wrap(Wrap { trim: false });
+
+        f.render_widget(paragraph, chunks[num_tools]);
+    }
 }
 
 fn format_time_ago(time: chrono::DateTime<chrono::Utc>) -> String {
@@ -1228,6 +1807,15 @@ fn draw_input(f: &mut Frame, area: Rect, app: &App) {
     ));
 }
 
+/// A directory with the home directory shortened to `~`.
+fn display_dir(dir: &std::path::Path) -> String {
+    match dirs::home_dir().and_then(|home| dir.strip_prefix(home).ok().map(|rest| rest.to_path_buf())) {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => dir.display().to_string(),
+    }
+}
+
 fn draw_status_bar(f: &mut Frame, area: Rect, app: &App) {
     let mut spans = Vec::new();
 
@@ -1235,7 +1823,8 @@ fn draw_status_bar(f: &mut Frame, area: Rect, app: &App) {
         let tool_names: Vec<_> = app.multi_model.selected_tools.iter()
             .map(|t| t.as_str())
             .collect();
-        spans.push(Span::styled("Multi-Model: ", Style::default().fg(Color::Yellow)));
+        let label = if app.multi_model.race { "Race: " } else { "Multi-Model: " };
+        spans.push(Span::styled(label, Style::default().fg(Color::Yellow)));
         spans.push(Span::styled(tool_names.join(", "), Style::default().fg(Color::Green)));
     } else {
         let tool_name = app.current_tool
@@ -1243,8 +1832,15 @@ fn draw_status_bar(f: &mut Frame, area: Rect, app: &App) {
             .unwrap_or("None");
         spans.push(Span::styled("Tool: ", Style::default().fg(Color::Gray)));
         spans.push(Span::styled(tool_name, Style::default().fg(Color::Cyan)));
+        if let Some(model) = app.current_tool.and_then(|t| app.models.get(&t)) {
+            spans.push(Span::styled(format!(" ({})", model), Style::default().fg(Color::Cyan)));
+        }
     }
 
+    spans.push(Span::raw(" | "));
+    spans.push(Span::styled("Dir: ", Style::default().fg(Color::Gray)));
+    spans.push(Span::styled(display_dir(&app.project_dir), Style::default().fg(Color::Cyan)));
+
     spans.push(Span::raw(" | "));
     spans.push(Span::raw("Ctrl+M multi-mode | Ctrl+Q quit"));
 
@@ -135,6 +135,12 @@ pub struct ToolInstanceConfig {
     pub args: Vec<String>,
     #[serde(default)]
     pub env: Vec<(String, String)>,
+    /// Model to request when the tool is backed by a model server (Ollama)
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Base URLs of the model servers, tried in order
+    #[serde(default)]
+    pub hosts: Vec<String>,
 }
 
 impl ToolInstanceConfig {
@@ -145,6 +151,8 @@ impl ToolInstanceConfig {
             priority: 1,
             args: vec![],
             env: vec![],
+            model: None,
+            hosts: vec![],
         }
     }
 
@@ -155,6 +163,8 @@ impl ToolInstanceConfig {
             priority: 2,
             args: vec![],
             env: vec![],
+            model: None,
+            hosts: vec![],
         }
     }
 
@@ -165,6 +175,8 @@ impl ToolInstanceConfig {
             priority: 3,
             args: vec![],
             env: vec![],
+            model: None,
+            hosts: vec![],
         }
     }
 
@@ -175,6 +187,8 @@ impl ToolInstanceConfig {
             priority: 4,
             args: vec!["copilot".to_string()],
             env: vec![],
+            model: None,
+            hosts: vec![],
         }
     }
 
@@ -187,6 +201,8 @@ impl ToolInstanceConfig {
                 priority: 5,
                 args: vec!["cursor-agent".to_string()],
                 env: vec![],
+                model: None,
+                hosts: vec![],
             }
         }
         #[cfg(not(windows))]
@@ -197,6 +213,8 @@ impl ToolInstanceConfig {
                 priority: 5,
                 args: vec![],
                 env: vec![],
+                model: None,
+                hosts: vec![],
             }
         }
     }
@@ -206,8 +224,10 @@ impl ToolInstanceConfig {
             enabled: true,
             path: "ollama".to_string(),
             priority: 7,
-            args: vec!["run".to_string(), "codellama".to_string()],
+            args: vec![],
             env: vec![],
+            model: Some("codellama".to_string()),
+            hosts: vec![polyglot_common::DEFAULT_OLLAMA_HOST.to_string()],
         }
     }
 }
@@ -13,7 +13,7 @@ mod protocol;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::sync::atomic::{AtomicBool, Ordering};
 
 use anyhow::{Context, Result};
@@ -30,8 +30,9 @@ use std::collections::HashMap;
 
 use polyglot_common::{
     ClientMessage, ServerMessage, Tool,
-    ErrorCode, ToolInfo, SwitchReason,
-    PROTOCOL_VERSION,
+    ErrorCode, ToolInfo, SwitchReason, Message,
+    QuotaTracker, QuotaConfig, FileRefLimits,
+    PROTOCOL_VERSION, expand_file_refs,
 };
 
 use config::ServerConfig;
@@ -124,6 +125,9 @@ enum Commands {
     },
 }
 
+/// Conversation turns kept per session for tools that accept chat history
+const MAX_SESSION_HISTORY: usize = 40;
+
 struct ServerState {
     config: ServerConfig,
     session_manager: SessionManager,
@@ -132,12 +136,25 @@ struct ServerState {
     invite_manager: auth::InviteManager,
     tool_manager: ToolManager,
     sync_manager: SyncManager,
-    #[allow(dead_code)]
     usage_tracker: UsageTracker,
+    quota_tracker: QuotaTracker,
     session_env: RwLock<HashMap<Uuid, Vec<(String, String)>>>,
+    session_history: RwLock<HashMap<Uuid, Vec<Message>>>,
+    /// Each tool's own session ID per client session, for `--resume`
+    native_sessions: RwLock<HashMap<Uuid, HashMap<Tool, String>>>,
     shutdown: AtomicBool,
 }
 
+impl ServerState {
+    /// Forgets everything kept for a session once its client is gone.
+    fn cleanup_session(&self, sid: Uuid) {
+        self.session_manager.remove_session(sid);
+        self.session_env.write().remove(&sid);
+        self.session_history.write().remove(&sid);
+        self.native_sessions.write().remove(&sid);
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
@@ -233,7 +250,10 @@ async fn start_server(config: ServerConfig) -> Result<()> {
         tool_manager,
         sync_manager,
         usage_tracker,
+        quota_tracker: QuotaTracker::new(QuotaConfig::default()),
         session_env: RwLock::new(HashMap::new()),
+        session_history: RwLock::new(HashMap::new()),
+        native_sessions: RwLock::new(HashMap::new()),
         shutdown: AtomicBool::new(false),
     });
 
@@ -397,8 +417,7 @@ async fn handle_connection(
     }
 
     if let Some(sid) = session_id {
-        state.session_manager.remove_session(sid);
-        state.session_env.write().remove(&sid);
+        state.cleanup_session(sid);
     }
 
     info!("Connection closed: {}", remote_addr);
@@ -494,18 +513,48 @@ async fn handle_message(
             }).await.ok();
         }
 
-        ClientMessage::Prompt { tool, message, working_dir } => {
+        ClientMessage::Prompt { tool, message, working_dir, model } => {
             let tool = tool.or(*current_tool).unwrap_or(state.config.tools.default_tool);
 
             let session_env = session_id
                 .and_then(|sid| state.session_env.read().get(&sid).cloned())
                 .unwrap_or_default();
 
+            let history = session_id
+                .and_then(|sid| state.session_history.read().get(&sid).cloned())
+                .unwrap_or_default();
+            let model = model.or_else(|| state.tool_manager.default_model(tool));
+            let resume_session = session_id
+                .and_then(|sid| state.native_sessions.read().get(&sid)?.get(&tool).cloned());
+
+            // `@file` references are read from the client's synced workspace.
+            let sync_dir = state.sync_manager.user_sync_dir(
+                &session_id.map(|s| s.to_string()).unwrap_or_default()
+            );
+            let workspace = sync_dir.canonicalize().unwrap_or(sync_dir);
+            let expanded = expand_file_refs(&message, &workspace, &FileRefLimits::default(), |path| {
+                if path.starts_with(&workspace) {
+                    Ok(())
+                } else {
+                    Err("outside the synced workspace".to_string())
+                }
+            });
+            for problem in &expanded.problems {
+                response_tx.send(ServerMessage::ToolOutput {
+                    tool,
+                    output_type: polyglot_common::OutputType::Stderr,
+                    content: problem.to_string(),
+                }).await.ok();
+            }
+
             let request = ToolRequest {
-                message,
+                message: expanded.prompt,
                 working_dir,
-                context_files: Vec::new(),
+                context_files: expanded.files.into_iter().map(|f| f.path).collect(),
                 env: session_env,
+                history,
+                model: model.clone(),
+                resume_session,
             };
 
             let (tool_tx, mut tool_rx) = mpsc::channel::<ToolOutput>(100);
@@ -513,6 +562,9 @@ async fn handle_message(
             let tool_manager = state.tool_manager.clone();
             let response_tx_clone = response_tx.clone();
             let switch_delay = state.config.tools.switch_delay;
+            let task_state = state.clone();
+            let task_sid = *session_id;
+            let started = Instant::now();
 
             tokio::spawn(async move {
                 let execute_handle = tokio::spawn({
@@ -522,9 +574,13 @@ async fn handle_message(
                     }
                 });
 
+                let mut reply = String::new();
+
                 while let Some(output) = tool_rx.recv().await {
                     match output {
                         ToolOutput::Stdout(line) => {
+                            reply.push_str(&line);
+                            reply.push('\n');
                             response_tx_clone.send(ServerMessage::ToolResponse {
                                 tool,
                                 content: line,
@@ -539,7 +595,34 @@ async fn handle_message(
                                 content: line,
                             }).await.ok();
                         }
+                        ToolOutput::TextDelta(content) => {
+                            reply.push_str(&content);
+                            response_tx_clone.send(ServerMessage::TextDelta { tool, content }).await.ok();
+                        }
+                        ToolOutput::Action(action) => {
+                            response_tx_clone.send(ServerMessage::ToolAction { tool, action }).await.ok();
+                        }
+                        ToolOutput::Usage(_) => {}
+                        ToolOutput::Session(native_id) => {
+                            if let Some(sid) = task_sid {
+                                task_state.native_sessions.write()
+                                    .entry(sid)
+                                    .or_default()
+                                    .insert(tool, native_id);
+                            }
+                        }
                         ToolOutput::Done { tokens } => {
+                            record_prompt_usage(&task_state, task_sid, tool, tokens.unwrap_or(0), None, started);
+                            if let Some(sid) = task_sid {
+                                let mut histories = task_state.session_history.write();
+                                let turns = histories.entry(sid).or_default();
+                                turns.push(Message::user(message.clone()));
+                                turns.push(Message::assistant(reply.trim_end().to_string()).with_model(model.clone()));
+                                if turns.len() > MAX_SESSION_HISTORY {
+                                    let excess = turns.len() - MAX_SESSION_HISTORY;
+                                    turns.drain(..excess);
+                                }
+                            }
                             response_tx_clone.send(ServerMessage::ToolResponse {
                                 tool,
                                 content: String::new(),
@@ -548,6 +631,7 @@ async fn handle_message(
                             }).await.ok();
                         }
                         ToolOutput::Error(e) => {
+                            record_prompt_usage(&task_state, task_sid, tool, 0, Some(&e), started);
                             response_tx_clone.send(ServerMessage::Error {
                                 code: ErrorCode::ToolError,
                                 message: e,
@@ -613,9 +697,14 @@ async fn handle_message(
 
         ClientMessage::ListTools => {
             let available = state.tool_manager.available_tools().await;
-            let tools: Vec<ToolInfo> = Tool::all()
-                .iter()
-                .map(|t| ToolInfo {
+            let mut tools: Vec<ToolInfo> = Vec::with_capacity(Tool::all().len());
+            for t in Tool::all() {
+                let models = if available.contains(t) {
+                    state.tool_manager.list_models(*t).await
+                } else {
+                    Vec::new()
+                };
+                tools.push(ToolInfo {
                     tool: *t,
                     enabled: state.tool_manager.get_usage()
                         .iter()
@@ -632,8 +721,10 @@ async fn handle_message(
                         Tool::Cursor => 6,
                         Tool::Ollama => 7,
                     },
-                })
-                .collect();
+                    models,
+                    default_model: state.tool_manager.default_model(*t),
+                });
+            }
 
             response_tx.send(ServerMessage::ToolList {
                 tools,
@@ -717,10 +808,34 @@ async fn handle_message(
             }).await.ok();
         }
 
+        ClientMessage::QuotaCheck => {
+            let user_id = session_id
+                .and_then(|sid| state.session_manager.get_session(sid).ok())
+                .map(|s| s.user_id);
+
+            let Some(user_id) = user_id else {
+                response_tx.send(ServerMessage::Error {
+                    code: ErrorCode::AuthFailed,
+                    message: "Authenticate before checking quotas.".to_string(),
+                }).await.ok();
+                return Ok(());
+            };
+
+            let status = state.quota_tracker.get_status(&user_id.to_string());
+            response_tx.send(ServerMessage::QuotaInfo {
+                daily_limit: status.daily_limit,
+                daily_used: status.daily_used,
+                monthly_limit: status.monthly_limit,
+                monthly_used: status.monthly_used,
+                reset_at: Some(status.daily_reset),
+                daily_tokens_used: status.daily_tokens_used,
+                monthly_tokens_used: status.monthly_tokens_used,
+            }).await.ok();
+        }
+
         ClientMessage::Disconnect => {
             if let Some(sid) = session_id.take() {
-                state.session_manager.remove_session(sid);
-                state.session_env.write().remove(&sid);
+                state.cleanup_session(sid);
             }
         }
 
@@ -735,6 +850,36 @@ async fn handle_message(
     Ok(())
 }
 
+/// Persists one finished prompt to the usage database and the user's quota.
+fn record_prompt_usage(
+    state: &ServerState,
+    session_id: Option<Uuid>,
+    tool: Tool,
+    tokens: u64,
+    error: Option<&str>,
+    started: Instant,
+) {
+    let user_id = session_id
+        .and_then(|sid| state.session_manager.get_session(sid).ok())
+        .map(|s| s.user_id);
+
+    if let Err(e) = state.usage_tracker.record_usage(
+        tool,
+        user_id,
+        session_id,
+        tokens,
+        error.is_none(),
+        error,
+        started.elapsed().as_millis() as u64,
+    ) {
+        error!("Failed to record usage: {}", e);
+    }
+
+    if let Some(user_id) = user_id {
+        state.quota_tracker.record_usage(&user_id.to_string(), tokens);
+    }
+}
+
 fn sanitize_env_entries(entries: Vec<(String, String)>) -> Vec<(String, String)> {
     let mut seen = HashMap::new();
     for (key, value) in entries {
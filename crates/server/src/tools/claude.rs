@@ -1,4 +1,7 @@
 //! Claude Code CLI adapter
+//!
+//! Runs `claude --print` in stream-json mode so text, tool actions and
+//! token usage arrive as structured events.
 
 use async_trait::async_trait;
 use tokio::process::Command;
@@ -7,8 +10,8 @@ use tokio::sync::mpsc;
 use std::process::Stdio;
 use std::sync::Arc;
 use parking_lot::Mutex;
-use polyglot_common::Tool;
-use super::{ToolAdapter, ToolError, ToolOutput, ToolRequest, is_rate_limit_message};
+use polyglot_common::{Tool, ClaudeStreamParser, ClaudeEvent, CLAUDE_STREAM_ARGS};
+use super::{ToolAdapter, ToolError, ToolOutput, ToolRequest, is_rate_limit_message, model_args};
 
 pub struct ClaudeAdapter {
     path: String,
@@ -55,8 +58,11 @@ impl ToolAdapter for ClaudeAdapter {
         for arg in &self.args {
             cmd.arg(arg);
         }
+        cmd.args(model_args(Tool::Claude, request.model.as_deref()));
+        cmd.args(Tool::Claude.resume_args(request.resume_session.as_deref()));
 
         cmd.arg("--print");
+        cmd.args(CLAUDE_STREAM_ARGS);
         cmd.arg(&request.message);
 
         if let Some(ref dir) = request.working_dir {
@@ -91,12 +97,32 @@ impl ToolAdapter for ClaudeAdapter {
         let stdout_handle = tokio::spawn(async move {
             let reader = BufReader::new(stdout);
             let mut lines = reader.lines();
-
-            while let Ok(Some(line)) = lines.next_line().await {
-                if output_tx_stdout.send(ToolOutput::Stdout(line)).await.is_err() {
-                    break;
+            let mut parser = ClaudeStreamParser::new();
+            let mut tokens = None;
+            let mut error = None;
+
+            'read: while let Ok(Some(line)) = lines.next_line().await {
+                for event in parser.parse_line(&line) {
+                    let output = match event {
+                        ClaudeEvent::Text(text) => ToolOutput::TextDelta(text),
+                        ClaudeEvent::Action(action) => ToolOutput::Action(action),
+                        ClaudeEvent::Usage(usage) => {
+                            tokens = Some(usage.total());
+                            ToolOutput::Usage(usage)
+                        }
+                        ClaudeEvent::Session(id) => ToolOutput::Session(id),
+                        ClaudeEvent::Error(message) => {
+                            error = Some(message);
+                            continue;
+                        }
+                    };
+                    if output_tx_stdout.send(output).await.is_err() {
+                        break 'read;
+                    }
                 }
             }
+
+            (tokens, error)
         });
 
         let stderr_handle = tokio::spawn(async move {
@@ -118,7 +144,7 @@ impl ToolAdapter for ClaudeAdapter {
 
         let status = child.wait().await?;
 
-        let _ = stdout_handle.await;
+        let (tokens, reported_error) = stdout_handle.await.unwrap_or((None, None));
         let rate_limited = stderr_handle.await.unwrap_or(false);
 
         *self.current_process.lock() = None;
@@ -128,8 +154,13 @@ impl ToolAdapter for ClaudeAdapter {
             return Err(ToolError::RateLimited);
         }
 
+        if let Some(error_msg) = reported_error {
+            output_tx.send(ToolOutput::Error(error_msg.clone())).await.ok();
+            return Err(ToolError::ExecutionFailed(error_msg));
+        }
+
         if status.success() {
-            output_tx.send(ToolOutput::Done { tokens: None }).await.ok();
+            output_tx.send(ToolOutput::Done { tokens }).await.ok();
             Ok(())
         } else {
             let error_msg = format!("Claude exited with code: {:?}", status.code());
@@ -162,7 +193,10 @@ impl ToolAdapter for ClaudeAdapter {
     fn get_command(&self, request: &ToolRequest) -> String {
         let mut parts = vec![self.path.clone()];
         parts.extend(self.args.clone());
+        parts.extend(model_args(Tool::Claude, request.model.as_deref()));
+        parts.extend(Tool::Claude.resume_args(request.resume_session.as_deref()));
         parts.push("--print".to_string());
+        parts.extend(CLAUDE_STREAM_ARGS.iter().map(|a| a.to_string()));
         parts.push(format!("\"{}\"", request.message));
         parts.join(" ")
     }
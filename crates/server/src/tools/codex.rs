@@ -8,7 +8,7 @@ use std::process::Stdio;
 use std::sync::Arc;
 use parking_lot::Mutex;
 use polyglot_common::Tool;
-use super::{ToolAdapter, ToolError, ToolOutput, ToolRequest, is_rate_limit_message};
+use super::{ToolAdapter, ToolError, ToolOutput, ToolRequest, is_rate_limit_message, model_args};
 
 pub struct CodexAdapter {
     path: String,
@@ -55,6 +55,7 @@ impl ToolAdapter for CodexAdapter {
         for arg in &self.args {
             cmd.arg(arg);
         }
+        cmd.args(model_args(Tool::Codex, request.model.as_deref()));
 
         cmd.arg(&request.message);
 
@@ -161,6 +162,7 @@ impl ToolAdapter for CodexAdapter {
     fn get_command(&self, request: &ToolRequest) -> String {
         let mut parts = vec![self.path.clone()];
         parts.extend(self.args.clone());
+        parts.extend(model_args(Tool::Codex, request.model.as_deref()));
         parts.push(format!("\"{}\"", request.message));
         parts.join(" ")
     }
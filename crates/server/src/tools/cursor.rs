@@ -10,7 +10,7 @@ use std::process::Stdio;
 use std::sync::Arc;
 use parking_lot::Mutex;
 use polyglot_common::Tool;
-use super::{ToolAdapter, ToolError, ToolOutput, ToolRequest, is_rate_limit_message};
+use super::{ToolAdapter, ToolError, ToolOutput, ToolRequest, is_rate_limit_message, model_args};
 
 pub struct CursorAdapter {
     path: String,
@@ -80,6 +80,7 @@ impl ToolAdapter for CursorAdapter {
         for arg in &self.args {
             cmd.arg(arg);
         }
+        cmd.args(model_args(Tool::Cursor, request.model.as_deref()));
 
         cmd.arg("-p");
         cmd.arg(&request.message);
@@ -186,6 +187,7 @@ impl ToolAdapter for CursorAdapter {
     fn get_command(&self, request: &ToolRequest) -> String {
         let mut parts = vec![self.path.clone()];
         parts.extend(self.args.clone());
+        parts.extend(model_args(Tool::Cursor, request.model.as_deref()));
         parts.push("--prompt".to_string());
         parts.push(format!("\"{}\"", request.message.chars().take(50).collect::<String>()));
         parts.join(" ")
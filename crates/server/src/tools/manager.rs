@@ -17,6 +17,7 @@ struct ToolManagerInner {
     switch_delay: u8,
     default_tool: Tool,
     current_tool: RwLock<Tool>,
+    default_models: HashMap<Tool, String>,
 }
 
 #[derive(Clone)]
@@ -101,22 +102,29 @@ impl ToolManager {
 
         if let Some(ref ollama_config) = config.ollama {
             if ollama_config.enabled {
-                let model = ollama_config.args.get(1)
-                    .cloned()
-                    .unwrap_or_else(|| "codellama".to_string());
+                // Older configs carried the model as `args = ["run", "<model>"]`.
+                let model = ollama_config.model.clone()
+                    .or_else(|| ollama_config.args.get(1).cloned());
                 adapters.insert(
                     Tool::Ollama,
-                    Arc::new(OllamaAdapter::new(
-                        ollama_config.path.clone(),
-                        model,
-                        ollama_config.args.clone(),
-                        ollama_config.env.clone(),
-                    )),
+                    Arc::new(OllamaAdapter::new(ollama_config.hosts.clone(), model)),
                 );
                 usage.insert(Tool::Ollama, ToolUsage::new(Tool::Ollama));
             }
         }
 
+        let default_models = [
+            (Tool::Claude, &config.claude),
+            (Tool::Gemini, &config.gemini),
+            (Tool::Codex, &config.codex),
+            (Tool::Copilot, &config.copilot),
+            (Tool::Cursor, &config.cursor),
+            (Tool::Ollama, &config.ollama),
+        ]
+        .into_iter()
+        .filter_map(|(tool, c)| c.as_ref().and_then(|c| c.model.clone()).map(|m| (tool, m)))
+        .collect();
+
         Self {
             inner: Arc::new(ToolManagerInner {
                 adapters,
@@ -125,6 +133,7 @@ impl ToolManager {
                 switch_delay: config.switch_delay,
                 default_tool: config.default_tool,
                 current_tool: RwLock::new(config.default_tool),
+                default_models,
             }),
         }
     }
@@ -155,6 +164,17 @@ impl ToolManager {
         self.inner.usage.read().values().cloned().collect()
     }
 
+    pub fn default_model(&self, tool: Tool) -> Option<String> {
+        self.inner.default_models.get(&tool).cloned()
+    }
+
+    pub async fn list_models(&self, tool: Tool) -> Vec<String> {
+        match self.inner.adapters.get(&tool) {
+            Some(adapter) => adapter.list_models().await,
+            None => Vec::new(),
+        }
+    }
+
     pub fn switch_delay(&self) -> u8 {
         self.inner.switch_delay
     }
@@ -162,11 +182,15 @@ impl ToolManager {
     pub async fn execute(
         &self,
         tool: Option<Tool>,
-        request: ToolRequest,
+        mut request: ToolRequest,
         output_tx: mpsc::Sender<ToolOutput>,
     ) -> Result<Tool, ToolError> {
         let tool = tool.unwrap_or_else(|| self.current_tool());
 
+        if request.model.is_none() {
+            request.model = self.default_model(tool);
+        }
+
         let adapter = self.inner.adapters.get(&tool)
             .ok_or(ToolError::NotAvailable(tool))?;
 
@@ -186,6 +210,7 @@ impl ToolManager {
         let monitor_handle = tokio::spawn(async move {
             let mut rate_limited = false;
             let mut tokens = None;
+            let mut exact_usage = false;
 
             while let Some(output) = internal_rx.recv().await {
                 match &output {
@@ -197,9 +222,17 @@ impl ToolManager {
                             stats.is_available = false;
                         }
                     }
+                    ToolOutput::Usage(reported) => {
+                        exact_usage = true;
+                        let mut usage = inner_clone.usage.write();
+                        if let Some(stats) = usage.get_mut(&tool_clone) {
+                            stats.record_tokens(reported);
+                        }
+                    }
                     ToolOutput::Done { tokens: t } => {
                         tokens = *t;
-                        if let Some(count) = t {
+                        // Tools that sent `Usage` have already been counted.
+                        if let (Some(count), false) = (t, exact_usage) {
                             let mut usage = inner_clone.usage.write();
                             if let Some(stats) = usage.get_mut(&tool_clone) {
                                 stats.tokens_used += count;
@@ -21,7 +21,7 @@ pub use ollama::OllamaAdapter;
 use async_trait::async_trait;
 use thiserror::Error;
 use tokio::sync::mpsc;
-use polyglot_common::Tool;
+use polyglot_common::{Tool, Message, ToolAction, TokenUsage};
 
 #[derive(Debug, Error)]
 pub enum ToolError {
@@ -43,6 +43,13 @@ pub enum ToolError {
 pub enum ToolOutput {
     Stdout(String),
     Stderr(String),
+    /// Partial text from tools that stream below line granularity
+    TextDelta(String),
+    Action(ToolAction),
+    /// Exact token counts; sent before `Done` by tools that report them
+    Usage(TokenUsage),
+    /// The tool's own session ID, for resuming it on the next prompt
+    Session(String),
     Done { tokens: Option<u64> },
     Error(String),
     RateLimited,
@@ -54,6 +61,12 @@ pub struct ToolRequest {
     pub working_dir: Option<String>,
     pub context_files: Vec<String>,
     pub env: Vec<(String, String)>,
+    /// Earlier turns of the conversation, oldest first
+    pub history: Vec<Message>,
+    /// Model to run; `None` leaves the choice to the tool
+    pub model: Option<String>,
+    /// Native session to continue, for tools with a resume flag
+    pub resume_session: Option<String>,
 }
 
 #[async_trait]
@@ -71,6 +84,19 @@ pub trait ToolAdapter: Send + Sync {
     async fn cancel(&self) -> Result<(), ToolError>;
 
     fn get_command(&self, request: &ToolRequest) -> String;
+
+    /// Models that can be requested through `ToolRequest::model`.
+    async fn list_models(&self) -> Vec<String> {
+        self.tool().known_models().iter().map(|m| m.to_string()).collect()
+    }
+}
+
+/// Translates a model choice into the tool's CLI flag, e.g. `--model opus`.
+pub fn model_args(tool: Tool, model: Option<&str>) -> Vec<String> {
+    match (tool.model_flag(), model) {
+        (Some(flag), Some(model)) => vec![flag.to_string(), model.to_string()],
+        _ => Vec::new(),
+    }
 }
 
 pub fn is_rate_limit_message(output: &str) -> bool {
@@ -107,3 +133,15 @@ pub fn parse_token_count(output: &str) -> Option<u64> {
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_args() {
+        assert_eq!(model_args(Tool::Claude, Some("opus")), vec!["--model", "opus"]);
+        assert!(model_args(Tool::Claude, None).is_empty());
+        assert!(model_args(Tool::Copilot, Some("gpt-5")).is_empty());
+    }
+}
@@ -1,204 +1,366 @@
 //! Ollama adapter for local LLM support
 //! Ollama runs LLMs locally: https://ollama.ai
+//!
+//! Talks to the Ollama REST API rather than the `ollama` CLI so that
+//! conversation history and exact token counts are available.
 
-use async_trait::async_trait;
-use tokio::process::Command;
-use tokio::io::{BufReader, AsyncBufReadExt};
-use tokio::sync::mpsc;
-use std::process::Stdio;
 use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
 use parking_lot::Mutex;
-use polyglot_common::Tool;
+use tokio::sync::{mpsc, watch};
+use polyglot_common::{
+    Tool, NdjsonBuffer, OllamaChatChunk, OllamaChatRequest, OllamaPullProgress,
+    OllamaPullRequest, OllamaTags, ollama_url, DEFAULT_OLLAMA_HOST,
+};
 use super::{ToolAdapter, ToolError, ToolOutput, ToolRequest};
 
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
 pub struct OllamaAdapter {
-    path: String,
-    model: String,
-    args: Vec<String>,
-    env: Vec<(String, String)>,
-    current_process: Arc<Mutex<Option<u32>>>,
+    hosts: Vec<String>,
+    model: Option<String>,
+    client: reqwest::Client,
+    /// One cancel signal per request in flight
+    runs: Arc<Mutex<Vec<watch::Sender<bool>>>>,
+}
+
+/// A request's cancel signal, registered with the adapter until the request
+/// ends.
+struct Run {
+    runs: Arc<Mutex<Vec<watch::Sender<bool>>>>,
+    cancel: watch::Sender<bool>,
+}
+
+impl Run {
+    fn start(runs: &Arc<Mutex<Vec<watch::Sender<bool>>>>) -> (Self, watch::Receiver<bool>) {
+        let (cancel, cancelled) = watch::channel(false);
+        runs.lock().push(cancel.clone());
+        (Self { runs: runs.clone(), cancel }, cancelled)
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        self.runs.lock().retain(|run| !run.same_channel(&self.cancel));
+    }
+}
+
+/// Resolves once the request is cancelled.
+async fn cancelled(cancel: &mut watch::Receiver<bool>) {
+    if cancel.wait_for(|&cancelled| cancelled).await.is_err() {
+        std::future::pending::<()>().await;
+    }
 }
 
 impl OllamaAdapter {
-    pub fn new(path: String, model: String, args: Vec<String>, env: Vec<(String, String)>) -> Self {
+    /// `hosts` are tried in order; an empty list means the default local daemon.
+    /// Without a configured `model` the first model installed on the host is used.
+    pub fn new(hosts: Vec<String>, model: Option<String>) -> Self {
+        let hosts = if hosts.is_empty() {
+            vec![DEFAULT_OLLAMA_HOST.to_string()]
+        } else {
+            hosts
+        };
+
         Self {
-            path,
+            hosts,
             model,
-            args,
-            env,
-            current_process: Arc::new(Mutex::new(None)),
+            client: reqwest::Client::new(),
+            runs: Arc::default(),
         }
     }
 
-    pub fn with_default_model(env: Vec<(String, String)>) -> Self {
-        Self::new(
-            "ollama".to_string(),
-            "codellama".to_string(),
-            vec![],
-            env,
-        )
+    pub fn with_default_model() -> Self {
+        Self::new(vec![], Some("codellama".to_string()))
     }
 
-    async fn is_model_downloaded(&self) -> Result<bool, ToolError> {
-        let output = Command::new(&self.path)
-            .arg("list")
-            .output()
-            .await?;
+    pub fn hosts(&self) -> &[String] {
+        &self.hosts
+    }
+
+    async fn fetch_tags(&self, host: &str) -> Result<OllamaTags, ToolError> {
+        let response = self.client
+            .get(ollama_url(host, "/api/tags"))
+            .timeout(DISCOVERY_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| ToolError::ExecutionFailed(format!("Ollama at {} unreachable: {}", host, e)))?;
 
-        if !output.status.success() {
-            return Ok(false);
+        if !response.status().is_success() {
+            return Err(ToolError::ExecutionFailed(format!(
+                "Ollama at {} returned {}", host, response.status()
+            )));
         }
 
-        let list_output = String::from_utf8_lossy(&output.stdout);
-        Ok(list_output.lines().any(|line| line.starts_with(&self.model)))
+        response.json::<OllamaTags>().await
+            .map_err(|e| ToolError::ExecutionFailed(format!("Invalid /api/tags response: {}", e)))
     }
-}
 
-#[async_trait]
-impl ToolAdapter for OllamaAdapter {
-    fn tool(&self) -> Tool {
-        Tool::Ollama
-    }
+    /// Picks the host and model to use and reports whether a pull is needed:
+    /// a reachable host that already has the model wins, otherwise the first
+    /// reachable host is used.
+    async fn resolve_target(&self, model: Option<&str>) -> Result<(String, String, bool), ToolError> {
+        let model = model.or(self.model.as_deref());
+        let mut first_reachable: Option<String> = None;
+
+        for host in &self.hosts {
+            let tags = match self.fetch_tags(host).await {
+                Ok(tags) => tags,
+                Err(e) => {
+                    tracing::debug!("{}", e);
+                    continue;
+                }
+            };
 
-    async fn is_available(&self) -> bool {
-        Command::new(&self.path)
-            .arg("--version")
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()
-            .await
-            .map(|s| s.success())
-            .unwrap_or(false)
+            match model {
+                Some(model) if tags.has_model(model) => {
+                    return Ok((host.clone(), model.to_string(), false));
+                }
+                None => {
+                    if let Some(installed) = tags.models.first() {
+                        return Ok((host.clone(), installed.name.clone(), false));
+                    }
+                }
+                _ => {}
+            }
+
+            if first_reachable.is_none() {
+                first_reachable = Some(host.clone());
+            }
+        }
+
+        match (first_reachable, model) {
+            (Some(host), Some(model)) => Ok((host, model.to_string(), true)),
+            (Some(host), None) => Err(ToolError::ExecutionFailed(format!(
+                "No models installed on {}. Set `model` in the ollama tool config.", host
+            ))),
+            (None, _) => Err(ToolError::NotAvailable(Tool::Ollama)),
+        }
     }
 
-    async fn execute(
+    async fn pull_model(
         &self,
-        request: ToolRequest,
-        output_tx: mpsc::Sender<ToolOutput>,
+        host: &str,
+        model: &str,
+        output_tx: &mpsc::Sender<ToolOutput>,
+        cancel: &mut watch::Receiver<bool>,
     ) -> Result<(), ToolError> {
-        let is_downloaded = self.is_model_downloaded().await.unwrap_or(false);
-
-        if !is_downloaded {
-            output_tx.send(ToolOutput::Stderr(format!("⚠️  Model '{}' is not downloaded.", self.model))).await.ok();
-            output_tx.send(ToolOutput::Stderr("".to_string())).await.ok();
-            output_tx.send(ToolOutput::Stderr("This will download the model, which may:".to_string())).await.ok();
-            output_tx.send(ToolOutput::Stderr("  - Take several minutes to complete".to_string())).await.ok();
-            output_tx.send(ToolOutput::Stderr("  - Use significant bandwidth".to_string())).await.ok();
-            output_tx.send(ToolOutput::Stderr("  - Require several GB of disk space".to_string())).await.ok();
-            output_tx.send(ToolOutput::Stderr("".to_string())).await.ok();
-            output_tx.send(ToolOutput::Stderr("Press Ctrl+C within 5 seconds to cancel...".to_string())).await.ok();
-
-            for i in (1..=5).rev() {
-                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                output_tx.send(ToolOutput::Stderr(format!("Starting download in {}...", i))).await.ok();
-            }
+        output_tx.send(ToolOutput::Stderr(format!(
+            "Model '{}' is not on {}; pulling it now (this may take a while)...", model, host
+        ))).await.ok();
+
+        let mut response = self.client
+            .post(ollama_url(host, "/api/pull"))
+            .json(&OllamaPullRequest { model: model.to_string(), stream: true })
+            .send()
+            .await
+            .map_err(|e| ToolError::ExecutionFailed(format!("Pull request failed: {}", e)))?;
 
-            output_tx.send(ToolOutput::Stderr("".to_string())).await.ok();
-            output_tx.send(ToolOutput::Stderr("Starting model download...".to_string())).await.ok();
+        if !response.status().is_success() {
+            return Err(ToolError::ExecutionFailed(format!(
+                "Pulling '{}' failed with {}", model, response.status()
+            )));
         }
 
-        let mut cmd = Command::new(&self.path);
+        let mut buffer = NdjsonBuffer::new();
+        let mut last_status = String::new();
+
+        loop {
+            let chunk = tokio::select! {
+                chunk = response.chunk() => chunk
+                    .map_err(|e| ToolError::ExecutionFailed(format!("Pull stream error: {}", e)))?,
+                _ = cancelled(cancel) => return Err(ToolError::ExecutionFailed("Cancelled".to_string())),
+            };
+            let finished = chunk.is_none();
+            let lines = match chunk {
+                Some(bytes) => buffer.push(&bytes),
+                None => buffer.finish().into_iter().collect(),
+            };
+
+            for line in &lines {
+                let Ok(progress) = serde_json::from_str::<OllamaPullProgress>(line) else {
+                    continue;
+                };
+                if let Some(err) = progress.error {
+                    return Err(ToolError::ExecutionFailed(format!("Pull failed: {}", err)));
+                }
 
-        cmd.arg("run");
-        cmd.arg(&self.model);
+                // Only forward changes so the download doesn't flood the client.
+                let status = progress.describe();
+                if status != last_status {
+                    output_tx.send(ToolOutput::Stderr(status.clone())).await.ok();
+                    last_status = status;
+                }
+            }
 
-        for arg in &self.args {
-            cmd.arg(arg);
+            if finished {
+                break;
+            }
         }
 
-        cmd.arg(&request.message);
+        Ok(())
+    }
+}
 
-        if let Some(ref dir) = request.working_dir {
-            cmd.current_dir(dir);
-        }
+#[async_trait]
+impl ToolAdapter for OllamaAdapter {
+    fn tool(&self) -> Tool {
+        Tool::Ollama
+    }
 
-        for (key, value) in &self.env {
-            cmd.env(key, value);
-        }
-        for (key, value) in &request.env {
-            cmd.env(key, value);
+    async fn is_available(&self) -> bool {
+        for host in &self.hosts {
+            if self.fetch_tags(host).await.is_ok() {
+                return true;
+            }
         }
+        false
+    }
 
-        cmd.stdout(Stdio::piped());
-        cmd.stderr(Stdio::piped());
-        cmd.stdin(Stdio::null());
+    async fn execute(
+        &self,
+        request: ToolRequest,
+        output_tx: mpsc::Sender<ToolOutput>,
+    ) -> Result<(), ToolError> {
+        let (_run, mut cancel) = Run::start(&self.runs);
 
-        let mut child = cmd.spawn()?;
+        let (host, model, needs_pull) = match self.resolve_target(request.model.as_deref()).await {
+            Ok(target) => target,
+            Err(e) => {
+                output_tx.send(ToolOutput::Error(e.to_string())).await.ok();
+                return Err(e);
+            }
+        };
 
-        if let Some(pid) = child.id() {
-            *self.current_process.lock() = Some(pid);
+        if needs_pull {
+            if let Err(e) = self.pull_model(&host, &model, &output_tx, &mut cancel).await {
+                output_tx.send(ToolOutput::Error(e.to_string())).await.ok();
+                return Err(e);
+            }
         }
 
-        let stdout = child.stdout.take()
-            .ok_or_else(|| ToolError::ExecutionFailed("Failed to capture stdout".to_string()))?;
-        let stderr = child.stderr.take()
-            .ok_or_else(|| ToolError::ExecutionFailed("Failed to capture stderr".to_string()))?;
-
-        let output_tx_stdout = output_tx.clone();
-        let output_tx_stderr = output_tx.clone();
+        let body = OllamaChatRequest::new(&model, &request.history, &request.message);
+        let mut response = match self.client
+            .post(ollama_url(&host, "/api/chat"))
+            .json(&body)
+            .send()
+            .await
+        {
+            Ok(r) if r.status().is_success() => r,
+            Ok(r) => {
+                let error_msg = format!("Ollama returned {}: {}", r.status(), r.text().await.unwrap_or_default());
+                output_tx.send(ToolOutput::Error(error_msg.clone())).await.ok();
+                return Err(ToolError::ExecutionFailed(error_msg));
+            }
+            Err(e) => {
+                let error_msg = format!("Ollama request failed: {}", e);
+                output_tx.send(ToolOutput::Error(error_msg.clone())).await.ok();
+                return Err(ToolError::ExecutionFailed(error_msg));
+            }
+        };
+
+        let mut buffer = NdjsonBuffer::new();
+        // The API streams token fragments, but our outputs are line-oriented.
+        let mut line = String::new();
+        let mut tokens = None;
+
+        loop {
+            // Dropping the response closes the connection, which stops generation.
+            let chunk = tokio::select! {
+                chunk = response.chunk() => chunk
+                    .map_err(|e| ToolError::ExecutionFailed(format!("Ollama stream error: {}", e)))?,
+                _ = cancelled(&mut cancel) => break,
+            };
+            let finished = chunk.is_none();
+            let lines = match chunk {
+                Some(bytes) => buffer.push(&bytes),
+                None => buffer.finish().into_iter().collect(),
+            };
+
+            for raw in lines {
+                let parsed: OllamaChatChunk = match serde_json::from_str(&raw) {
+                    Ok(c) => c,
+                    Err(_) => continue,
+                };
+
+                if let Some(err) = parsed.error {
+                    output_tx.send(ToolOutput::Error(err.clone())).await.ok();
+                    return Err(ToolError::ExecutionFailed(err));
+                }
 
-        let stdout_handle = tokio::spawn(async move {
-            let reader = BufReader::new(stdout);
-            let mut lines = reader.lines();
+                line.push_str(parsed.content());
+                while let Some(pos) = line.find('\n') {
+                    let complete: String = line.drain(..=pos).collect();
+                    if output_tx.send(ToolOutput::Stdout(complete.trim_end_matches('\n').to_string())).await.is_err() {
+                        return Ok(());
+                    }
+                }
 
-            while let Ok(Some(line)) = lines.next_line().await {
-                if output_tx_stdout.send(ToolOutput::Stdout(line)).await.is_err() {
-                    break;
+                if parsed.done {
+                    tokens = parsed.total_tokens();
                 }
             }
-        });
-
-        let stderr_handle = tokio::spawn(async move {
-            let reader = BufReader::new(stderr);
-            let mut lines = reader.lines();
 
-            while let Ok(Some(line)) = lines.next_line().await {
-                let _ = output_tx_stderr.send(ToolOutput::Stderr(line)).await;
+            if finished {
+                break;
             }
-        });
-
-        let status = child.wait().await?;
-
-        let _ = stdout_handle.await;
-        let _ = stderr_handle.await;
-
-        *self.current_process.lock() = None;
+        }
 
-        if status.success() {
-            output_tx.send(ToolOutput::Done { tokens: None }).await.ok();
-            Ok(())
-        } else {
-            let error_msg = format!("Ollama exited with code: {:?}", status.code());
-            output_tx.send(ToolOutput::Error(error_msg.clone())).await.ok();
-            Err(ToolError::ExecutionFailed(error_msg))
+        if !line.is_empty() {
+            output_tx.send(ToolOutput::Stdout(line)).await.ok();
         }
+
+        output_tx.send(ToolOutput::Done { tokens }).await.ok();
+        Ok(())
     }
 
     async fn cancel(&self) -> Result<(), ToolError> {
-        let pid = *self.current_process.lock();
-        if let Some(pid) = pid {
-            #[cfg(unix)]
-            {
-                use nix::sys::signal::{kill, Signal};
-                use nix::unistd::Pid;
-                let _ = kill(Pid::from_raw(pid as i32), Signal::SIGTERM);
-            }
-            #[cfg(windows)]
-            {
-                let _ = Command::new("taskkill")
-                    .args(["/PID", &pid.to_string(), "/F"])
-                    .status()
-                    .await;
-            }
+        // Requests don't carry an ID here, so every one in flight stops.
+        for run in self.runs.lock().iter() {
+            run.send_replace(true);
         }
-        *self.current_process.lock() = None;
         Ok(())
     }
 
     fn get_command(&self, request: &ToolRequest) -> String {
-        let mut parts = vec![self.path.clone(), "run".to_string(), self.model.clone()];
-        parts.extend(self.args.clone());
-        parts.push(format!("\"{}\"", request.message));
-        parts.join(" ")
+        format!(
+            "POST {} model={} messages={}",
+            ollama_url(&self.hosts[0], "/api/chat"),
+            request.model.as_deref().or(self.model.as_deref()).unwrap_or("<auto>"),
+            request.history.len() + 1
+        )
+    }
+
+    async fn list_models(&self) -> Vec<String> {
+        for host in &self.hosts {
+            if let Ok(tags) = self.fetch_tags(host).await {
+                return tags.model_names();
+            }
+        }
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancel_stops_only_requests_in_flight() {
+        let adapter = OllamaAdapter::new(vec![], None);
+        let (first, first_cancel) = Run::start(&adapter.runs);
+        adapter.cancel().await.unwrap();
+
+        // A later request must not un-cancel the earlier one.
+        let (second, second_cancel) = Run::start(&adapter.runs);
+        assert!(*first_cancel.borrow());
+        assert!(!*second_cancel.borrow());
+
+        drop(first);
+        assert_eq!(adapter.runs.lock().len(), 1);
+        drop(second);
+        assert!(adapter.runs.lock().is_empty());
     }
 }
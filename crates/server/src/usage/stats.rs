@@ -166,6 +166,10 @@ impl UsageTracker {
             last_used: last_used.and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
                 .map(|dt| dt.with_timezone(&Utc)),
             is_available: true,
+            input_tokens: 0,
+            output_tokens: 0,
+            peak_memory_bytes: 0,
+            cpu_time_ms: 0,
         })
     }
 
@@ -198,6 +202,10 @@ impl UsageTracker {
                 last_used: last_used.and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
                     .map(|dt| dt.with_timezone(&Utc)),
                 is_available: true,
+                input_tokens: 0,
+                output_tokens: 0,
+                peak_memory_bytes: 0,
+                cpu_time_ms: 0,
             });
         }
 